@@ -9,6 +9,8 @@ use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation}
 use serde::{Deserialize, Serialize};
 use std::time::{SystemTime, UNIX_EPOCH};
 
+pub mod quota;
+
 /// JWT Claims
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Claims {
@@ -167,3 +169,38 @@ impl OptionalAuthUser {
         self.0.as_ref().map(|u| u.tier.as_str()).unwrap_or("free")
     }
 }
+
+/// Admin user extractor
+/// Requires a valid token whose user ID is listed in ADMIN_USER_IDS (comma separated)
+/// Example: async fn my_handler(admin: AdminUser) -> impl IntoResponse { ... }
+#[derive(Debug, Clone)]
+pub struct AdminUser(pub AuthUser);
+
+fn is_admin(user_id: &str) -> bool {
+    std::env::var("ADMIN_USER_IDS")
+        .map(|ids| ids.split(',').any(|id| id.trim() == user_id))
+        .unwrap_or(false)
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AdminUser
+where
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let user = AuthUser::from_request_parts(parts, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+        if !is_admin(&user.user_id) {
+            let body = Json(serde_json::json!({
+                "error": "Admin access required"
+            }));
+            return Err((StatusCode::FORBIDDEN, body).into_response());
+        }
+
+        Ok(AdminUser(user))
+    }
+}
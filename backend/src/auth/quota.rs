@@ -0,0 +1,205 @@
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::request::Parts,
+    response::{IntoResponse, Response},
+    Json,
+};
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use super::OptionalAuthUser;
+use crate::db;
+use crate::models::ApiResponse;
+use crate::services::usage_ledger::SharedLedger;
+
+/// An AI operation and what it costs from the daily AI quota
+pub trait AiOperation: Send + Sync + 'static {
+    const WEIGHT: i32;
+}
+
+macro_rules! ai_operations {
+    ($($(#[$doc:meta])* $name:ident = $weight:expr;)*) => {
+        $(
+            $(#[$doc])*
+            pub struct $name;
+
+            impl AiOperation for $name {
+                const WEIGHT: i32 = $weight;
+            }
+        )*
+    };
+}
+
+/// Weights roughly follow how many tokens each operation uses
+pub mod ops {
+    use super::AiOperation;
+
+    ai_operations! {
+        Ask = 1;
+        Analyze = 2;
+        Translate = 2;
+        Vocabulary = 1;
+        Mindmap = 3;
+        Slides = 5;
+        Chapters = 3;
+        /// A whole review session: several questions at once
+        ReviewSession = 3;
+        ReviewQuestion = 1;
+        ReviewAnswer = 1;
+        MemoryCard = 1;
+    }
+}
+
+/// Daily AI quota extractor: reserves the units of operation `O` before the handler runs and
+/// rejects the request when they would go over the user's tier limit, or when the quota cannot
+/// be checked. The handler calls [`AiQuota::charge`] once the AI call has succeeded; otherwise
+/// the reservation is refunded when the extractor is dropped
+/// Reservations go to the `SharedLedger` request extension
+/// Unauthenticated requests share the "default" user's free quota
+/// Example: async fn my_handler(quota: AiQuota<ops::Slides>) -> impl IntoResponse { ... }
+pub struct AiQuota<O> {
+    pub auth: OptionalAuthUser,
+    ledger: SharedLedger,
+    /// Units reserved for the operation
+    units: i32,
+    charged: AtomicBool,
+    operation: PhantomData<O>,
+}
+
+#[async_trait]
+impl<S, O> FromRequestParts<S> for AiQuota<O>
+where
+    S: Send + Sync,
+    O: AiOperation,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let auth = OptionalAuthUser::from_request_parts(parts, state).await.unwrap_or_else(|e| match e {});
+        let limit = db::ai_daily_unit_limit(auth.tier_or_default());
+        let reserved = match parts.extensions.get::<SharedLedger>().cloned() {
+            Some(ledger) => ledger
+                .reserve_ai_units(auth.user_id_or_default(), O::WEIGHT, limit)
+                .await
+                .map(|reserved| reserved.then_some(ledger)),
+            None => Err(anyhow::anyhow!("no usage ledger on the request")),
+        };
+
+        match reserved {
+            Ok(Some(ledger)) => Ok(AiQuota {
+                auth,
+                ledger,
+                units: O::WEIGHT,
+                charged: AtomicBool::new(false),
+                operation: PhantomData,
+            }),
+            Ok(None) => Err(Json(ApiResponse::<()>::error_with_code(
+                "RATE_LIMIT_EXCEEDED",
+                "Daily AI limit reached. Please try again tomorrow.",
+            ))
+            .into_response()),
+            Err(e) => {
+                tracing::error!("Failed to reserve AI quota: {}", e);
+                Err(Json(ApiResponse::<()>::error_with_code(
+                    "QUOTA_UNAVAILABLE",
+                    "AI features are temporarily unavailable. Please try again later.",
+                ))
+                .into_response())
+            }
+        }
+    }
+}
+
+impl<O: AiOperation> AiQuota<O> {
+    pub fn user_id(&self) -> &str {
+        self.auth.user_id_or_default()
+    }
+
+    /// Keep the reserved units: the operation happened and counts against today's quota
+    pub fn charge(&self) {
+        self.charged.store(true, Ordering::Relaxed);
+    }
+}
+
+impl<O> Drop for AiQuota<O> {
+    fn drop(&mut self) {
+        if self.charged.load(Ordering::Relaxed) {
+            return;
+        }
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let ledger = self.ledger.clone();
+        let user_id = self.auth.user_id_or_default().to_string();
+        let units = self.units;
+        runtime.spawn(async move {
+            if let Err(e) = ledger.refund_ai_units(&user_id, units).await {
+                tracing::warn!("Failed to refund AI quota: {}", e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::test_support::MemoryLedger;
+    use axum::http::Request;
+    use std::sync::Arc;
+
+    async fn extract<O: AiOperation>(ledger: Option<&Arc<MemoryLedger>>, user_id: &str, tier: &str) -> Result<AiQuota<O>, Response> {
+        let token = crate::auth::generate_token(user_id, "quota@example.com", "Quota", None, "test", tier).unwrap();
+        let (mut parts, _) = Request::builder()
+            .header("Authorization", format!("Bearer {}", token))
+            .body(())
+            .unwrap()
+            .into_parts();
+        if let Some(ledger) = ledger {
+            parts.extensions.insert::<SharedLedger>(ledger.clone());
+        }
+        AiQuota::<O>::from_request_parts(&mut parts, &()).await
+    }
+
+    async fn error_code(result: Result<AiQuota<ops::Ask>, Response>) -> String {
+        let body = axum::body::to_bytes(result.err().unwrap().into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        body["code"].as_str().unwrap_or_default().to_string()
+    }
+
+    #[tokio::test]
+    async fn test_quota_rejects_requests_over_the_daily_limit() {
+        let ledger = Arc::new(MemoryLedger::default());
+        let limit = db::ai_daily_unit_limit("free").unwrap();
+        let attempts = (limit / ops::Slides::WEIGHT + 5) as usize;
+        let results = futures_util::future::join_all((0..attempts).map(|_| extract::<ops::Slides>(Some(&ledger), "quota-user", "free"))).await;
+
+        let granted: Vec<_> = results.into_iter().filter_map(Result::ok).collect();
+        assert_eq!(granted.len() as i32, limit / ops::Slides::WEIGHT);
+        granted.iter().for_each(AiQuota::charge);
+        assert!(ledger.ai_units_used("quota-user") <= limit);
+        assert_eq!(error_code(extract::<ops::Ask>(Some(&ledger), "quota-user", "free").await).await, "RATE_LIMIT_EXCEEDED");
+        assert!(extract::<ops::Ask>(Some(&ledger), "quota-pro", "pro").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_quota_fails_closed_without_a_ledger() {
+        assert_eq!(error_code(extract::<ops::Ask>(None, "quota-user", "free").await).await, "QUOTA_UNAVAILABLE");
+    }
+
+    #[tokio::test]
+    async fn test_uncharged_reservation_is_refunded() {
+        let ledger = Arc::new(MemoryLedger::default());
+        let quota = extract::<ops::Mindmap>(Some(&ledger), "quota-user", "free").await.ok().unwrap();
+        assert_eq!(ledger.ai_units_used("quota-user"), ops::Mindmap::WEIGHT);
+        drop(quota);
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert_eq!(ledger.ai_units_used("quota-user"), 0);
+
+        let quota = extract::<ops::Mindmap>(Some(&ledger), "quota-user", "free").await.ok().unwrap();
+        quota.charge();
+        drop(quota);
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert_eq!(ledger.ai_units_used("quota-user"), ops::Mindmap::WEIGHT);
+    }
+}
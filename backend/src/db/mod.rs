@@ -1,7 +1,16 @@
 use anyhow::Result;
-use sqlx::{PgPool, postgres::PgPoolOptions, Row};
+use rand::Rng;
+use sqlx::{PgConnection, PgPool, postgres::PgPoolOptions, Row};
 use chrono::Utc;
 
+use crate::models::{DeckOptions, LearnerProfile};
+use crate::services::ai_usage::AiCall;
+use crate::services::fsrs;
+use crate::services::fsrs_optimizer::{Fit, ReviewEntry};
+use crate::services::review_queue::SiblingKey;
+use crate::services::scheduling::{self, CardState};
+use crate::services::language::{DEFAULT_NATIVE_LANGUAGE, DEFAULT_TARGET_LANGUAGE};
+
 pub type DbPool = PgPool;
 
 /// Initialize database and create tables
@@ -39,6 +48,16 @@ pub async fn init_db() -> Result<DbPool> {
     sqlx::query("ALTER TABLE users ADD COLUMN IF NOT EXISTS invited_by TEXT")
         .execute(&pool).await.ok();
 
+    // Add language settings columns (for existing databases)
+    sqlx::query("ALTER TABLE users ADD COLUMN IF NOT EXISTS native_language TEXT DEFAULT 'zh'")
+        .execute(&pool).await.ok();
+    sqlx::query("ALTER TABLE users ADD COLUMN IF NOT EXISTS target_language TEXT DEFAULT 'en'")
+        .execute(&pool).await.ok();
+
+    // Migration: spaced repetition scheduler ('sm2' or 'fsrs')
+    sqlx::query("ALTER TABLE users ADD COLUMN IF NOT EXISTS scheduler TEXT DEFAULT 'sm2'")
+        .execute(&pool).await.ok();
+
     // Create vocabulary table
     sqlx::query(
         "CREATE TABLE IF NOT EXISTS vocabulary (
@@ -64,15 +83,111 @@ pub async fn init_db() -> Result<DbPool> {
             due_at TEXT,
             review_count INTEGER DEFAULT 0,
             learning_step INTEGER DEFAULT 0,
+            phase TEXT,
             lapses INTEGER DEFAULT 0,
+            stability DOUBLE PRECISION,
+            difficulty DOUBLE PRECISION,
             source_video_id TEXT,
             source_sentence TEXT,
+            source_timestamp DOUBLE PRECISION,
             created_at TIMESTAMPTZ DEFAULT NOW(),
             last_reviewed_at TIMESTAMPTZ,
             UNIQUE(user_id, vocabulary_id)
         )"
     ).execute(&pool).await?;
 
+    // Migration: FSRS memory state, NULL until the card is reviewed with FSRS
+    sqlx::query(
+        "ALTER TABLE user_vocabulary ADD COLUMN IF NOT EXISTS stability DOUBLE PRECISION"
+    ).execute(&pool).await.ok();
+    sqlx::query(
+        "ALTER TABLE user_vocabulary ADD COLUMN IF NOT EXISTS difficulty DOUBLE PRECISION"
+    ).execute(&pool).await.ok();
+
+    // Migration: learning/review/relearning phase, previously implied by learning_step >= 4
+    sqlx::query(
+        "ALTER TABLE user_vocabulary ADD COLUMN IF NOT EXISTS phase TEXT"
+    ).execute(&pool).await.ok();
+    sqlx::query(
+        "UPDATE user_vocabulary
+         SET phase = CASE WHEN COALESCE(learning_step, 0) >= 4 THEN 'review' ELSE 'learning' END
+         WHERE phase IS NULL"
+    ).execute(&pool).await.ok();
+
+    // Migration: where in the source video the word was saved from
+    sqlx::query(
+        "ALTER TABLE user_vocabulary ADD COLUMN IF NOT EXISTS source_timestamp DOUBLE PRECISION"
+    ).execute(&pool).await.ok();
+
+    // Append-only log of every review, used to fit scheduler parameters; undone reviews stay in
+    // the log with `undone_at` set and are left out of everything that reads it
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS review_log (
+            id BIGSERIAL PRIMARY KEY,
+            user_id TEXT NOT NULL,
+            vocabulary_id INTEGER NOT NULL,
+            reviewed_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+            rating INTEGER NOT NULL,
+            scheduler TEXT NOT NULL,
+            elapsed_days DOUBLE PRECISION,
+            previous_interval_minutes INTEGER NOT NULL,
+            next_interval_minutes INTEGER NOT NULL,
+            source TEXT NOT NULL,
+            phase TEXT,
+            undone_at TIMESTAMPTZ
+        )"
+    ).execute(&pool).await?;
+
+    // Migration: phase of the card before the review, used for the daily review limit
+    sqlx::query(
+        "ALTER TABLE review_log ADD COLUMN IF NOT EXISTS phase TEXT"
+    ).execute(&pool).await.ok();
+
+    // Migration: when a review was undone
+    sqlx::query(
+        "ALTER TABLE review_log ADD COLUMN IF NOT EXISTS undone_at TIMESTAMPTZ"
+    ).execute(&pool).await.ok();
+
+    sqlx::query(
+        "CREATE INDEX IF NOT EXISTS idx_review_log_user ON review_log(user_id, vocabulary_id, reviewed_at)"
+    ).execute(&pool).await?;
+
+    // State before each of a user's recent reviews, to undo them
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS review_snapshots (
+            review_log_id BIGINT PRIMARY KEY REFERENCES review_log(id) ON DELETE CASCADE,
+            user_id TEXT NOT NULL,
+            snapshot TEXT NOT NULL
+        )"
+    ).execute(&pool).await?;
+
+    // Per-user learning steps, daily limits and interval settings; defaults when missing
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS deck_options (
+            user_id TEXT PRIMARY KEY,
+            learning_steps TEXT NOT NULL,
+            relearning_steps TEXT NOT NULL,
+            graduating_interval_days INTEGER NOT NULL,
+            new_cards_per_day INTEGER NOT NULL,
+            reviews_per_day INTEGER NOT NULL,
+            maximum_interval_days INTEGER NOT NULL,
+            easy_bonus DOUBLE PRECISION NOT NULL,
+            updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+        )"
+    ).execute(&pool).await?;
+
+    // FSRS weights fitted to each user's review log
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS scheduler_params (
+            user_id TEXT PRIMARY KEY,
+            weights TEXT NOT NULL,
+            scored_reviews INTEGER NOT NULL,
+            log_loss DOUBLE PRECISION NOT NULL,
+            default_log_loss DOUBLE PRECISION NOT NULL,
+            fitted_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+        )"
+    ).execute(&pool).await?;
+
     // Create learning statistics table
     sqlx::query(
         "CREATE TABLE IF NOT EXISTS learning_stats (
@@ -110,7 +225,8 @@ pub async fn init_db() -> Result<DbPool> {
             video_id TEXT NOT NULL,
             timestamp REAL NOT NULL,
             english TEXT,
-            chinese TEXT,
+            translation TEXT,
+            translation_lang TEXT,
             note_text TEXT,
             created_at TIMESTAMPTZ DEFAULT NOW()
         )"
@@ -126,6 +242,15 @@ pub async fn init_db() -> Result<DbPool> {
         "ALTER TABLE notes ALTER COLUMN english DROP NOT NULL"
     ).execute(&pool).await.ok(); // Ignore error if already nullable
 
+    // Rename chinese column to translation (migration)
+    sqlx::query(
+        "ALTER TABLE notes RENAME COLUMN chinese TO translation"
+    ).execute(&pool).await.ok(); // Ignore error if already renamed
+
+    sqlx::query(
+        "ALTER TABLE notes ADD COLUMN IF NOT EXISTS translation_lang TEXT"
+    ).execute(&pool).await.ok(); // Ignore error if column exists
+
     // Create index on notes
     sqlx::query(
         "CREATE INDEX IF NOT EXISTS idx_notes_user_video ON notes(user_id, video_id)"
@@ -149,6 +274,37 @@ pub async fn init_db() -> Result<DbPool> {
         "CREATE INDEX IF NOT EXISTS idx_watch_history_user ON watch_history(user_id, watched_at DESC)"
     ).execute(&pool).await?;
 
+    // Create AI chat threads table (one conversation per user and video, several allowed)
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS chat_threads (
+            id TEXT PRIMARY KEY,
+            user_id TEXT NOT NULL,
+            video_id TEXT NOT NULL,
+            title TEXT NOT NULL DEFAULT '',
+            created_at TIMESTAMPTZ DEFAULT NOW(),
+            updated_at TIMESTAMPTZ DEFAULT NOW()
+        )"
+    ).execute(&pool).await?;
+
+    sqlx::query(
+        "CREATE INDEX IF NOT EXISTS idx_chat_threads_user_video ON chat_threads(user_id, video_id, updated_at DESC)"
+    ).execute(&pool).await?;
+
+    // Create AI chat messages table
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS chat_messages (
+            id SERIAL PRIMARY KEY,
+            thread_id TEXT NOT NULL REFERENCES chat_threads(id) ON DELETE CASCADE,
+            role TEXT NOT NULL,
+            content TEXT NOT NULL,
+            created_at TIMESTAMPTZ DEFAULT NOW()
+        )"
+    ).execute(&pool).await?;
+
+    sqlx::query(
+        "CREATE INDEX IF NOT EXISTS idx_chat_messages_thread ON chat_messages(thread_id, id)"
+    ).execute(&pool).await?;
+
     // Create daily usage table for rate limiting
     sqlx::query(
         "CREATE TABLE IF NOT EXISTS daily_usage (
@@ -157,6 +313,9 @@ pub async fn init_db() -> Result<DbPool> {
             date TEXT NOT NULL,
             video_parse_count INTEGER DEFAULT 0,
             ai_chat_count INTEGER DEFAULT 0,
+            ai_units INTEGER DEFAULT 0,
+            apify_count INTEGER DEFAULT 0,
+            supadata_count INTEGER DEFAULT 0,
             created_at TIMESTAMPTZ DEFAULT NOW(),
             UNIQUE(user_id, date)
         )"
@@ -167,21 +326,87 @@ pub async fn init_db() -> Result<DbPool> {
         "ALTER TABLE daily_usage ADD COLUMN IF NOT EXISTS ai_chat_count INTEGER DEFAULT 0"
     ).execute(&pool).await.ok(); // Ignore error if column exists
 
+    // Migration: weighted AI quota shared by every AI endpoint
+    sqlx::query(
+        "ALTER TABLE daily_usage ADD COLUMN IF NOT EXISTS ai_units INTEGER DEFAULT 0"
+    ).execute(&pool).await.ok();
+
+    // Migration: paid transcript source calls (previously counted in memory)
+    sqlx::query(
+        "ALTER TABLE daily_usage ADD COLUMN IF NOT EXISTS apify_count INTEGER DEFAULT 0"
+    ).execute(&pool).await.ok();
+    sqlx::query(
+        "ALTER TABLE daily_usage ADD COLUMN IF NOT EXISTS supadata_count INTEGER DEFAULT 0"
+    ).execute(&pool).await.ok();
+
     // Create index on daily usage
     sqlx::query(
         "CREATE INDEX IF NOT EXISTS idx_daily_usage_user_date ON daily_usage(user_id, date)"
     ).execute(&pool).await?;
 
+    // Create AI call log (one row per model API call, for token and cost accounting)
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS ai_calls (
+            id SERIAL PRIMARY KEY,
+            user_id TEXT NOT NULL,
+            endpoint TEXT NOT NULL,
+            provider TEXT NOT NULL,
+            model TEXT NOT NULL,
+            input_tokens BIGINT NOT NULL DEFAULT 0,
+            output_tokens BIGINT NOT NULL DEFAULT 0,
+            latency_ms BIGINT NOT NULL DEFAULT 0,
+            cost_usd DOUBLE PRECISION NOT NULL DEFAULT 0,
+            created_at TIMESTAMPTZ DEFAULT NOW()
+        )"
+    ).execute(&pool).await?;
+
+    sqlx::query(
+        "CREATE INDEX IF NOT EXISTS idx_ai_calls_user_created ON ai_calls(user_id, created_at)"
+    ).execute(&pool).await?;
+
+    sqlx::query(
+        "CREATE INDEX IF NOT EXISTS idx_ai_calls_created ON ai_calls(created_at)"
+    ).execute(&pool).await?;
+
+    // Create transcript fetch log (which source served each video info / subtitle request)
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS transcript_fetches (
+            id SERIAL PRIMARY KEY,
+            user_id TEXT NOT NULL,
+            video_id TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            lang TEXT,
+            source TEXT,
+            error TEXT,
+            latency_ms BIGINT NOT NULL DEFAULT 0,
+            created_at TIMESTAMPTZ DEFAULT NOW()
+        )"
+    ).execute(&pool).await?;
+
+    sqlx::query(
+        "CREATE INDEX IF NOT EXISTS idx_transcript_fetches_created ON transcript_fetches(created_at)"
+    ).execute(&pool).await?;
+
     // Create video mindmap cache table
     sqlx::query(
         "CREATE TABLE IF NOT EXISTS video_mindmaps (
             id SERIAL PRIMARY KEY,
-            video_id TEXT UNIQUE NOT NULL,
+            video_id TEXT NOT NULL,
+            lang TEXT NOT NULL DEFAULT 'zh',
             markdown TEXT NOT NULL,
-            created_at TIMESTAMPTZ DEFAULT NOW()
+            created_at TIMESTAMPTZ DEFAULT NOW(),
+            UNIQUE(video_id, lang)
         )"
     ).execute(&pool).await?;
 
+    // Migration: mindmaps are written in the learner's native language, cache one per language
+    sqlx::query("ALTER TABLE video_mindmaps ADD COLUMN IF NOT EXISTS lang TEXT NOT NULL DEFAULT 'zh'")
+        .execute(&pool).await.ok();
+    sqlx::query("ALTER TABLE video_mindmaps DROP CONSTRAINT IF EXISTS video_mindmaps_video_id_key")
+        .execute(&pool).await.ok();
+    sqlx::query("CREATE UNIQUE INDEX IF NOT EXISTS idx_video_mindmaps_video_lang ON video_mindmaps(video_id, lang)")
+        .execute(&pool).await.ok();
+
     // Create video slides cache table
     sqlx::query(
         "CREATE TABLE IF NOT EXISTS video_slides (
@@ -192,6 +417,61 @@ pub async fn init_db() -> Result<DbPool> {
         )"
     ).execute(&pool).await?;
 
+    // Create video subtitles cache table (one row per video, language and source)
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS video_subtitles (
+            id SERIAL PRIMARY KEY,
+            video_id TEXT NOT NULL,
+            lang TEXT NOT NULL,
+            source TEXT NOT NULL,
+            subtitles_json TEXT NOT NULL,
+            sentences_json TEXT,
+            created_at TIMESTAMPTZ DEFAULT NOW(),
+            UNIQUE(video_id, lang, source)
+        )"
+    ).execute(&pool).await?;
+
+    // Migration: sentence-level track stored next to the raw cues
+    sqlx::query(
+        "ALTER TABLE video_subtitles ADD COLUMN IF NOT EXISTS sentences_json TEXT"
+    ).execute(&pool).await.ok();
+
+    // Create uploaded videos table (user-provided recordings with their own subtitle file)
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS uploaded_videos (
+            id TEXT PRIMARY KEY,
+            user_id TEXT NOT NULL,
+            title TEXT NOT NULL,
+            duration DOUBLE PRECISION NOT NULL DEFAULT 0,
+            lang TEXT NOT NULL,
+            subtitle_format TEXT NOT NULL,
+            subtitles_json TEXT NOT NULL,
+            media_key TEXT,
+            media_url TEXT,
+            media_type TEXT,
+            created_at TIMESTAMPTZ DEFAULT NOW()
+        )"
+    ).execute(&pool).await?;
+
+    sqlx::query(
+        "CREATE INDEX IF NOT EXISTS idx_uploaded_videos_user ON uploaded_videos(user_id, created_at)"
+    ).execute(&pool).await?;
+
+    // Create subtitle translation store (one row per line, target language and provider)
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS subtitle_translations (
+            id SERIAL PRIMARY KEY,
+            video_id TEXT NOT NULL,
+            subtitle_index INTEGER NOT NULL,
+            lang TEXT NOT NULL,
+            provider TEXT NOT NULL,
+            source_text TEXT NOT NULL,
+            translation TEXT NOT NULL,
+            created_at TIMESTAMPTZ DEFAULT NOW(),
+            UNIQUE(video_id, subtitle_index, lang, provider)
+        )"
+    ).execute(&pool).await?;
+
     // Initialize default user progress
     sqlx::query(
         "INSERT INTO user_progress (user_id) VALUES ('default') ON CONFLICT DO NOTHING"
@@ -368,6 +648,116 @@ pub async fn get_bonus_quota(pool: &DbPool, user_id: &str) -> Result<i32> {
     Ok(result.map(|row| row.get::<i32, _>("bonus_quota")).unwrap_or(0))
 }
 
+// ============ Language Settings Functions ============
+
+/// Get a user's language settings (defaults for anonymous or unknown users)
+pub async fn get_learner_profile(pool: &DbPool, user_id: &str) -> Result<LearnerProfile> {
+    let result = sqlx::query("SELECT native_language, target_language FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_optional(pool).await?;
+
+    let (native_language, target_language) = match result {
+        Some(row) => (
+            row.get::<Option<String>, _>("native_language"),
+            row.get::<Option<String>, _>("target_language"),
+        ),
+        None => (None, None),
+    };
+
+    Ok(LearnerProfile {
+        native_language: native_language.unwrap_or_else(|| DEFAULT_NATIVE_LANGUAGE.to_string()),
+        target_language: target_language.unwrap_or_else(|| DEFAULT_TARGET_LANGUAGE.to_string()),
+    })
+}
+
+/// Update a user's native language
+pub async fn set_native_language(pool: &DbPool, user_id: &str, native_language: &str) -> Result<()> {
+    sqlx::query("UPDATE users SET native_language = $1 WHERE id = $2")
+        .bind(native_language)
+        .bind(user_id)
+        .execute(pool).await?;
+    Ok(())
+}
+
+/// Update the language a user is learning
+pub async fn set_target_language(pool: &DbPool, user_id: &str, target_language: &str) -> Result<()> {
+    sqlx::query("UPDATE users SET target_language = $1 WHERE id = $2")
+        .bind(target_language)
+        .bind(user_id)
+        .execute(pool).await?;
+    Ok(())
+}
+
+pub use crate::services::scheduling::{CardPhase, Scheduler};
+
+/// Get a user's scheduler (SM-2 for anonymous or unknown users)
+pub async fn get_scheduler(pool: &DbPool, user_id: &str) -> Result<Scheduler> {
+    let result = sqlx::query("SELECT scheduler FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_optional(pool).await?;
+
+    Ok(result
+        .and_then(|row| row.get::<Option<String>, _>("scheduler"))
+        .and_then(|name| Scheduler::from_name(&name))
+        .unwrap_or_default())
+}
+
+/// Update the scheduler a user reviews with
+pub async fn set_scheduler(pool: &DbPool, user_id: &str, scheduler: Scheduler) -> Result<()> {
+    sqlx::query("UPDATE users SET scheduler = $1 WHERE id = $2")
+        .bind(scheduler.as_str())
+        .bind(user_id)
+        .execute(pool).await?;
+    Ok(())
+}
+
+/// Get a user's deck options (the defaults until they are changed)
+pub async fn get_deck_options(pool: &DbPool, user_id: &str) -> Result<DeckOptions> {
+    let row = sqlx::query(
+        "SELECT learning_steps, relearning_steps, graduating_interval_days, new_cards_per_day,
+                reviews_per_day, maximum_interval_days, easy_bonus
+         FROM deck_options WHERE user_id = $1"
+    )
+    .bind(user_id)
+    .fetch_optional(pool).await?;
+
+    let Some(row) = row else {
+        return Ok(DeckOptions::default());
+    };
+    Ok(DeckOptions {
+        learning_steps: serde_json::from_str(&row.get::<String, _>("learning_steps"))?,
+        relearning_steps: serde_json::from_str(&row.get::<String, _>("relearning_steps"))?,
+        graduating_interval_days: row.get("graduating_interval_days"),
+        new_cards_per_day: row.get("new_cards_per_day"),
+        reviews_per_day: row.get("reviews_per_day"),
+        maximum_interval_days: row.get("maximum_interval_days"),
+        easy_bonus: row.get("easy_bonus"),
+    })
+}
+
+pub async fn save_deck_options(pool: &DbPool, user_id: &str, options: &DeckOptions) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO deck_options
+         (user_id, learning_steps, relearning_steps, graduating_interval_days, new_cards_per_day,
+          reviews_per_day, maximum_interval_days, easy_bonus, updated_at)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, NOW())
+         ON CONFLICT (user_id) DO UPDATE SET
+            learning_steps = $2, relearning_steps = $3, graduating_interval_days = $4,
+            new_cards_per_day = $5, reviews_per_day = $6, maximum_interval_days = $7,
+            easy_bonus = $8, updated_at = NOW()"
+    )
+    .bind(user_id)
+    .bind(serde_json::to_string(&options.learning_steps)?)
+    .bind(serde_json::to_string(&options.relearning_steps)?)
+    .bind(options.graduating_interval_days)
+    .bind(options.new_cards_per_day)
+    .bind(options.reviews_per_day)
+    .bind(options.maximum_interval_days)
+    .bind(options.easy_bonus)
+    .execute(pool).await?;
+    Ok(())
+}
+
 // ============ Vocabulary Functions ============
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -384,19 +774,34 @@ pub struct SavedVocabulary {
     pub due_at: Option<String>,
     pub review_count: i32,
     pub learning_step: i32,
+    pub phase: CardPhase,
     pub source_video_id: Option<String>,
     pub source_sentence: Option<String>,
+    /// Seconds into the source video where the word is spoken
+    pub source_timestamp: Option<f64>,
     pub created_at: String,
     pub last_reviewed_at: Option<String>,
-    pub memory_strength: f64,
+    /// FSRS memory state; `None` for cards only reviewed with SM-2
+    pub stability: Option<f64>,
+    pub difficulty: Option<f64>,
+    /// Probability (0-1) the word is recalled right now
+    pub retrievability: f64,
 }
 
-const LEARNING_INTERVALS: [i32; 4] = [20, 60, 540, 1440];
+/// Where a saved word was met
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VocabularySource<'a> {
+    pub video_id: Option<&'a str>,
+    pub sentence: Option<&'a str>,
+    pub timestamp: Option<f64>,
+}
 
-fn calculate_memory_strength(
+/// Forgetting curve of the card: FSRS cards use their stability, SM-2 cards one equal to their
+/// current interval (which SM-2 schedules at roughly 90% recall)
+fn calculate_retrievability(
     last_reviewed_at: Option<&str>,
+    stability: Option<f64>,
     interval_minutes: i32,
-    learning_step: i32,
     created_at: &str,
 ) -> f64 {
     let now = Utc::now();
@@ -415,16 +820,10 @@ fn calculate_memory_strength(
             .unwrap_or_else(|_| now.naive_utc())
     });
 
-    let elapsed_minutes = (now.naive_utc() - last_time).num_minutes() as f64;
-
-    let half_life = if learning_step == 0 && interval_minutes <= 20 {
-        15.0
-    } else {
-        (interval_minutes as f64 * 0.4).max(10.0)
-    };
+    let elapsed_days = (now.naive_utc() - last_time).num_seconds() as f64 / 86400.0;
+    let stability = stability.unwrap_or(interval_minutes as f64 / 1440.0);
 
-    let strength = (-elapsed_minutes / half_life).exp();
-    strength.clamp(0.0, 1.0)
+    fsrs::retrievability(elapsed_days, stability).clamp(0.0, 1.0)
 }
 
 pub async fn save_vocabulary(
@@ -434,8 +833,7 @@ pub async fn save_vocabulary(
     meaning: &str,
     level: &str,
     example: Option<&str>,
-    source_video_id: Option<&str>,
-    source_sentence: Option<&str>,
+    source: VocabularySource<'_>,
 ) -> Result<i32> {
     // Insert or get vocabulary
     sqlx::query(
@@ -453,18 +851,20 @@ pub async fn save_vocabulary(
         .fetch_one(pool).await?
         .get("id");
 
+    // New cards are first due after the first learning step, or right away without steps
+    let first_step = get_deck_options(pool, user_id).await?.learning_steps.first().copied().unwrap_or(0);
     let now = Utc::now();
     let today = now.format("%Y-%m-%d").to_string();
     let due_at = now
-        .checked_add_signed(chrono::Duration::minutes(LEARNING_INTERVALS[0] as i64))
+        .checked_add_signed(chrono::Duration::minutes(first_step as i64))
         .unwrap()
         .format("%Y-%m-%dT%H:%M:%S")
         .to_string();
 
     sqlx::query(
         "INSERT INTO user_vocabulary
-         (user_id, vocabulary_id, due_date, due_at, interval_minutes, learning_step, source_video_id, source_sentence)
-         VALUES ($1, $2, $3, $4, $5, 0, $6, $7)
+         (user_id, vocabulary_id, due_date, due_at, interval_minutes, learning_step, phase, source_video_id, source_sentence, source_timestamp)
+         VALUES ($1, $2, $3, $4, $5, 0, 'learning', $6, $7, $8)
          ON CONFLICT (user_id, vocabulary_id) DO UPDATE SET
             due_date = $3, due_at = $4, interval_minutes = $5"
     )
@@ -472,9 +872,10 @@ pub async fn save_vocabulary(
     .bind(vocab_id)
     .bind(&today)
     .bind(&due_at)
-    .bind(LEARNING_INTERVALS[0])
-    .bind(source_video_id)
-    .bind(source_sentence)
+    .bind(first_step)
+    .bind(source.video_id)
+    .bind(source.sentence)
+    .bind(source.timestamp)
     .execute(pool).await?;
 
     Ok(vocab_id)
@@ -488,7 +889,7 @@ pub async fn get_vocabulary_list(pool: &DbPool, user_id: &str, due_only: bool) -
             "SELECT v.id, v.word, v.meaning, v.level, v.example,
                     uv.ease_factor, uv.interval_days, COALESCE(uv.interval_minutes, 0) as interval_minutes,
                     uv.due_date, uv.due_at, uv.review_count, COALESCE(uv.learning_step, 0) as learning_step,
-                    uv.source_video_id, uv.source_sentence,
+                    uv.phase, uv.source_video_id, uv.source_sentence, uv.source_timestamp, uv.stability, uv.difficulty,
                     to_char(uv.created_at, 'YYYY-MM-DD HH24:MI:SS') as created_at,
                     to_char(uv.last_reviewed_at, 'YYYY-MM-DD HH24:MI:SS') as last_reviewed_at
              FROM vocabulary v
@@ -507,7 +908,7 @@ pub async fn get_vocabulary_list(pool: &DbPool, user_id: &str, due_only: bool) -
             "SELECT v.id, v.word, v.meaning, v.level, v.example,
                     uv.ease_factor, uv.interval_days, COALESCE(uv.interval_minutes, 0) as interval_minutes,
                     uv.due_date, uv.due_at, uv.review_count, COALESCE(uv.learning_step, 0) as learning_step,
-                    uv.source_video_id, uv.source_sentence,
+                    uv.phase, uv.source_video_id, uv.source_sentence, uv.source_timestamp, uv.stability, uv.difficulty,
                     to_char(uv.created_at, 'YYYY-MM-DD HH24:MI:SS') as created_at,
                     to_char(uv.last_reviewed_at, 'YYYY-MM-DD HH24:MI:SS') as last_reviewed_at
              FROM vocabulary v
@@ -523,12 +924,12 @@ pub async fn get_vocabulary_list(pool: &DbPool, user_id: &str, due_only: bool) -
         let created_at: String = row.get("created_at");
         let last_reviewed_at: Option<String> = row.get("last_reviewed_at");
         let interval_minutes: i32 = row.get("interval_minutes");
-        let learning_step: i32 = row.get("learning_step");
+        let stability: Option<f64> = row.get("stability");
 
-        let memory_strength = calculate_memory_strength(
+        let retrievability = calculate_retrievability(
             last_reviewed_at.as_deref(),
+            stability,
             interval_minutes,
-            learning_step,
             &created_at,
         );
 
@@ -544,138 +945,549 @@ pub async fn get_vocabulary_list(pool: &DbPool, user_id: &str, due_only: bool) -
             due_date: row.get("due_date"),
             due_at: row.get("due_at"),
             review_count: row.get("review_count"),
-            learning_step,
+            learning_step: row.get("learning_step"),
+            phase: row
+                .get::<Option<String>, _>("phase")
+                .and_then(|phase| CardPhase::from_name(&phase))
+                .unwrap_or_default(),
             source_video_id: row.get("source_video_id"),
             source_sentence: row.get("source_sentence"),
+            source_timestamp: row.get("source_timestamp"),
             created_at,
             last_reviewed_at,
-            memory_strength,
+            stability,
+            difficulty: row.get("difficulty"),
+            retrievability,
         }
     }).collect();
 
+    if due_only {
+        return apply_daily_limits(pool, user_id, results).await;
+    }
     Ok(results)
 }
 
-pub async fn review_vocabulary(pool: &DbPool, user_id: &str, vocab_id: i32, quality: i32) -> Result<()> {
+/// Start of the current day, which daily limits and burying count from
+fn utc_day_start() -> chrono::DateTime<Utc> {
+    Utc::now().date_naive().and_hms_opt(0, 0, 0).unwrap().and_utc()
+}
+
+/// Hold back due cards beyond the user's daily limits: new cards (never reviewed) count against
+/// `new_cards_per_day`, review-phase cards against `reviews_per_day`, both including the reviews
+/// already done today (UTC); learning and relearning cards are never held back
+async fn apply_daily_limits(pool: &DbPool, user_id: &str, due: Vec<SavedVocabulary>) -> Result<Vec<SavedVocabulary>> {
+    let options = get_deck_options(pool, user_id).await?;
     let row = sqlx::query(
-        "SELECT ease_factor, COALESCE(learning_step, 0) as learning_step, interval_days
-         FROM user_vocabulary WHERE vocabulary_id = $1 AND user_id = $2"
+        "SELECT COUNT(*) FILTER (WHERE elapsed_days IS NULL) as new_reviewed,
+                COUNT(*) FILTER (WHERE phase = 'review') as reviews_done
+         FROM review_log WHERE user_id = $1 AND reviewed_at >= $2 AND undone_at IS NULL"
     )
-    .bind(vocab_id)
     .bind(user_id)
+    .bind(utc_day_start())
     .fetch_one(pool).await?;
 
-    let ease_factor: f64 = row.get("ease_factor");
-    let learning_step: i32 = row.get("learning_step");
-    let interval_days: i32 = row.get("interval_days");
+    let mut new_left = (options.new_cards_per_day as i64 - row.get::<i64, _>("new_reviewed")).max(0);
+    let mut reviews_left = (options.reviews_per_day as i64 - row.get::<i64, _>("reviews_done")).max(0);
+    Ok(due
+        .into_iter()
+        .filter(|card| {
+            let left = match card.phase {
+                _ if card.review_count == 0 => &mut new_left,
+                CardPhase::Review => &mut reviews_left,
+                _ => return true,
+            };
+            *left -= 1;
+            *left >= 0
+        })
+        .collect())
+}
 
-    let now = Utc::now();
+/// Cards reviewed today that were saved from a sentence, with the sentence's sibling key
+pub async fn get_sentences_reviewed_today(pool: &DbPool, user_id: &str) -> Result<Vec<(i32, SiblingKey)>> {
+    let rows = sqlx::query(
+        "SELECT DISTINCT uv.vocabulary_id, uv.source_video_id, TRIM(uv.source_sentence) as source_sentence
+         FROM review_log l
+         JOIN user_vocabulary uv ON uv.user_id = l.user_id AND uv.vocabulary_id = l.vocabulary_id
+         WHERE l.user_id = $1 AND l.reviewed_at >= $2 AND l.undone_at IS NULL
+           AND uv.source_sentence IS NOT NULL AND TRIM(uv.source_sentence) <> ''"
+    )
+    .bind(user_id)
+    .bind(utc_day_start())
+    .fetch_all(pool).await?;
 
-    let (new_interval_minutes, new_learning_step, new_ease, new_interval_days) = if quality < 2 {
-        (LEARNING_INTERVALS[0], 0, (ease_factor - 0.2).max(1.3), 0)
-    } else if learning_step < 4 {
-        let next_step = (learning_step + 1).min(4);
-        if next_step < 4 {
-            (LEARNING_INTERVALS[next_step as usize], next_step, ease_factor, 0)
-        } else {
-            (2 * 24 * 60, 4, ease_factor, 2)
-        }
-    } else {
-        let new_interval_days = match quality {
-            2 => ((interval_days as f64 * ease_factor) as i32).max(2),
-            3 => ((interval_days as f64 * ease_factor * 1.3) as i32).max(3),
-            _ => interval_days.max(2),
-        };
-        let new_ease = if quality == 3 {
-            (ease_factor + 0.1).min(3.0)
-        } else {
-            ease_factor
-        };
-        (new_interval_days * 24 * 60, learning_step + 1, new_ease, new_interval_days)
-    };
+    Ok(rows
+        .into_iter()
+        .map(|row| (row.get("vocabulary_id"), (row.get("source_video_id"), row.get("source_sentence"))))
+        .collect())
+}
 
-    let due_at = now
-        .checked_add_signed(chrono::Duration::minutes(new_interval_minutes as i64))
-        .unwrap()
-        .format("%Y-%m-%dT%H:%M:%S")
-        .to_string();
+/// What a review was made from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReviewSource {
+    /// The learner rated the card themselves
+    Manual,
+    /// Rated from the AI's evaluation of a review answer
+    AiReview,
+}
 
-    let due_date = now
-        .checked_add_signed(chrono::Duration::minutes(new_interval_minutes as i64))
-        .unwrap()
-        .format("%Y-%m-%d")
-        .to_string();
+impl ReviewSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReviewSource::Manual => "manual",
+            ReviewSource::AiReview => "ai_review",
+        }
+    }
+}
 
-    sqlx::query(
-        "UPDATE user_vocabulary
-         SET ease_factor = $1, interval_days = $2, interval_minutes = $3,
-             due_date = $4, due_at = $5, learning_step = $6,
-             review_count = review_count + 1, last_reviewed_at = NOW()
-         WHERE vocabulary_id = $7 AND user_id = $8"
-    )
-    .bind(new_ease)
-    .bind(new_interval_days)
-    .bind(new_interval_minutes)
-    .bind(&due_date)
-    .bind(&due_at)
-    .bind(new_learning_step)
-    .bind(vocab_id)
-    .bind(user_id)
-    .execute(pool).await?;
+/// Reviews that can be undone, most recent first
+const MAX_UNDO_STEPS: i64 = 20;
 
-    Ok(())
+/// A card and the user's review statistics as they were before a review
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct ReviewSnapshot {
+    card: CardState,
+    stats: StatsSnapshot,
 }
 
-pub async fn delete_vocabulary(pool: &DbPool, user_id: &str, vocab_id: i32) -> Result<()> {
-    sqlx::query("DELETE FROM user_vocabulary WHERE vocabulary_id = $1 AND user_id = $2")
-        .bind(vocab_id)
-        .bind(user_id)
-        .execute(pool).await?;
-    Ok(())
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct StatsSnapshot {
+    /// Day the review was counted on in `learning_stats`
+    stats_date: String,
+    /// Review counts of that day; `None` when the day had no row yet
+    daily_stats: Option<(i32, i32, i32)>,
+    progress: Option<ProgressSnapshot>,
 }
 
-pub async fn is_vocabulary_saved(pool: &DbPool, user_id: &str, word: &str) -> Result<bool> {
-    let count: i64 = sqlx::query(
-        "SELECT COUNT(*) as count FROM vocabulary v
-         JOIN user_vocabulary uv ON v.id = uv.vocabulary_id
-         WHERE v.word = $1 AND uv.user_id = $2"
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct ProgressSnapshot {
+    total_reviews: i32,
+    current_streak: i32,
+    longest_streak: i32,
+    last_study_date: Option<String>,
+}
+
+/// Review statistics before `record_review` counts a review on `date`; locks the user's progress
+/// row so concurrent reviews are counted one after the other
+async fn take_stats_snapshot(conn: &mut PgConnection, user_id: &str, date: &str) -> Result<StatsSnapshot> {
+    let daily_stats = sqlx::query(
+        "SELECT COALESCE(words_reviewed, 0) as words_reviewed, COALESCE(correct_count, 0) as correct_count,
+                COALESCE(incorrect_count, 0) as incorrect_count
+         FROM learning_stats WHERE user_id = $1 AND date = $2"
     )
-    .bind(word)
     .bind(user_id)
-    .fetch_one(pool).await?
-    .get("count");
+    .bind(date)
+    .fetch_optional(&mut *conn).await?
+    .map(|row| (row.get("words_reviewed"), row.get("correct_count"), row.get("incorrect_count")));
+
+    let progress = sqlx::query(
+        "SELECT COALESCE(total_reviews, 0) as total_reviews, COALESCE(current_streak, 0) as current_streak,
+                COALESCE(longest_streak, 0) as longest_streak, last_study_date
+         FROM user_progress WHERE user_id = $1
+         FOR UPDATE"
+    )
+    .bind(user_id)
+    .fetch_optional(&mut *conn).await?
+    .map(|row| ProgressSnapshot {
+        total_reviews: row.get("total_reviews"),
+        current_streak: row.get("current_streak"),
+        longest_streak: row.get("longest_streak"),
+        last_study_date: row.get("last_study_date"),
+    });
 
-    Ok(count > 0)
+    Ok(StatsSnapshot {
+        stats_date: date.to_string(),
+        daily_stats,
+        progress,
+    })
 }
 
-// ============ Notes Functions ============
+/// Reschedule a card after a review, append the review to `review_log` and count it in the
+/// user's statistics, all in one transaction
+pub async fn review_vocabulary(
+    pool: &DbPool,
+    user_id: &str,
+    vocab_id: i32,
+    quality: i32,
+    is_correct: bool,
+    source: ReviewSource,
+) -> Result<()> {
+    let scheduler = get_scheduler(pool, user_id).await?;
+    let options = get_deck_options(pool, user_id).await?;
+    let params = match scheduler {
+        Scheduler::Sm2 => fsrs::Parameters::default(),
+        Scheduler::Fsrs => get_fsrs_parameters(pool, user_id).await?,
+    };
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
-pub struct Note {
-    pub id: String,
-    pub user_id: String,
-    pub video_id: String,
-    pub timestamp: f64,
-    pub english: Option<String>,
-    pub chinese: Option<String>,
-    pub note_text: Option<String>,
-    pub images: Option<String>,  // JSON array of image URLs
-    pub created_at: String,
-}
+    let mut tx = pool.begin().await?;
+
+    // Locked until commit, so a concurrent review of the same card waits and schedules from this one
+    let row = sqlx::query(
+        "SELECT ease_factor, COALESCE(learning_step, 0) as learning_step, phase, interval_days,
+                COALESCE(interval_minutes, 0) as interval_minutes, COALESCE(review_count, 0) as review_count,
+                stability, difficulty, last_reviewed_at, due_date, due_at, COALESCE(lapses, 0) as lapses
+         FROM user_vocabulary WHERE vocabulary_id = $1 AND user_id = $2
+         FOR UPDATE"
+    )
+    .bind(vocab_id)
+    .bind(user_id)
+    .fetch_one(&mut *tx).await?;
+    let before = card_state_from_row(&row);
+    let card = before.schedule();
+
+    let now = Utc::now();
+    let snapshot = ReviewSnapshot {
+        card: before.clone(),
+        stats: take_stats_snapshot(&mut tx, user_id, &now.format("%Y-%m-%d").to_string()).await?,
+    };
+    let outcome = scheduling::schedule_review(scheduler, &params, &options, &card, quality, now)
+        .with_fuzz(&options, rand::thread_rng().gen_range(0.0..1.0));
+    write_card_state(&mut tx, user_id, vocab_id, &before.reviewed(&outcome, now)).await?;
+
+    let log_id: i64 = sqlx::query(
+        "INSERT INTO review_log
+         (user_id, vocabulary_id, reviewed_at, rating, scheduler, elapsed_days,
+          previous_interval_minutes, next_interval_minutes, source, phase)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+         RETURNING id"
+    )
+    .bind(user_id)
+    .bind(vocab_id)
+    .bind(now)
+    .bind(quality.clamp(0, 3))
+    .bind(scheduler.as_str())
+    .bind(card.elapsed_days(now))
+    .bind(card.interval_minutes)
+    .bind(outcome.interval_minutes)
+    .bind(source.as_str())
+    .bind(card.phase.as_str())
+    .fetch_one(&mut *tx).await?
+    .get("id");
+
+    sqlx::query("INSERT INTO review_snapshots (review_log_id, user_id, snapshot) VALUES ($1, $2, $3)")
+        .bind(log_id)
+        .bind(user_id)
+        .bind(serde_json::to_string(&snapshot)?)
+        .execute(&mut *tx).await?;
+
+    sqlx::query(
+        "DELETE FROM review_snapshots WHERE user_id = $1 AND review_log_id NOT IN (
+            SELECT review_log_id FROM review_snapshots WHERE user_id = $1
+            ORDER BY review_log_id DESC LIMIT $2
+         )"
+    )
+    .bind(user_id)
+    .bind(MAX_UNDO_STEPS)
+    .execute(&mut *tx).await?;
+
+    record_review(&mut tx, user_id, is_correct).await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Scheduling columns of a `user_vocabulary` row
+fn card_state_from_row(row: &sqlx::postgres::PgRow) -> CardState {
+    let learning_step: i32 = row.get("learning_step");
+    CardState {
+        ease_factor: row.get("ease_factor"),
+        interval_days: row.get("interval_days"),
+        interval_minutes: row.get("interval_minutes"),
+        due_date: row.get("due_date"),
+        due_at: row.get("due_at"),
+        learning_step,
+        phase: row
+            .get::<Option<String>, _>("phase")
+            .and_then(|phase| CardPhase::from_name(&phase))
+            .unwrap_or(if learning_step >= 4 { CardPhase::Review } else { CardPhase::Learning }),
+        stability: row.get("stability"),
+        difficulty: row.get("difficulty"),
+        lapses: row.get("lapses"),
+        review_count: row.get("review_count"),
+        last_reviewed_at: row.get("last_reviewed_at"),
+    }
+}
+
+async fn write_card_state(conn: &mut PgConnection, user_id: &str, vocab_id: i32, card: &CardState) -> Result<()> {
+    sqlx::query(
+        "UPDATE user_vocabulary
+         SET ease_factor = $1, interval_days = $2, interval_minutes = $3, due_date = $4, due_at = $5,
+             learning_step = $6, phase = $7, stability = $8, difficulty = $9, lapses = $10,
+             review_count = $11, last_reviewed_at = $12
+         WHERE vocabulary_id = $13 AND user_id = $14"
+    )
+    .bind(card.ease_factor)
+    .bind(card.interval_days)
+    .bind(card.interval_minutes)
+    .bind(&card.due_date)
+    .bind(&card.due_at)
+    .bind(card.learning_step)
+    .bind(card.phase.as_str())
+    .bind(card.stability)
+    .bind(card.difficulty)
+    .bind(card.lapses)
+    .bind(card.review_count)
+    .bind(card.last_reviewed_at)
+    .bind(vocab_id)
+    .bind(user_id)
+    .execute(conn).await?;
+    Ok(())
+}
+
+/// The review an undo reverted
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct UndoneReview {
+    pub vocab_id: i32,
+    pub word: String,
+    /// Rating of the undone review, 0-3
+    pub quality: i32,
+}
+
+/// Undo a user's most recent review: the card gets back its schedule from before the review,
+/// the review is marked undone in the log and the statistics it added are taken off again. Earlier reviews
+/// can be undone in turn, up to `MAX_UNDO_STEPS`; `None` when there is nothing to undo
+pub async fn undo_last_review(pool: &DbPool, user_id: &str) -> Result<Option<UndoneReview>> {
+    let mut tx = pool.begin().await?;
+
+    let latest = sqlx::query(
+        "SELECT l.id, l.vocabulary_id, l.rating, s.snapshot, v.word
+         FROM review_log l
+         LEFT JOIN review_snapshots s ON s.review_log_id = l.id
+         LEFT JOIN vocabulary v ON v.id = l.vocabulary_id
+         WHERE l.user_id = $1 AND l.undone_at IS NULL
+         ORDER BY l.reviewed_at DESC, l.id DESC
+         LIMIT 1
+         FOR UPDATE OF l"
+    )
+    .bind(user_id)
+    .fetch_optional(&mut *tx).await?;
+
+    // Only the latest review can be undone, and only while its snapshot is kept
+    let Some((row, snapshot)) = latest.and_then(|row| {
+        let snapshot = row.get::<Option<String>, _>("snapshot")?;
+        Some((row, snapshot))
+    }) else {
+        return Ok(None);
+    };
+    let ReviewSnapshot { card, stats } = serde_json::from_str(&snapshot)?;
+    let vocab_id: i32 = row.get("vocabulary_id");
+
+    write_card_state(&mut tx, user_id, vocab_id, &card).await?;
+
+    // Later reviews have all been undone, so the counts from before this one are exact
+    let (words_reviewed, correct_count, incorrect_count) = stats.daily_stats.unwrap_or_default();
+    sqlx::query(
+        "UPDATE learning_stats SET words_reviewed = $1, correct_count = $2, incorrect_count = $3
+         WHERE user_id = $4 AND date = $5"
+    )
+    .bind(words_reviewed)
+    .bind(correct_count)
+    .bind(incorrect_count)
+    .bind(user_id)
+    .bind(&stats.stats_date)
+    .execute(&mut *tx).await?;
+
+    if let Some(progress) = stats.progress {
+        sqlx::query("UPDATE user_progress SET total_reviews = $1 WHERE user_id = $2")
+            .bind(progress.total_reviews)
+            .bind(user_id)
+            .execute(&mut *tx).await?;
+
+        // The streak goes back too, unless the day still has other study (reviews or saved words)
+        sqlx::query(
+            "UPDATE user_progress
+             SET current_streak = $1, longest_streak = $2, last_study_date = $3
+             WHERE user_id = $4 AND last_study_date = $5
+               AND NOT EXISTS (
+                   SELECT 1 FROM learning_stats
+                   WHERE user_id = $4 AND date = $5
+                     AND (COALESCE(words_reviewed, 0) > 0 OR COALESCE(words_learned, 0) > 0)
+               )"
+        )
+        .bind(progress.current_streak)
+        .bind(progress.longest_streak)
+        .bind(&progress.last_study_date)
+        .bind(user_id)
+        .bind(&stats.stats_date)
+        .execute(&mut *tx).await?;
+    }
+
+    let log_id: i64 = row.get("id");
+    sqlx::query("UPDATE review_log SET undone_at = NOW() WHERE id = $1")
+        .bind(log_id)
+        .execute(&mut *tx).await?;
+    sqlx::query("DELETE FROM review_snapshots WHERE review_log_id = $1")
+        .bind(log_id)
+        .execute(&mut *tx).await?;
+
+    tx.commit().await?;
+    Ok(Some(UndoneReview {
+        vocab_id,
+        word: row.get::<Option<String>, _>("word").unwrap_or_default(),
+        quality: row.get("rating"),
+    }))
+}
+
+/// Give a user's reviewed SM-2 cards the FSRS memory state matching their current schedule, so
+/// switching to FSRS keeps their due dates; returns how many cards were converted
+pub async fn migrate_cards_to_fsrs(pool: &DbPool, user_id: &str) -> Result<u64> {
+    let rows = sqlx::query(
+        "SELECT vocabulary_id, ease_factor, COALESCE(interval_minutes, 0) as interval_minutes
+         FROM user_vocabulary
+         WHERE user_id = $1 AND stability IS NULL AND COALESCE(review_count, 0) > 0"
+    )
+    .bind(user_id)
+    .fetch_all(pool).await?;
+
+    let params = get_fsrs_parameters(pool, user_id).await?;
+    let mut converted = 0;
+    for row in rows {
+        let interval_minutes: i32 = row.get("interval_minutes");
+        let memory = params.memory_from_sm2(row.get("ease_factor"), interval_minutes as f64 / 1440.0);
+
+        converted += sqlx::query(
+            "UPDATE user_vocabulary SET stability = $1, difficulty = $2
+             WHERE user_id = $3 AND vocabulary_id = $4 AND stability IS NULL"
+        )
+        .bind(memory.stability)
+        .bind(memory.difficulty)
+        .bind(user_id)
+        .bind(row.get::<i32, _>("vocabulary_id"))
+        .execute(pool).await?
+        .rows_affected();
+    }
+
+    Ok(converted)
+}
+
+/// FSRS parameters for a user: fitted weights when there are some, the defaults otherwise
+pub async fn get_fsrs_parameters(pool: &DbPool, user_id: &str) -> Result<fsrs::Parameters> {
+    let weights = sqlx::query("SELECT weights FROM scheduler_params WHERE user_id = $1")
+        .bind(user_id)
+        .fetch_optional(pool).await?
+        .and_then(|row| serde_json::from_str::<[f64; 19]>(&row.get::<String, _>("weights")).ok());
+
+    Ok(match weights {
+        Some(weights) => fsrs::Parameters { weights, ..fsrs::Parameters::default() },
+        None => fsrs::Parameters::default(),
+    })
+}
+
+pub async fn save_fsrs_weights(pool: &DbPool, user_id: &str, fit: &Fit) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO scheduler_params (user_id, weights, scored_reviews, log_loss, default_log_loss, fitted_at)
+         VALUES ($1, $2, $3, $4, $5, NOW())
+         ON CONFLICT (user_id) DO UPDATE SET
+            weights = $2, scored_reviews = $3, log_loss = $4, default_log_loss = $5, fitted_at = NOW()"
+    )
+    .bind(user_id)
+    .bind(serde_json::to_string(&fit.weights)?)
+    .bind(fit.scored_reviews as i32)
+    .bind(fit.log_loss)
+    .bind(fit.default_log_loss)
+    .execute(pool).await?;
+    Ok(())
+}
+
+/// Users with at least `min_reviews` logged reviews, never fitted or not fitted for a day and
+/// with reviews since
+pub async fn users_due_for_fitting(pool: &DbPool, min_reviews: i64) -> Result<Vec<String>> {
+    let rows = sqlx::query(
+        "SELECT l.user_id
+         FROM review_log l
+         LEFT JOIN scheduler_params p ON p.user_id = l.user_id
+         WHERE l.undone_at IS NULL
+         GROUP BY l.user_id, p.fitted_at
+         HAVING COUNT(*) >= $1
+            AND (p.fitted_at IS NULL
+                 OR (MAX(l.reviewed_at) > p.fitted_at AND p.fitted_at < NOW() - INTERVAL '1 day'))"
+    )
+    .bind(min_reviews)
+    .fetch_all(pool).await?;
+
+    Ok(rows.into_iter().map(|row| row.get("user_id")).collect())
+}
+
+/// A user's logged reviews grouped by card, oldest first
+pub async fn get_review_histories(pool: &DbPool, user_id: &str) -> Result<Vec<Vec<ReviewEntry>>> {
+    let rows = sqlx::query(
+        "SELECT vocabulary_id, rating, elapsed_days FROM review_log
+         WHERE user_id = $1 AND undone_at IS NULL
+         ORDER BY vocabulary_id, reviewed_at, id"
+    )
+    .bind(user_id)
+    .fetch_all(pool).await?;
+
+    let mut histories: Vec<Vec<ReviewEntry>> = Vec::new();
+    let mut current_card = None;
+    for row in rows {
+        let vocabulary_id: i32 = row.get("vocabulary_id");
+        if current_card != Some(vocabulary_id) {
+            histories.push(Vec::new());
+            current_card = Some(vocabulary_id);
+        }
+        if let Some(history) = histories.last_mut() {
+            history.push(ReviewEntry {
+                rating: fsrs::Rating::from_quality(row.get("rating")),
+                elapsed_days: row.get("elapsed_days"),
+            });
+        }
+    }
+
+    Ok(histories)
+}
+
+pub async fn delete_vocabulary(pool: &DbPool, user_id: &str, vocab_id: i32) -> Result<()> {
+    sqlx::query("DELETE FROM user_vocabulary WHERE vocabulary_id = $1 AND user_id = $2")
+        .bind(vocab_id)
+        .bind(user_id)
+        .execute(pool).await?;
+    Ok(())
+}
+
+pub async fn is_vocabulary_saved(pool: &DbPool, user_id: &str, word: &str) -> Result<bool> {
+    let count: i64 = sqlx::query(
+        "SELECT COUNT(*) as count FROM vocabulary v
+         JOIN user_vocabulary uv ON v.id = uv.vocabulary_id
+         WHERE v.word = $1 AND uv.user_id = $2"
+    )
+    .bind(word)
+    .bind(user_id)
+    .fetch_one(pool).await?
+    .get("count");
+
+    Ok(count > 0)
+}
+
+// ============ Notes Functions ============
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Note {
+    pub id: String,
+    pub user_id: String,
+    pub video_id: String,
+    pub timestamp: f64,
+    pub english: Option<String>,
+    pub translation: Option<String>,
+    pub translation_lang: Option<String>,
+    pub note_text: Option<String>,
+    pub images: Option<String>,  // JSON array of image URLs
+    pub created_at: String,
+}
 
 pub async fn save_note(pool: &DbPool, note: &Note) -> Result<()> {
     sqlx::query(
-        "INSERT INTO notes (id, user_id, video_id, timestamp, english, chinese, note_text, images, created_at)
-         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, NOW())
+        "INSERT INTO notes (id, user_id, video_id, timestamp, english, translation, translation_lang, note_text, images, created_at)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, NOW())
          ON CONFLICT (id) DO UPDATE SET
-            english = $5, chinese = $6, note_text = $7, images = $8"
+            english = $5, translation = $6, translation_lang = $7, note_text = $8, images = $9"
     )
     .bind(&note.id)
     .bind(&note.user_id)
     .bind(&note.video_id)
     .bind(note.timestamp)
     .bind(&note.english)
-    .bind(&note.chinese)
+    .bind(&note.translation)
+    .bind(&note.translation_lang)
     .bind(&note.note_text)
     .bind(&note.images)
     .execute(pool).await?;
@@ -685,7 +1497,7 @@ pub async fn save_note(pool: &DbPool, note: &Note) -> Result<()> {
 
 pub async fn get_notes(pool: &DbPool, user_id: &str) -> Result<Vec<Note>> {
     let rows = sqlx::query(
-        "SELECT id, user_id, video_id, timestamp, english, chinese, note_text, images,
+        "SELECT id, user_id, video_id, timestamp, english, translation, translation_lang, note_text, images,
                 to_char(created_at, 'YYYY-MM-DD HH24:MI:SS') as created_at
          FROM notes WHERE user_id = $1 ORDER BY created_at DESC"
     )
@@ -698,7 +1510,8 @@ pub async fn get_notes(pool: &DbPool, user_id: &str) -> Result<Vec<Note>> {
         video_id: row.get("video_id"),
         timestamp: row.get("timestamp"),
         english: row.get("english"),
-        chinese: row.get("chinese"),
+        translation: row.get("translation"),
+        translation_lang: row.get("translation_lang"),
         note_text: row.get("note_text"),
         images: row.get("images"),
         created_at: row.get("created_at"),
@@ -707,7 +1520,7 @@ pub async fn get_notes(pool: &DbPool, user_id: &str) -> Result<Vec<Note>> {
 
 pub async fn get_notes_by_video(pool: &DbPool, user_id: &str, video_id: &str) -> Result<Vec<Note>> {
     let rows = sqlx::query(
-        "SELECT id, user_id, video_id, timestamp, english, chinese, note_text, images,
+        "SELECT id, user_id, video_id, timestamp, english, translation, translation_lang, note_text, images,
                 to_char(created_at, 'YYYY-MM-DD HH24:MI:SS') as created_at
          FROM notes WHERE user_id = $1 AND video_id = $2 ORDER BY timestamp ASC"
     )
@@ -721,7 +1534,8 @@ pub async fn get_notes_by_video(pool: &DbPool, user_id: &str, video_id: &str) ->
         video_id: row.get("video_id"),
         timestamp: row.get("timestamp"),
         english: row.get("english"),
-        chinese: row.get("chinese"),
+        translation: row.get("translation"),
+        translation_lang: row.get("translation_lang"),
         note_text: row.get("note_text"),
         images: row.get("images"),
         created_at: row.get("created_at"),
@@ -775,11 +1589,12 @@ pub async fn record_word_learned(pool: &DbPool, user_id: &str) -> Result<()> {
     .bind(user_id)
     .execute(pool).await?;
 
-    update_streak(pool, user_id, &today).await?;
+    update_streak(&mut *pool.acquire().await?, user_id, &today).await?;
     Ok(())
 }
 
-pub async fn record_review(pool: &DbPool, user_id: &str, is_correct: bool) -> Result<()> {
+/// Count a review in the day's statistics and the user's progress
+async fn record_review(conn: &mut PgConnection, user_id: &str, is_correct: bool) -> Result<()> {
     let today = Utc::now().format("%Y-%m-%d").to_string();
 
     if is_correct {
@@ -792,7 +1607,7 @@ pub async fn record_review(pool: &DbPool, user_id: &str, is_correct: bool) -> Re
         )
         .bind(user_id)
         .bind(&today)
-        .execute(pool).await?;
+        .execute(&mut *conn).await?;
     } else {
         sqlx::query(
             "INSERT INTO learning_stats (user_id, date, words_reviewed, incorrect_count)
@@ -803,20 +1618,20 @@ pub async fn record_review(pool: &DbPool, user_id: &str, is_correct: bool) -> Re
         )
         .bind(user_id)
         .bind(&today)
-        .execute(pool).await?;
+        .execute(&mut *conn).await?;
     }
 
     sqlx::query(
         "UPDATE user_progress SET total_reviews = total_reviews + 1 WHERE user_id = $1"
     )
     .bind(user_id)
-    .execute(pool).await?;
+    .execute(&mut *conn).await?;
 
-    update_streak(pool, user_id, &today).await?;
+    update_streak(conn, user_id, &today).await?;
     Ok(())
 }
 
-async fn update_streak(pool: &DbPool, user_id: &str, today: &str) -> Result<()> {
+async fn update_streak(conn: &mut PgConnection, user_id: &str, today: &str) -> Result<()> {
     let yesterday = (Utc::now() - chrono::Duration::days(1))
         .format("%Y-%m-%d")
         .to_string();
@@ -825,7 +1640,7 @@ async fn update_streak(pool: &DbPool, user_id: &str, today: &str) -> Result<()>
         "SELECT last_study_date FROM user_progress WHERE user_id = $1"
     )
     .bind(user_id)
-    .fetch_optional(pool).await?
+    .fetch_optional(&mut *conn).await?
     .and_then(|row| row.get("last_study_date"));
 
     match last_study_date {
@@ -842,7 +1657,7 @@ async fn update_streak(pool: &DbPool, user_id: &str, today: &str) -> Result<()>
             )
             .bind(today)
             .bind(user_id)
-            .execute(pool).await?;
+            .execute(&mut *conn).await?;
         }
         _ => {
             sqlx::query(
@@ -854,7 +1669,7 @@ async fn update_streak(pool: &DbPool, user_id: &str, today: &str) -> Result<()>
             )
             .bind(today)
             .bind(user_id)
-            .execute(pool).await?;
+            .execute(&mut *conn).await?;
         }
     }
 
@@ -946,6 +1761,8 @@ pub struct WatchHistoryItem {
     pub title: String,
     pub thumbnail: String,
     pub watched_at: String,
+    /// Most recently used AI chat thread for this video, to reopen the conversation
+    pub latest_thread_id: Option<String>,
 }
 
 pub async fn add_watch_history(pool: &DbPool, user_id: &str, video_id: &str, title: &str, thumbnail: &str) -> Result<()> {
@@ -968,11 +1785,14 @@ pub async fn add_watch_history(pool: &DbPool, user_id: &str, video_id: &str, tit
 
 pub async fn get_watch_history(pool: &DbPool, user_id: &str, limit: i32) -> Result<Vec<WatchHistoryItem>> {
     let rows = sqlx::query(
-        "SELECT video_id, title, thumbnail,
-                to_char(watched_at, 'YYYY-MM-DD HH24:MI:SS') as watched_at
-         FROM watch_history
-         WHERE user_id = $1
-         ORDER BY watched_at DESC
+        "SELECT h.video_id, h.title, h.thumbnail,
+                to_char(h.watched_at, 'YYYY-MM-DD HH24:MI:SS') as watched_at,
+                (SELECT t.id FROM chat_threads t
+                 WHERE t.user_id = h.user_id AND t.video_id = h.video_id
+                 ORDER BY t.updated_at DESC LIMIT 1) as latest_thread_id
+         FROM watch_history h
+         WHERE h.user_id = $1
+         ORDER BY h.watched_at DESC
          LIMIT $2"
     )
     .bind(user_id)
@@ -984,6 +1804,7 @@ pub async fn get_watch_history(pool: &DbPool, user_id: &str, limit: i32) -> Resu
         title: row.get("title"),
         thumbnail: row.get("thumbnail"),
         watched_at: row.get("watched_at"),
+        latest_thread_id: row.get("latest_thread_id"),
     }).collect())
 }
 
@@ -1002,14 +1823,202 @@ pub async fn clear_watch_history(pool: &DbPool, user_id: &str) -> Result<()> {
     Ok(())
 }
 
+// ============ AI Chat Thread Functions ============
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ChatThread {
+    pub id: String,
+    pub video_id: String,
+    pub title: String,
+    pub message_count: i64,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ChatMessage {
+    pub id: i32,
+    pub role: String,
+    pub content: String,
+    pub created_at: String,
+}
+
+const CHAT_THREAD_COLUMNS: &str =
+    "t.id, t.video_id, t.title,
+     (SELECT COUNT(*) FROM chat_messages m WHERE m.thread_id = t.id) as message_count,
+     to_char(t.created_at, 'YYYY-MM-DD HH24:MI:SS') as created_at,
+     to_char(t.updated_at, 'YYYY-MM-DD HH24:MI:SS') as updated_at";
+
+fn chat_thread_from_row(row: &sqlx::postgres::PgRow) -> ChatThread {
+    ChatThread {
+        id: row.get("id"),
+        video_id: row.get("video_id"),
+        title: row.get("title"),
+        message_count: row.get("message_count"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    }
+}
+
+pub async fn create_chat_thread(pool: &DbPool, user_id: &str, video_id: &str, title: &str) -> Result<ChatThread> {
+    let id = uuid::Uuid::new_v4().to_string();
+    sqlx::query("INSERT INTO chat_threads (id, user_id, video_id, title) VALUES ($1, $2, $3, $4)")
+        .bind(&id)
+        .bind(user_id)
+        .bind(video_id)
+        .bind(title)
+        .execute(pool).await?;
+
+    get_chat_thread(pool, user_id, &id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Chat thread not found after insert"))
+}
+
+/// List a user's chat threads, most recently used first
+pub async fn list_chat_threads(pool: &DbPool, user_id: &str, video_id: Option<&str>) -> Result<Vec<ChatThread>> {
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM chat_threads t
+         WHERE t.user_id = $1 AND ($2::TEXT IS NULL OR t.video_id = $2)
+         ORDER BY t.updated_at DESC",
+        CHAT_THREAD_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(video_id)
+    .fetch_all(pool).await?;
+
+    Ok(rows.iter().map(chat_thread_from_row).collect())
+}
+
+/// Get a chat thread, only if it belongs to the user
+pub async fn get_chat_thread(pool: &DbPool, user_id: &str, thread_id: &str) -> Result<Option<ChatThread>> {
+    let row = sqlx::query(&format!(
+        "SELECT {} FROM chat_threads t WHERE t.id = $1 AND t.user_id = $2",
+        CHAT_THREAD_COLUMNS
+    ))
+    .bind(thread_id)
+    .bind(user_id)
+    .fetch_optional(pool).await?;
+
+    Ok(row.as_ref().map(chat_thread_from_row))
+}
+
+/// Rename a chat thread, returns false if the user has no such thread
+pub async fn rename_chat_thread(pool: &DbPool, user_id: &str, thread_id: &str, title: &str) -> Result<bool> {
+    let result = sqlx::query("UPDATE chat_threads SET title = $1 WHERE id = $2 AND user_id = $3")
+        .bind(title)
+        .bind(thread_id)
+        .bind(user_id)
+        .execute(pool).await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Delete a chat thread and its messages, returns false if the user has no such thread
+pub async fn delete_chat_thread(pool: &DbPool, user_id: &str, thread_id: &str) -> Result<bool> {
+    let result = sqlx::query("DELETE FROM chat_threads WHERE id = $1 AND user_id = $2")
+        .bind(thread_id)
+        .bind(user_id)
+        .execute(pool).await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Messages of a thread in the order they were sent
+pub async fn get_chat_messages(pool: &DbPool, thread_id: &str) -> Result<Vec<ChatMessage>> {
+    let rows = sqlx::query(
+        "SELECT id, role, content, to_char(created_at, 'YYYY-MM-DD HH24:MI:SS') as created_at
+         FROM chat_messages WHERE thread_id = $1 ORDER BY id ASC"
+    )
+    .bind(thread_id)
+    .fetch_all(pool).await?;
+
+    Ok(rows.into_iter().map(|row| ChatMessage {
+        id: row.get("id"),
+        role: row.get("role"),
+        content: row.get("content"),
+        created_at: row.get("created_at"),
+    }).collect())
+}
+
+/// Store a question and its answer, naming the thread after its first question
+pub async fn append_chat_exchange(pool: &DbPool, thread_id: &str, question: &str, answer: &str) -> Result<()> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query("INSERT INTO chat_messages (thread_id, role, content) VALUES ($1, 'user', $2), ($1, 'assistant', $3)")
+        .bind(thread_id)
+        .bind(question)
+        .bind(answer)
+        .execute(&mut *tx).await?;
+
+    let title: String = question.chars().take(60).collect();
+    sqlx::query(
+        "UPDATE chat_threads
+         SET updated_at = NOW(), title = CASE WHEN title = '' THEN $2 ELSE title END
+         WHERE id = $1"
+    )
+    .bind(thread_id)
+    .bind(title.trim())
+    .execute(&mut *tx).await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
 // ============ Daily Usage / Rate Limiting Functions ============
 
 /// Base daily free quota (resets each day)
 pub const FREE_DAILY_BASE_LIMIT: i32 = 5;
 /// Bonus quota per successful invite (permanent, cumulative)
 pub const INVITE_BONUS_QUOTA: i32 = 3;
-/// AI Chat daily limit (per user)
-pub const AI_CHAT_DAILY_LIMIT: i32 = 20;
+/// Daily AI quota in units for free users; each AI operation costs its weight (see `auth::quota::ops`)
+pub const AI_DAILY_UNIT_LIMIT: i32 = 50;
+
+/// Apify calls per user per day
+pub const APIFY_DAILY_LIMIT_DEFAULT: i32 = 2;
+/// Apify calls per day for users who have invited friends
+pub const APIFY_DAILY_LIMIT_INVITED: i32 = 3;
+
+/// Paid transcript services, metered per user per day
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExternalSource {
+    Apify,
+    Supadata,
+}
+
+impl ExternalSource {
+    pub const ALL: [ExternalSource; 2] = [ExternalSource::Apify, ExternalSource::Supadata];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ExternalSource::Apify => "apify",
+            ExternalSource::Supadata => "supadata",
+        }
+    }
+
+    /// `daily_usage` column counting today's calls
+    fn column(self) -> &'static str {
+        match self {
+            ExternalSource::Apify => "apify_count",
+            ExternalSource::Supadata => "supadata_count",
+        }
+    }
+
+    /// Calls per user per day, `None` when unlimited
+    /// Supadata calls are counted but not capped
+    pub fn daily_limit(self, has_invited: bool) -> Option<i32> {
+        match self {
+            ExternalSource::Apify if has_invited => Some(APIFY_DAILY_LIMIT_INVITED),
+            ExternalSource::Apify => Some(APIFY_DAILY_LIMIT_DEFAULT),
+            ExternalSource::Supadata => None,
+        }
+    }
+}
+
+/// Daily AI unit limit for a tier, `None` when unlimited
+pub fn ai_daily_unit_limit(tier: &str) -> Option<i32> {
+    match tier {
+        "pro" => None,
+        _ => Some(AI_DAILY_UNIT_LIMIT),
+    }
+}
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct UsageStatus {
@@ -1019,8 +2028,18 @@ pub struct UsageStatus {
     pub bonus_quota: i32,  // From invitations (permanent)
 }
 
+/// AI quota in units; `limit` and `remaining` are -1 when unlimited
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
-pub struct AiChatUsageStatus {
+pub struct AiQuotaStatus {
+    pub used: i32,
+    pub limit: i32,
+    pub remaining: i32,
+}
+
+/// Today's calls to one paid transcript source; `limit` and `remaining` are -1 when unlimited
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SourceUsageStatus {
+    pub source: String,
     pub used: i32,
     pub limit: i32,
     pub remaining: i32,
@@ -1029,27 +2048,30 @@ pub struct AiChatUsageStatus {
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct DailyUsageStatus {
     pub video_parse: UsageStatus,
-    pub ai_chat: AiChatUsageStatus,
+    pub ai: AiQuotaStatus,
+    pub sources: Vec<SourceUsageStatus>,
 }
 
 /// Get today's usage for a user (includes bonus quota from invitations)
-pub async fn get_daily_usage(pool: &DbPool, user_id: &str) -> Result<DailyUsageStatus> {
+pub async fn get_daily_usage(pool: &DbPool, user_id: &str, tier: &str) -> Result<DailyUsageStatus> {
     let today = Utc::now().format("%Y-%m-%d").to_string();
 
     // Get today's usage count
     let usage_result = sqlx::query(
-        "SELECT video_parse_count, ai_chat_count FROM daily_usage WHERE user_id = $1 AND date = $2"
+        "SELECT video_parse_count, ai_units, apify_count, supadata_count FROM daily_usage WHERE user_id = $1 AND date = $2"
     )
     .bind(user_id)
     .bind(&today)
     .fetch_optional(pool).await?;
 
-    let (video_used, ai_chat_used) = usage_result
+    let (video_used, ai_used) = usage_result
+        .as_ref()
         .map(|row| (
             row.get::<i32, _>("video_parse_count"),
-            row.get::<i32, _>("ai_chat_count")
+            row.get::<i32, _>("ai_units")
         ))
         .unwrap_or((0, 0));
+    let ai_limit = ai_daily_unit_limit(tier);
 
     // Get user's bonus quota from invitations
     let bonus_quota = get_bonus_quota(pool, user_id).await.unwrap_or(0);
@@ -1057,6 +2079,23 @@ pub async fn get_daily_usage(pool: &DbPool, user_id: &str) -> Result<DailyUsageS
     // Total daily limit = base + bonus
     let total_limit = FREE_DAILY_BASE_LIMIT + bonus_quota;
 
+    let sources = ExternalSource::ALL
+        .iter()
+        .map(|&source| {
+            let used = usage_result
+                .as_ref()
+                .and_then(|row| row.get::<Option<i32>, _>(source.column()))
+                .unwrap_or(0);
+            let limit = source.daily_limit(bonus_quota > 0);
+            SourceUsageStatus {
+                source: source.as_str().to_string(),
+                used,
+                limit: limit.unwrap_or(-1),
+                remaining: limit.map(|limit| (limit - used).max(0)).unwrap_or(-1),
+            }
+        })
+        .collect();
+
     Ok(DailyUsageStatus {
         video_parse: UsageStatus {
             used: video_used,
@@ -1064,11 +2103,12 @@ pub async fn get_daily_usage(pool: &DbPool, user_id: &str) -> Result<DailyUsageS
             remaining: (total_limit - video_used).max(0),
             bonus_quota,
         },
-        ai_chat: AiChatUsageStatus {
-            used: ai_chat_used,
-            limit: AI_CHAT_DAILY_LIMIT,
-            remaining: (AI_CHAT_DAILY_LIMIT - ai_chat_used).max(0),
+        ai: AiQuotaStatus {
+            used: ai_used,
+            limit: ai_limit.unwrap_or(-1),
+            remaining: ai_limit.map(|limit| (limit - ai_used).max(0)).unwrap_or(-1),
         },
+        sources,
     })
 }
 
@@ -1126,75 +2166,349 @@ pub async fn increment_video_parse_count(pool: &DbPool, user_id: &str) -> Result
     Ok(result.get("video_parse_count"))
 }
 
-/// Check if user can use AI chat
-/// Returns (allowed, remaining_after)
-pub async fn check_can_ai_chat(pool: &DbPool, user_id: &str, tier: &str) -> Result<(bool, i32)> {
-    // Pro users have unlimited access
-    if tier == "pro" {
-        return Ok((true, -1)); // -1 means unlimited
+/// Take `units` from today's AI quota unless that would go over `limit` (`None`: no limit)
+/// Check and increment happen in one statement, so concurrent requests cannot overdraw the quota
+/// Returns whether the units were reserved
+pub async fn try_reserve_ai_units(pool: &DbPool, user_id: &str, units: i32, limit: Option<i32>) -> Result<bool> {
+    if limit.is_some_and(|limit| units > limit) {
+        return Ok(false);
     }
-
     let today = Utc::now().format("%Y-%m-%d").to_string();
 
-    // Get today's AI chat count
-    let usage_result = sqlx::query(
-        "SELECT ai_chat_count FROM daily_usage WHERE user_id = $1 AND date = $2"
+    let result = sqlx::query(
+        "INSERT INTO daily_usage (user_id, date, ai_units)
+         VALUES ($1, $2, $3)
+         ON CONFLICT(user_id, date) DO UPDATE SET ai_units = COALESCE(daily_usage.ai_units, 0) + $3
+         WHERE $4::INTEGER IS NULL OR COALESCE(daily_usage.ai_units, 0) + $3 <= $4
+         RETURNING ai_units"
     )
     .bind(user_id)
     .bind(&today)
+    .bind(units)
+    .bind(limit)
     .fetch_optional(pool).await?;
 
-    let used = usage_result.map(|row| row.get::<i32, _>("ai_chat_count")).unwrap_or(0);
-    let remaining = AI_CHAT_DAILY_LIMIT - used;
-
-    Ok((remaining > 0, remaining - 1))
+    Ok(result.is_some())
 }
 
-/// Increment AI chat count for a user
-pub async fn increment_ai_chat_count(pool: &DbPool, user_id: &str) -> Result<i32> {
+/// Give back units reserved by `try_reserve_ai_units` when the AI call did not happen or failed
+pub async fn refund_ai_units(pool: &DbPool, user_id: &str, units: i32) -> Result<()> {
     let today = Utc::now().format("%Y-%m-%d").to_string();
 
     sqlx::query(
-        "INSERT INTO daily_usage (user_id, date, ai_chat_count)
-         VALUES ($1, $2, 1)
-         ON CONFLICT(user_id, date) DO UPDATE SET ai_chat_count = daily_usage.ai_chat_count + 1"
+        "UPDATE daily_usage SET ai_units = GREATEST(COALESCE(ai_units, 0) - $3, 0)
+         WHERE user_id = $1 AND date = $2"
     )
     .bind(user_id)
     .bind(&today)
+    .bind(units)
     .execute(pool).await?;
 
-    // Return new count
-    let result = sqlx::query(
-        "SELECT ai_chat_count FROM daily_usage WHERE user_id = $1 AND date = $2"
-    )
+    Ok(())
+}
+
+/// Count one call to `source` for today unless `limit` calls have already been made (`None`: no limit)
+/// Check and increment happen in one statement, so concurrent requests cannot both take the last call
+/// Returns the new count, or `None` when the limit is reached
+pub async fn try_consume_source_call(pool: &DbPool, user_id: &str, source: ExternalSource, limit: Option<i32>) -> Result<Option<i32>> {
+    if limit.is_some_and(|limit| limit <= 0) {
+        return Ok(None);
+    }
+    let today = Utc::now().format("%Y-%m-%d").to_string();
+    let column = source.column();
+
+    let result = sqlx::query(&format!(
+        "INSERT INTO daily_usage (user_id, date, {column})
+         VALUES ($1, $2, 1)
+         ON CONFLICT(user_id, date) DO UPDATE SET {column} = COALESCE(daily_usage.{column}, 0) + 1
+         WHERE $3::INTEGER IS NULL OR COALESCE(daily_usage.{column}, 0) < $3
+         RETURNING {column}"
+    ))
     .bind(user_id)
     .bind(&today)
-    .fetch_one(pool).await?;
+    .bind(limit)
+    .fetch_optional(pool).await?;
+
+    Ok(result.map(|row| row.get::<i32, _>(column)))
+}
+
+/// Give back a call taken by `try_consume_source_call` when the source failed
+pub async fn refund_source_call(pool: &DbPool, user_id: &str, source: ExternalSource) -> Result<()> {
+    let today = Utc::now().format("%Y-%m-%d").to_string();
+    let column = source.column();
+
+    sqlx::query(&format!(
+        "UPDATE daily_usage SET {column} = GREATEST(COALESCE({column}, 0) - 1, 0)
+         WHERE user_id = $1 AND date = $2"
+    ))
+    .bind(user_id)
+    .bind(&today)
+    .execute(pool).await?;
+
+    Ok(())
+}
+
+// ============ Uploaded Videos ============
+
+/// A recording uploaded by a user; its subtitles are served like a YouTube video's
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct UploadedVideo {
+    pub video_id: String,
+    pub title: String,
+    pub duration: f64,
+    pub lang: String,
+    pub subtitle_format: String,
+    /// R2 object key of the media file, if one was uploaded
+    #[serde(skip)]
+    pub media_key: Option<String>,
+    pub media_url: Option<String>,
+    pub media_type: Option<String>,
+    pub created_at: String,
+}
+
+/// Fields stored when a video is uploaded
+pub struct NewUploadedVideo<'a> {
+    pub video_id: &'a str,
+    pub title: &'a str,
+    pub duration: f64,
+    pub lang: &'a str,
+    pub subtitle_format: &'a str,
+    pub subtitles_json: &'a str,
+    pub media_key: Option<&'a str>,
+    pub media_url: Option<&'a str>,
+    pub media_type: Option<&'a str>,
+}
+
+const UPLOADED_VIDEO_COLUMNS: &str =
+    "id, title, duration, lang, subtitle_format, media_key, media_url, media_type,
+     to_char(created_at, 'YYYY-MM-DD HH24:MI:SS') as created_at";
+
+fn uploaded_video_from_row(row: &sqlx::postgres::PgRow) -> UploadedVideo {
+    UploadedVideo {
+        video_id: row.get("id"),
+        title: row.get("title"),
+        duration: row.get("duration"),
+        lang: row.get("lang"),
+        subtitle_format: row.get("subtitle_format"),
+        media_key: row.get("media_key"),
+        media_url: row.get("media_url"),
+        media_type: row.get("media_type"),
+        created_at: row.get("created_at"),
+    }
+}
+
+pub async fn save_uploaded_video(pool: &DbPool, user_id: &str, video: &NewUploadedVideo<'_>) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO uploaded_videos
+            (id, user_id, title, duration, lang, subtitle_format, subtitles_json, media_key, media_url, media_type)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)"
+    )
+    .bind(video.video_id)
+    .bind(user_id)
+    .bind(video.title)
+    .bind(video.duration)
+    .bind(video.lang)
+    .bind(video.subtitle_format)
+    .bind(video.subtitles_json)
+    .bind(video.media_key)
+    .bind(video.media_url)
+    .bind(video.media_type)
+    .execute(pool).await?;
+
+    Ok(())
+}
+
+/// A user's uploaded videos, newest first
+pub async fn list_uploaded_videos(pool: &DbPool, user_id: &str) -> Result<Vec<UploadedVideo>> {
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM uploaded_videos WHERE user_id = $1 ORDER BY created_at DESC",
+        UPLOADED_VIDEO_COLUMNS
+    ))
+    .bind(user_id)
+    .fetch_all(pool).await?;
+
+    Ok(rows.iter().map(uploaded_video_from_row).collect())
+}
+
+pub async fn get_uploaded_video(pool: &DbPool, user_id: &str, video_id: &str) -> Result<Option<UploadedVideo>> {
+    let row = sqlx::query(&format!(
+        "SELECT {} FROM uploaded_videos WHERE id = $1 AND user_id = $2",
+        UPLOADED_VIDEO_COLUMNS
+    ))
+    .bind(video_id)
+    .bind(user_id)
+    .fetch_optional(pool).await?;
 
-    Ok(result.get("ai_chat_count"))
+    Ok(row.as_ref().map(uploaded_video_from_row))
+}
+
+/// Subtitles of an uploaded video with their language, as stored JSON
+pub async fn get_uploaded_subtitles(pool: &DbPool, user_id: &str, video_id: &str) -> Result<Option<(String, String)>> {
+    let row = sqlx::query(
+        "SELECT lang, subtitles_json FROM uploaded_videos WHERE id = $1 AND user_id = $2"
+    )
+    .bind(video_id)
+    .bind(user_id)
+    .fetch_optional(pool).await?;
+
+    Ok(row.map(|row| (row.get("lang"), row.get("subtitles_json"))))
+}
+
+/// Delete an uploaded video, returning it so its media file can be removed too
+pub async fn delete_uploaded_video(pool: &DbPool, user_id: &str, video_id: &str) -> Result<Option<UploadedVideo>> {
+    let video = get_uploaded_video(pool, user_id, video_id).await?;
+    if video.is_some() {
+        sqlx::query("DELETE FROM uploaded_videos WHERE id = $1 AND user_id = $2")
+            .bind(video_id)
+            .bind(user_id)
+            .execute(pool).await?;
+    }
+    Ok(video)
+}
+
+// ============ Transcript Fetch Log ============
+
+/// One video info or subtitle request; `source` is None when every source failed
+pub struct TranscriptFetch<'a> {
+    pub user_id: &'a str,
+    pub video_id: &'a str,
+    /// "info" or "subtitles"
+    pub kind: &'a str,
+    pub lang: Option<&'a str>,
+    pub source: Option<&'a str>,
+    pub error: Option<&'a str>,
+    pub latency_ms: i64,
+}
+
+pub async fn save_transcript_fetch(pool: &DbPool, fetch: &TranscriptFetch<'_>) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO transcript_fetches (user_id, video_id, kind, lang, source, error, latency_ms)
+         VALUES ($1, $2, $3, $4, $5, $6, $7)"
+    )
+    .bind(fetch.user_id)
+    .bind(fetch.video_id)
+    .bind(fetch.kind)
+    .bind(fetch.lang)
+    .bind(fetch.source)
+    .bind(fetch.error)
+    .bind(fetch.latency_ms)
+    .execute(pool).await?;
+
+    Ok(())
+}
+
+// ============ AI Call Accounting ============
+
+/// Store the model calls made while serving one request
+pub async fn save_ai_calls(pool: &DbPool, user_id: &str, endpoint: &str, calls: &[AiCall]) -> Result<()> {
+    for call in calls {
+        sqlx::query(
+            "INSERT INTO ai_calls (user_id, endpoint, provider, model, input_tokens, output_tokens, latency_ms, cost_usd)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)"
+        )
+        .bind(user_id)
+        .bind(endpoint)
+        .bind(call.provider)
+        .bind(&call.model)
+        .bind(call.usage.input_tokens)
+        .bind(call.usage.output_tokens)
+        .bind(call.latency_ms)
+        .bind(call.cost_usd)
+        .execute(pool).await?;
+    }
+
+    Ok(())
+}
+
+/// Totals for one group of AI calls (an endpoint, or a provider and model)
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct AiUsageRow {
+    pub key: String,
+    pub calls: i64,
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+    pub cost_usd: f64,
+    pub avg_latency_ms: i64,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct AiUsageBreakdown {
+    pub days: i32,
+    pub total_calls: i64,
+    pub total_cost_usd: f64,
+    pub by_endpoint: Vec<AiUsageRow>,
+    pub by_model: Vec<AiUsageRow>,
+}
+
+/// AI calls of the last `days` days grouped by endpoint and by model, most expensive first
+/// `user_id: None` covers all users
+pub async fn get_ai_usage_breakdown(pool: &DbPool, user_id: Option<&str>, days: i32) -> Result<AiUsageBreakdown> {
+    let by_endpoint = ai_usage_rows(pool, "endpoint", user_id, days).await?;
+    let by_model = ai_usage_rows(pool, "provider || '/' || model", user_id, days).await?;
+
+    Ok(AiUsageBreakdown {
+        days,
+        total_calls: by_endpoint.iter().map(|row| row.calls).sum(),
+        total_cost_usd: by_endpoint.iter().map(|row| row.cost_usd).sum(),
+        by_endpoint,
+        by_model,
+    })
+}
+
+/// `group_expr` is one of the fixed expressions above, never user input
+async fn ai_usage_rows(pool: &DbPool, group_expr: &str, user_id: Option<&str>, days: i32) -> Result<Vec<AiUsageRow>> {
+    let query = format!(
+        "SELECT {group_expr} AS key,
+                COUNT(*) AS calls,
+                COALESCE(SUM(input_tokens), 0)::BIGINT AS input_tokens,
+                COALESCE(SUM(output_tokens), 0)::BIGINT AS output_tokens,
+                COALESCE(SUM(cost_usd), 0) AS cost_usd,
+                COALESCE(AVG(latency_ms), 0)::BIGINT AS avg_latency_ms
+         FROM ai_calls
+         WHERE ($1::TEXT IS NULL OR user_id = $1)
+           AND created_at >= NOW() - make_interval(days => $2)
+         GROUP BY 1
+         ORDER BY cost_usd DESC, calls DESC"
+    );
+
+    let rows = sqlx::query(&query)
+        .bind(user_id)
+        .bind(days)
+        .fetch_all(pool).await?;
+
+    Ok(rows.iter().map(|row| AiUsageRow {
+        key: row.get("key"),
+        calls: row.get("calls"),
+        input_tokens: row.get("input_tokens"),
+        output_tokens: row.get("output_tokens"),
+        cost_usd: row.get("cost_usd"),
+        avg_latency_ms: row.get("avg_latency_ms"),
+    }).collect())
 }
 
 // ============ AI Content Cache Functions ============
 
 /// Get cached mindmap for a video
-pub async fn get_cached_mindmap(pool: &DbPool, video_id: &str) -> Result<Option<String>> {
+pub async fn get_cached_mindmap(pool: &DbPool, video_id: &str, lang: &str) -> Result<Option<String>> {
     let result = sqlx::query(
-        "SELECT markdown FROM video_mindmaps WHERE video_id = $1"
+        "SELECT markdown FROM video_mindmaps WHERE video_id = $1 AND lang = $2"
     )
     .bind(video_id)
+    .bind(lang)
     .fetch_optional(pool).await?;
 
     Ok(result.map(|row| row.get("markdown")))
 }
 
 /// Save mindmap to cache
-pub async fn save_mindmap_cache(pool: &DbPool, video_id: &str, markdown: &str) -> Result<()> {
+pub async fn save_mindmap_cache(pool: &DbPool, video_id: &str, lang: &str, markdown: &str) -> Result<()> {
     sqlx::query(
-        "INSERT INTO video_mindmaps (video_id, markdown)
-         VALUES ($1, $2)
-         ON CONFLICT(video_id) DO UPDATE SET markdown = $2, created_at = NOW()"
+        "INSERT INTO video_mindmaps (video_id, lang, markdown)
+         VALUES ($1, $2, $3)
+         ON CONFLICT(video_id, lang) DO UPDATE SET markdown = $3, created_at = NOW()"
     )
     .bind(video_id)
+    .bind(lang)
     .bind(markdown)
     .execute(pool).await?;
 
@@ -1225,3 +2539,142 @@ pub async fn save_slides_cache(pool: &DbPool, video_id: &str, slides_json: &str)
 
     Ok(())
 }
+
+// ============ Subtitle Cache Functions ============
+
+#[derive(Debug, Clone)]
+pub struct CachedSubtitles {
+    pub source: String,
+    pub subtitles_json: String,
+    /// Sentence track; missing on rows cached before it existed
+    pub sentences_json: Option<String>,
+    pub fetched_at: chrono::DateTime<Utc>,
+}
+
+/// Get the most recently fetched subtitles for a video and language (any source)
+pub async fn get_cached_subtitles(pool: &DbPool, video_id: &str, lang: &str) -> Result<Option<CachedSubtitles>> {
+    let result = sqlx::query(
+        "SELECT source, subtitles_json, sentences_json, created_at FROM video_subtitles
+         WHERE video_id = $1 AND lang = $2
+         ORDER BY created_at DESC
+         LIMIT 1"
+    )
+    .bind(video_id)
+    .bind(lang)
+    .fetch_optional(pool).await?;
+
+    Ok(result.map(|row| CachedSubtitles {
+        source: row.get("source"),
+        subtitles_json: row.get("subtitles_json"),
+        sentences_json: row.get("sentences_json"),
+        fetched_at: row.get("created_at"),
+    }))
+}
+
+/// Save subtitles (raw cues and sentence track) to cache
+pub async fn save_subtitles_cache(
+    pool: &DbPool,
+    video_id: &str,
+    lang: &str,
+    source: &str,
+    subtitles_json: &str,
+    sentences_json: &str,
+) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO video_subtitles (video_id, lang, source, subtitles_json, sentences_json)
+         VALUES ($1, $2, $3, $4, $5)
+         ON CONFLICT(video_id, lang, source) DO UPDATE SET subtitles_json = $4, sentences_json = $5, created_at = NOW()"
+    )
+    .bind(video_id)
+    .bind(lang)
+    .bind(source)
+    .bind(subtitles_json)
+    .bind(sentences_json)
+    .execute(pool).await?;
+
+    Ok(())
+}
+
+/// Delete cached subtitles for a video (all languages if lang is None)
+/// Returns the number of rows removed
+pub async fn purge_subtitles_cache(pool: &DbPool, video_id: &str, lang: Option<&str>) -> Result<u64> {
+    let result = sqlx::query(
+        "DELETE FROM video_subtitles WHERE video_id = $1 AND ($2::TEXT IS NULL OR lang = $2)"
+    )
+    .bind(video_id)
+    .bind(lang)
+    .execute(pool).await?;
+
+    Ok(result.rows_affected())
+}
+
+// ============ Subtitle Translation Functions ============
+
+#[derive(Debug, Clone)]
+pub struct StoredTranslation {
+    pub subtitle_index: i32,
+    pub source_text: String,
+    pub translation: String,
+}
+
+/// Get stored translations for a video, one per subtitle line
+/// When several providers translated the same line, the preferred provider wins
+pub async fn get_subtitle_translations(
+    pool: &DbPool,
+    video_id: &str,
+    lang: &str,
+    preferred_provider: Option<&str>,
+) -> Result<Vec<StoredTranslation>> {
+    let rows = sqlx::query(
+        "SELECT DISTINCT ON (subtitle_index) subtitle_index, source_text, translation
+         FROM subtitle_translations
+         WHERE video_id = $1 AND lang = $2
+         ORDER BY subtitle_index, (provider = $3) DESC, created_at DESC"
+    )
+    .bind(video_id)
+    .bind(lang)
+    .bind(preferred_provider.unwrap_or(""))
+    .fetch_all(pool).await?;
+
+    Ok(rows.into_iter().map(|row| StoredTranslation {
+        subtitle_index: row.get("subtitle_index"),
+        source_text: row.get("source_text"),
+        translation: row.get("translation"),
+    }).collect())
+}
+
+/// Save translated subtitle lines
+pub async fn save_subtitle_translations(
+    pool: &DbPool,
+    video_id: &str,
+    lang: &str,
+    provider: &str,
+    translations: &[StoredTranslation],
+) -> Result<()> {
+    if translations.is_empty() {
+        return Ok(());
+    }
+
+    let indices: Vec<i32> = translations.iter().map(|t| t.subtitle_index).collect();
+    let source_texts: Vec<&str> = translations.iter().map(|t| t.source_text.as_str()).collect();
+    let texts: Vec<&str> = translations.iter().map(|t| t.translation.as_str()).collect();
+
+    sqlx::query(
+        "INSERT INTO subtitle_translations (video_id, lang, provider, subtitle_index, source_text, translation)
+         SELECT $1, $2, $3, t.subtitle_index, t.source_text, t.translation
+         FROM UNNEST($4::INTEGER[], $5::TEXT[], $6::TEXT[]) AS t(subtitle_index, source_text, translation)
+         ON CONFLICT(video_id, subtitle_index, lang, provider) DO UPDATE SET
+            source_text = EXCLUDED.source_text,
+            translation = EXCLUDED.translation,
+            created_at = NOW()"
+    )
+    .bind(video_id)
+    .bind(lang)
+    .bind(provider)
+    .bind(&indices)
+    .bind(&source_texts)
+    .bind(&texts)
+    .execute(pool).await?;
+
+    Ok(())
+}
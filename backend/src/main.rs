@@ -18,6 +18,9 @@ async fn main() {
     // Initialize database
     let db_pool = db::init_db().await.expect("Failed to initialize database");
 
+    // Periodically fit each user's scheduler weights to their review log
+    services::fsrs_optimizer::spawn_fitting_job(db_pool.clone());
+
     // Log AI provider configuration
     let ai_provider = std::env::var("AI_PROVIDER").unwrap_or_else(|_| "gemini".to_string());
     tracing::info!("AI Provider: {}", ai_provider);
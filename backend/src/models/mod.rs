@@ -16,6 +16,17 @@ pub struct Subtitle {
     pub text: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub translation: Option<String>,
+    /// Per-word timings, when the source has them (YouTube auto captions)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub words: Vec<SubtitleWord>,
+}
+
+/// One word of a subtitle and when it is spoken, in seconds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SubtitleWord {
+    pub text: String,
+    pub start: f64,
+    pub end: f64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -23,6 +34,13 @@ pub struct SubtitleResponse {
     pub video_id: String,
     pub subtitles: Vec<Subtitle>,
     pub language: String,
+    #[serde(default)]
+    pub source: String,
+    #[serde(default)]
+    pub cached: bool,
+    /// "raw" or "sentences"
+    #[serde(default)]
+    pub track: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -35,6 +53,94 @@ pub struct Note {
     pub created_at: String,
 }
 
+/// Per-user language settings, passed to every AI call
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LearnerProfile {
+    pub native_language: String,
+    pub target_language: String,
+}
+
+impl Default for LearnerProfile {
+    fn default() -> Self {
+        Self {
+            native_language: crate::services::language::DEFAULT_NATIVE_LANGUAGE.to_string(),
+            target_language: crate::services::language::DEFAULT_TARGET_LANGUAGE.to_string(),
+        }
+    }
+}
+
+impl LearnerProfile {
+    /// Native language name for prompts, e.g. "Japanese"
+    pub fn native_name(&self) -> &'static str {
+        crate::services::language::language_name(&self.native_language)
+    }
+
+    /// Name of the language being learned, e.g. "French"
+    pub fn target_name(&self) -> &'static str {
+        crate::services::language::language_name(&self.target_language)
+    }
+
+    /// Level system used to tag vocabulary of the target language
+    pub fn level_system(&self) -> crate::services::language::LevelSystem {
+        crate::services::language::level_system(&self.target_language)
+    }
+}
+
+/// Per-user review settings, in the style of Anki's deck options
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeckOptions {
+    /// Minutes between the reviews of a new card before it graduates
+    pub learning_steps: Vec<i32>,
+    /// Minutes between the reviews of a forgotten card before it returns to review
+    pub relearning_steps: Vec<i32>,
+    /// Days until the first review after graduating
+    pub graduating_interval_days: i32,
+    /// New cards introduced per day
+    pub new_cards_per_day: i32,
+    /// Review-phase cards shown per day; learning cards are never held back
+    pub reviews_per_day: i32,
+    pub maximum_interval_days: i32,
+    /// Extra interval multiplier for an Easy answer (SM-2)
+    pub easy_bonus: f64,
+}
+
+impl Default for DeckOptions {
+    fn default() -> Self {
+        Self {
+            learning_steps: vec![20, 60, 540, 1440],
+            relearning_steps: vec![20, 60, 540, 1440],
+            graduating_interval_days: 2,
+            new_cards_per_day: 20,
+            reviews_per_day: 200,
+            maximum_interval_days: 36500,
+            easy_bonus: 1.3,
+        }
+    }
+}
+
+impl DeckOptions {
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, steps) in [("learning_steps", &self.learning_steps), ("relearning_steps", &self.relearning_steps)] {
+            if steps.len() > 10 || steps.iter().any(|&minutes| !(1..=60 * 24 * 30).contains(&minutes)) {
+                return Err(format!("{} must be at most 10 steps of 1 minute to 30 days", name));
+            }
+        }
+        if !(1..=36500).contains(&self.maximum_interval_days) {
+            return Err("maximum_interval_days must be between 1 and 36500".to_string());
+        }
+        if !(1..=self.maximum_interval_days).contains(&self.graduating_interval_days) {
+            return Err("graduating_interval_days must be between 1 and maximum_interval_days".to_string());
+        }
+        if !(0..=9999).contains(&self.new_cards_per_day) || !(0..=9999).contains(&self.reviews_per_day) {
+            return Err("Daily limits must be between 0 and 9999".to_string());
+        }
+        if !(1.0..=5.0).contains(&self.easy_bonus) {
+            return Err("easy_bonus must be between 1.0 and 5.0".to_string());
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Serialize)]
 pub struct ApiResponse<T> {
     pub success: bool,
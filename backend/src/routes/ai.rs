@@ -1,15 +1,28 @@
-use axum::{extract::State, routing::post, Json, Router};
+use axum::{
+    extract::State,
+    response::{sse::{Event, KeepAlive, Sse}, IntoResponse, Response},
+    routing::post,
+    Json, Router,
+};
+use futures_util::stream::{self, StreamExt};
 use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use tokio::sync::mpsc;
 
-use crate::auth::OptionalAuthUser;
-use crate::db::{DbPool, get_cached_mindmap, save_mindmap_cache, get_cached_slides, save_slides_cache, check_can_ai_chat, increment_ai_chat_count};
+use crate::auth::quota::{ops, AiQuota};
+use crate::db::{self, DbPool, get_cached_mindmap, save_mindmap_cache, get_cached_slides, save_slides_cache, get_learner_profile};
 use crate::models::{ApiResponse, Subtitle};
-use crate::services::ai::{get_ai_provider, Chapter, Slide, VocabularyItem};
+use crate::services::ai::{get_ai_provider, Chapter, ChatRole, ChatTurn, Slide, VocabularyItem};
+use crate::services::ai_usage::track;
+use crate::services::language;
+use crate::services::segmentation::SubtitleTrack;
+use crate::services::translation::translate_subtitles_cached;
 
 pub fn routes(db_pool: DbPool) -> Router {
     Router::new()
         .route("/analyze", post(analyze_highlights))
         .route("/ask", post(ask_question))
+        .route("/ask/stream", post(ask_question_stream))
         .route("/translate", post(translate_subtitles))
         .route("/vocabulary", post(extract_vocabulary))
         .route("/mindmap", post(generate_mindmap))
@@ -29,15 +42,24 @@ pub struct AnalyzeResponse {
 }
 
 async fn analyze_highlights(
+    State(pool): State<DbPool>,
+    quota: AiQuota<ops::Analyze>,
     Json(payload): Json<AnalyzeRequest>,
 ) -> Json<ApiResponse<AnalyzeResponse>> {
+    let user_id = quota.user_id();
+    let profile = get_learner_profile(&pool, user_id).await.unwrap_or_default();
+
     let provider = match get_ai_provider() {
         Ok(p) => p,
         Err(e) => return Json(ApiResponse::error(format!("AI provider error: {}", e))),
     };
 
-    match provider.analyze_highlights(&payload.subtitles).await {
-        Ok(highlights) => Json(ApiResponse::success(AnalyzeResponse { highlights })),
+    let analysis = provider.analyze_highlights(&payload.subtitles, &profile);
+    match track(&pool, user_id, "ai/analyze", analysis).await {
+        Ok(highlights) => {
+            quota.charge();
+            Json(ApiResponse::success(AnalyzeResponse { highlights }))
+        }
         Err(e) => Json(ApiResponse::error(format!("Analysis failed: {}", e))),
     }
 }
@@ -46,6 +68,8 @@ async fn analyze_highlights(
 pub struct AskRequest {
     context: String,
     question: String,
+    /// Chat thread to continue: its messages are sent as history and the new exchange is saved to it
+    thread_id: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -53,50 +77,142 @@ pub struct AskResponse {
     answer: String,
 }
 
+/// Previous messages of the user's chat thread (empty when no thread is given)
+async fn load_thread_history(pool: &DbPool, user_id: &str, thread_id: Option<&str>) -> Result<Vec<ChatTurn>, String> {
+    let Some(thread_id) = thread_id else {
+        return Ok(Vec::new());
+    };
+
+    match db::get_chat_thread(pool, user_id, thread_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return Err("Chat thread not found".to_string()),
+        Err(e) => return Err(format!("Failed to load chat thread: {}", e)),
+    }
+
+    let messages = db::get_chat_messages(pool, thread_id)
+        .await
+        .map_err(|e| format!("Failed to load chat thread: {}", e))?;
+
+    Ok(messages
+        .into_iter()
+        .filter_map(|m| {
+            Some(ChatTurn {
+                role: ChatRole::parse(&m.role)?,
+                content: m.content,
+            })
+        })
+        .collect())
+}
+
+/// Count a completed answer and save it to the thread it belongs to
+async fn finish_answer(pool: &DbPool, quota: &AiQuota<ops::Ask>, thread_id: Option<&str>, question: &str, answer: &str) {
+    quota.charge();
+    if let Some(thread_id) = thread_id {
+        if let Err(e) = db::append_chat_exchange(pool, thread_id, question, answer).await {
+            tracing::warn!("Failed to save chat messages to thread {}: {}", thread_id, e);
+        }
+    }
+}
+
 async fn ask_question(
     State(pool): State<DbPool>,
-    auth: OptionalAuthUser,
+    quota: AiQuota<ops::Ask>,
     Json(payload): Json<AskRequest>,
 ) -> Json<ApiResponse<AskResponse>> {
-    let user_id = auth.user_id_or_default();
-    let tier = auth.tier_or_default();
-
-    // Check rate limit
-    match check_can_ai_chat(&pool, user_id, &tier).await {
-        Ok((allowed, _remaining)) => {
-            if !allowed {
-                return Json(ApiResponse::error_with_code(
-                    "RATE_LIMIT_EXCEEDED".to_string(),
-                    "Daily AI chat limit reached. Please try again tomorrow.".to_string(),
-                ));
-            }
-        }
-        Err(e) => {
-            tracing::warn!("Failed to check AI chat limit: {}", e);
-            // Continue anyway if rate limit check fails
-        }
-    }
+    let user_id = quota.user_id();
 
     let provider = match get_ai_provider() {
         Ok(p) => p,
         Err(e) => return Json(ApiResponse::error(format!("AI provider error: {}", e))),
     };
 
-    match provider.ask_question(&payload.context, &payload.question).await {
+    let profile = get_learner_profile(&pool, user_id).await.unwrap_or_default();
+    let thread_id = payload.thread_id.as_deref();
+    let history = match load_thread_history(&pool, user_id, thread_id).await {
+        Ok(history) => history,
+        Err(e) => return Json(ApiResponse::error(e)),
+    };
+
+    let answer = provider.ask_question(&payload.context, &payload.question, &history, &profile);
+    match track(&pool, user_id, "ai/ask", answer).await {
         Ok(answer) => {
-            // Increment usage count on success
-            if let Err(e) = increment_ai_chat_count(&pool, user_id).await {
-                tracing::warn!("Failed to increment AI chat count: {}", e);
-            }
+            finish_answer(&pool, &quota, thread_id, &payload.question, &answer).await;
             Json(ApiResponse::success(AskResponse { answer }))
         }
         Err(e) => Json(ApiResponse::error(format!("Question failed: {}", e))),
     }
 }
 
+/// Streaming variant of `/ask`, sent as server-sent events:
+/// `delta` events carry answer text as it arrives, then a final `done` (full answer) or `error` event.
+/// Errors before the stream starts (rate limit, provider setup) are returned as a normal JSON response.
+async fn ask_question_stream(
+    State(pool): State<DbPool>,
+    quota: AiQuota<ops::Ask>,
+    Json(payload): Json<AskRequest>,
+) -> Response {
+    let user_id = quota.user_id().to_string();
+
+    let provider = match get_ai_provider() {
+        Ok(p) => p,
+        Err(e) => return Json(ApiResponse::<AskResponse>::error(format!("AI provider error: {}", e))).into_response(),
+    };
+
+    let profile = get_learner_profile(&pool, &user_id).await.unwrap_or_default();
+    let history = match load_thread_history(&pool, &user_id, payload.thread_id.as_deref()).await {
+        Ok(history) => history,
+        Err(e) => return Json(ApiResponse::<AskResponse>::error(e)).into_response(),
+    };
+
+    let (chunk_tx, chunk_rx) = mpsc::channel::<String>(64);
+    let answer_task = tokio::spawn(async move {
+        // Fails if the client disconnects mid-answer, so only completed answers are counted and saved
+        let answer = provider.ask_question_stream(&payload.context, &payload.question, &history, &profile, chunk_tx);
+        match track(&pool, &user_id, "ai/ask/stream", answer).await {
+            Ok(answer) => {
+                finish_answer(&pool, &quota, payload.thread_id.as_deref(), &payload.question, &answer).await;
+                Event::default()
+                    .event("done")
+                    .data(serde_json::json!({ "answer": answer }).to_string())
+            }
+            Err(e) => stream_error_event(format!("Question failed: {}", e)),
+        }
+    });
+
+    let deltas = stream::unfold(chunk_rx, |mut rx| async move {
+        let text = rx.recv().await?;
+        let event = Event::default()
+            .event("delta")
+            .data(serde_json::json!({ "text": text }).to_string());
+        Some((event, rx))
+    });
+    let finished = stream::once(async move {
+        answer_task
+            .await
+            .unwrap_or_else(|e| stream_error_event(format!("Question failed: {}", e)))
+    });
+
+    Sse::new(deltas.chain(finished).map(Ok::<_, Infallible>))
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+fn stream_error_event(message: String) -> Event {
+    Event::default()
+        .event("error")
+        .data(serde_json::json!({ "error": message }).to_string())
+}
+
 #[derive(Deserialize)]
 pub struct TranslateRequest {
     subtitles: Vec<Subtitle>,
+    /// When set, translations are stored per video and only missing lines are sent to the AI
+    video_id: Option<String>,
+    /// Subtitle track the lines come from ("raw" or "sentences"), so each keeps its own translations
+    track: Option<String>,
+    /// Language of the lines, so each source language keeps its own translations
+    /// (default: the learner's target language, as for the subtitle routes)
+    lang: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -105,15 +221,39 @@ pub struct TranslateResponse {
 }
 
 async fn translate_subtitles(
+    State(db_pool): State<DbPool>,
+    quota: AiQuota<ops::Translate>,
     Json(payload): Json<TranslateRequest>,
 ) -> Json<ApiResponse<TranslateResponse>> {
+    let user_id = quota.user_id();
+    let profile = get_learner_profile(&db_pool, user_id).await.unwrap_or_default();
+
     let provider = match get_ai_provider() {
         Ok(p) => p,
         Err(e) => return Json(ApiResponse::error(format!("AI provider error: {}", e))),
     };
 
-    match provider.translate_subtitles(&payload.subtitles).await {
-        Ok(translations) => Json(ApiResponse::success(TranslateResponse { translations })),
+    let lang = payload.lang.as_deref().unwrap_or(&profile.target_language);
+    let Some(lang) = language::canonical_code(lang) else {
+        return Json(ApiResponse::error(format!("Unsupported language: {}", lang)));
+    };
+    let key = payload.video_id.as_deref().map(|video_id| {
+        let track = payload.track.as_deref().and_then(SubtitleTrack::from_name).unwrap_or_default();
+        track.translation_key(video_id, lang)
+    });
+
+    let translation = async {
+        match key {
+            Some(key) => translate_subtitles_cached(&db_pool, provider.as_ref(), &key, &profile, &payload.subtitles).await,
+            None => provider.translate_subtitles(&payload.subtitles, &profile).await,
+        }
+    };
+
+    match track(&db_pool, user_id, "ai/translate", translation).await {
+        Ok(translations) => {
+            quota.charge();
+            Json(ApiResponse::success(TranslateResponse { translations }))
+        }
         Err(e) => Json(ApiResponse::error(format!("Translation failed: {}", e))),
     }
 }
@@ -129,15 +269,27 @@ pub struct VocabularyResponse {
 }
 
 async fn extract_vocabulary(
+    State(pool): State<DbPool>,
+    quota: AiQuota<ops::Vocabulary>,
     Json(payload): Json<VocabularyRequest>,
 ) -> Json<ApiResponse<VocabularyResponse>> {
+    let user_id = quota.user_id();
+    let profile = get_learner_profile(&pool, user_id).await.unwrap_or_default();
+
     let provider = match get_ai_provider() {
         Ok(p) => p,
         Err(e) => return Json(ApiResponse::error(format!("AI provider error: {}", e))),
     };
 
-    match provider.extract_vocabulary(&payload.text).await {
-        Ok(vocabulary) => Json(ApiResponse::success(VocabularyResponse { vocabulary })),
+    match track(&pool, user_id, "ai/vocabulary", provider.extract_vocabulary(&payload.text, &profile)).await {
+        Ok(mut vocabulary) => {
+            quota.charge();
+            let levels = profile.level_system();
+            for item in &mut vocabulary {
+                item.level = levels.normalize(&item.level);
+            }
+            Json(ApiResponse::success(VocabularyResponse { vocabulary }))
+        }
         Err(e) => Json(ApiResponse::error(format!("Vocabulary extraction failed: {}", e))),
     }
 }
@@ -159,11 +311,15 @@ pub struct MindMapResponse {
 
 async fn generate_mindmap(
     State(db_pool): State<DbPool>,
+    quota: AiQuota<ops::Mindmap>,
     Json(payload): Json<MindMapRequest>,
 ) -> Json<ApiResponse<MindMapResponse>> {
+    let user_id = quota.user_id();
+    let profile = get_learner_profile(&db_pool, user_id).await.unwrap_or_default();
+
     // Check cache first (skip if regenerate is requested)
     if !payload.regenerate {
-        if let Ok(Some(cached_markdown)) = get_cached_mindmap(&db_pool, &payload.video_id).await {
+        if let Ok(Some(cached_markdown)) = get_cached_mindmap(&db_pool, &payload.video_id, &profile.native_language).await {
             return Json(ApiResponse::success(MindMapResponse {
                 markdown: cached_markdown,
                 cached: true,
@@ -176,10 +332,12 @@ async fn generate_mindmap(
         Err(e) => return Json(ApiResponse::error(format!("AI provider error: {}", e))),
     };
 
-    match provider.generate_mindmap(&payload.title, &payload.content).await {
+    let mindmap = provider.generate_mindmap(&payload.title, &payload.content, &profile);
+    match track(&db_pool, user_id, "ai/mindmap", mindmap).await {
         Ok(markdown) => {
+            quota.charge();
             // Save to cache (ignore errors)
-            let _ = save_mindmap_cache(&db_pool, &payload.video_id, &markdown).await;
+            let _ = save_mindmap_cache(&db_pool, &payload.video_id, &profile.native_language, &markdown).await;
             Json(ApiResponse::success(MindMapResponse { markdown, cached: false }))
         }
         Err(e) => Json(ApiResponse::error(format!("Mind map generation failed: {}", e))),
@@ -203,8 +361,12 @@ pub struct SlidesResponse {
 
 async fn generate_slides(
     State(db_pool): State<DbPool>,
+    quota: AiQuota<ops::Slides>,
     Json(payload): Json<SlidesRequest>,
 ) -> Json<ApiResponse<SlidesResponse>> {
+    let user_id = quota.user_id();
+    let profile = get_learner_profile(&db_pool, user_id).await.unwrap_or_default();
+
     // Check cache first (skip if regenerate is requested)
     if !payload.regenerate {
         if let Ok(Some(cached_json)) = get_cached_slides(&db_pool, &payload.video_id).await {
@@ -222,8 +384,10 @@ async fn generate_slides(
         Err(e) => return Json(ApiResponse::error(format!("AI provider error: {}", e))),
     };
 
-    match provider.generate_slides(&payload.title, &payload.content).await {
+    let slides = provider.generate_slides(&payload.title, &payload.content, &profile);
+    match track(&db_pool, user_id, "ai/slides", slides).await {
         Ok(slides) => {
+            quota.charge();
             // Save to cache (ignore errors)
             if let Ok(slides_json) = serde_json::to_string(&slides) {
                 let _ = save_slides_cache(&db_pool, &payload.video_id, &slides_json).await;
@@ -245,15 +409,108 @@ pub struct ChaptersResponse {
 }
 
 async fn generate_chapters(
+    State(pool): State<DbPool>,
+    quota: AiQuota<ops::Chapters>,
     Json(payload): Json<ChaptersRequest>,
 ) -> Json<ApiResponse<ChaptersResponse>> {
+    let user_id = quota.user_id();
+    let profile = get_learner_profile(&pool, user_id).await.unwrap_or_default();
+
     let provider = match get_ai_provider() {
         Ok(p) => p,
         Err(e) => return Json(ApiResponse::error(format!("AI provider error: {}", e))),
     };
 
-    match provider.generate_chapters(&payload.subtitles).await {
-        Ok(chapters) => Json(ApiResponse::success(ChaptersResponse { chapters })),
+    match track(&pool, user_id, "ai/chapters", provider.generate_chapters(&payload.subtitles, &profile)).await {
+        Ok(chapters) => {
+            quota.charge();
+            Json(ApiResponse::success(ChaptersResponse { chapters }))
+        }
         Err(e) => Json(ApiResponse::error(format!("Chapters generation failed: {}", e))),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::test_support::{post_json, unreachable_db, use_mock_ai, with_memory_ledger};
+    use serde_json::json;
+
+    fn app() -> Router {
+        use_mock_ai();
+        with_memory_ledger(routes(unreachable_db()))
+    }
+
+    fn subtitles(count: usize) -> serde_json::Value {
+        let subtitles: Vec<_> = (0..count)
+            .map(|i| json!({"index": i, "start": i as f64 * 2.0, "end": i as f64 * 2.0 + 2.0, "text": format!("Line number {}", i)}))
+            .collect();
+        json!(subtitles)
+    }
+
+    #[tokio::test]
+    async fn test_translate_returns_one_line_per_subtitle() {
+        let body = post_json(app(), "/translate", json!({"subtitles": subtitles(3)}), false).await;
+        assert_eq!(body["success"], true, "{}", body);
+        assert_eq!(body["data"]["translations"], json!(["[zh] Line number 0", "[zh] Line number 1", "[zh] Line number 2"]));
+    }
+
+    #[tokio::test]
+    async fn test_translate_for_video_survives_storage_errors() {
+        let body = post_json(app(), "/translate", json!({"subtitles": subtitles(2), "video_id": "abc"}), false).await;
+        assert_eq!(body["success"], true, "{}", body);
+        assert_eq!(body["data"]["translations"].as_array().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_translate_rejects_unsupported_source_language() {
+        let body = post_json(app(), "/translate", json!({"subtitles": subtitles(2), "video_id": "abc", "lang": "xx"}), false).await;
+        assert_eq!(body["error"], "Unsupported language: xx");
+    }
+
+    #[tokio::test]
+    async fn test_mindmap_is_generated_when_not_cached() {
+        let body = post_json(
+            app(),
+            "/mindmap",
+            json!({"video_id": "abc", "title": "Rust", "content": "Ownership and borrowing explained"}),
+            false,
+        )
+        .await;
+        assert_eq!(body["success"], true, "{}", body);
+        assert_eq!(body["data"]["cached"], false);
+        assert!(body["data"]["markdown"].as_str().unwrap().starts_with("# Rust\n"));
+    }
+
+    #[tokio::test]
+    async fn test_slides_have_title_content_and_summary() {
+        let body = post_json(
+            app(),
+            "/slides",
+            json!({"video_id": "abc", "title": "Rust", "content": "Ownership and borrowing explained"}),
+            false,
+        )
+        .await;
+        assert_eq!(body["success"], true, "{}", body);
+        let types: Vec<&str> = body["data"]["slides"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|s| s["slide_type"].as_str().unwrap())
+            .collect();
+        assert_eq!(types, vec!["title", "content", "summary"]);
+    }
+
+    #[tokio::test]
+    async fn test_chapters_start_at_subtitle_times() {
+        let body = post_json(app(), "/chapters", json!({"subtitles": subtitles(25)}), false).await;
+        assert_eq!(body["success"], true, "{}", body);
+        let starts: Vec<f64> = body["data"]["chapters"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| c["start_time"].as_f64().unwrap())
+            .collect();
+        assert_eq!(starts, vec![0.0, 20.0, 40.0]);
+    }
+}
@@ -0,0 +1,120 @@
+use axum::{
+    extract::{Path, Query, State},
+    routing::get,
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::auth::AuthUser;
+use crate::db::{self, ChatMessage, ChatThread, DbPool};
+use crate::models::ApiResponse;
+
+/// AI chat threads; questions are asked through `/api/ai/ask` with a `thread_id`
+pub fn routes(db_pool: DbPool) -> Router {
+    Router::new()
+        .route("/threads", get(list_threads).post(create_thread))
+        .route("/threads/:id", get(get_thread).patch(rename_thread).delete(delete_thread))
+        .with_state(db_pool)
+}
+
+#[derive(Deserialize)]
+pub struct ListThreadsQuery {
+    video_id: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ThreadsResponse {
+    threads: Vec<ChatThread>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateThreadRequest {
+    video_id: String,
+    /// Defaults to the first question asked in the thread
+    title: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ThreadResponse {
+    thread: ChatThread,
+    messages: Vec<ChatMessage>,
+}
+
+#[derive(Deserialize)]
+pub struct RenameThreadRequest {
+    title: String,
+}
+
+/// List the user's chat threads, optionally for one video
+async fn list_threads(
+    State(pool): State<DbPool>,
+    auth: AuthUser,
+    Query(query): Query<ListThreadsQuery>,
+) -> Json<ApiResponse<ThreadsResponse>> {
+    match db::list_chat_threads(&pool, &auth.user_id, query.video_id.as_deref()).await {
+        Ok(threads) => Json(ApiResponse::success(ThreadsResponse { threads })),
+        Err(e) => Json(ApiResponse::error(format!("Failed to list chat threads: {}", e))),
+    }
+}
+
+/// Start a new chat thread for a video
+async fn create_thread(
+    State(pool): State<DbPool>,
+    auth: AuthUser,
+    Json(payload): Json<CreateThreadRequest>,
+) -> Json<ApiResponse<ThreadResponse>> {
+    let title = payload.title.as_deref().unwrap_or("").trim();
+
+    match db::create_chat_thread(&pool, &auth.user_id, &payload.video_id, title).await {
+        Ok(thread) => Json(ApiResponse::success(ThreadResponse {
+            thread,
+            messages: Vec::new(),
+        })),
+        Err(e) => Json(ApiResponse::error(format!("Failed to create chat thread: {}", e))),
+    }
+}
+
+/// Get a chat thread with all of its messages
+async fn get_thread(
+    State(pool): State<DbPool>,
+    auth: AuthUser,
+    Path(thread_id): Path<String>,
+) -> Json<ApiResponse<ThreadResponse>> {
+    let thread = match db::get_chat_thread(&pool, &auth.user_id, &thread_id).await {
+        Ok(Some(thread)) => thread,
+        Ok(None) => return Json(ApiResponse::error("Chat thread not found".to_string())),
+        Err(e) => return Json(ApiResponse::error(format!("Failed to get chat thread: {}", e))),
+    };
+
+    match db::get_chat_messages(&pool, &thread_id).await {
+        Ok(messages) => Json(ApiResponse::success(ThreadResponse { thread, messages })),
+        Err(e) => Json(ApiResponse::error(format!("Failed to get chat messages: {}", e))),
+    }
+}
+
+/// Rename a chat thread
+async fn rename_thread(
+    State(pool): State<DbPool>,
+    auth: AuthUser,
+    Path(thread_id): Path<String>,
+    Json(payload): Json<RenameThreadRequest>,
+) -> Json<ApiResponse<()>> {
+    match db::rename_chat_thread(&pool, &auth.user_id, &thread_id, payload.title.trim()).await {
+        Ok(true) => Json(ApiResponse::success(())),
+        Ok(false) => Json(ApiResponse::error("Chat thread not found".to_string())),
+        Err(e) => Json(ApiResponse::error(format!("Failed to rename chat thread: {}", e))),
+    }
+}
+
+/// Delete a chat thread and its messages
+async fn delete_thread(
+    State(pool): State<DbPool>,
+    auth: AuthUser,
+    Path(thread_id): Path<String>,
+) -> Json<ApiResponse<()>> {
+    match db::delete_chat_thread(&pool, &auth.user_id, &thread_id).await {
+        Ok(true) => Json(ApiResponse::success(())),
+        Ok(false) => Json(ApiResponse::error("Chat thread not found".to_string())),
+        Err(e) => Json(ApiResponse::error(format!("Failed to delete chat thread: {}", e))),
+    }
+}
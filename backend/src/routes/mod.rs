@@ -1,29 +1,37 @@
 pub mod ai;
 pub mod auth;
+pub mod chat;
 pub mod history;
 pub mod invite;
 pub mod notes;
+pub mod settings;
 pub mod stats;
+#[cfg(test)]
+pub(crate) mod test_support;
 pub mod upload;
 pub mod usage;
 pub mod video;
 pub mod vocabulary;
 
-use axum::Router;
+use axum::{Extension, Router};
 use std::sync::Arc;
 use crate::db::DbPool;
 use crate::services::r2::R2Client;
+use crate::services::usage_ledger::PgUsageLedger;
 
 pub fn api_routes(db_pool: DbPool, r2_client: Option<Arc<R2Client>>) -> Router {
+    let ledger = PgUsageLedger::shared(db_pool.clone());
     let mut router = Router::new()
-        .nest("/video", video::routes(db_pool.clone()))
+        .nest("/video", video::routes(db_pool.clone()).merge(upload::video_routes(db_pool.clone(), r2_client.clone())))
         .nest("/ai", ai::routes(db_pool.clone()))
+        .nest("/chat", chat::routes(db_pool.clone()))
         .nest("/auth", auth::routes(db_pool.clone()))
         .nest("/vocabulary", vocabulary::routes(db_pool.clone()))
         .nest("/stats", stats::routes(db_pool.clone()))
         .nest("/notes", notes::routes(db_pool.clone()))
         .nest("/history", history::routes(db_pool.clone()))
         .nest("/usage", usage::routes(db_pool.clone()))
+        .nest("/settings", settings::routes(db_pool.clone()))
         .nest("/invite", invite::routes(db_pool));
 
     // Add upload routes if R2 is configured
@@ -31,5 +39,6 @@ pub fn api_routes(db_pool: DbPool, r2_client: Option<Arc<R2Client>>) -> Router {
         router = router.nest("/upload", upload::routes(r2));
     }
 
-    router
+    // Quotas are reserved from this ledger
+    router.layer(Extension(ledger))
 }
@@ -29,7 +29,8 @@ pub struct NoteResponse {
     pub video_id: String,
     pub timestamp: f64,
     pub english: Option<String>,
-    pub chinese: Option<String>,
+    pub translation: Option<String>,
+    pub translation_lang: Option<String>,
     pub note_text: Option<String>,
     pub images: Option<Vec<String>>,
     pub created_at: String,
@@ -44,7 +45,8 @@ impl From<Note> for NoteResponse {
             video_id: note.video_id,
             timestamp: note.timestamp,
             english: note.english,
-            chinese: note.chinese,
+            translation: note.translation,
+            translation_lang: note.translation_lang,
             note_text: note.note_text,
             images,
             created_at: note.created_at,
@@ -58,7 +60,10 @@ pub struct CreateNoteRequest {
     pub video_id: String,
     pub timestamp: f64,
     pub english: Option<String>,
-    pub chinese: Option<String>,
+    #[serde(alias = "chinese")]
+    pub translation: Option<String>,
+    /// Language of `translation`, defaults to the user's native language
+    pub translation_lang: Option<String>,
     pub note_text: Option<String>,
     pub images: Option<Vec<String>>,
 }
@@ -110,6 +115,15 @@ async fn save_note(
         )
     });
 
+    let translation_lang = match request.translation_lang {
+        Some(lang) => Some(lang),
+        None if request.translation.is_some() => db::get_learner_profile(&db_pool, user_id)
+            .await
+            .ok()
+            .map(|p| p.native_language),
+        None => None,
+    };
+
     // Convert images Vec to JSON string for storage
     let images_json = request.images.map(|imgs| serde_json::to_string(&imgs).unwrap_or_default());
 
@@ -119,7 +133,8 @@ async fn save_note(
         video_id: request.video_id,
         timestamp: request.timestamp,
         english: request.english,
-        chinese: request.chinese,
+        translation: request.translation,
+        translation_lang,
         note_text: request.note_text,
         images: images_json,
         created_at: chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
@@ -0,0 +1,191 @@
+use axum::{
+    extract::State,
+    routing::get,
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::auth::AuthUser;
+use crate::db::{self, DbPool, Scheduler};
+use crate::models::{ApiResponse, DeckOptions, LearnerProfile};
+use crate::services::language;
+
+pub fn routes(db_pool: DbPool) -> Router {
+    Router::new()
+        .route("/", get(get_settings).put(update_settings))
+        .route("/deck", get(get_deck_options).put(update_deck_options))
+        .with_state(db_pool)
+}
+
+#[derive(Serialize)]
+pub struct LanguageOption {
+    pub code: &'static str,
+    pub name: &'static str,
+}
+
+#[derive(Serialize)]
+pub struct LevelSystemInfo {
+    pub name: &'static str,
+    pub levels: &'static [&'static str],
+}
+
+#[derive(Serialize)]
+pub struct SettingsResponse {
+    #[serde(flatten)]
+    pub profile: LearnerProfile,
+    /// Levels used to tag vocabulary of the target language
+    pub level_system: LevelSystemInfo,
+    pub supported_languages: Vec<LanguageOption>,
+    /// Spaced repetition algorithm: "sm2" or "fsrs"
+    pub scheduler: &'static str,
+    pub supported_schedulers: Vec<&'static str>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateSettingsRequest {
+    native_language: Option<String>,
+    target_language: Option<String>,
+    scheduler: Option<String>,
+}
+
+fn settings_response(profile: LearnerProfile, scheduler: Scheduler) -> SettingsResponse {
+    let levels = profile.level_system();
+    SettingsResponse {
+        profile,
+        level_system: LevelSystemInfo {
+            name: levels.name,
+            levels: levels.levels,
+        },
+        supported_languages: language::SUPPORTED_LANGUAGES
+            .iter()
+            .map(|(code, name)| LanguageOption { code, name })
+            .collect(),
+        scheduler: scheduler.as_str(),
+        supported_schedulers: Scheduler::ALL.iter().map(Scheduler::as_str).collect(),
+    }
+}
+
+async fn load_settings(pool: &DbPool, user_id: &str) -> Json<ApiResponse<SettingsResponse>> {
+    let settings = async {
+        let profile = db::get_learner_profile(pool, user_id).await?;
+        let scheduler = db::get_scheduler(pool, user_id).await?;
+        anyhow::Ok(settings_response(profile, scheduler))
+    };
+    match settings.await {
+        Ok(settings) => Json(ApiResponse::success(settings)),
+        Err(e) => Json(ApiResponse::error(format!("Failed to get settings: {}", e))),
+    }
+}
+
+/// Get the user's learning settings
+async fn get_settings(
+    State(pool): State<DbPool>,
+    auth: AuthUser,
+) -> Json<ApiResponse<SettingsResponse>> {
+    load_settings(&pool, &auth.user_id).await
+}
+
+/// Update the user's learning settings
+async fn update_settings(
+    State(pool): State<DbPool>,
+    auth: AuthUser,
+    Json(payload): Json<UpdateSettingsRequest>,
+) -> Json<ApiResponse<SettingsResponse>> {
+    // Validate everything before writing so a bad value doesn't leave a partial update
+    for lang in [&payload.native_language, &payload.target_language].into_iter().flatten() {
+        if !language::is_supported(lang) {
+            return Json(ApiResponse::error(format!("Unsupported language: {}", lang)));
+        }
+    }
+    let scheduler = match payload.scheduler.as_deref() {
+        Some(name) => match Scheduler::from_name(name) {
+            Some(scheduler) => Some(scheduler),
+            None => return Json(ApiResponse::error(format!("Unsupported scheduler: {}", name))),
+        },
+        None => None,
+    };
+
+    if let Some(native_language) = payload.native_language.as_deref() {
+        if let Err(e) = db::set_native_language(&pool, &auth.user_id, native_language).await {
+            return Json(ApiResponse::error(format!("Failed to update settings: {}", e)));
+        }
+    }
+
+    if let Some(target_language) = payload.target_language.as_deref() {
+        if let Err(e) = db::set_target_language(&pool, &auth.user_id, target_language).await {
+            return Json(ApiResponse::error(format!("Failed to update settings: {}", e)));
+        }
+    }
+
+    if let Some(scheduler) = scheduler {
+        // Existing cards get an FSRS state from their SM-2 schedule; cards missed here convert on their next review
+        if scheduler == Scheduler::Fsrs {
+            match db::migrate_cards_to_fsrs(&pool, &auth.user_id).await {
+                Ok(converted) => tracing::info!("Converted {} cards of {} to FSRS", converted, auth.user_id),
+                Err(e) => tracing::warn!("Failed to convert cards to FSRS: {}", e),
+            }
+        }
+        if let Err(e) = db::set_scheduler(&pool, &auth.user_id, scheduler).await {
+            return Json(ApiResponse::error(format!("Failed to update settings: {}", e)));
+        }
+    }
+
+    load_settings(&pool, &auth.user_id).await
+}
+
+#[derive(Deserialize)]
+pub struct UpdateDeckOptionsRequest {
+    learning_steps: Option<Vec<i32>>,
+    relearning_steps: Option<Vec<i32>>,
+    graduating_interval_days: Option<i32>,
+    new_cards_per_day: Option<i32>,
+    reviews_per_day: Option<i32>,
+    maximum_interval_days: Option<i32>,
+    easy_bonus: Option<f64>,
+}
+
+impl UpdateDeckOptionsRequest {
+    fn apply_to(self, options: DeckOptions) -> DeckOptions {
+        DeckOptions {
+            learning_steps: self.learning_steps.unwrap_or(options.learning_steps),
+            relearning_steps: self.relearning_steps.unwrap_or(options.relearning_steps),
+            graduating_interval_days: self.graduating_interval_days.unwrap_or(options.graduating_interval_days),
+            new_cards_per_day: self.new_cards_per_day.unwrap_or(options.new_cards_per_day),
+            reviews_per_day: self.reviews_per_day.unwrap_or(options.reviews_per_day),
+            maximum_interval_days: self.maximum_interval_days.unwrap_or(options.maximum_interval_days),
+            easy_bonus: self.easy_bonus.unwrap_or(options.easy_bonus),
+        }
+    }
+}
+
+/// Get the user's learning steps, daily limits and interval settings
+async fn get_deck_options(
+    State(pool): State<DbPool>,
+    auth: AuthUser,
+) -> Json<ApiResponse<DeckOptions>> {
+    match db::get_deck_options(&pool, &auth.user_id).await {
+        Ok(options) => Json(ApiResponse::success(options)),
+        Err(e) => Json(ApiResponse::error(format!("Failed to get deck options: {}", e))),
+    }
+}
+
+/// Update some of the user's deck options; cards already scheduled keep their due dates
+async fn update_deck_options(
+    State(pool): State<DbPool>,
+    auth: AuthUser,
+    Json(payload): Json<UpdateDeckOptionsRequest>,
+) -> Json<ApiResponse<DeckOptions>> {
+    let current = match db::get_deck_options(&pool, &auth.user_id).await {
+        Ok(options) => options,
+        Err(e) => return Json(ApiResponse::error(format!("Failed to get deck options: {}", e))),
+    };
+    let options = payload.apply_to(current);
+    if let Err(e) = options.validate() {
+        return Json(ApiResponse::error(e));
+    }
+
+    match db::save_deck_options(&pool, &auth.user_id, &options).await {
+        Ok(()) => Json(ApiResponse::success(options)),
+        Err(e) => Json(ApiResponse::error(format!("Failed to update deck options: {}", e))),
+    }
+}
@@ -124,7 +124,7 @@ async fn get_memory_distribution(
 ) -> Json<ApiResponse<MemoryDistribution>> {
     let user_id = auth.user_id_or_default();
 
-    // Get all vocabulary with memory_strength
+    // Get all vocabulary with its current recall probability
     let vocab_list = match db::get_vocabulary_list(&pool, user_id, false).await {
         Ok(list) => list,
         Err(e) => return Json(ApiResponse::error(format!("Failed to get vocabulary: {}", e))),
@@ -136,11 +136,11 @@ async fn get_memory_distribution(
     let mut critical = 0;
 
     for vocab in &vocab_list {
-        if vocab.memory_strength >= 0.7 {
+        if vocab.retrievability >= 0.7 {
             strong += 1;
-        } else if vocab.memory_strength >= 0.4 {
+        } else if vocab.retrievability >= 0.4 {
             good += 1;
-        } else if vocab.memory_strength >= 0.2 {
+        } else if vocab.retrievability >= 0.2 {
             weak += 1;
         } else {
             critical += 1;
@@ -1,15 +1,33 @@
 use axum::{
-    extract::{Multipart, State},
+    extract::{
+        multipart::{Field, MultipartError},
+        DefaultBodyLimit, FromRef, Multipart, Path, State,
+    },
     http::StatusCode,
     response::IntoResponse,
-    routing::post,
+    routing::{get, post},
     Json, Router,
 };
 use serde::Serialize;
+use std::path::PathBuf;
 use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
 
-use crate::auth::OptionalAuthUser;
+use crate::auth::{AuthUser, OptionalAuthUser};
+use crate::db::{self, DbPool, NewUploadedVideo, UploadedVideo};
+use crate::models::ApiResponse;
+use crate::services::language;
 use crate::services::r2::R2Client;
+use crate::services::subtitle_parser::{self, SubtitleFormat};
+
+/// Video IDs of uploaded videos start with this; YouTube IDs never do
+pub const UPLOADED_VIDEO_PREFIX: &str = "upload_";
+/// Largest subtitle file accepted (2MB)
+const MAX_SUBTITLE_BYTES: usize = 2 * 1024 * 1024;
+/// Largest media file accepted (500MB)
+const MAX_MEDIA_BYTES: usize = 500 * 1024 * 1024;
+/// Largest title or language field accepted
+const MAX_TEXT_FIELD_BYTES: usize = 4 * 1024;
 
 pub fn routes(r2_client: Arc<R2Client>) -> Router {
     Router::new()
@@ -17,6 +35,35 @@ pub fn routes(r2_client: Arc<R2Client>) -> Router {
         .with_state(r2_client)
 }
 
+#[derive(Clone)]
+pub struct VideoUploadState {
+    pool: DbPool,
+    /// Media files need R2; subtitle-only uploads work without it
+    r2_client: Option<Arc<R2Client>>,
+}
+
+impl FromRef<VideoUploadState> for DbPool {
+    fn from_ref(state: &VideoUploadState) -> DbPool {
+        state.pool.clone()
+    }
+}
+
+/// Uploaded videos, merged into the /video routes
+pub fn video_routes(db_pool: DbPool, r2_client: Option<Arc<R2Client>>) -> Router {
+    Router::new()
+        .route(
+            "/upload",
+            post(upload_video).layer(DefaultBodyLimit::max(MAX_MEDIA_BYTES + MAX_SUBTITLE_BYTES + 64 * 1024)),
+        )
+        .route("/uploads", get(list_uploaded_videos))
+        .route("/uploads/:video_id", get(get_uploaded_video).delete(delete_uploaded_video))
+        .with_state(VideoUploadState { pool: db_pool, r2_client })
+}
+
+pub fn is_uploaded_video_id(video_id: &str) -> bool {
+    video_id.starts_with(UPLOADED_VIDEO_PREFIX)
+}
+
 #[derive(Debug, Serialize)]
 pub struct UploadResponse {
     pub url: String,
@@ -111,3 +158,317 @@ async fn upload_image(
     )
         .into_response()
 }
+
+struct UploadedFile {
+    file_name: String,
+    data: Vec<u8>,
+}
+
+/// A media file as uploaded, kept on disk until it is sent to R2
+struct MediaFile {
+    file_name: String,
+    content_type: String,
+    spooled: SpooledFile,
+}
+
+/// Temporary file that is removed when dropped
+struct SpooledFile {
+    path: PathBuf,
+}
+
+impl Drop for SpooledFile {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            tracing::warn!("Failed to remove spooled upload {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// Upload a subtitle file (SRT, VTT, ASS or YouTube json3/srv3) and optionally the recording it belongs to
+/// Multipart fields: "subtitles" (required), "media" (audio/video, needs R2), "title", "lang"
+async fn upload_video(
+    State(state): State<VideoUploadState>,
+    auth: AuthUser,
+    mut multipart: Multipart,
+) -> Json<ApiResponse<UploadedVideo>> {
+    let mut subtitles_file = None;
+    let mut media_file = None;
+    let mut title = None;
+    let mut lang = None;
+
+    loop {
+        let mut field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(e) => return Json(ApiResponse::error(format!("Invalid upload: {}", e))),
+        };
+        let name = field.name().unwrap_or("").to_string();
+        let file_name = field.file_name().unwrap_or("").to_string();
+        let content_type = field.content_type().unwrap_or("").to_string();
+
+        // Reject a field before reading it where possible, and stop reading once it is too large
+        let (limit, too_large) = match name.as_str() {
+            "subtitles" => (MAX_SUBTITLE_BYTES, "Subtitle file too large (max 2MB)"),
+            "media" => {
+                if !content_type.starts_with("video/") && !content_type.starts_with("audio/") {
+                    return Json(ApiResponse::error("Only audio and video files are allowed"));
+                }
+                if state.r2_client.is_none() {
+                    return Json(ApiResponse::error("Media storage is not configured"));
+                }
+                (MAX_MEDIA_BYTES, "Media file too large (max 500MB)")
+            }
+            "title" | "lang" => (MAX_TEXT_FIELD_BYTES, "Title or language too long"),
+            _ => continue,
+        };
+
+        // Media can be hundreds of megabytes, so it goes to disk rather than memory
+        if name == "media" {
+            match spool_field(&mut field, limit).await {
+                Ok(Some(spooled)) => media_file = Some(MediaFile { file_name, content_type, spooled }),
+                Ok(None) => return Json(ApiResponse::error(too_large)),
+                Err(e) => {
+                    tracing::error!("Failed to read upload field {}: {}", name, e);
+                    return Json(ApiResponse::error("Failed to read file"));
+                }
+            }
+            continue;
+        }
+
+        let data = match read_field(&mut field, limit).await {
+            Ok(Some(data)) => data,
+            Ok(None) => return Json(ApiResponse::error(too_large)),
+            Err(e) => {
+                tracing::error!("Failed to read upload field {}: {}", name, e);
+                return Json(ApiResponse::error("Failed to read file"));
+            }
+        };
+
+        match name.as_str() {
+            "subtitles" => subtitles_file = Some(UploadedFile { file_name, data }),
+            "title" => title = Some(String::from_utf8_lossy(&data).trim().to_string()),
+            _ => lang = Some(String::from_utf8_lossy(&data).trim().to_string()),
+        }
+    }
+
+    let Some(subtitles_file) = subtitles_file else {
+        return Json(ApiResponse::error("No subtitle file provided"));
+    };
+
+    let content = String::from_utf8_lossy(&subtitles_file.data);
+    let Some(format) = SubtitleFormat::from_file_name(&subtitles_file.file_name).or_else(|| SubtitleFormat::detect(&content)) else {
+        return Json(ApiResponse::error("Unsupported subtitle format (use SRT, VTT, ASS, json3 or srv3)"));
+    };
+    let subtitles = match subtitle_parser::parse(&content, format) {
+        Ok(subtitles) if !subtitles.is_empty() => subtitles,
+        Ok(_) => return Json(ApiResponse::error("No subtitles found in the file")),
+        Err(e) => return Json(ApiResponse::error(format!("Failed to parse subtitles: {}", e))),
+    };
+
+    let lang = match lang.filter(|l| !l.is_empty()) {
+        Some(lang) => match language::canonical_code(&lang) {
+            Some(code) => code.to_string(),
+            None => return Json(ApiResponse::error(format!("Unsupported language: {}", lang))),
+        },
+        None => db::get_learner_profile(&state.pool, &auth.user_id).await.unwrap_or_default().target_language,
+    };
+    let title = title
+        .filter(|t| !t.is_empty())
+        .or_else(|| media_file.as_ref().map(|m| m.file_name.clone()).filter(|n| !n.is_empty()))
+        .unwrap_or_else(|| subtitles_file.file_name.clone());
+
+    let video_id = format!("{}{}", UPLOADED_VIDEO_PREFIX, uuid::Uuid::new_v4().simple());
+
+    // Store the recording first so a failed upload leaves nothing behind in the database, and
+    // remove it again if the database insert fails
+    let mut media = None;
+    if let Some(file) = media_file {
+        let Some(r2_client) = state.r2_client.as_ref() else {
+            return Json(ApiResponse::error("Media storage is not configured"));
+        };
+
+        let key = format!("media/{}/{}.{}", auth.user_id, video_id, media_extension(&file.content_type));
+        match r2_client.upload_file(&key, &file.spooled.path, &file.content_type).await {
+            Ok(url) => media = Some((key, url, file.content_type)),
+            Err(e) => {
+                tracing::error!("Failed to upload media to R2: {}", e);
+                return Json(ApiResponse::error("Failed to upload media file"));
+            }
+        }
+    }
+
+    let subtitles_json = match serde_json::to_string(&subtitles) {
+        Ok(json) => json,
+        Err(e) => return Json(ApiResponse::error(format!("Failed to store subtitles: {}", e))),
+    };
+    let duration = subtitles.iter().map(|s| s.end).fold(0.0, f64::max);
+
+    let new_video = NewUploadedVideo {
+        video_id: &video_id,
+        title: &title,
+        duration,
+        lang: &lang,
+        subtitle_format: format.as_str(),
+        subtitles_json: &subtitles_json,
+        media_key: media.as_ref().map(|(key, _, _)| key.as_str()),
+        media_url: media.as_ref().map(|(_, url, _)| url.as_str()),
+        media_type: media.as_ref().map(|(_, _, content_type)| content_type.as_str()),
+    };
+    if let Err(e) = db::save_uploaded_video(&state.pool, &auth.user_id, &new_video).await {
+        // Nothing will point at the stored recording, so remove it
+        if let (Some((key, _, _)), Some(r2_client)) = (media.as_ref(), state.r2_client.as_ref()) {
+            if let Err(e) = r2_client.delete(key).await {
+                tracing::warn!("Failed to delete orphaned media {} from R2: {}", key, e);
+            }
+        }
+        return Json(ApiResponse::error(format!("Failed to save video: {}", e)));
+    }
+
+    match db::get_uploaded_video(&state.pool, &auth.user_id, &video_id).await {
+        Ok(Some(video)) => Json(ApiResponse::success(video)),
+        Ok(None) => Json(ApiResponse::error("Video not found after upload")),
+        Err(e) => Json(ApiResponse::error(format!("Failed to get video: {}", e))),
+    }
+}
+
+/// Extension for a stored recording, from its content type; the client's file name is not trusted
+fn media_extension(content_type: &str) -> &'static str {
+    match content_type {
+        "video/mp4" => "mp4",
+        "video/webm" => "webm",
+        "video/quicktime" => "mov",
+        "video/x-matroska" => "mkv",
+        "audio/mpeg" | "audio/mp3" => "mp3",
+        "audio/mp4" | "audio/x-m4a" => "m4a",
+        "audio/aac" => "aac",
+        "audio/wav" | "audio/x-wav" | "audio/wave" => "wav",
+        "audio/ogg" => "ogg",
+        "audio/webm" => "weba",
+        "audio/flac" => "flac",
+        _ => "bin",
+    }
+}
+
+/// Write the field to a temporary file, or return None as soon as it grows past `limit` bytes
+async fn spool_field(field: &mut Field<'_>, limit: usize) -> anyhow::Result<Option<SpooledFile>> {
+    let spooled = SpooledFile {
+        path: std::env::temp_dir().join(format!("upload-{}", uuid::Uuid::new_v4().simple())),
+    };
+    let mut file = tokio::fs::File::create(&spooled.path).await?;
+    let mut size = 0;
+    while let Some(chunk) = field.chunk().await? {
+        size += chunk.len();
+        if size > limit {
+            return Ok(None);
+        }
+        file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
+    Ok(Some(spooled))
+}
+
+/// The field's contents, or None as soon as it grows past `limit` bytes
+async fn read_field(field: &mut Field<'_>, limit: usize) -> Result<Option<Vec<u8>>, MultipartError> {
+    let mut data = Vec::new();
+    while let Some(chunk) = field.chunk().await? {
+        if data.len() + chunk.len() > limit {
+            return Ok(None);
+        }
+        data.extend_from_slice(&chunk);
+    }
+    Ok(Some(data))
+}
+
+async fn list_uploaded_videos(State(pool): State<DbPool>, auth: AuthUser) -> Json<ApiResponse<Vec<UploadedVideo>>> {
+    match db::list_uploaded_videos(&pool, &auth.user_id).await {
+        Ok(videos) => Json(ApiResponse::success(videos)),
+        Err(e) => Json(ApiResponse::error(format!("Failed to list videos: {}", e))),
+    }
+}
+
+async fn get_uploaded_video(
+    State(pool): State<DbPool>,
+    auth: AuthUser,
+    Path(video_id): Path<String>,
+) -> Json<ApiResponse<UploadedVideo>> {
+    match db::get_uploaded_video(&pool, &auth.user_id, &video_id).await {
+        Ok(Some(video)) => Json(ApiResponse::success(video)),
+        Ok(None) => Json(ApiResponse::error("Video not found")),
+        Err(e) => Json(ApiResponse::error(format!("Failed to get video: {}", e))),
+    }
+}
+
+/// Delete an uploaded video and its media file; notes and vocabulary saved from it are kept
+async fn delete_uploaded_video(
+    State(state): State<VideoUploadState>,
+    auth: AuthUser,
+    Path(video_id): Path<String>,
+) -> Json<ApiResponse<()>> {
+    let video = match db::delete_uploaded_video(&state.pool, &auth.user_id, &video_id).await {
+        Ok(Some(video)) => video,
+        Ok(None) => return Json(ApiResponse::error("Video not found")),
+        Err(e) => return Json(ApiResponse::error(format!("Failed to delete video: {}", e))),
+    };
+
+    if let (Some(key), Some(r2_client)) = (video.media_key.as_deref(), state.r2_client.as_ref()) {
+        if let Err(e) = r2_client.delete(key).await {
+            tracing::warn!("Failed to delete media {} from R2: {}", key, e);
+        }
+    }
+
+    Json(ApiResponse::success(()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::test_support::{post_multipart, unreachable_db};
+
+    fn app() -> Router {
+        let pool = unreachable_db();
+        crate::routes::video::routes(pool.clone()).merge(video_routes(pool, None))
+    }
+
+    #[tokio::test]
+    async fn test_rejects_unknown_subtitle_format() {
+        let body = post_multipart(app(), "/upload", &[("subtitles", "notes.txt", "just some text"), ("lang", "", "en")]).await;
+        assert_eq!(body["success"], false);
+        assert!(body["error"].as_str().unwrap().contains("Unsupported subtitle format"));
+    }
+
+    #[tokio::test]
+    async fn test_rejects_oversized_subtitle_file() {
+        let huge = "a".repeat(MAX_SUBTITLE_BYTES + 1);
+        let body = post_multipart(app(), "/upload", &[("subtitles", "talk.srt", &huge)]).await;
+        assert_eq!(body["error"], "Subtitle file too large (max 2MB)");
+    }
+
+    #[tokio::test]
+    async fn test_media_must_be_audio_or_video() {
+        let srt = "1\n00:00:01,000 --> 00:00:02,000\nHello\n";
+        let body = post_multipart(
+            app(),
+            "/upload",
+            &[("subtitles", "talk.srt", srt), ("media", "talk.mp3", "ID3"), ("lang", "", "en")],
+        )
+        .await;
+        assert_eq!(body["error"], "Only audio and video files are allowed");
+    }
+
+    #[tokio::test]
+    async fn test_language_is_matched_case_insensitively() {
+        let srt = "1\n00:00:01,000 --> 00:00:02,000\nHello\n";
+        // A supported code gets past validation to the database, which is unreachable here
+        let body = post_multipart(app(), "/upload", &[("subtitles", "talk.srt", srt), ("lang", "", "zh-Hant")]).await;
+        assert!(body["error"].as_str().unwrap().starts_with("Failed to save video"), "{}", body);
+        let body = post_multipart(app(), "/upload", &[("subtitles", "talk.srt", srt), ("lang", "", "xx-yy")]).await;
+        assert_eq!(body["error"], "Unsupported language: xx-yy");
+    }
+
+    #[test]
+    fn test_media_extension_comes_from_content_type() {
+        assert_eq!(media_extension("video/mp4"), "mp4");
+        assert_eq!(media_extension("audio/mpeg"), "mp3");
+        assert_eq!(media_extension("video/x-evil"), "bin");
+    }
+}
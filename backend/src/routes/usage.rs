@@ -1,28 +1,71 @@
 use axum::{
-    extract::State,
+    extract::{Query, State},
     routing::get,
     Json, Router,
 };
+use serde::Deserialize;
 
-use crate::auth::OptionalAuthUser;
-use crate::db::{self, DbPool, DailyUsageStatus};
+use crate::auth::{AdminUser, OptionalAuthUser};
+use crate::db::{self, AiUsageBreakdown, DbPool, DailyUsageStatus};
 use crate::models::ApiResponse;
 
+const AI_USAGE_DEFAULT_DAYS: i32 = 30;
+const AI_USAGE_MAX_DAYS: i32 = 365;
+
 pub fn routes(db_pool: DbPool) -> Router {
     Router::new()
         .route("/status", get(get_usage_status))
+        .route("/ai", get(get_ai_usage))
+        .route("/ai/all", get(get_all_ai_usage))
         .with_state(db_pool)
 }
 
-/// Get current usage status for the user
+/// Get current usage status for the user: video parses, AI units and paid transcript sources
 async fn get_usage_status(
     State(pool): State<DbPool>,
     auth: OptionalAuthUser,
 ) -> Json<ApiResponse<DailyUsageStatus>> {
     let user_id = auth.user_id_or_default();
 
-    match db::get_daily_usage(&pool, user_id).await {
+    match db::get_daily_usage(&pool, user_id, auth.tier_or_default()).await {
         Ok(status) => Json(ApiResponse::success(status)),
         Err(e) => Json(ApiResponse::error(format!("Failed to get usage status: {}", e))),
     }
 }
+
+#[derive(Deserialize)]
+pub struct AiUsageQuery {
+    days: Option<i32>,
+}
+
+impl AiUsageQuery {
+    fn days(&self) -> i32 {
+        self.days.unwrap_or(AI_USAGE_DEFAULT_DAYS).clamp(1, AI_USAGE_MAX_DAYS)
+    }
+}
+
+/// Tokens and estimated cost of the user's AI calls, per endpoint and per model
+async fn get_ai_usage(
+    State(pool): State<DbPool>,
+    auth: OptionalAuthUser,
+    Query(query): Query<AiUsageQuery>,
+) -> Json<ApiResponse<AiUsageBreakdown>> {
+    let user_id = auth.user_id_or_default();
+
+    match db::get_ai_usage_breakdown(&pool, Some(user_id), query.days()).await {
+        Ok(breakdown) => Json(ApiResponse::success(breakdown)),
+        Err(e) => Json(ApiResponse::error(format!("Failed to get AI usage: {}", e))),
+    }
+}
+
+/// Same breakdown across all users - admin only
+async fn get_all_ai_usage(
+    State(pool): State<DbPool>,
+    _admin: AdminUser,
+    Query(query): Query<AiUsageQuery>,
+) -> Json<ApiResponse<AiUsageBreakdown>> {
+    match db::get_ai_usage_breakdown(&pool, None, query.days()).await {
+        Ok(breakdown) => Json(ApiResponse::success(breakdown)),
+        Err(e) => Json(ApiResponse::error(format!("Failed to get AI usage: {}", e))),
+    }
+}
@@ -1,15 +1,28 @@
 use axum::{
-    extract::{Path, Query, State},
-    routing::{get, post},
+    extract::{Extension, Path, Query, State},
+    http::header,
+    response::{IntoResponse, Response},
+    routing::{delete, get, post},
     Json, Router,
 };
+use chrono::Utc;
 use serde::{Deserialize, Serialize};
 
-use crate::auth::OptionalAuthUser;
+use crate::auth::{AdminUser, OptionalAuthUser};
 use crate::db::{self, DbPool};
-use crate::models::{ApiResponse, SubtitleResponse, VideoInfo};
+use crate::models::{ApiResponse, LearnerProfile, Subtitle, SubtitleResponse, VideoInfo};
+use crate::routes::upload;
+use crate::services::language;
+use crate::services::transcript::{self, FetchContext, SourceHealth};
+use crate::services::segmentation::{self, SubtitleTrack};
+use crate::services::subtitle_export::{self, ExportFormat};
+use crate::services::translation;
+use crate::services::usage_ledger::{SharedLedger, UsageLedger};
 use crate::services::youtube;
 
+/// Default number of days cached subtitles are served before being re-fetched
+const SUBTITLE_CACHE_TTL_DAYS_DEFAULT: i64 = 30;
+
 #[derive(Deserialize)]
 pub struct ParseRequest {
     url: String,
@@ -18,6 +31,36 @@ pub struct ParseRequest {
 #[derive(Deserialize)]
 pub struct SubtitleQuery {
     lang: Option<String>,
+    /// Bypass the subtitle cache and re-fetch from the sources (logged-in users only)
+    #[serde(default)]
+    refresh: bool,
+    /// Attach stored translations; new ones are made by the metered /api/ai/translate
+    #[serde(default)]
+    bilingual: bool,
+    /// "raw" (default): cues as the source delivered them, or "sentences": one cue per sentence
+    track: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ExportQuery {
+    /// "srt" (default), "vtt" or "txt"
+    format: Option<String>,
+    lang: Option<String>,
+    /// Put stored translations under each line
+    #[serde(default)]
+    bilingual: bool,
+    /// "raw" (default) or "sentences"
+    track: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct PurgeCacheQuery {
+    lang: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct PurgeCacheResponse {
+    pub deleted: u64,
 }
 
 #[derive(Serialize)]
@@ -36,11 +79,15 @@ pub fn routes(db_pool: DbPool) -> Router {
     Router::new()
         .route("/parse", post(parse_video))
         .route("/:video_id/subtitles", get(get_subtitles))
+        .route("/:video_id/subtitles/export", get(export_subtitles))
+        .route("/:video_id/subtitles/cache", delete(purge_subtitle_cache))
+        .route("/sources/health", get(get_source_health))
         .with_state(db_pool)
 }
 
 async fn parse_video(
     State(pool): State<DbPool>,
+    Extension(ledger): Extension<SharedLedger>,
     auth: OptionalAuthUser,
     Json(payload): Json<ParseRequest>,
 ) -> Json<ApiResponse<ParseVideoResponse>> {
@@ -95,14 +142,14 @@ async fn parse_video(
         remaining
     };
 
-    // Fetch video info (pass user_id for Apify rate limiting)
-    let user_id_opt = if is_logged_in { Some(user_id) } else { None };
+    // Fetch video info (paid fallbacks count against the user's daily source quota)
     let has_invited = if is_logged_in {
         db::get_bonus_quota(&pool, user_id).await.unwrap_or(0) > 0
     } else {
         false
     };
-    match youtube::fetch_video_info(&video_id, user_id_opt, has_invited).await {
+    let ctx = FetchContext { pool: &pool, ledger: ledger.as_ref(), user_id, has_invited };
+    match transcript::fetch_video_info(&ctx, &video_id).await {
         Ok(info) => {
             // Increment usage count on success (skip for demo video)
             if !is_demo {
@@ -127,28 +174,260 @@ async fn parse_video(
 
 async fn get_subtitles(
     State(pool): State<DbPool>,
+    Extension(ledger): Extension<SharedLedger>,
     auth: OptionalAuthUser,
     Path(video_id): Path<String>,
     Query(query): Query<SubtitleQuery>,
 ) -> Json<ApiResponse<SubtitleResponse>> {
-    let lang = query.lang.unwrap_or_else(|| "en".to_string());
+    let bilingual = query.bilingual;
+    let user_id = auth.user_id_or_default();
+    let profile = db::get_learner_profile(&pool, user_id).await.unwrap_or_default();
+    let Json(mut response) = load_subtitles(&pool, ledger.as_ref(), &auth, &profile, video_id, query).await;
+
+    // Only translations already made are attached; new ones go through the metered /api/ai/translate
+    if bilingual {
+        if let Some(data) = response.data.as_mut() {
+            let key = response_track(data).translation_key(&data.video_id, &data.language);
+            translation::apply_stored_translations(&pool, &key, &profile.native_language, &mut data.subtitles).await;
+        }
+    }
+
+    Json(response)
+}
+
+/// Download subtitles as an SRT, WebVTT or text file, optionally with stored translations
+/// Only translations already made are included; this never calls the AI
+async fn export_subtitles(
+    State(pool): State<DbPool>,
+    Extension(ledger): Extension<SharedLedger>,
+    auth: OptionalAuthUser,
+    Path(video_id): Path<String>,
+    Query(query): Query<ExportQuery>,
+) -> Response {
+    let format_name = query.format.as_deref().unwrap_or("srt");
+    let Some(format) = ExportFormat::from_name(format_name) else {
+        return Json(ApiResponse::<()>::error(format!("Unsupported export format: {}", format_name))).into_response();
+    };
+
+    let profile = db::get_learner_profile(&pool, auth.user_id_or_default()).await.unwrap_or_default();
+    let subtitle_query = SubtitleQuery {
+        lang: query.lang,
+        refresh: false,
+        bilingual: false,
+        track: query.track,
+    };
+    let Json(response) = load_subtitles(&pool, ledger.as_ref(), &auth, &profile, video_id, subtitle_query).await;
+    let Some(mut data) = response.data else {
+        return Json(response).into_response();
+    };
+
+    if query.bilingual {
+        let key = response_track(&data).translation_key(&data.video_id, &data.language);
+        translation::apply_stored_translations(&pool, &key, &profile.native_language, &mut data.subtitles).await;
+    }
+
+    let body = subtitle_export::render(&data.subtitles, format, query.bilingual);
+    let file_name = format!(
+        "{}.{}{}.{}",
+        data.video_id,
+        data.language,
+        if query.bilingual { format!("-{}", profile.native_language) } else { String::new() },
+        format.extension()
+    );
+
+    (
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", file_name)),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+fn response_track(response: &SubtitleResponse) -> SubtitleTrack {
+    SubtitleTrack::from_name(&response.track).unwrap_or_default()
+}
+
+async fn load_subtitles(
+    pool: &DbPool,
+    ledger: &dyn UsageLedger,
+    auth: &OptionalAuthUser,
+    profile: &LearnerProfile,
+    video_id: String,
+    query: SubtitleQuery,
+) -> Json<ApiResponse<SubtitleResponse>> {
+    // Default to the language the user is learning
+    // The code is used in cache keys and passed on to the transcript sources, so only known codes get through
+    let lang = query.lang.unwrap_or_else(|| profile.target_language.clone());
+    let Some(lang) = language::canonical_code(&lang).map(str::to_string) else {
+        return Json(ApiResponse::error(format!("Unsupported language: {}", lang)));
+    };
+    let Some(track) = query.track.as_deref().map(SubtitleTrack::from_name).unwrap_or(Some(SubtitleTrack::Raw)) else {
+        return Json(ApiResponse::error("Unknown subtitle track (use raw or sentences)"));
+    };
     let user_id = auth.user_id_or_default();
     let is_logged_in = user_id != "default";
-    let user_id_opt = if is_logged_in { Some(user_id) } else { None };
     let has_invited = if is_logged_in {
-        db::get_bonus_quota(&pool, user_id).await.unwrap_or(0) > 0
+        db::get_bonus_quota(pool, user_id).await.unwrap_or(0) > 0
     } else {
         false
     };
 
+    // Uploaded videos have exactly the subtitles their owner provided
+    if upload::is_uploaded_video_id(&video_id) {
+        return match db::get_uploaded_subtitles(pool, user_id, &video_id).await {
+            Ok(Some((language, subtitles_json))) => match serde_json::from_str::<Vec<Subtitle>>(&subtitles_json) {
+                Ok(subtitles) => Json(ApiResponse::success(SubtitleResponse {
+                    video_id,
+                    subtitles: select_track(track, subtitles, None),
+                    language,
+                    source: "upload".to_string(),
+                    cached: false,
+                    track: track.as_str().to_string(),
+                })),
+                Err(e) => Json(ApiResponse::error(format!("Failed to read uploaded subtitles: {}", e))),
+            },
+            Ok(None) => Json(ApiResponse::error("Video not found")),
+            Err(e) => Json(ApiResponse::error(format!("Failed to get subtitles: {}", e))),
+        };
+    }
+
+    // Serve from cache first unless a refresh is requested
+    let refresh = query.refresh && is_logged_in;
+    let cached = match db::get_cached_subtitles(pool, &video_id, &lang).await {
+        Ok(cached) => cached,
+        Err(e) => {
+            tracing::warn!("Failed to read subtitle cache for {}: {}", video_id, e);
+            None
+        }
+    };
+    let cached = cached.and_then(|c| {
+        let sentences = c
+            .sentences_json
+            .as_deref()
+            .and_then(|json| serde_json::from_str::<Vec<Subtitle>>(json).ok());
+        serde_json::from_str::<Vec<Subtitle>>(&c.subtitles_json)
+            .ok()
+            .map(|subtitles| (c.source, c.fetched_at, subtitles, sentences))
+    });
+
+    if let Some((source, fetched_at, subtitles, sentences)) = &cached {
+        if !refresh && is_cache_fresh(*fetched_at, Utc::now(), subtitle_cache_ttl_days()) {
+            return Json(ApiResponse::success(SubtitleResponse {
+                video_id,
+                subtitles: select_track(track, subtitles.clone(), sentences.clone()),
+                language: lang,
+                source: source.clone(),
+                cached: true,
+                track: track.as_str().to_string(),
+            }));
+        }
+    }
+
     // Try to fetch subtitles in requested language from YouTube
     // Note: For Chinese, if YouTube doesn't have it, frontend will use on-demand AI translation
-    match youtube::fetch_subtitles(&video_id, &lang, user_id_opt, has_invited).await {
-        Ok(subtitles) => Json(ApiResponse::success(SubtitleResponse {
-            video_id,
-            subtitles,
-            language: lang,
-        })),
-        Err(e) => Json(ApiResponse::error(format!("No {} subtitles available: {}", lang, e))),
+    let ctx = FetchContext { pool, ledger, user_id, has_invited };
+    match transcript::fetch_subtitles(&ctx, &video_id, &lang).await {
+        Ok((subtitles, source)) => {
+            let sentences = segmentation::to_sentences(&subtitles);
+            // Save both tracks to cache (ignore errors); empty results are not worth keeping
+            if !subtitles.is_empty() {
+                if let (Ok(subtitles_json), Ok(sentences_json)) = (serde_json::to_string(&subtitles), serde_json::to_string(&sentences)) {
+                    let _ = db::save_subtitles_cache(pool, &video_id, &lang, source, &subtitles_json, &sentences_json).await;
+                }
+            }
+            Json(ApiResponse::success(SubtitleResponse {
+                video_id,
+                subtitles: select_track(track, subtitles, Some(sentences)),
+                language: lang,
+                source: source.to_string(),
+                cached: false,
+                track: track.as_str().to_string(),
+            }))
+        }
+        Err(e) => match cached {
+            // A stale copy is better than nothing when every source fails
+            Some((source, _, subtitles, sentences)) => {
+                tracing::warn!("Serving stale cached subtitles for {}: {}", video_id, e);
+                Json(ApiResponse::success(SubtitleResponse {
+                    video_id,
+                    subtitles: select_track(track, subtitles, sentences),
+                    language: lang,
+                    source,
+                    cached: true,
+                    track: track.as_str().to_string(),
+                }))
+            }
+            None => Json(ApiResponse::error(format!("No {} subtitles available: {}", lang, e))),
+        },
+    }
+}
+
+/// The requested track; sentences are segmented here when none were stored
+fn select_track(track: SubtitleTrack, raw: Vec<Subtitle>, sentences: Option<Vec<Subtitle>>) -> Vec<Subtitle> {
+    match track {
+        SubtitleTrack::Raw => raw,
+        SubtitleTrack::Sentences => sentences.unwrap_or_else(|| segmentation::to_sentences(&raw)),
+    }
+}
+
+/// Purge cached subtitles for a video - admin only
+async fn purge_subtitle_cache(
+    State(pool): State<DbPool>,
+    admin: AdminUser,
+    Path(video_id): Path<String>,
+    Query(query): Query<PurgeCacheQuery>,
+) -> Json<ApiResponse<PurgeCacheResponse>> {
+    match db::purge_subtitles_cache(&pool, &video_id, query.lang.as_deref()).await {
+        Ok(deleted) => {
+            tracing::info!("Subtitle cache purged for {} by {} ({} rows)", video_id, admin.0.user_id, deleted);
+            Json(ApiResponse::success(PurgeCacheResponse { deleted }))
+        }
+        Err(e) => Json(ApiResponse::error(format!("Failed to purge subtitle cache: {}", e))),
+    }
+}
+
+/// Subtitle cache TTL in days, configurable via SUBTITLE_CACHE_TTL_DAYS
+fn subtitle_cache_ttl_days() -> i64 {
+    std::env::var("SUBTITLE_CACHE_TTL_DAYS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(SUBTITLE_CACHE_TTL_DAYS_DEFAULT)
+}
+
+/// Cached subtitles are served until exactly `ttl_days` after they were fetched
+fn is_cache_fresh(fetched_at: chrono::DateTime<Utc>, now: chrono::DateTime<Utc>, ttl_days: i64) -> bool {
+    now - fetched_at < chrono::Duration::days(ttl_days)
+}
+
+/// Circuit state of each transcript source - admin only
+async fn get_source_health(_admin: AdminUser) -> Json<ApiResponse<Vec<SourceHealth>>> {
+    Json(ApiResponse::success(transcript::source_health()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::test_support::{get_json, unreachable_db, with_memory_ledger};
+
+    #[tokio::test]
+    async fn test_rejects_unsupported_subtitle_language() {
+        let app = with_memory_ledger(routes(unreachable_db()));
+        let body = get_json(app, "/dQw4w9WgXcQ/subtitles?lang=en.*%2C..%2Fx").await;
+        assert_eq!(body["success"], false);
+        assert_eq!(body["error"], "Unsupported language: en.*,../x");
+    }
+
+    #[test]
+    fn test_cache_expires_at_the_ttl() {
+        let fetched_at = Utc::now();
+        let expiry = fetched_at + chrono::Duration::days(30);
+
+        assert!(is_cache_fresh(fetched_at, fetched_at, 30));
+        assert!(is_cache_fresh(fetched_at, expiry - chrono::Duration::seconds(1), 30));
+        assert!(!is_cache_fresh(fetched_at, expiry, 30));
+        // A TTL of 0 turns the cache off
+        assert!(!is_cache_fresh(fetched_at, fetched_at, 0));
     }
 }
@@ -5,16 +5,21 @@ use axum::{
 };
 use serde::{Deserialize, Serialize};
 
+use crate::auth::quota::{ops, AiQuota};
 use crate::auth::{AuthUser, OptionalAuthUser};
-use crate::db::{self, DbPool, SavedVocabulary};
+use crate::db::{self, DbPool, ReviewSource, SavedVocabulary, UndoneReview, VocabularySource};
 use crate::models::ApiResponse;
 use crate::services::ai::{get_ai_provider, ReviewQuestion, ReviewEvaluation, VocabForReview, MemoryCard};
+use crate::services::ai_usage::track;
+use crate::services::review_queue::{self, QueueOptions, ReviewQueue};
 
 pub fn routes(db_pool: DbPool) -> Router {
     Router::new()
         .route("/save", post(save_vocabulary))
         .route("/list", get(list_vocabulary))
+        .route("/queue", get(review_queue))
         .route("/review", post(review_vocabulary))
+        .route("/review/undo", post(undo_review))
         .route("/delete/{id}", delete(delete_vocabulary))
         .route("/check/{word}", get(check_vocabulary))
         .route("/ai-review", post(start_ai_review))
@@ -32,6 +37,8 @@ pub struct SaveVocabularyRequest {
     example: Option<String>,
     source_video_id: Option<String>,
     source_sentence: Option<String>,
+    /// Seconds into the video, e.g. the start of the clicked word
+    source_timestamp: Option<f64>,
 }
 
 #[derive(Serialize)]
@@ -53,8 +60,11 @@ async fn save_vocabulary(
         &payload.meaning,
         &payload.level,
         payload.example.as_deref(),
-        payload.source_video_id.as_deref(),
-        payload.source_sentence.as_deref(),
+        VocabularySource {
+            video_id: payload.source_video_id.as_deref(),
+            sentence: payload.source_sentence.as_deref(),
+            timestamp: payload.source_timestamp.filter(|t| t.is_finite() && *t >= 0.0),
+        },
     ).await {
         Ok(id) => {
             // Record learning statistics
@@ -93,6 +103,42 @@ async fn list_vocabulary(
     }
 }
 
+#[derive(Deserialize)]
+pub struct QueueQuery {
+    limit: Option<usize>,
+    new_limit: Option<usize>,
+    bury_siblings: Option<bool>,
+}
+
+/// Due cards as a review session: new, learning and review cards interleaved, within the
+/// daily limits, with at most one card per source sentence
+async fn review_queue(
+    State(pool): State<DbPool>,
+    auth: OptionalAuthUser,
+    axum::extract::Query(query): axum::extract::Query<QueueQuery>,
+) -> Json<ApiResponse<ReviewQueue>> {
+    let user_id = auth.user_id_or_default();
+    let options = QueueOptions {
+        limit: query.limit.unwrap_or(review_queue::DEFAULT_SESSION_SIZE).clamp(1, review_queue::MAX_SESSION_SIZE),
+        new_limit: query.new_limit,
+        bury_siblings: query.bury_siblings.unwrap_or(true),
+    };
+
+    let queue = async {
+        let due = db::get_vocabulary_list(&pool, user_id, true).await?;
+        let reviewed_today = if options.bury_siblings {
+            db::get_sentences_reviewed_today(&pool, user_id).await?
+        } else {
+            Vec::new()
+        };
+        anyhow::Ok(review_queue::build_queue(due, &reviewed_today, &options))
+    };
+    match queue.await {
+        Ok(queue) => Json(ApiResponse::success(queue)),
+        Err(e) => Json(ApiResponse::error(format!("Failed to build review queue: {}", e))),
+    }
+}
+
 #[derive(Deserialize)]
 pub struct ReviewRequest {
     vocab_id: i32,
@@ -106,16 +152,28 @@ async fn review_vocabulary(
 ) -> Json<ApiResponse<()>> {
     let user_id = auth.user_id_or_default();
 
-    match db::review_vocabulary(&pool, user_id, payload.vocab_id, payload.quality).await {
-        Ok(_) => {
-            // Record review statistics (quality >= 2 is considered correct)
-            let _ = db::record_review(&pool, user_id, payload.quality >= 2).await;
-            Json(ApiResponse::success(()))
-        }
+    // Quality >= 2 is counted as correct in the statistics
+    let is_correct = payload.quality >= 2;
+    match db::review_vocabulary(&pool, user_id, payload.vocab_id, payload.quality, is_correct, ReviewSource::Manual).await {
+        Ok(_) => Json(ApiResponse::success(())),
         Err(e) => Json(ApiResponse::error(format!("Failed to review: {}", e))),
     }
 }
 
+/// Undo the most recent review, restoring the card's schedule and the review statistics
+async fn undo_review(
+    State(pool): State<DbPool>,
+    auth: OptionalAuthUser,
+) -> Json<ApiResponse<UndoneReview>> {
+    let user_id = auth.user_id_or_default();
+
+    match db::undo_last_review(&pool, user_id).await {
+        Ok(Some(undone)) => Json(ApiResponse::success(undone)),
+        Ok(None) => Json(ApiResponse::error_with_code("NOTHING_TO_UNDO", "No review to undo")),
+        Err(e) => Json(ApiResponse::error(format!("Failed to undo review: {}", e))),
+    }
+}
+
 async fn delete_vocabulary(
     State(pool): State<DbPool>,
     auth: OptionalAuthUser,
@@ -164,6 +222,7 @@ pub struct StartAIReviewResponse {
 async fn start_ai_review(
     State(pool): State<DbPool>,
     auth: AuthUser,  // Requires login
+    quota: AiQuota<ops::ReviewSession>,
     Json(payload): Json<StartAIReviewRequest>,
 ) -> Json<ApiResponse<StartAIReviewResponse>> {
     let user_id = &auth.user_id;
@@ -196,10 +255,14 @@ async fn start_ai_review(
         Err(e) => return Json(ApiResponse::error(format!("AI provider error: {}", e))),
     };
 
-    let questions = match ai_provider.generate_review_questions(&vocab_for_review).await {
+    let profile = db::get_learner_profile(&pool, user_id).await.unwrap_or_default();
+
+    let generation = ai_provider.generate_review_questions(&vocab_for_review, &profile);
+    let questions = match track(&pool, user_id, "vocabulary/ai-review", generation).await {
         Ok(q) => q,
         Err(e) => return Json(ApiResponse::error(format!("Failed to generate questions: {}", e))),
     };
+    quota.charge();
 
     // Generate a session ID
     let session_id = uuid::Uuid::new_v4().to_string();
@@ -228,7 +291,9 @@ pub struct GenerateSingleQuestionResponse {
 
 /// Generate a single review question - requires authentication
 async fn generate_single_question(
-    _auth: AuthUser,  // Requires login
+    State(pool): State<DbPool>,
+    auth: AuthUser,  // Requires login
+    quota: AiQuota<ops::ReviewQuestion>,
     Json(payload): Json<GenerateSingleQuestionRequest>,
 ) -> Json<ApiResponse<GenerateSingleQuestionResponse>> {
     let question_types = ["meaning", "usage", "context", "spelling"];
@@ -248,10 +313,14 @@ async fn generate_single_question(
         source_sentence: payload.source_sentence,
     };
 
-    let question = match ai_provider.generate_single_review_question(&vocab, question_type).await {
+    let profile = db::get_learner_profile(&pool, &auth.user_id).await.unwrap_or_default();
+
+    let generation = ai_provider.generate_single_review_question(&vocab, question_type, &profile);
+    let question = match track(&pool, &auth.user_id, "vocabulary/ai-review/question", generation).await {
         Ok(q) => q,
         Err(e) => return Json(ApiResponse::error(format!("Failed to generate question: {}", e))),
     };
+    quota.charge();
 
     Json(ApiResponse::success(GenerateSingleQuestionResponse { question }))
 }
@@ -274,6 +343,7 @@ pub struct SubmitAnswerResponse {
 async fn submit_ai_review_answer(
     State(pool): State<DbPool>,
     auth: AuthUser,  // Requires login
+    quota: AiQuota<ops::ReviewAnswer>,
     Json(payload): Json<SubmitAnswerRequest>,
 ) -> Json<ApiResponse<SubmitAnswerResponse>> {
     let user_id = &auth.user_id;
@@ -284,24 +354,35 @@ async fn submit_ai_review_answer(
         Err(e) => return Json(ApiResponse::error(format!("AI provider error: {}", e))),
     };
 
-    let evaluation = match ai_provider.evaluate_review_answer(
+    let profile = db::get_learner_profile(&pool, user_id).await.unwrap_or_default();
+
+    let evaluation = ai_provider.evaluate_review_answer(
         &payload.word,
         &payload.meaning,
         &payload.question,
         &payload.user_answer,
-    ).await {
+        &profile,
+    );
+    let evaluation = match track(&pool, user_id, "vocabulary/ai-review/answer", evaluation).await {
         Ok(eval) => eval,
         Err(e) => return Json(ApiResponse::error(format!("Failed to evaluate: {}", e))),
     };
+    quota.charge();
 
-    // Update vocabulary review status based on AI evaluation
-    if let Err(e) = db::review_vocabulary(&pool, user_id, payload.vocab_id, evaluation.quality).await {
+    // Update vocabulary review status and statistics based on AI evaluation
+    if let Err(e) = db::review_vocabulary(
+        &pool,
+        user_id,
+        payload.vocab_id,
+        evaluation.quality,
+        evaluation.is_correct,
+        ReviewSource::AiReview,
+    )
+    .await
+    {
         tracing::warn!("Failed to update review status: {}", e);
     }
 
-    // Record review statistics
-    let _ = db::record_review(&pool, user_id, evaluation.is_correct).await;
-
     Json(ApiResponse::success(SubmitAnswerResponse { evaluation }))
 }
 
@@ -321,7 +402,9 @@ pub struct GenerateMemoryCardResponse {
 
 /// Generate AI memory card for vocabulary learning
 async fn generate_memory_card(
-    _auth: AuthUser,  // Requires login
+    State(pool): State<DbPool>,
+    auth: AuthUser,  // Requires login
+    quota: AiQuota<ops::MemoryCard>,
     Json(payload): Json<GenerateMemoryCardRequest>,
 ) -> Json<ApiResponse<GenerateMemoryCardResponse>> {
     // Get AI provider
@@ -330,15 +413,98 @@ async fn generate_memory_card(
         Err(e) => return Json(ApiResponse::error(format!("AI provider error: {}", e))),
     };
 
+    let profile = db::get_learner_profile(&pool, &auth.user_id).await.unwrap_or_default();
+
     // Generate memory card
-    let card = match ai_provider.generate_memory_card(
+    let card = ai_provider.generate_memory_card(
         &payload.word,
         &payload.meaning,
         payload.source_sentence.as_deref(),
-    ).await {
+        &profile,
+    );
+    let card = match track(&pool, &auth.user_id, "vocabulary/memory-card", card).await {
         Ok(c) => c,
         Err(e) => return Json(ApiResponse::error(format!("Failed to generate memory card: {}", e))),
     };
+    quota.charge();
 
     Json(ApiResponse::success(GenerateMemoryCardResponse { card }))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::test_support::{post_json, unreachable_db, use_mock_ai, with_memory_ledger};
+    use serde_json::json;
+
+    fn app() -> Router {
+        use_mock_ai();
+        with_memory_ledger(routes(unreachable_db()))
+    }
+
+    #[tokio::test]
+    async fn test_review_question_requires_login() {
+        let body = post_json(app(), "/ai-review/question", json!({"vocab_id": 1, "word": "w", "meaning": "m"}), false).await;
+        assert!(body["error"].as_str().unwrap().contains("authorization"), "{}", body);
+    }
+
+    #[tokio::test]
+    async fn test_review_question_uses_requested_type() {
+        let body = post_json(
+            app(),
+            "/ai-review/question",
+            json!({"vocab_id": 7, "word": "resilient", "meaning": "able to recover", "question_type": "usage"}),
+            true,
+        )
+        .await;
+        assert_eq!(body["success"], true, "{}", body);
+        let question = &body["data"]["question"];
+        assert_eq!(question["vocab_id"], 7);
+        assert_eq!(question["question_type"], "usage");
+        assert!(question["question"].as_str().unwrap().contains("resilient"));
+    }
+
+    #[tokio::test]
+    async fn test_review_answer_is_evaluated() {
+        let request = |answer: &str| {
+            json!({
+                "vocab_id": 7,
+                "word": "resilient",
+                "meaning": "able to recover",
+                "question": "What does resilient mean?",
+                "user_answer": answer,
+            })
+        };
+
+        let correct = post_json(app(), "/ai-review/answer", request("It means able to recover quickly"), true).await;
+        assert_eq!(correct["success"], true, "{}", correct);
+        assert_eq!(correct["data"]["evaluation"]["is_correct"], true);
+        assert_eq!(correct["data"]["evaluation"]["quality"], 3);
+
+        let wrong = post_json(app(), "/ai-review/answer", request("No idea"), true).await;
+        assert_eq!(wrong["data"]["evaluation"]["is_correct"], false);
+        assert_eq!(wrong["data"]["evaluation"]["quality"], 0);
+    }
+
+    #[tokio::test]
+    async fn test_undo_reports_database_errors() {
+        let body = post_json(app(), "/review/undo", json!({}), false).await;
+        assert_eq!(body["success"], false);
+        assert!(body["error"].as_str().unwrap().starts_with("Failed to undo review"), "{}", body);
+    }
+
+    #[tokio::test]
+    async fn test_memory_card_uses_source_sentence() {
+        let body = post_json(
+            app(),
+            "/memory-card",
+            json!({"word": "resilient", "meaning": "able to recover", "source_sentence": "Kids are resilient."}),
+            true,
+        )
+        .await;
+        assert_eq!(body["success"], true, "{}", body);
+        let card = &body["data"]["card"];
+        assert_eq!(card["word"], "resilient");
+        assert_eq!(card["example_sentence"], "Kids are resilient.");
+    }
+}
@@ -1,10 +1,103 @@
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
-use reqwest::Client;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::env;
+use tokio::sync::mpsc;
 
-use crate::models::Subtitle;
+use crate::models::{LearnerProfile, Subtitle};
+use crate::services::ai_json::{
+    generate_json, generate_json_with, ChapterList, HighlightList, MemoryCardResponse, SlideList, TranslationList,
+    VocabularyList,
+};
+use crate::services::ai_mock::MockProvider;
+use crate::services::ai_prompts::{self, PromptTask};
+use crate::services::ai_router::{is_transient, AiRouter};
+use crate::services::ai_transport::{AiTransport, ClaudeTransport, GeminiTransport, OpenAITransport};
+use crate::services::language;
+
+/// Sender for streamed answers; each message is the next piece of answer text
+pub type TextChunkSender = mpsc::Sender<String>;
+
+/// Speaker of a previous message in a chat thread
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatRole {
+    User,
+    Assistant,
+}
+
+impl ChatRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChatRole::User => "user",
+            ChatRole::Assistant => "assistant",
+        }
+    }
+
+    pub fn parse(role: &str) -> Option<Self> {
+        match role {
+            "user" => Some(ChatRole::User),
+            "assistant" => Some(ChatRole::Assistant),
+            _ => None,
+        }
+    }
+}
+
+/// One previous message sent along with a follow-up question
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatTurn {
+    pub role: ChatRole,
+    pub content: String,
+}
+
+/// Most previous messages sent with a question
+pub const MAX_HISTORY_TURNS: usize = 20;
+/// Character budget for previous messages (~1.5k tokens), keeps prompts within every model's limit
+pub const MAX_HISTORY_CHARS: usize = 6000;
+
+/// Keep the most recent turns that fit both limits, always starting on a user message
+pub fn truncate_history(history: &[ChatTurn], max_turns: usize, max_chars: usize) -> &[ChatTurn] {
+    let mut start = history.len();
+    let mut chars = 0;
+    while start > 0 && history.len() - start < max_turns {
+        let len = history[start - 1].content.chars().count();
+        if chars + len > max_chars {
+            break;
+        }
+        chars += len;
+        start -= 1;
+    }
+
+    // A reply without its question is confusing context
+    while start < history.len() && history[start].role != ChatRole::User {
+        start += 1;
+    }
+
+    &history[start..]
+}
+
+/// Prompt for answering a learner's question about the current subtitles
+fn ask_question_prompt(context: &str, question: &str, history: &[ChatTurn], profile: &LearnerProfile) -> String {
+    let history = truncate_history(history, MAX_HISTORY_TURNS, MAX_HISTORY_CHARS);
+    let conversation = if history.is_empty() {
+        String::new()
+    } else {
+        let turns: Vec<String> = history
+            .iter()
+            .map(|turn| format!("{}: {}", turn.role.as_str(), turn.content))
+            .collect();
+        format!("\nConversation so far:\n{}\n", turns.join("\n"))
+    };
+
+    ai_prompts::template(PromptTask::Ask).render(&[
+        ("target", profile.target_name()),
+        ("native", profile.native_name()),
+        ("context", context),
+        ("conversation", &conversation),
+        ("question", question),
+    ])
+}
 
 /// Sample subtitles evenly across the entire video for chapter generation
 /// Returns a condensed representation that covers the whole video
@@ -23,7 +116,7 @@ fn sample_subtitles_for_chapters(subtitles: &[Subtitle], max_chars: usize) -> St
             .map(|s| format!("[{:.0}s] {}", s.start, s.text))
             .collect::<Vec<_>>()
             .join("\n");
-        return text[..text.len().min(max_chars)].to_string();
+        return truncate_at_char_boundary(&text, max_chars).to_string();
     }
 
     // For longer videos, sample evenly
@@ -48,20 +141,32 @@ fn sample_subtitles_for_chapters(subtitles: &[Subtitle], max_chars: usize) -> St
     }
 
     let result = sampled.join("\n");
-    result[..result.len().min(max_chars)].to_string()
+    truncate_at_char_boundary(&result, max_chars).to_string()
+}
+
+/// Cut `text` to at most `max_bytes` without splitting a character
+fn truncate_at_char_boundary(text: &str, max_bytes: usize) -> &str {
+    if text.len() <= max_bytes {
+        return text;
+    }
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
 }
 
 /// Vocabulary item extracted from subtitle
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct VocabularyItem {
     pub word: String,
     pub meaning: String,
-    pub level: String,  // "雅思", "四级", "六级", "托福", "日常"
+    pub level: String,  // Tag from the target language's level system, e.g. "B2", "N3", "HSK4", "Phrase"
     pub example: String,
 }
 
 /// Slide structure for presentation
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Slide {
     pub slide_type: String, // "title", "content", "summary"
     pub title: String,
@@ -71,7 +176,7 @@ pub struct Slide {
 }
 
 /// Chapter/section of a video for table of contents
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Chapter {
     pub title: String,
     pub start_time: f64, // Start time in seconds
@@ -98,7 +203,7 @@ pub struct VocabForReview {
 }
 
 /// AI evaluation of user's answer
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ReviewEvaluation {
     pub is_correct: bool,
     pub feedback: String,
@@ -123,32 +228,56 @@ pub struct MemoryCard {
 /// AI Provider trait - implement this for each provider
 #[async_trait]
 pub trait AiProvider: Send + Sync {
+    /// Short provider name, used to key stored AI output (e.g. "gemini")
+    fn name(&self) -> &'static str;
+
     /// Analyze subtitles and return indices of important sentences
-    async fn analyze_highlights(&self, subtitles: &[Subtitle]) -> Result<Vec<usize>>;
+    async fn analyze_highlights(&self, subtitles: &[Subtitle], profile: &LearnerProfile) -> Result<Vec<usize>>;
+
+    /// Answer a question about the given context, following up on earlier messages in `history`
+    async fn ask_question(&self, context: &str, question: &str, history: &[ChatTurn], profile: &LearnerProfile) -> Result<String>;
+
+    /// Answer a question, sending the text to `chunks` as it is generated
+    /// Returns the full answer once the provider has finished
+    async fn ask_question_stream(
+        &self,
+        context: &str,
+        question: &str,
+        history: &[ChatTurn],
+        profile: &LearnerProfile,
+        chunks: TextChunkSender,
+    ) -> Result<String>;
 
-    /// Answer a question about the given context
-    async fn ask_question(&self, context: &str, question: &str) -> Result<String>;
+    /// Translate subtitles to the learner's native language
+    async fn translate_subtitles(&self, subtitles: &[Subtitle], profile: &LearnerProfile) -> Result<Vec<String>>;
 
-    /// Translate subtitles to Chinese
-    async fn translate_subtitles(&self, subtitles: &[Subtitle]) -> Result<Vec<String>>;
+    /// [`AiProvider::translate_subtitles`], with the name of the provider that made the translations
+    /// (a router reports the provider that served the call, not itself)
+    async fn translate_subtitles_named(
+        &self,
+        subtitles: &[Subtitle],
+        profile: &LearnerProfile,
+    ) -> Result<(Vec<String>, &'static str)> {
+        Ok((self.translate_subtitles(subtitles, profile).await?, self.name()))
+    }
 
     /// Extract important vocabulary from subtitle text
-    async fn extract_vocabulary(&self, text: &str) -> Result<Vec<VocabularyItem>>;
+    async fn extract_vocabulary(&self, text: &str, profile: &LearnerProfile) -> Result<Vec<VocabularyItem>>;
 
     /// Generate a mind map markdown from video content
-    async fn generate_mindmap(&self, title: &str, content: &str) -> Result<String>;
+    async fn generate_mindmap(&self, title: &str, content: &str, profile: &LearnerProfile) -> Result<String>;
 
     /// Generate presentation slides from video content
-    async fn generate_slides(&self, title: &str, content: &str) -> Result<Vec<Slide>>;
+    async fn generate_slides(&self, title: &str, content: &str, profile: &LearnerProfile) -> Result<Vec<Slide>>;
 
     /// Generate table of contents / chapters from subtitles
-    async fn generate_chapters(&self, subtitles: &[Subtitle]) -> Result<Vec<Chapter>>;
+    async fn generate_chapters(&self, subtitles: &[Subtitle], profile: &LearnerProfile) -> Result<Vec<Chapter>>;
 
     /// Generate review questions for vocabulary
-    async fn generate_review_questions(&self, vocab_list: &[VocabForReview]) -> Result<Vec<ReviewQuestion>>;
+    async fn generate_review_questions(&self, vocab_list: &[VocabForReview], profile: &LearnerProfile) -> Result<Vec<ReviewQuestion>>;
 
     /// Generate a single review question for one vocabulary item
-    async fn generate_single_review_question(&self, vocab: &VocabForReview, question_type: &str) -> Result<ReviewQuestion>;
+    async fn generate_single_review_question(&self, vocab: &VocabForReview, question_type: &str, profile: &LearnerProfile) -> Result<ReviewQuestion>;
 
     /// Evaluate user's answer to a review question
     async fn evaluate_review_answer(
@@ -157,6 +286,7 @@ pub trait AiProvider: Send + Sync {
         meaning: &str,
         question: &str,
         user_answer: &str,
+        profile: &LearnerProfile,
     ) -> Result<ReviewEvaluation>;
 
     /// Generate AI memory card for vocabulary learning
@@ -165,97 +295,98 @@ pub trait AiProvider: Send + Sync {
         word: &str,
         meaning: &str,
         context: Option<&str>,
+        profile: &LearnerProfile,
     ) -> Result<MemoryCard>;
 }
 
+const DEFAULT_GEMINI_MODEL: &str = "gemini-2.0-flash";
+const DEFAULT_CLAUDE_MODEL: &str = "claude-3-haiku-20240307";
+const DEFAULT_OPENAI_MODEL: &str = "gpt-3.5-turbo";
+/// Ollama's OpenAI-compatible endpoint on a default local install
+const DEFAULT_OLLAMA_BASE_URL: &str = "http://localhost:11434/v1";
+const DEFAULT_OLLAMA_MODEL: &str = "llama3.2";
+
 /// Get the configured AI provider
+/// `AI_PROVIDER` may list several providers in failover order, see [`AiRouter::from_env`]
 pub fn get_ai_provider() -> Result<Box<dyn AiProvider>> {
-    let provider = env::var("AI_PROVIDER").unwrap_or_else(|_| "gemini".to_string());
+    Ok(Box::new(AiRouter::from_env()?))
+}
 
-    match provider.to_lowercase().as_str() {
+/// Build a single provider from a spec like "gemini", "openai:gpt-4o-mini" or "ollama:llama3.2:3b"
+/// (everything after the first ':' is the model)
+pub fn build_provider(spec: &str) -> Result<Box<dyn AiProvider>> {
+    let (kind, model) = match spec.trim().split_once(':') {
+        Some((kind, model)) => (kind, Some(model.to_string())),
+        None => (spec.trim(), None),
+    };
+
+    match kind.to_lowercase().as_str() {
         "gemini" => {
             let api_key = env::var("GEMINI_API_KEY")
                 .map_err(|_| anyhow!("GEMINI_API_KEY not set"))?;
-            Ok(Box::new(GeminiProvider::new(api_key)))
+            let model = model.unwrap_or_else(|| DEFAULT_GEMINI_MODEL.to_string());
+            Ok(Box::new(PromptedProvider::new(GeminiTransport::new(api_key, model))))
         }
         "claude" => {
             let api_key = env::var("CLAUDE_API_KEY")
                 .map_err(|_| anyhow!("CLAUDE_API_KEY not set"))?;
-            Ok(Box::new(ClaudeProvider::new(api_key)))
+            let model = model.unwrap_or_else(|| DEFAULT_CLAUDE_MODEL.to_string());
+            Ok(Box::new(PromptedProvider::new(ClaudeTransport::new(api_key, model))))
         }
         "openai" => {
             let api_key = env::var("OPENAI_API_KEY")
                 .map_err(|_| anyhow!("OPENAI_API_KEY not set"))?;
-            Ok(Box::new(OpenAIProvider::new(api_key)))
-        }
-        _ => Err(anyhow!("Unknown AI provider: {}", provider)),
+            let model = model.unwrap_or_else(|| DEFAULT_OPENAI_MODEL.to_string());
+            Ok(Box::new(PromptedProvider::new(OpenAITransport::new(api_key, model))))
+        }
+        // Any server speaking the OpenAI chat completions API (vLLM, LM Studio, llama.cpp, a test mock...)
+        "openai_compatible" => {
+            let base_url = env::var("OPENAI_COMPATIBLE_BASE_URL")
+                .map_err(|_| anyhow!("OPENAI_COMPATIBLE_BASE_URL not set"))?;
+            let model = match model {
+                Some(model) => model,
+                None => env::var("OPENAI_COMPATIBLE_MODEL")
+                    .map_err(|_| anyhow!("OPENAI_COMPATIBLE_MODEL not set"))?,
+            };
+            let api_key = env::var("OPENAI_COMPATIBLE_API_KEY").ok().filter(|k| !k.is_empty());
+            let transport = OpenAITransport::compatible("openai_compatible", base_url, model, api_key);
+            Ok(Box::new(PromptedProvider::new(transport)))
+        }
+        "ollama" => {
+            let base_url = env::var("OLLAMA_BASE_URL").unwrap_or_else(|_| DEFAULT_OLLAMA_BASE_URL.to_string());
+            let model = model
+                .or_else(|| env::var("OLLAMA_MODEL").ok())
+                .unwrap_or_else(|| DEFAULT_OLLAMA_MODEL.to_string());
+            Ok(Box::new(PromptedProvider::new(OpenAITransport::compatible("ollama", base_url, model, None))))
+        }
+        // Canned output, no API key needed
+        "mock" => Ok(Box::new(MockProvider)),
+        _ => Err(anyhow!("Unknown AI provider: {}", kind)),
     }
 }
 
 // ============================================================================
-// Gemini Provider
+// Prompt layer, shared by every model API
 // ============================================================================
 
-pub struct GeminiProvider {
-    api_key: String,
-    client: Client,
+/// Implements every AI feature once on top of a model API, with prompts from [`ai_prompts`]
+pub struct PromptedProvider<T> {
+    transport: T,
 }
 
-impl GeminiProvider {
-    pub fn new(api_key: String) -> Self {
-        Self {
-            api_key,
-            client: Client::new(),
-        }
+impl<T: AiTransport> PromptedProvider<T> {
+    pub fn new(transport: T) -> Self {
+        Self { transport }
     }
 }
 
-#[derive(Serialize)]
-struct GeminiRequest {
-    contents: Vec<GeminiContent>,
-    #[serde(rename = "generationConfig")]
-    generation_config: GeminiGenerationConfig,
-}
-
-#[derive(Serialize)]
-struct GeminiGenerationConfig {
-    #[serde(rename = "maxOutputTokens")]
-    max_output_tokens: u32,
-}
-
-#[derive(Serialize)]
-struct GeminiContent {
-    parts: Vec<GeminiPart>,
-}
-
-#[derive(Serialize)]
-struct GeminiPart {
-    text: String,
-}
-
-#[derive(Deserialize)]
-struct GeminiResponse {
-    candidates: Option<Vec<GeminiCandidate>>,
-}
-
-#[derive(Deserialize)]
-struct GeminiCandidate {
-    content: GeminiContentResponse,
-}
-
-#[derive(Deserialize)]
-struct GeminiContentResponse {
-    parts: Vec<GeminiPartResponse>,
-}
-
-#[derive(Deserialize)]
-struct GeminiPartResponse {
-    text: String,
-}
-
 #[async_trait]
-impl AiProvider for GeminiProvider {
-    async fn analyze_highlights(&self, subtitles: &[Subtitle]) -> Result<Vec<usize>> {
+impl<T: AiTransport> AiProvider for PromptedProvider<T> {
+    fn name(&self) -> &'static str {
+        self.transport.name()
+    }
+
+    async fn analyze_highlights(&self, subtitles: &[Subtitle], profile: &LearnerProfile) -> Result<Vec<usize>> {
         let subtitle_text: String = subtitles
             .iter()
             .enumerate()
@@ -263,64 +394,39 @@ impl AiProvider for GeminiProvider {
             .collect::<Vec<_>>()
             .join("\n");
 
-        let prompt = format!(
-            r#"Analyze these English subtitles from a video and identify the most important/educational sentences for English learners.
-
-Subtitles:
-{}
-
-Return ONLY a JSON array of indices (numbers) for the 5-10 most important sentences.
-Important sentences include: key phrases, idiomatic expressions, useful grammar patterns, or main points.
-
-Example response: [0, 3, 7, 12, 15]
+        let prompt = ai_prompts::template(PromptTask::Highlights).render(&[
+            ("target", profile.target_name()),
+            ("subtitles", &subtitle_text),
+        ]);
 
-Response:"#,
-            subtitle_text
-        );
-
-        let response = self.call_gemini(&prompt).await?;
-
-        // Parse the response to extract indices
-        let indices: Vec<usize> = serde_json::from_str(&response)
-            .or_else(|_| {
-                // Try to extract array from response
-                let re = regex::Regex::new(r"\[[\d,\s]+\]").unwrap();
-                if let Some(mat) = re.find(&response) {
-                    serde_json::from_str(mat.as_str())
-                } else {
-                    Ok(vec![])
-                }
-            })
-            .unwrap_or_default();
-
-        Ok(indices)
+        let list: HighlightList = generate_json(&self.transport, &prompt).await?;
+        Ok(valid_highlights(list, subtitles.len()))
     }
 
-    async fn ask_question(&self, context: &str, question: &str) -> Result<String> {
-        let prompt = format!(
-            r#"你是一位英语学习助手。用户正在观看英语视频并提问。
-
-当前字幕上下文:
-"{}"
-
-用户问题: {}
-
-回答要求:
-1. 如果问题与视频内容无关或是简单的闲聊（如"OK"、"好的"、"谢谢"），只需一句话简短回应
-2. 只有当问题确实与视频内容或英语学习相关时，才详细解释
-3. 回答要简洁，通常2-3句话足够，最多不超过100字
-4. 解释词汇或语法时要结合视频上下文
-
-用与问题相同的语言回答。"#,
-            context, question
-        );
+    async fn ask_question(&self, context: &str, question: &str, history: &[ChatTurn], profile: &LearnerProfile) -> Result<String> {
+        let prompt = ask_question_prompt(context, question, history, profile);
+        self.transport.complete(&prompt).await
+    }
 
-        self.call_gemini(&prompt).await
+    async fn ask_question_stream(
+        &self,
+        context: &str,
+        question: &str,
+        history: &[ChatTurn],
+        profile: &LearnerProfile,
+        chunks: TextChunkSender,
+    ) -> Result<String> {
+        let prompt = ask_question_prompt(context, question, history, profile);
+        self.transport.complete_stream(&prompt, chunks).await
     }
 
-    async fn translate_subtitles(&self, subtitles: &[Subtitle]) -> Result<Vec<String>> {
+    async fn translate_subtitles(&self, subtitles: &[Subtitle], profile: &LearnerProfile) -> Result<Vec<String>> {
         // Batch subtitles for efficient translation (max 20 per batch)
+        // A batch whose output cannot be used becomes empty strings so the batches that worked are
+        // kept; only when every batch fails is the call an error
+        // Rate limits and outages end the call, so a router can retry it or fail over
         let mut all_translations = Vec::new();
+        let mut last_error = None;
 
         for chunk in subtitles.chunks(20) {
             let texts: Vec<String> = chunk
@@ -329,614 +435,118 @@ Response:"#,
                 .map(|(i, s)| format!("[{}] {}", i, s.text))
                 .collect();
 
-            let prompt = format!(
-                r#"Translate the following English subtitles to Chinese (Simplified).
-Keep translations natural and conversational.
-Return ONLY a JSON array of translated strings, in the same order.
+            let prompt = ai_prompts::template(PromptTask::Translate).render(&[
+                ("target", profile.target_name()),
+                ("native", profile.native_name()),
+                ("subtitles", &texts.join("\n")),
+            ]);
 
-Subtitles:
-{}
-
-Example response format: ["翻译1", "翻译2", "翻译3"]
-
-Response (JSON array only):"#,
-                texts.join("\n")
-            );
-
-            let response = self.call_gemini(&prompt).await?;
-
-            // Parse JSON array from response
-            let translations: Vec<String> = parse_translation_response(&response, chunk.len());
-
-            all_translations.extend(translations);
+            let list = generate_json_with(&self.transport, &prompt, |list: &TranslationList| {
+                expect_count(list.translations.len(), chunk.len())
+            })
+            .await;
+            match list {
+                Ok(list) => all_translations.extend(list.translations),
+                Err(e) if is_transient(&e) => return Err(e),
+                Err(e) => {
+                    tracing::warn!("Failed to translate {} subtitles starting at {}: {}", chunk.len(), chunk[0].index, e);
+                    all_translations.extend(std::iter::repeat_n(String::new(), chunk.len()));
+                    last_error = Some(e);
+                }
+            }
         }
 
-        // Ensure we have the right number of translations
-        while all_translations.len() < subtitles.len() {
-            all_translations.push(String::new());
+        match last_error {
+            Some(e) if all_translations.iter().all(String::is_empty) => Err(e),
+            _ => Ok(all_translations),
         }
-        all_translations.truncate(subtitles.len());
-
-        Ok(all_translations)
     }
 
-    async fn extract_vocabulary(&self, text: &str) -> Result<Vec<VocabularyItem>> {
-        let prompt = format!(
-            r#"从以下英文内容中提取重点词汇和常用短语。
-
-内容: "{}"
-
-提取要求:
-1. 重点词汇：CET-4、CET-6、IELTS、TOEFL、GRE 核心词汇
-2. 常用短语：实用的固定搭配、习语、口语表达（如 "figure out", "in terms of", "take advantage of"）
-3. 不要提取简单词（如 the, is, a, have, do）
-4. 每项提供：词性+中文释义、等级、实用例句
-5. 等级标记：CET-4、CET-6、IELTS、TOEFL、GRE、Phrase（短语用 Phrase）
-6. 去重：相同词汇只保留一个
-
-返回JSON数组格式:
-[
-  {{"word": "leverage", "meaning": "(v.) 充分利用", "level": "CET-6", "example": "Let's leverage this opportunity."}},
-  {{"word": "figure out", "meaning": "(phrase) 弄清楚，想出", "level": "Phrase", "example": "I need to figure out the problem."}},
-  {{"word": "in terms of", "meaning": "(phrase) 就...而言", "level": "Phrase", "example": "In terms of cost, it's affordable."}}
-]
+    async fn extract_vocabulary(&self, text: &str, profile: &LearnerProfile) -> Result<Vec<VocabularyItem>> {
+        let levels = profile.level_system();
+        let prompt = ai_prompts::template(PromptTask::Vocabulary).render(&[
+            ("text", text),
+            ("native", profile.native_name()),
+            ("target", profile.target_name()),
+            ("system", levels.name),
+            ("range", &level_range(&levels)),
+            ("tags", &levels.prompt_tags()),
+            ("phrase", language::PHRASE_LEVEL),
+            ("example_level", levels.levels[levels.levels.len() / 2]),
+        ]);
 
-只返回JSON数组，不要其他内容:"#,
-            text
-        );
-
-        let response = self.call_gemini(&prompt).await?;
-
-        // Parse JSON response
-        let items: Vec<VocabularyItem> = parse_vocabulary_response(&response);
-        Ok(items)
+        let list: VocabularyList = generate_json(&self.transport, &prompt).await?;
+        Ok(list.vocabulary)
     }
 
-    async fn generate_mindmap(&self, title: &str, content: &str) -> Result<String> {
-        let prompt = format!(
-            r#"基于以下视频内容，生成一个思维导图的 Markdown 格式。
-
-视频标题: {}
-
-视频字幕内容:
-{}
-
-要求:
-1. 用 Markdown 标题格式表示层级关系（# 一级, ## 二级, ### 三级）
-2. 提取3-5个主要主题作为二级标题
-3. 每个主题下列出2-4个关键点作为三级标题
-4. 关键点下可以用列表(-)补充具体内容
-5. 内容要精炼，每个点不超过15个字
-6. 用中文输出，但保留重要的英文术语
-
-示例格式:
-# 视频主题
-## 主题一
-### 关键点1
-- 补充说明
-### 关键点2
-## 主题二
-### 关键点1
-
-请直接输出 Markdown 格式，不要其他解释:"#,
-            title,
-            &content[..content.len().min(8000)] // Limit content length
-        );
-
-        self.call_gemini(&prompt).await
-    }
-
-    async fn generate_slides(&self, title: &str, content: &str) -> Result<Vec<Slide>> {
-        let prompt = format!(
-            r#"Generate presentation slides based on video content to help users quickly understand and review the key points.
-
-Video Title: {}
+    async fn generate_mindmap(&self, title: &str, content: &str, profile: &LearnerProfile) -> Result<String> {
+        let prompt = ai_prompts::template(PromptTask::Mindmap).render(&[
+            ("title", title),
+            ("content", truncate_at_char_boundary(content, 8000)),
+            ("native", profile.native_name()),
+            ("target", profile.target_name()),
+        ]);
 
-Content:
-{}
-
-## Requirements
-
-1. Create 8-12 slides with rich content
-2. Structure: Title slide + 6-10 content slides + Summary slide
-3. Each slide should have 3-5 bullet points, each expressing a complete idea
-4. Bullet points should be specific and informative, avoid vague statements
-5. Write in English
-6. Use notes field for key details or examples (1-2 sentences)
-
-### JSON Format:
-[
-  {{"slide_type": "title", "title": "Main Title", "subtitle": "Speaker/Source", "bullets": [], "notes": null}},
-  {{"slide_type": "content", "title": "Key Point Title", "subtitle": null, "bullets": ["Complete description of the point", "Another informative bullet"], "notes": "Additional details or examples"}},
-  {{"slide_type": "summary", "title": "Key Takeaways", "subtitle": null, "bullets": ["Specific takeaway 1", "Specific takeaway 2"], "notes": null}}
-]
+        self.transport.complete(&prompt).await
+    }
 
-Output JSON array only:"#,
-            title,
-            &content[..content.len().min(12000)]
-        );
+    async fn generate_slides(&self, title: &str, content: &str, profile: &LearnerProfile) -> Result<Vec<Slide>> {
+        let prompt = ai_prompts::template(PromptTask::Slides).render(&[
+            ("title", title),
+            ("content", truncate_at_char_boundary(content, 12000)),
+            ("target", profile.target_name()),
+        ]);
 
-        let response = self.call_gemini(&prompt).await?;
-        parse_slides_response(&response)
+        let list: SlideList = generate_json(&self.transport, &prompt).await?;
+        Ok(list.slides)
     }
 
-    async fn generate_chapters(&self, subtitles: &[Subtitle]) -> Result<Vec<Chapter>> {
+    async fn generate_chapters(&self, subtitles: &[Subtitle], profile: &LearnerProfile) -> Result<Vec<Chapter>> {
         // Sample subtitles evenly across the entire video
         let sampled_text = sample_subtitles_for_chapters(subtitles, 15000);
 
         // Get total duration for context
         let total_duration = subtitles.last().map(|s| s.end).unwrap_or(0.0);
-        let duration_min = (total_duration / 60.0).ceil() as i32;
-
-        let prompt = format!(
-            r#"Analyze the following video subtitles and create a table of contents with 6-12 chapters.
-
-Video duration: approximately {} minutes
-Subtitles (sampled with timestamps in seconds):
-{}
-
-Requirements:
-1. Create 6-12 main chapters covering the ENTIRE video
-2. Each chapter should have a clear, concise title (in English, max 6 words)
-3. Use the exact start_time (in seconds) where each topic begins
-4. Chapters MUST be distributed across the full video duration (0 to ~{}s)
-5. First chapter should start at 0
-6. Last chapter should be in the final third of the video
-
-Return ONLY a JSON array in this format:
-[
-  {{"title": "Introduction", "start_time": 0}},
-  {{"title": "Main Topic One", "start_time": 120}},
-  {{"title": "Key Concepts", "start_time": 300}},
-  {{"title": "Final Thoughts", "start_time": 600}}
-]
-
-JSON array only:"#,
-            duration_min,
-            sampled_text,
-            total_duration as i32
-        );
-
-        let response = self.call_gemini(&prompt).await?;
-        parse_chapters_response(&response)
-    }
-
-    async fn generate_review_questions(&self, vocab_list: &[VocabForReview]) -> Result<Vec<ReviewQuestion>> {
-        let mut questions = Vec::new();
-        let question_types = ["meaning", "usage", "context", "spelling"];
-
-        for (i, vocab) in vocab_list.iter().enumerate() {
-            let question_type = question_types[i % question_types.len()];
-            let context_hint = vocab.source_sentence.as_deref().unwrap_or("无语境");
-
-            let prompt = format!(
-                r#"你是一位友好的英语老师，正在帮学生复习单词。
-
-单词: "{}"
-中文含义: "{}"
-原句语境: "{}"
-
-请生成一个自然的复习问题。问题类型: {}
-- meaning: 直接问这个词是什么意思
-- usage: 让学生用这个词造一个句子
-- context: 回顾语境，问学生是否记得这个词在原句中的意思
-- spelling: 告诉学生你会播放发音，让他们拼写这个单词
-
-要求:
-1. 用中文提问
-2. 语气友好轻松，像朋友聊天
-3. 问题要简洁，不超过30字
-4. 不要直接透露答案
-
-只返回问题本身，不要其他内容:"#,
-                vocab.word, vocab.meaning, context_hint, question_type
-            );
-
-            let question_text = self.call_gemini(&prompt).await.unwrap_or_else(|_| {
-                match question_type {
-                    "meaning" => format!("「{}」这个词是什么意思？", vocab.word),
-                    "usage" => format!("用「{}」造一个句子吧！", vocab.word),
-                    "context" => format!("还记得「{}」在视频里是什么意思吗？", vocab.word),
-                    "spelling" => "听发音，把这个单词拼出来吧！".to_string(),
-                    _ => format!("「{}」是什么意思？", vocab.word),
-                }
-            });
-
-            questions.push(ReviewQuestion {
-                vocab_id: vocab.id,
-                word: vocab.word.clone(),
-                meaning: vocab.meaning.clone(),
-                source_sentence: vocab.source_sentence.clone(),
-                question_type: question_type.to_string(),
-                question: question_text.trim().to_string(),
-            });
-        }
-
-        Ok(questions)
-    }
-
-    async fn generate_single_review_question(&self, vocab: &VocabForReview, question_type: &str) -> Result<ReviewQuestion> {
-        let context_hint = vocab.source_sentence.as_deref().unwrap_or("无语境");
-
-        let prompt = format!(
-            r#"你是一位友好的英语老师，正在帮学生复习单词。
-
-单词: "{}"
-中文含义: "{}"
-原句语境: "{}"
-
-请生成一个自然的复习问题。问题类型: {}
-- meaning: 直接问这个词是什么意思
-- usage: 让学生用这个词造一个句子
-- context: 回顾语境，问学生是否记得这个词在原句中的意思
-- spelling: 告诉学生你会播放发音，让他们拼写这个单词
-
-要求:
-1. 用中文提问
-2. 语气友好轻松，像朋友聊天
-3. 问题要简洁，不超过30字
-4. 不要直接透露答案
-
-只返回问题本身，不要其他内容:"#,
-            vocab.word, vocab.meaning, context_hint, question_type
-        );
-
-        let question_text = self.call_gemini(&prompt).await.unwrap_or_else(|_| {
-            match question_type {
-                "meaning" => format!("「{}」这个词是什么意思？", vocab.word),
-                "usage" => format!("用「{}」造一个句子吧！", vocab.word),
-                "context" => format!("还记得「{}」在视频里是什么意思吗？", vocab.word),
-                "spelling" => "听发音，把这个单词拼出来吧！".to_string(),
-                _ => format!("「{}」是什么意思？", vocab.word),
-            }
-        });
-
-        Ok(ReviewQuestion {
-            vocab_id: vocab.id,
-            word: vocab.word.clone(),
-            meaning: vocab.meaning.clone(),
-            source_sentence: vocab.source_sentence.clone(),
-            question_type: question_type.to_string(),
-            question: question_text.trim().to_string(),
-        })
-    }
-
-    async fn evaluate_review_answer(
-        &self,
-        word: &str,
-        meaning: &str,
-        question: &str,
-        user_answer: &str,
-    ) -> Result<ReviewEvaluation> {
-        let prompt = format!(
-            r#"你是一位友好的英语老师，正在批改学生的复习答案。
-
-单词: "{}"
-中文含义: "{}"
-问题: "{}"
-学生回答: "{}"
-
-请评估学生的回答:
-1. 判断是否正确（宽松判断，意思对或接近即可）
-2. 给出简短友好的反馈（不超过20字）
-3. 如果回答不太完整，可以给一个追问帮助学生加深理解（可选）
-4. 给出质量分数: 0=完全不记得, 1=记得但很困难, 2=基本掌握, 3=非常熟练
-
-返回JSON格式:
-{{"is_correct": true/false, "feedback": "反馈内容", "follow_up": "追问内容或null", "quality": 0-3}}
-
-只返回JSON，不要其他内容:"#,
-            word, meaning, question, user_answer
-        );
-
-        let response = self.call_gemini(&prompt).await?;
-        parse_review_evaluation(&response)
-    }
-
-    async fn generate_memory_card(
-        &self,
-        word: &str,
-        meaning: &str,
-        context: Option<&str>,
-    ) -> Result<MemoryCard> {
-        let context_text = context.unwrap_or("No specific context");
-
-        let prompt = format!(
-            r#"You are a vocabulary expert helping students understand English word origins and usage.
-
-Word: "{}"
-Meaning: "{}"
-Context: "{}"
-
-Generate a memory card with:
-
-1. **Etymology**: Analyze word roots, prefixes, suffixes and explain the origin
-   - Example: "insulin" = insula (Latin for "island") + -in → substance secreted by islets of Langerhans
-   - If no clear roots, explain the word's historical evolution
-
-2. **Real-life Example**: A sentence from everyday American life
-   - Must be specific, realistic - like from American TV shows or daily conversations
-   - Example: At the pharmacy, "I need to pick up my insulin prescription."
-
-Return JSON format only:
-{{
-  "phonetic": "IPA phonetic transcription",
-  "part_of_speech": "noun/verb/adj/adv/etc",
-  "etymology": "Etymology explanation in English",
-  "example_sentence": "Real-life example sentence in a specific American scenario"
-}}
-
-Return ONLY the JSON, nothing else:"#,
-            word, meaning, context_text
-        );
-
-        let response = self.call_gemini(&prompt).await?;
-        parse_memory_card_response(&response, word, meaning)
-    }
-}
-
-impl GeminiProvider {
-    async fn call_gemini(&self, prompt: &str) -> Result<String> {
-        let url = format!(
-            "https://generativelanguage.googleapis.com/v1beta/models/gemini-2.0-flash:generateContent?key={}",
-            self.api_key
-        );
-
-        let request = GeminiRequest {
-            contents: vec![GeminiContent {
-                parts: vec![GeminiPart {
-                    text: prompt.to_string(),
-                }],
-            }],
-            generation_config: GeminiGenerationConfig {
-                max_output_tokens: 8192,
-            },
-        };
-
-        let response = self
-            .client
-            .post(&url)
-            .json(&request)
-            .send()
-            .await?
-            .json::<GeminiResponse>()
-            .await?;
-
-        let text = response
-            .candidates
-            .and_then(|c| c.into_iter().next())
-            .and_then(|c| c.content.parts.into_iter().next())
-            .map(|p| p.text)
-            .ok_or_else(|| anyhow!("No response from Gemini"))?;
-
-        Ok(text)
-    }
-}
-
-// ============================================================================
-// Claude Provider (placeholder)
-// ============================================================================
-
-pub struct ClaudeProvider {
-    api_key: String,
-    client: Client,
-}
-
-impl ClaudeProvider {
-    pub fn new(api_key: String) -> Self {
-        Self {
-            api_key,
-            client: Client::new(),
-        }
-    }
-}
-
-#[async_trait]
-impl AiProvider for ClaudeProvider {
-    async fn analyze_highlights(&self, subtitles: &[Subtitle]) -> Result<Vec<usize>> {
-        let subtitle_text: String = subtitles
-            .iter()
-            .enumerate()
-            .map(|(i, s)| format!("[{}] {}", i, s.text))
-            .collect::<Vec<_>>()
-            .join("\n");
-
-        let prompt = format!(
-            r#"Analyze these English subtitles and identify 5-10 important sentences for English learners.
-Return ONLY a JSON array of indices.
-
-Subtitles:
-{}
-
-Response (JSON array only):"#,
-            subtitle_text
-        );
-
-        let response = self.call_claude(&prompt).await?;
-
-        let re = regex::Regex::new(r"\[[\d,\s]+\]").unwrap();
-        let indices: Vec<usize> = re
-            .find(&response)
-            .and_then(|m| serde_json::from_str(m.as_str()).ok())
-            .unwrap_or_default();
-
-        Ok(indices)
-    }
-
-    async fn ask_question(&self, context: &str, question: &str) -> Result<String> {
-        let prompt = format!(
-            r#"你是一位英语学习助手。用户正在观看英语视频并提问。
-
-当前字幕上下文:
-"{}"
-
-用户问题: {}
-
-回答要求:
-1. 如果问题与视频内容无关或是简单的闲聊（如"OK"、"好的"、"谢谢"），只需一句话简短回应
-2. 只有当问题确实与视频内容或英语学习相关时，才详细解释
-3. 回答要简洁，通常2-3句话足够，最多不超过100字
-4. 解释词汇或语法时要结合视频上下文
-
-用与问题相同的语言回答。"#,
-            context, question
-        );
-
-        self.call_claude(&prompt).await
-    }
+        let duration_minutes = ((total_duration / 60.0).ceil() as i32).to_string();
+        let duration_seconds = (total_duration as i32).to_string();
 
-    async fn translate_subtitles(&self, subtitles: &[Subtitle]) -> Result<Vec<String>> {
-        let mut all_translations = Vec::new();
-
-        for chunk in subtitles.chunks(20) {
-            let texts: Vec<String> = chunk
-                .iter()
-                .enumerate()
-                .map(|(i, s)| format!("[{}] {}", i, s.text))
-                .collect();
-
-            let prompt = format!(
-                r#"Translate these English subtitles to Chinese (Simplified).
-Return ONLY a JSON array of translated strings.
-
-Subtitles:
-{}
-
-Response (JSON array only):"#,
-                texts.join("\n")
-            );
-
-            let response = self.call_claude(&prompt).await?;
-            let translations = parse_translation_response(&response, chunk.len());
-            all_translations.extend(translations);
-        }
-
-        while all_translations.len() < subtitles.len() {
-            all_translations.push(String::new());
-        }
-        all_translations.truncate(subtitles.len());
-
-        Ok(all_translations)
-    }
-
-    async fn extract_vocabulary(&self, text: &str) -> Result<Vec<VocabularyItem>> {
-        let prompt = format!(
-            r#"Extract important vocabulary (IELTS, TOEFL, CET-4/6) from this sentence: "{}"
-Return JSON array: [{{"word": "...", "meaning": "(v.) Chinese meaning", "level": "雅思/四级/六级/托福", "example": "Short daily example"}}]
-Only return JSON array:"#,
-            text
-        );
-
-        let response = self.call_claude(&prompt).await?;
-        Ok(parse_vocabulary_response(&response))
-    }
-
-    async fn generate_mindmap(&self, title: &str, content: &str) -> Result<String> {
-        let prompt = format!(
-            r#"Generate a mind map in Markdown format for this video.
-Title: {}
-Content: {}
-
-Use # for main topic, ## for themes (3-5), ### for key points (2-4 each).
-Output in Chinese, keep important English terms. Be concise.
-Output Markdown only:"#,
-            title,
-            &content[..content.len().min(8000)]
-        );
-
-        self.call_claude(&prompt).await
-    }
+        let prompt = ai_prompts::template(PromptTask::Chapters).render(&[
+            ("duration_minutes", &duration_minutes),
+            ("duration_seconds", &duration_seconds),
+            ("subtitles", &sampled_text),
+            ("target", profile.target_name()),
+        ]);
 
-    async fn generate_slides(&self, title: &str, content: &str) -> Result<Vec<Slide>> {
-        let prompt = format!(
-            r#"Generate presentation slides based on video content.
-
-Title: {}
-Content: {}
-
-Requirements:
-- Create 8-12 slides with rich content
-- Structure: Title slide + 6-10 content slides + Summary slide
-- Each slide: 3-5 bullet points, each expressing a complete idea
-- Bullet points should be specific and informative
-- Write in English
-- Use notes field for key details or examples
-
-Return JSON array:
-[{{"slide_type": "title", "title": "Main Title", "subtitle": "Source", "bullets": [], "notes": null}},
-{{"slide_type": "content", "title": "Key Point", "subtitle": null, "bullets": ["Complete description"], "notes": "Additional details"}},
-{{"slide_type": "summary", "title": "Key Takeaways", "subtitle": null, "bullets": ["Specific takeaway"], "notes": null}}]"#,
-            title,
-            &content[..content.len().min(12000)]
-        );
-
-        let response = self.call_claude(&prompt).await?;
-        parse_slides_response(&response)
+        let list: ChapterList = generate_json(&self.transport, &prompt).await?;
+        Ok(list.chapters)
     }
 
-    async fn generate_chapters(&self, subtitles: &[Subtitle]) -> Result<Vec<Chapter>> {
-        // Sample subtitles evenly across the entire video
-        let sampled_text = sample_subtitles_for_chapters(subtitles, 12000);
-        let total_duration = subtitles.last().map(|s| s.end).unwrap_or(0.0);
-        let duration_min = (total_duration / 60.0).ceil() as i32;
-
-        let prompt = format!(
-            r#"Create 6-12 chapters for this ~{}min video covering the ENTIRE duration (0 to {}s).
-Subtitles: {}
-Return JSON array: [{{"title": "Chapter Name", "start_time": 0}}]"#,
-            duration_min,
-            total_duration as i32,
-            sampled_text
-        );
-
-        let response = self.call_claude(&prompt).await?;
-        parse_chapters_response(&response)
-    }
-
-    async fn generate_review_questions(&self, vocab_list: &[VocabForReview]) -> Result<Vec<ReviewQuestion>> {
+    async fn generate_review_questions(&self, vocab_list: &[VocabForReview], profile: &LearnerProfile) -> Result<Vec<ReviewQuestion>> {
         let mut questions = Vec::new();
         let question_types = ["meaning", "usage", "context", "spelling"];
 
         for (i, vocab) in vocab_list.iter().enumerate() {
             let question_type = question_types[i % question_types.len()];
-            let context_hint = vocab.source_sentence.as_deref().unwrap_or("无语境");
-
-            let prompt = format!(
-                r#"生成一个复习问题。单词: "{}", 含义: "{}", 语境: "{}", 类型: {}
-meaning=问含义, usage=造句, context=回顾语境, spelling=听写
-用中文，友好简洁，不超过30字，不透露答案。只返回问题:"#,
-                vocab.word, vocab.meaning, context_hint, question_type
-            );
-
-            let question_text = self.call_claude(&prompt).await.unwrap_or_else(|_| {
-                format!("「{}」是什么意思？", vocab.word)
-            });
-
-            questions.push(ReviewQuestion {
-                vocab_id: vocab.id,
-                word: vocab.word.clone(),
-                meaning: vocab.meaning.clone(),
-                source_sentence: vocab.source_sentence.clone(),
-                question_type: question_type.to_string(),
-                question: question_text.trim().to_string(),
-            });
+            questions.push(self.generate_single_review_question(vocab, question_type, profile).await?);
         }
 
         Ok(questions)
     }
 
-    async fn generate_single_review_question(&self, vocab: &VocabForReview, question_type: &str) -> Result<ReviewQuestion> {
-        let context_hint = vocab.source_sentence.as_deref().unwrap_or("无语境");
+    async fn generate_single_review_question(&self, vocab: &VocabForReview, question_type: &str, profile: &LearnerProfile) -> Result<ReviewQuestion> {
+        let context_hint = vocab.source_sentence.as_deref().unwrap_or("(no context)");
 
-        let prompt = format!(
-            r#"生成一个复习问题。单词: "{}", 含义: "{}", 语境: "{}", 类型: {}
-meaning=问含义, usage=造句, context=回顾语境, spelling=听写
-用中文，友好简洁，不超过30字，不透露答案。只返回问题:"#,
-            vocab.word, vocab.meaning, context_hint, question_type
-        );
+        let prompt = ai_prompts::template(PromptTask::ReviewQuestion).render(&[
+            ("word", &vocab.word),
+            ("meaning", &vocab.meaning),
+            ("context", context_hint),
+            ("question_type", question_type),
+            ("native", profile.native_name()),
+            ("target", profile.target_name()),
+        ]);
 
-        let question_text = self.call_claude(&prompt).await.unwrap_or_else(|_| {
-            format!("「{}」是什么意思？", vocab.word)
+        let question_text = self.transport.complete(&prompt).await.unwrap_or_else(|_| {
+            language::fallback_review_question(&profile.native_language, question_type, &vocab.word)
         });
 
         Ok(ReviewQuestion {
@@ -955,15 +565,18 @@ meaning=问含义, usage=造句, context=回顾语境, spelling=听写
         meaning: &str,
         question: &str,
         user_answer: &str,
+        profile: &LearnerProfile,
     ) -> Result<ReviewEvaluation> {
-        let prompt = format!(
-            r#"评估答案。单词:"{}" 含义:"{}" 问题:"{}" 回答:"{}"
-返回JSON: {{"is_correct":bool,"feedback":"简短反馈","follow_up":null或追问,"quality":0-3}}"#,
-            word, meaning, question, user_answer
-        );
-
-        let response = self.call_claude(&prompt).await?;
-        parse_review_evaluation(&response)
+        let prompt = ai_prompts::template(PromptTask::EvaluateAnswer).render(&[
+            ("word", word),
+            ("meaning", meaning),
+            ("question", question),
+            ("answer", user_answer),
+            ("native", profile.native_name()),
+            ("target", profile.target_name()),
+        ]);
+
+        generate_json(&self.transport, &prompt).await
     }
 
     async fn generate_memory_card(
@@ -971,654 +584,273 @@ meaning=问含义, usage=造句, context=回顾语境, spelling=听写
         word: &str,
         meaning: &str,
         context: Option<&str>,
+        profile: &LearnerProfile,
     ) -> Result<MemoryCard> {
-        let context_text = context.unwrap_or("No specific context");
-        let prompt = format!(
-            r#"Generate vocabulary memory card. Word:"{}" Meaning:"{}" Context:"{}"
-Requirements: etymology (word roots analysis), real-life American example sentence
-Return JSON:{{"phonetic":"IPA","part_of_speech":"pos","etymology":"word origin in English","example_sentence":"realistic American daily life sentence"}}"#,
-            word, meaning, context_text
-        );
-        let response = self.call_claude(&prompt).await?;
-        parse_memory_card_response(&response, word, meaning)
+        let prompt = ai_prompts::template(PromptTask::MemoryCard).render(&[
+            ("word", word),
+            ("meaning", meaning),
+            ("context", context.unwrap_or("No specific context")),
+            ("native", profile.native_name()),
+            ("target", profile.target_name()),
+        ]);
+
+        let card: MemoryCardResponse = generate_json(&self.transport, &prompt).await?;
+        Ok(memory_card(word, meaning, card))
     }
 }
 
-impl ClaudeProvider {
-    async fn call_claude(&self, prompt: &str) -> Result<String> {
-        #[derive(Serialize)]
-        struct ClaudeRequest {
-            model: String,
-            max_tokens: u32,
-            messages: Vec<ClaudeMessage>,
-        }
-
-        #[derive(Serialize)]
-        struct ClaudeMessage {
-            role: String,
-            content: String,
-        }
-
-        #[derive(Deserialize)]
-        struct ClaudeResponse {
-            content: Vec<ClaudeContent>,
-        }
-
-        #[derive(Deserialize)]
-        struct ClaudeContent {
-            text: String,
-        }
-
-        let request = ClaudeRequest {
-            model: "claude-3-haiku-20240307".to_string(),
-            max_tokens: 1024,
-            messages: vec![ClaudeMessage {
-                role: "user".to_string(),
-                content: prompt.to_string(),
-            }],
-        };
-
-        let response = self
-            .client
-            .post("https://api.anthropic.com/v1/messages")
-            .header("x-api-key", &self.api_key)
-            .header("anthropic-version", "2023-06-01")
-            .header("content-type", "application/json")
-            .json(&request)
-            .send()
-            .await?
-            .json::<ClaudeResponse>()
-            .await?;
-
-        response
-            .content
-            .into_iter()
-            .next()
-            .map(|c| c.text)
-            .ok_or_else(|| anyhow!("No response from Claude"))
+/// Level range shown in prompts, e.g. "N5-N1"
+fn level_range(levels: &language::LevelSystem) -> String {
+    match (levels.levels.first(), levels.levels.last()) {
+        (Some(first), Some(last)) => format!("{}-{}", first, last),
+        _ => String::new(),
     }
 }
 
-// ============================================================================
-// OpenAI Provider (placeholder)
-// ============================================================================
-
-pub struct OpenAIProvider {
-    api_key: String,
-    client: Client,
+/// Drop indices the model made up
+fn valid_highlights(list: HighlightList, subtitle_count: usize) -> Vec<usize> {
+    list.indices.into_iter().filter(|i| *i < subtitle_count).collect()
 }
 
-impl OpenAIProvider {
-    pub fn new(api_key: String) -> Self {
-        Self {
-            api_key,
-            client: Client::new(),
-        }
+fn expect_count(actual: usize, expected: usize) -> std::result::Result<(), String> {
+    if actual == expected {
+        Ok(())
+    } else {
+        Err(format!("expected {} items, got {}", expected, actual))
     }
 }
 
-#[async_trait]
-impl AiProvider for OpenAIProvider {
-    async fn analyze_highlights(&self, subtitles: &[Subtitle]) -> Result<Vec<usize>> {
-        let subtitle_text: String = subtitles
-            .iter()
-            .enumerate()
-            .map(|(i, s)| format!("[{}] {}", i, s.text))
-            .collect::<Vec<_>>()
-            .join("\n");
-
-        let prompt = format!(
-            r#"Analyze these English subtitles and identify 5-10 important sentences for English learners.
-Return ONLY a JSON array of indices.
-
-Subtitles:
-{}
-
-Response:"#,
-            subtitle_text
-        );
-
-        let response = self.call_openai(&prompt).await?;
-
-        let re = regex::Regex::new(r"\[[\d,\s]+\]").unwrap();
-        let indices: Vec<usize> = re
-            .find(&response)
-            .and_then(|m| serde_json::from_str(m.as_str()).ok())
-            .unwrap_or_default();
-
-        Ok(indices)
-    }
-
-    async fn ask_question(&self, context: &str, question: &str) -> Result<String> {
-        let prompt = format!(
-            r#"你是一位英语学习助手。用户正在观看英语视频并提问。
-
-当前字幕上下文:
-"{}"
-
-用户问题: {}
-
-回答要求:
-1. 如果问题与视频内容无关或是简单的闲聊（如"OK"、"好的"、"谢谢"），只需一句话简短回应
-2. 只有当问题确实与视频内容或英语学习相关时，才详细解释
-3. 回答要简洁，通常2-3句话足够，最多不超过100字
-4. 解释词汇或语法时要结合视频上下文
-
-用与问题相同的语言回答。"#,
-            context, question
-        );
-
-        self.call_openai(&prompt).await
+fn memory_card(word: &str, meaning: &str, card: MemoryCardResponse) -> MemoryCard {
+    MemoryCard {
+        word: word.to_string(),
+        phonetic: card.phonetic,
+        part_of_speech: card.part_of_speech,
+        meaning: meaning.to_string(),
+        etymology: card.etymology,
+        mnemonic: card.mnemonic,
+        memory_story: card.memory_story,
+        example_sentence: card.example_sentence,
+        visual_hint: card.visual_hint,
     }
+}
 
-    async fn translate_subtitles(&self, subtitles: &[Subtitle]) -> Result<Vec<String>> {
-        let mut all_translations = Vec::new();
-
-        for chunk in subtitles.chunks(20) {
-            let texts: Vec<String> = chunk
-                .iter()
-                .enumerate()
-                .map(|(i, s)| format!("[{}] {}", i, s.text))
-                .collect();
-
-            let prompt = format!(
-                r#"Translate these English subtitles to Chinese (Simplified).
-Return ONLY a JSON array of translated strings.
-
-Subtitles:
-{}
-
-Response (JSON array only):"#,
-                texts.join("\n")
-            );
-
-            let response = self.call_openai(&prompt).await?;
-            let translations = parse_translation_response(&response, chunk.len());
-            all_translations.extend(translations);
-        }
-
-        while all_translations.len() < subtitles.len() {
-            all_translations.push(String::new());
-        }
-        all_translations.truncate(subtitles.len());
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::ai_json::OutputSchema;
+    use crate::services::ai_usage;
 
-        Ok(all_translations)
+    fn turn(role: ChatRole, content: &str) -> ChatTurn {
+        ChatTurn { role, content: content.to_string() }
     }
 
-    async fn extract_vocabulary(&self, text: &str) -> Result<Vec<VocabularyItem>> {
-        let prompt = format!(
-            r#"Extract important vocabulary (IELTS, TOEFL, CET-4/6) from this sentence: "{}"
-Return JSON array: [{{"word": "...", "meaning": "(v.) Chinese meaning", "level": "雅思/四级/六级/托福", "example": "Short daily example"}}]
-Only return JSON array:"#,
-            text
-        );
+    #[test]
+    fn test_truncate_history_keeps_recent_turns() {
+        let history = vec![
+            turn(ChatRole::User, "first question"),
+            turn(ChatRole::Assistant, "first answer"),
+            turn(ChatRole::User, "second question"),
+            turn(ChatRole::Assistant, "second answer"),
+        ];
 
-        let response = self.call_openai(&prompt).await?;
-        Ok(parse_vocabulary_response(&response))
+        assert_eq!(truncate_history(&history, 20, 10_000).len(), 4);
+        assert_eq!(truncate_history(&history, 2, 10_000)[0].content, "second question");
+        assert!(truncate_history(&history, 20, 5).is_empty());
     }
 
-    async fn generate_mindmap(&self, title: &str, content: &str) -> Result<String> {
-        let prompt = format!(
-            r#"Generate a mind map in Markdown format for this video.
-Title: {}
-Content: {}
+    #[test]
+    fn test_truncate_history_starts_on_user_turn() {
+        let history = vec![
+            turn(ChatRole::User, "question"),
+            turn(ChatRole::Assistant, "a long answer"),
+            turn(ChatRole::User, "next"),
+            turn(ChatRole::Assistant, "ok"),
+        ];
 
-Use # for main topic, ## for themes (3-5), ### for key points (2-4 each).
-Output in Chinese, keep important English terms. Be concise.
-Output Markdown only:"#,
-            title,
-            &content[..content.len().min(8000)]
-        );
-
-        self.call_openai(&prompt).await
-    }
-
-    async fn generate_slides(&self, title: &str, content: &str) -> Result<Vec<Slide>> {
-        let prompt = format!(
-            r#"Generate presentation slides based on video content to help users quickly understand and review the key points.
-
-Video Title: {}
-Content: {}
-
-Requirements:
-- Create 8-12 slides with rich content
-- Structure: Title slide + 6-10 content slides + Summary slide
-- Each slide should have 3-5 bullet points, each expressing a complete idea
-- Bullet points should be specific and informative, avoid vague statements
-- Write in English
-- Use notes field for key details or examples (1-2 sentences)
-
-Return JSON array:
-[{{"slide_type": "title", "title": "Main Title", "subtitle": "Speaker/Source", "bullets": [], "notes": null}},
-{{"slide_type": "content", "title": "Key Point Title", "subtitle": null, "bullets": ["Complete description of the point"], "notes": "Additional details"}},
-{{"slide_type": "summary", "title": "Key Takeaways", "subtitle": null, "bullets": ["Specific takeaway"], "notes": null}}]"#,
-            title,
-            &content[..content.len().min(12000)]
-        );
-
-        let response = self.call_openai(&prompt).await?;
-        parse_slides_response(&response)
+        // The budget fits the last three turns, but the orphaned answer is dropped
+        let kept = truncate_history(&history, 3, 10_000);
+        assert_eq!(kept.len(), 2);
+        assert_eq!(kept[0].role, ChatRole::User);
     }
 
-    async fn generate_chapters(&self, subtitles: &[Subtitle]) -> Result<Vec<Chapter>> {
-        // Sample subtitles evenly across the entire video
-        let sampled_text = sample_subtitles_for_chapters(subtitles, 12000);
-        let total_duration = subtitles.last().map(|s| s.end).unwrap_or(0.0);
-        let duration_min = (total_duration / 60.0).ceil() as i32;
-
-        let prompt = format!(
-            r#"Create 6-12 chapters for this ~{}min video covering the ENTIRE duration (0 to {}s).
-Subtitles: {}
-Return JSON array: [{{"title": "Chapter Name", "start_time": 0}}]"#,
-            duration_min,
-            total_duration as i32,
-            sampled_text
-        );
-
-        let response = self.call_openai(&prompt).await?;
-        parse_chapters_response(&response)
-    }
+    /// Minimal OpenAI-compatible server: replies with the requested model name,
+    /// as one message or as two streamed chunks; JSON-mode chapter requests get one chapter named after the model
+    async fn spawn_mock_openai_server() -> String {
+        use axum::{http::header, response::IntoResponse, routing::post, Json, Router};
 
-    async fn generate_review_questions(&self, vocab_list: &[VocabForReview]) -> Result<Vec<ReviewQuestion>> {
-        let mut questions = Vec::new();
-        let question_types = ["meaning", "usage", "context", "spelling"];
-
-        for (i, vocab) in vocab_list.iter().enumerate() {
-            let question_type = question_types[i % question_types.len()];
-            let context_hint = vocab.source_sentence.as_deref().unwrap_or("无语境");
-
-            let prompt = format!(
-                r#"生成一个复习问题。单词: "{}", 含义: "{}", 语境: "{}", 类型: {}
-meaning=问含义, usage=造句, context=回顾语境, spelling=听写
-用中文，友好简洁，不超过30字，不透露答案。只返回问题:"#,
-                vocab.word, vocab.meaning, context_hint, question_type
-            );
-
-            let question_text = self.call_openai(&prompt).await.unwrap_or_else(|_| {
-                format!("「{}」是什么意思？", vocab.word)
-            });
-
-            questions.push(ReviewQuestion {
-                vocab_id: vocab.id,
-                word: vocab.word.clone(),
-                meaning: vocab.meaning.clone(),
-                source_sentence: vocab.source_sentence.clone(),
-                question_type: question_type.to_string(),
-                question: question_text.trim().to_string(),
-            });
+        async fn chat_completions(Json(body): Json<serde_json::Value>) -> axum::response::Response {
+            let model = body["model"].as_str().unwrap_or_default().to_string();
+            if body["response_format"]["json_schema"]["name"] == "chapters" {
+                let content = serde_json::json!({"chapters": [{"title": model, "start_time": 0}]}).to_string();
+                return Json(serde_json::json!({"choices": [{"message": {"content": content}}]})).into_response();
+            }
+            if body["stream"].as_bool().unwrap_or(false) {
+                let events = format!(
+                    "data: {}\n\ndata: {}\n\ndata: [DONE]\n\n",
+                    serde_json::json!({"choices": [{"delta": {"content": "model="}}]}),
+                    serde_json::json!({"choices": [{"delta": {"content": model}}]}),
+                );
+                ([(header::CONTENT_TYPE, "text/event-stream")], events).into_response()
+            } else {
+                Json(serde_json::json!({
+                    "choices": [{"message": {"content": format!("model={}", model)}}],
+                    "usage": {"prompt_tokens": 7, "completion_tokens": 3},
+                }))
+                .into_response()
+            }
         }
 
-        Ok(questions)
-    }
-
-    async fn generate_single_review_question(&self, vocab: &VocabForReview, question_type: &str) -> Result<ReviewQuestion> {
-        let context_hint = vocab.source_sentence.as_deref().unwrap_or("无语境");
-
-        let prompt = format!(
-            r#"生成一个复习问题。单词: "{}", 含义: "{}", 语境: "{}", 类型: {}
-meaning=问含义, usage=造句, context=回顾语境, spelling=听写
-用中文，友好简洁，不超过30字，不透露答案。只返回问题:"#,
-            vocab.word, vocab.meaning, context_hint, question_type
-        );
-
-        let question_text = self.call_openai(&prompt).await.unwrap_or_else(|_| {
-            format!("「{}」是什么意思？", vocab.word)
+        let app = Router::new().route("/v1/chat/completions", post(chat_completions));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
         });
-
-        Ok(ReviewQuestion {
-            vocab_id: vocab.id,
-            word: vocab.word.clone(),
-            meaning: vocab.meaning.clone(),
-            source_sentence: vocab.source_sentence.clone(),
-            question_type: question_type.to_string(),
-            question: question_text.trim().to_string(),
-        })
-    }
-
-    async fn evaluate_review_answer(
-        &self,
-        word: &str,
-        meaning: &str,
-        question: &str,
-        user_answer: &str,
-    ) -> Result<ReviewEvaluation> {
-        let prompt = format!(
-            r#"评估答案。单词:"{}" 含义:"{}" 问题:"{}" 回答:"{}"
-返回JSON: {{"is_correct":bool,"feedback":"简短反馈","follow_up":null或追问,"quality":0-3}}"#,
-            word, meaning, question, user_answer
-        );
-
-        let response = self.call_openai(&prompt).await?;
-        parse_review_evaluation(&response)
-    }
-
-    async fn generate_memory_card(
-        &self,
-        word: &str,
-        meaning: &str,
-        context: Option<&str>,
-    ) -> Result<MemoryCard> {
-        let context_text = context.unwrap_or("No specific context");
-        let prompt = format!(
-            r#"Generate vocabulary memory card. Word:"{}" Meaning:"{}" Context:"{}"
-Requirements: etymology (word roots analysis), real-life American example sentence
-Return JSON:{{"phonetic":"IPA","part_of_speech":"pos","etymology":"word origin in English","example_sentence":"realistic American daily life sentence"}}"#,
-            word, meaning, context_text
-        );
-        let response = self.call_openai(&prompt).await?;
-        parse_memory_card_response(&response, word, meaning)
-    }
-}
-
-impl OpenAIProvider {
-    async fn call_openai(&self, prompt: &str) -> Result<String> {
-        #[derive(Serialize)]
-        struct OpenAIRequest {
-            model: String,
-            messages: Vec<OpenAIMessage>,
-        }
-
-        #[derive(Serialize)]
-        struct OpenAIMessage {
-            role: String,
-            content: String,
-        }
-
-        #[derive(Deserialize)]
-        struct OpenAIResponse {
-            choices: Vec<OpenAIChoice>,
-        }
-
-        #[derive(Deserialize)]
-        struct OpenAIChoice {
-            message: OpenAIMessageResponse,
-        }
-
-        #[derive(Deserialize)]
-        struct OpenAIMessageResponse {
-            content: String,
-        }
-
-        let request = OpenAIRequest {
-            model: "gpt-3.5-turbo".to_string(),
-            messages: vec![OpenAIMessage {
-                role: "user".to_string(),
-                content: prompt.to_string(),
-            }],
-        };
-
-        let response = self
-            .client
-            .post("https://api.openai.com/v1/chat/completions")
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .json(&request)
-            .send()
-            .await?
-            .json::<OpenAIResponse>()
-            .await?;
-
-        response
-            .choices
-            .into_iter()
-            .next()
-            .map(|c| c.message.content)
-            .ok_or_else(|| anyhow!("No response from OpenAI"))
-    }
-}
-
-/// Helper function to parse translation response from AI
-fn parse_translation_response(response: &str, expected_count: usize) -> Vec<String> {
-    // Try to parse directly as JSON array
-    if let Ok(translations) = serde_json::from_str::<Vec<String>>(response) {
-        return translations;
-    }
-
-    // Clean up the response - remove markdown code blocks
-    let cleaned = response
-        .trim()
-        .trim_start_matches("```json")
-        .trim_start_matches("```")
-        .trim_end_matches("```")
-        .trim();
-
-    // Try parsing cleaned response
-    if let Ok(translations) = serde_json::from_str::<Vec<String>>(cleaned) {
-        return translations;
-    }
-
-    // Try to extract JSON array using regex (multiline support)
-    let re = regex::Regex::new(r"(?s)\[[\s\S]*\]").unwrap();
-    if let Some(mat) = re.find(cleaned) {
-        if let Ok(translations) = serde_json::from_str::<Vec<String>>(mat.as_str()) {
-            return translations;
-        }
-    }
-
-    // Log the failed response for debugging
-    tracing::warn!("Failed to parse translation response: {}", &response[..response.len().min(200)]);
-
-    // Return fallback (empty strings so UI won't show error text)
-    vec![String::new(); expected_count]
-}
-
-/// Helper function to parse vocabulary response from AI
-fn parse_vocabulary_response(response: &str) -> Vec<VocabularyItem> {
-    // Try to parse directly as JSON array
-    if let Ok(items) = serde_json::from_str::<Vec<VocabularyItem>>(response) {
-        return items;
+        format!("http://{}/v1/", addr)
     }
 
-    // Clean up the response - remove markdown code blocks
-    let cleaned = response
-        .trim()
-        .trim_start_matches("```json")
-        .trim_start_matches("```")
-        .trim_end_matches("```")
-        .trim();
-
-    // Try parsing cleaned response
-    if let Ok(items) = serde_json::from_str::<Vec<VocabularyItem>>(cleaned) {
-        return items;
-    }
+    /// Translates every `[i] text` line of a prompt, except for prompts containing "garbled"
+    /// (unparseable output) or "limited" (rate limited)
+    struct LineTranslator;
 
-    // Try to extract JSON array using regex (multiline support)
-    let re = regex::Regex::new(r"(?s)\[[\s\S]*\]").unwrap();
-    if let Some(mat) = re.find(cleaned) {
-        if let Ok(items) = serde_json::from_str::<Vec<VocabularyItem>>(mat.as_str()) {
-            return items;
+    #[async_trait]
+    impl AiTransport for LineTranslator {
+        fn name(&self) -> &'static str {
+            "lines"
         }
-    }
 
-    // Try to salvage partial data from truncated JSON
-    // Find all complete JSON objects {...} and parse them individually
-    let mut items = Vec::new();
-    let obj_re = regex::Regex::new(r#"\{[^{}]*"word"\s*:\s*"[^"]+"\s*,[^{}]*\}"#).unwrap();
-    for mat in obj_re.find_iter(cleaned) {
-        if let Ok(item) = serde_json::from_str::<VocabularyItem>(mat.as_str()) {
-            items.push(item);
+        async fn complete(&self, _prompt: &str) -> Result<String> {
+            Err(anyhow!("only structured output is supported"))
         }
-    }
-
-    if !items.is_empty() {
-        tracing::info!("Salvaged {} vocabulary items from truncated response", items.len());
-        return items;
-    }
-
-    // Log the failed response for debugging (truncate at char boundary)
-    let truncated = response.char_indices()
-        .take_while(|(i, _)| *i < 200)
-        .map(|(_, c)| c)
-        .collect::<String>();
-    tracing::warn!("Failed to parse vocabulary response: {}", truncated);
 
-    // Return empty
-    vec![]
-}
-
-/// Helper function to parse slides response from AI
-fn parse_slides_response(response: &str) -> Result<Vec<Slide>> {
-    // Try to parse directly as JSON array
-    if let Ok(slides) = serde_json::from_str::<Vec<Slide>>(response) {
-        return Ok(slides);
-    }
-
-    // Clean up the response - remove markdown code blocks
-    let cleaned = response
-        .trim()
-        .trim_start_matches("```json")
-        .trim_start_matches("```")
-        .trim_end_matches("```")
-        .trim();
-
-    // Try parsing cleaned response
-    if let Ok(slides) = serde_json::from_str::<Vec<Slide>>(cleaned) {
-        return Ok(slides);
-    }
-
-    // Try to extract JSON array using regex (multiline support)
-    let re = regex::Regex::new(r"(?s)\[[\s\S]*\]").unwrap();
-    if let Some(mat) = re.find(cleaned) {
-        if let Ok(slides) = serde_json::from_str::<Vec<Slide>>(mat.as_str()) {
-            return Ok(slides);
+        async fn complete_stream(&self, _prompt: &str, _chunks: TextChunkSender) -> Result<String> {
+            Err(anyhow!("only structured output is supported"))
         }
-    }
-
-    // Log the failed response for debugging
-    tracing::warn!("Failed to parse slides response: {}", &response[..response.len().min(500)]);
-
-    Err(anyhow!("Failed to parse slides from AI response"))
-}
-
-/// Helper function to parse chapters response from AI
-fn parse_chapters_response(response: &str) -> Result<Vec<Chapter>> {
-    // Try to parse directly as JSON array
-    if let Ok(chapters) = serde_json::from_str::<Vec<Chapter>>(response) {
-        return Ok(chapters);
-    }
 
-    // Clean up the response - remove markdown code blocks
-    let cleaned = response
-        .trim()
-        .trim_start_matches("```json")
-        .trim_start_matches("```")
-        .trim_end_matches("```")
-        .trim();
-
-    // Try parsing cleaned response
-    if let Ok(chapters) = serde_json::from_str::<Vec<Chapter>>(cleaned) {
-        return Ok(chapters);
-    }
-
-    // Try to extract JSON array using regex (multiline support)
-    let re = regex::Regex::new(r"(?s)\[[\s\S]*\]").unwrap();
-    if let Some(mat) = re.find(cleaned) {
-        if let Ok(chapters) = serde_json::from_str::<Vec<Chapter>>(mat.as_str()) {
-            return Ok(chapters);
-        }
-    }
-
-    // Log the failed response for debugging
-    tracing::warn!("Failed to parse chapters response: {}", &response[..response.len().min(500)]);
-
-    Err(anyhow!("Failed to parse chapters from AI response"))
-}
-
-/// Helper function to parse review evaluation response from AI
-fn parse_review_evaluation(response: &str) -> Result<ReviewEvaluation> {
-    // Try to parse directly as JSON
-    if let Ok(eval) = serde_json::from_str::<ReviewEvaluation>(response) {
-        return Ok(eval);
-    }
-
-    // Clean up the response - remove markdown code blocks
-    let cleaned = response
-        .trim()
-        .trim_start_matches("```json")
-        .trim_start_matches("```")
-        .trim_end_matches("```")
-        .trim();
-
-    // Try parsing cleaned response
-    if let Ok(eval) = serde_json::from_str::<ReviewEvaluation>(cleaned) {
-        return Ok(eval);
-    }
-
-    // Try to extract JSON object using regex
-    let re = regex::Regex::new(r"(?s)\{[\s\S]*\}").unwrap();
-    if let Some(mat) = re.find(cleaned) {
-        if let Ok(eval) = serde_json::from_str::<ReviewEvaluation>(mat.as_str()) {
-            return Ok(eval);
+        async fn complete_json(&self, prompt: &str, _schema: &OutputSchema) -> Result<String> {
+            if prompt.contains("garbled") {
+                return Ok("not json".to_string());
+            }
+            if prompt.contains("limited") {
+                let response = reqwest::Response::from(axum::http::Response::builder().status(429).body("").unwrap());
+                return Err(response.error_for_status().unwrap_err().into());
+            }
+            let translations: Vec<String> = prompt
+                .lines()
+                .filter_map(|line| line.strip_prefix('[').and_then(|rest| rest.split_once("] ")))
+                .map(|(_, text)| format!("translated {}", text))
+                .collect();
+            Ok(serde_json::json!({ "translations": translations }).to_string())
         }
     }
 
-    // Log the failed response for debugging
-    tracing::warn!("Failed to parse review evaluation: {}", &response[..response.len().min(200)]);
-
-    // Return a default evaluation if parsing fails
-    Ok(ReviewEvaluation {
-        is_correct: false,
-        feedback: "我没能理解你的回答，请再试一次".to_string(),
-        follow_up: None,
-        quality: 1,
-    })
-}
-
-/// Helper struct for parsing memory card JSON response
-#[derive(Debug, Deserialize)]
-struct MemoryCardResponse {
-    phonetic: Option<String>,
-    part_of_speech: Option<String>,
-    etymology: Option<String>,
-    mnemonic: Option<String>,
-    memory_story: Option<String>,
-    example_sentence: Option<String>,
-    visual_hint: Option<String>,
-}
-
-/// Helper function to parse memory card response from AI
-fn parse_memory_card_response(response: &str, word: &str, meaning: &str) -> Result<MemoryCard> {
-    // Clean up the response - remove markdown code blocks
-    let cleaned = response
-        .trim()
-        .trim_start_matches("```json")
-        .trim_start_matches("```")
-        .trim_end_matches("```")
-        .trim();
-
-    // Try to parse as JSON
-    let parsed: Option<MemoryCardResponse> = serde_json::from_str(cleaned).ok().or_else(|| {
-        // Try to extract JSON object using regex
-        let re = regex::Regex::new(r"(?s)\{[\s\S]*\}").unwrap();
-        re.find(cleaned)
-            .and_then(|mat| serde_json::from_str(mat.as_str()).ok())
-    });
-
-    match parsed {
-        Some(card) => Ok(MemoryCard {
-            word: word.to_string(),
-            phonetic: card.phonetic,
-            part_of_speech: card.part_of_speech,
-            meaning: meaning.to_string(),
-            etymology: card.etymology,
-            mnemonic: card.mnemonic,
-            memory_story: card.memory_story,
-            example_sentence: card.example_sentence,
-            visual_hint: card.visual_hint,
-        }),
-        None => {
-            tracing::warn!("Failed to parse memory card response: {}", &response[..response.len().min(300)]);
-            // Return a basic card with just word and meaning
-            Ok(MemoryCard {
-                word: word.to_string(),
-                phonetic: None,
-                part_of_speech: None,
-                meaning: meaning.to_string(),
-                etymology: None,
-                mnemonic: None,
-                memory_story: None,
-                example_sentence: None,
-                visual_hint: None,
+    fn lines(texts: &[String]) -> Vec<Subtitle> {
+        texts
+            .iter()
+            .enumerate()
+            .map(|(index, text)| Subtitle {
+                index,
+                start: index as f64,
+                end: index as f64 + 1.0,
+                text: text.clone(),
+                translation: None,
+                words: Vec::new(),
             })
-        }
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_failed_translation_batch_keeps_the_others() {
+        let provider = PromptedProvider::new(LineTranslator);
+        let mut texts: Vec<String> = (0..25).map(|i| format!("line {}", i)).collect();
+        texts[22] = "garbled".to_string();
+
+        let translations = provider.translate_subtitles(&lines(&texts), &LearnerProfile::default()).await.unwrap();
+        assert_eq!(translations.len(), 25);
+        assert_eq!(translations[0], "translated line 0");
+        assert_eq!(translations[19], "translated line 19");
+        assert!(translations[20..].iter().all(String::is_empty));
+
+        // Nothing translated at all is still an error
+        let all_garbled = vec!["garbled".to_string(); 3];
+        assert!(provider.translate_subtitles(&lines(&all_garbled), &LearnerProfile::default()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_translation_batch_fails_the_call() {
+        let provider = PromptedProvider::new(LineTranslator);
+        let mut texts: Vec<String> = (0..25).map(|i| format!("line {}", i)).collect();
+        texts[22] = "limited".to_string();
+
+        // An error the router can retry, not a partly empty translation
+        let error = provider.translate_subtitles(&lines(&texts), &LearnerProfile::default()).await.unwrap_err();
+        assert!(is_transient(&error));
+    }
+
+    #[tokio::test]
+    async fn test_openai_compatible_provider_uses_base_url_and_model() {
+        let base_url = spawn_mock_openai_server().await;
+        let provider = PromptedProvider::new(OpenAITransport::compatible("openai_compatible", base_url, "local-model".to_string(), None));
+
+        let answer = provider
+            .ask_question("context", "question", &[], &LearnerProfile::default())
+            .await
+            .unwrap();
+        assert_eq!(answer, "model=local-model");
+        assert_eq!(provider.name(), "openai_compatible");
+    }
+
+    #[tokio::test]
+    async fn test_openai_compatible_provider_records_usage() {
+        let base_url = spawn_mock_openai_server().await;
+        let provider = PromptedProvider::new(OpenAITransport::compatible("openai_compatible", base_url, "local-model".to_string(), None));
+
+        let (answer, calls) = ai_usage::collect(provider.ask_question("context", "question", &[], &LearnerProfile::default())).await;
+        assert!(answer.is_ok());
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].provider, "openai_compatible");
+        assert_eq!(calls[0].model, "local-model");
+        assert_eq!(calls[0].usage, ai_usage::TokenUsage { input_tokens: 7, output_tokens: 3 });
+        assert_eq!(calls[0].cost_usd, 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_openai_compatible_provider_streams() {
+        let base_url = spawn_mock_openai_server().await;
+        let provider = PromptedProvider::new(OpenAITransport::compatible("ollama", base_url, "llama3.2".to_string(), None));
+        let (tx, mut rx) = mpsc::channel(8);
+
+        let answer = provider
+            .ask_question_stream("context", "question", &[], &LearnerProfile::default(), tx)
+            .await
+            .unwrap();
+        assert_eq!(answer, "model=llama3.2");
+
+        let mut chunks = Vec::new();
+        while let Some(chunk) = rx.recv().await {
+            chunks.push(chunk);
+        }
+        assert_eq!(chunks, vec!["model=", "llama3.2"]);
+    }
+
+    #[tokio::test]
+    async fn test_openai_compatible_provider_requests_json_schema() {
+        let base_url = spawn_mock_openai_server().await;
+        let provider = PromptedProvider::new(OpenAITransport::compatible("openai_compatible", base_url, "local-model".to_string(), None));
+        let subtitles = vec![Subtitle {
+            index: 0,
+            start: 0.0,
+            end: 2.0,
+            text: "Hello".to_string(),
+            translation: None,
+            words: Vec::new(),
+        }];
+
+        let chapters = provider
+            .generate_chapters(&subtitles, &LearnerProfile::default())
+            .await
+            .unwrap();
+        assert_eq!(chapters.len(), 1);
+        assert_eq!(chapters[0].title, "local-model");
     }
 }
@@ -0,0 +1,393 @@
+use anyhow::{anyhow, Result};
+use schemars::{gen::SchemaSettings, JsonSchema};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::services::ai::{Chapter, ReviewEvaluation, Slide, VocabularyItem};
+use crate::services::ai_transport::AiTransport;
+
+/// Longest previous response quoted back to the model in a repair request
+const MAX_REPAIR_ECHO_CHARS: usize = 4000;
+
+/// A JSON response the AI is asked for with a schema
+/// Providers only accept an object at the top level, so lists come wrapped in one
+pub trait StructuredOutput: DeserializeOwned + JsonSchema {
+    /// Name of the schema in provider requests
+    const NAME: &'static str;
+
+    /// Checks the schema can't express
+    fn validate(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct HighlightList {
+    pub indices: Vec<usize>,
+}
+
+impl StructuredOutput for HighlightList {
+    const NAME: &'static str = "highlights";
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct TranslationList {
+    pub translations: Vec<String>,
+}
+
+impl StructuredOutput for TranslationList {
+    const NAME: &'static str = "translations";
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct VocabularyList {
+    pub vocabulary: Vec<VocabularyItem>,
+}
+
+impl StructuredOutput for VocabularyList {
+    const NAME: &'static str = "vocabulary";
+
+    fn validate(&self) -> Result<(), String> {
+        if self.vocabulary.iter().any(|item| item.word.trim().is_empty()) {
+            return Err("every vocabulary item needs a word".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SlideList {
+    pub slides: Vec<Slide>,
+}
+
+impl StructuredOutput for SlideList {
+    const NAME: &'static str = "slides";
+
+    fn validate(&self) -> Result<(), String> {
+        if self.slides.is_empty() {
+            return Err("no slides".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ChapterList {
+    pub chapters: Vec<Chapter>,
+}
+
+impl StructuredOutput for ChapterList {
+    const NAME: &'static str = "chapters";
+
+    fn validate(&self) -> Result<(), String> {
+        if self.chapters.is_empty() {
+            return Err("no chapters".to_string());
+        }
+        if let Some(chapter) = self.chapters.iter().find(|c| !c.start_time.is_finite() || c.start_time < 0.0) {
+            return Err(format!("invalid start_time {} for \"{}\"", chapter.start_time, chapter.title));
+        }
+        Ok(())
+    }
+}
+
+impl StructuredOutput for ReviewEvaluation {
+    const NAME: &'static str = "review_evaluation";
+
+    fn validate(&self) -> Result<(), String> {
+        if !(0..=3).contains(&self.quality) {
+            return Err(format!("quality must be 0-3, got {}", self.quality));
+        }
+        Ok(())
+    }
+}
+
+/// Parts of a `MemoryCard` written by the AI (word and meaning are already known)
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct MemoryCardResponse {
+    pub phonetic: Option<String>,
+    pub part_of_speech: Option<String>,
+    pub etymology: Option<String>,
+    pub mnemonic: Option<String>,
+    pub memory_story: Option<String>,
+    pub example_sentence: Option<String>,
+    pub visual_hint: Option<String>,
+}
+
+impl StructuredOutput for MemoryCardResponse {
+    const NAME: &'static str = "memory_card";
+}
+
+/// JSON schema for a structured output, in the strict form OpenAI and Claude accept:
+/// everything inlined, every property required (optional ones are nullable), no extra properties
+pub struct OutputSchema {
+    pub name: &'static str,
+    pub schema: Value,
+}
+
+impl OutputSchema {
+    pub fn of<T: StructuredOutput>() -> Self {
+        let generator = SchemaSettings::draft07()
+            .with(|s| {
+                s.inline_subschemas = true;
+                s.option_add_null_type = true;
+                s.option_nullable = false;
+                s.meta_schema = None;
+            })
+            .into_generator();
+        let mut schema = serde_json::to_value(generator.into_root_schema_for::<T>()).unwrap_or_default();
+        make_strict(&mut schema);
+
+        Self { name: T::NAME, schema }
+    }
+
+    /// The OpenAPI subset Gemini's `responseSchema` takes: `nullable` instead of null types,
+    /// no `additionalProperties`
+    pub fn gemini(&self) -> Value {
+        let mut schema = self.schema.clone();
+        to_openapi(&mut schema);
+        schema
+    }
+}
+
+fn make_strict(schema: &mut Value) {
+    let Some(object) = schema.as_object_mut() else {
+        return;
+    };
+    for key in ["$schema", "title", "format", "minimum", "definitions"] {
+        object.remove(key);
+    }
+
+    if let Some(properties) = object.get_mut("properties").and_then(Value::as_object_mut) {
+        let required: Vec<Value> = properties.keys().cloned().map(Value::String).collect();
+        properties.values_mut().for_each(make_strict);
+        object.insert("required".to_string(), Value::Array(required));
+        object.insert("additionalProperties".to_string(), Value::Bool(false));
+    }
+    if let Some(items) = object.get_mut("items") {
+        make_strict(items);
+    }
+}
+
+fn to_openapi(schema: &mut Value) {
+    let Some(object) = schema.as_object_mut() else {
+        return;
+    };
+    object.remove("additionalProperties");
+
+    if let Some(Value::Array(types)) = object.get("type") {
+        let nullable = types.iter().any(|t| t == "null");
+        let non_null = types.iter().find(|t| *t != "null").cloned();
+        if let Some(t) = non_null {
+            object.insert("type".to_string(), t);
+        }
+        if nullable {
+            object.insert("nullable".to_string(), Value::Bool(true));
+        }
+    }
+
+    if let Some(properties) = object.get_mut("properties").and_then(Value::as_object_mut) {
+        properties.values_mut().for_each(to_openapi);
+    }
+    if let Some(items) = object.get_mut("items") {
+        to_openapi(items);
+    }
+}
+
+/// Ask for a structured output, with one repair request if the response doesn't validate
+pub async fn generate_json<T, C>(client: &C, prompt: &str) -> Result<T>
+where
+    T: StructuredOutput,
+    C: AiTransport + ?Sized,
+{
+    generate_json_with(client, prompt, |_: &T| Ok(())).await
+}
+
+/// Like [`generate_json`] with an extra check that depends on the request (e.g. the number of items)
+pub async fn generate_json_with<T, C>(
+    client: &C,
+    prompt: &str,
+    check: impl Fn(&T) -> Result<(), String> + Send + Sync,
+) -> Result<T>
+where
+    T: StructuredOutput,
+    C: AiTransport + ?Sized,
+{
+    let schema = OutputSchema::of::<T>();
+    let validate = |value: &T| value.validate().and_then(|_| check(value));
+
+    let response = client.complete_json(prompt, &schema).await?;
+    let error = match parse_structured(&response, validate) {
+        Ok(value) => return Ok(value),
+        Err(e) => e,
+    };
+
+    tracing::warn!("Invalid {} from AI ({}), asking for a repair", T::NAME, error);
+    let response = client
+        .complete_json(&repair_prompt(prompt, &response, &error), &schema)
+        .await?;
+    parse_structured(&response, validate).map_err(|e| anyhow!("AI returned invalid {}: {}", T::NAME, e))
+}
+
+/// Parse and validate a structured response
+/// Tolerates markdown fences and surrounding text from servers that ignore the JSON mode
+pub fn parse_structured<T: DeserializeOwned>(
+    response: &str,
+    validate: impl Fn(&T) -> Result<(), String>,
+) -> Result<T, String> {
+    let value: T = serde_json::from_str(extract_json_object(response)).map_err(|e| e.to_string())?;
+    validate(&value)?;
+    Ok(value)
+}
+
+fn extract_json_object(response: &str) -> &str {
+    let trimmed = response.trim();
+    match (trimmed.find('{'), trimmed.rfind('}')) {
+        (Some(start), Some(end)) if start < end => &trimmed[start..=end],
+        _ => trimmed,
+    }
+}
+
+fn repair_prompt(prompt: &str, response: &str, error: &str) -> String {
+    let echoed: String = response.chars().take(MAX_REPAIR_ECHO_CHARS).collect();
+    format!(
+        "{prompt}\n\nYour previous response could not be used: {error}\nPrevious response:\n{echoed}\n\nReply again with only the corrected JSON."
+    )
+}
+
+/// Request body fragment asking an OpenAI-compatible server for `schema`
+pub fn openai_response_format(schema: &OutputSchema) -> Value {
+    json!({
+        "type": "json_schema",
+        "json_schema": {
+            "name": schema.name,
+            "strict": true,
+            "schema": schema.schema,
+        },
+    })
+}
+
+/// Claude has no JSON mode; forcing a tool call whose input is the schema gives the same result
+pub fn claude_tool(schema: &OutputSchema) -> (Value, Value) {
+    let tool = json!({
+        "name": schema.name,
+        "description": "Record the response",
+        "input_schema": schema.schema,
+    });
+    let tool_choice = json!({"type": "tool", "name": schema.name});
+    (tool, tool_choice)
+}
+
+/// The input of the forced tool call in a Claude response, as JSON text
+pub fn claude_tool_input(response: &Value) -> Option<String> {
+    response["content"]
+        .as_array()?
+        .iter()
+        .find(|block| block["type"] == "tool_use")
+        .map(|block| block["input"].to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::ai::TextChunkSender;
+    use async_trait::async_trait;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_schema_is_strict_and_inlined() {
+        let schema = OutputSchema::of::<SlideList>().schema;
+        assert_eq!(schema["type"], "object");
+        assert_eq!(schema["additionalProperties"], false);
+        assert_eq!(schema["required"], json!(["slides"]));
+
+        let slide = &schema["properties"]["slides"]["items"];
+        assert_eq!(slide["additionalProperties"], false);
+        assert_eq!(slide["required"], json!(["bullets", "notes", "slide_type", "subtitle", "title"]));
+        assert_eq!(slide["properties"]["notes"]["type"], json!(["string", "null"]));
+        assert!(schema.get("definitions").is_none());
+        assert!(schema.get("$schema").is_none());
+    }
+
+    #[test]
+    fn test_gemini_schema_uses_nullable() {
+        let schema = OutputSchema::of::<MemoryCardResponse>().gemini();
+        assert_eq!(schema["properties"]["phonetic"]["type"], "string");
+        assert_eq!(schema["properties"]["phonetic"]["nullable"], true);
+        assert!(schema.get("additionalProperties").is_none());
+    }
+
+    #[test]
+    fn test_parse_tolerates_fences() {
+        let response = "```json\n{\"chapters\": [{\"title\": \"Intro\", \"start_time\": 0}]}\n```";
+        let parsed: ChapterList = parse_structured(response, |_: &ChapterList| Ok(())).unwrap();
+        assert_eq!(parsed.chapters[0].title, "Intro");
+    }
+
+    #[test]
+    fn test_parse_runs_validation() {
+        let response = r#"{"is_correct": true, "feedback": "ok", "follow_up": null, "quality": 5}"#;
+        let error = parse_structured(response, ReviewEvaluation::validate).unwrap_err();
+        assert!(error.contains("quality"), "{}", error);
+    }
+
+    /// Replays canned responses and records the prompts it was sent
+    struct Scripted {
+        responses: Mutex<Vec<&'static str>>,
+        prompts: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl AiTransport for Scripted {
+        fn name(&self) -> &'static str {
+            "scripted"
+        }
+
+        async fn complete(&self, _prompt: &str) -> Result<String> {
+            Err(anyhow!("only structured output is scripted"))
+        }
+
+        async fn complete_stream(&self, _prompt: &str, _chunks: TextChunkSender) -> Result<String> {
+            Err(anyhow!("only structured output is scripted"))
+        }
+
+        async fn complete_json(&self, prompt: &str, _schema: &OutputSchema) -> Result<String> {
+            self.prompts.lock().unwrap().push(prompt.to_string());
+            Ok(self.responses.lock().unwrap().remove(0).to_string())
+        }
+    }
+
+    fn scripted(responses: Vec<&'static str>) -> Scripted {
+        Scripted {
+            responses: Mutex::new(responses),
+            prompts: Mutex::new(Vec::new()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_invalid_response_is_repaired_once() {
+        let client = scripted(vec![r#"{"translations": ["one"]}"#, r#"{"translations": ["one", "two"]}"#]);
+        let list: TranslationList = generate_json_with(&client, "translate", |list: &TranslationList| {
+            if list.translations.len() == 2 {
+                Ok(())
+            } else {
+                Err("expected 2 translations".to_string())
+            }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(list.translations, vec!["one", "two"]);
+        let prompts = client.prompts.lock().unwrap();
+        assert_eq!(prompts.len(), 2);
+        assert!(prompts[1].starts_with("translate\n"));
+        assert!(prompts[1].contains("expected 2 translations"));
+    }
+
+    #[tokio::test]
+    async fn test_fails_when_repair_is_invalid_too() {
+        let client = scripted(vec!["not json", r#"{"slides": []}"#]);
+        let error = generate_json::<SlideList, _>(&client, "slides").await.unwrap_err();
+        assert!(error.to_string().contains("invalid slides"), "{}", error);
+    }
+}
@@ -0,0 +1,208 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+
+use crate::models::{LearnerProfile, Subtitle};
+use crate::services::ai::{
+    AiProvider, Chapter, ChatTurn, MemoryCard, ReviewEvaluation, ReviewQuestion, Slide, TextChunkSender,
+    VocabForReview, VocabularyItem,
+};
+
+/// Subtitles per generated chapter
+const CHAPTER_SIZE: usize = 10;
+/// Words shorter than this are not picked as vocabulary
+const MIN_VOCAB_WORD_LEN: usize = 7;
+const MAX_VOCAB_ITEMS: usize = 5;
+
+/// Provider with canned, deterministic output and no network access (`AI_PROVIDER=mock`)
+/// Used by tests and for running the app as a demo without an API key
+pub struct MockProvider;
+
+fn first_words(text: &str, count: usize) -> String {
+    text.split_whitespace().take(count).collect::<Vec<_>>().join(" ")
+}
+
+fn mock_answer(question: &str, history: &[ChatTurn]) -> String {
+    format!("Mock answer to \"{}\" ({} earlier messages)", question.trim(), history.len())
+}
+
+fn mock_question(vocab: &VocabForReview, question_type: &str) -> ReviewQuestion {
+    ReviewQuestion {
+        vocab_id: vocab.id,
+        word: vocab.word.clone(),
+        meaning: vocab.meaning.clone(),
+        source_sentence: vocab.source_sentence.clone(),
+        question_type: question_type.to_string(),
+        question: format!("[{}] What does \"{}\" mean?", question_type, vocab.word),
+    }
+}
+
+#[async_trait]
+impl AiProvider for MockProvider {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    async fn analyze_highlights(&self, subtitles: &[Subtitle], _profile: &LearnerProfile) -> Result<Vec<usize>> {
+        Ok(subtitles.iter().map(|s| s.index).step_by(3).collect())
+    }
+
+    async fn ask_question(&self, _context: &str, question: &str, history: &[ChatTurn], _profile: &LearnerProfile) -> Result<String> {
+        Ok(mock_answer(question, history))
+    }
+
+    async fn ask_question_stream(
+        &self,
+        _context: &str,
+        question: &str,
+        history: &[ChatTurn],
+        _profile: &LearnerProfile,
+        chunks: TextChunkSender,
+    ) -> Result<String> {
+        let answer = mock_answer(question, history);
+        for word in answer.split_inclusive(' ') {
+            chunks
+                .send(word.to_string())
+                .await
+                .map_err(|_| anyhow!("Stream receiver closed"))?;
+        }
+        Ok(answer)
+    }
+
+    async fn translate_subtitles(&self, subtitles: &[Subtitle], profile: &LearnerProfile) -> Result<Vec<String>> {
+        Ok(subtitles
+            .iter()
+            .map(|s| format!("[{}] {}", profile.native_language, s.text))
+            .collect())
+    }
+
+    async fn extract_vocabulary(&self, text: &str, profile: &LearnerProfile) -> Result<Vec<VocabularyItem>> {
+        let levels = profile.level_system().levels;
+        let mut items: Vec<VocabularyItem> = Vec::new();
+
+        for word in text.split_whitespace() {
+            let word = word.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase();
+            if word.chars().count() < MIN_VOCAB_WORD_LEN || items.iter().any(|i| i.word == word) {
+                continue;
+            }
+            items.push(VocabularyItem {
+                meaning: format!("[{}] {}", profile.native_language, word),
+                level: levels[items.len() % levels.len()].to_string(),
+                example: format!("This sentence uses \"{}\".", word),
+                word,
+            });
+            if items.len() == MAX_VOCAB_ITEMS {
+                break;
+            }
+        }
+
+        Ok(items)
+    }
+
+    async fn generate_mindmap(&self, title: &str, content: &str, _profile: &LearnerProfile) -> Result<String> {
+        Ok(format!(
+            "# {}\n## Overview\n- {}\n## Key points\n- Point one\n- Point two",
+            title,
+            first_words(content, 8)
+        ))
+    }
+
+    async fn generate_slides(&self, title: &str, content: &str, _profile: &LearnerProfile) -> Result<Vec<Slide>> {
+        Ok(vec![
+            Slide {
+                slide_type: "title".to_string(),
+                title: title.to_string(),
+                subtitle: Some("Mock presentation".to_string()),
+                bullets: Vec::new(),
+                notes: None,
+            },
+            Slide {
+                slide_type: "content".to_string(),
+                title: "Main ideas".to_string(),
+                subtitle: None,
+                bullets: vec![first_words(content, 8)],
+                notes: Some("Generated by the mock provider".to_string()),
+            },
+            Slide {
+                slide_type: "summary".to_string(),
+                title: "Summary".to_string(),
+                subtitle: None,
+                bullets: vec![format!("{} in one slide", title)],
+                notes: None,
+            },
+        ])
+    }
+
+    async fn generate_chapters(&self, subtitles: &[Subtitle], _profile: &LearnerProfile) -> Result<Vec<Chapter>> {
+        Ok(subtitles
+            .chunks(CHAPTER_SIZE)
+            .enumerate()
+            .map(|(i, chunk)| Chapter {
+                title: format!("Part {}: {}", i + 1, first_words(&chunk[0].text, 5)),
+                start_time: chunk[0].start,
+            })
+            .collect())
+    }
+
+    async fn generate_review_questions(&self, vocab_list: &[VocabForReview], _profile: &LearnerProfile) -> Result<Vec<ReviewQuestion>> {
+        let question_types = ["meaning", "usage", "context", "spelling"];
+        Ok(vocab_list
+            .iter()
+            .enumerate()
+            .map(|(i, vocab)| mock_question(vocab, question_types[i % question_types.len()]))
+            .collect())
+    }
+
+    async fn generate_single_review_question(&self, vocab: &VocabForReview, question_type: &str, _profile: &LearnerProfile) -> Result<ReviewQuestion> {
+        Ok(mock_question(vocab, question_type))
+    }
+
+    /// Correct when the answer mentions the word or its meaning
+    async fn evaluate_review_answer(
+        &self,
+        word: &str,
+        meaning: &str,
+        _question: &str,
+        user_answer: &str,
+        _profile: &LearnerProfile,
+    ) -> Result<ReviewEvaluation> {
+        let answer = user_answer.to_lowercase();
+        let is_correct = [word, meaning]
+            .iter()
+            .any(|expected| !expected.trim().is_empty() && answer.contains(&expected.trim().to_lowercase()));
+
+        Ok(ReviewEvaluation {
+            is_correct,
+            feedback: if is_correct {
+                format!("Correct, \"{}\" means {}.", word, meaning)
+            } else {
+                format!("Not quite, \"{}\" means {}.", word, meaning)
+            },
+            follow_up: None,
+            quality: if is_correct { 3 } else { 0 },
+        })
+    }
+
+    async fn generate_memory_card(
+        &self,
+        word: &str,
+        meaning: &str,
+        context: Option<&str>,
+        _profile: &LearnerProfile,
+    ) -> Result<MemoryCard> {
+        Ok(MemoryCard {
+            word: word.to_string(),
+            phonetic: Some(format!("/{}/", word.to_lowercase())),
+            part_of_speech: Some("noun".to_string()),
+            meaning: meaning.to_string(),
+            etymology: None,
+            mnemonic: Some(format!("Picture \"{}\" when you think of {}.", word, meaning)),
+            memory_story: None,
+            example_sentence: Some(
+                context
+                    .map(str::to_string)
+                    .unwrap_or_else(|| format!("Here is an example with \"{}\".", word)),
+            ),
+            visual_hint: None,
+        })
+    }
+}
@@ -0,0 +1,483 @@
+//! Prompt templates for every AI task, versioned so variants can be A/B tested per task
+//!
+//! Templates use `{name}` placeholders filled by [`PromptTemplate::render`]; JSON examples
+//! can be written as-is since `{"` never matches a placeholder.
+//! Pick versions with `AI_PROMPT_<TASK>`, e.g. `AI_PROMPT_SLIDES=v2` or `AI_PROMPT_SLIDES=v1:80,v2:20`.
+
+use once_cell::sync::Lazy;
+use rand::Rng;
+use regex::{Captures, Regex};
+use std::env;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PromptTask {
+    Highlights,
+    Ask,
+    Translate,
+    Vocabulary,
+    Mindmap,
+    Slides,
+    Chapters,
+    ReviewQuestion,
+    EvaluateAnswer,
+    MemoryCard,
+}
+
+impl PromptTask {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PromptTask::Highlights => "highlights",
+            PromptTask::Ask => "ask",
+            PromptTask::Translate => "translate",
+            PromptTask::Vocabulary => "vocabulary",
+            PromptTask::Mindmap => "mindmap",
+            PromptTask::Slides => "slides",
+            PromptTask::Chapters => "chapters",
+            PromptTask::ReviewQuestion => "review_question",
+            PromptTask::EvaluateAnswer => "evaluate_answer",
+            PromptTask::MemoryCard => "memory_card",
+        }
+    }
+
+    fn env_key(&self) -> String {
+        format!("AI_PROMPT_{}", self.as_str().to_uppercase())
+    }
+}
+
+pub struct PromptTemplate {
+    pub task: PromptTask,
+    pub version: &'static str,
+    pub text: &'static str,
+}
+
+static PLACEHOLDER: Lazy<Regex> = Lazy::new(|| Regex::new(r"\{([a-z_]+)\}").unwrap());
+
+impl PromptTemplate {
+    /// Fill `{name}` placeholders in one pass, so values containing braces are left alone
+    /// Placeholders without a value are kept as written
+    pub fn render(&self, vars: &[(&str, &str)]) -> String {
+        PLACEHOLDER
+            .replace_all(self.text, |caps: &Captures| {
+                vars.iter()
+                    .find(|(name, _)| *name == &caps[1])
+                    .map(|(_, value)| value.to_string())
+                    .unwrap_or_else(|| caps[0].to_string())
+            })
+            .into_owned()
+    }
+}
+
+/// The version used when `AI_PROMPT_<TASK>` is not set
+pub const DEFAULT_VERSION: &str = "v1";
+
+/// Pick the template version for `task` from `AI_PROMPT_<TASK>`
+pub fn template(task: PromptTask) -> &'static PromptTemplate {
+    let spec = env::var(task.env_key()).unwrap_or_default();
+    let version = choose_version(&spec, rand::thread_rng().gen_range(0.0..1.0));
+
+    let template = find(task, version).unwrap_or_else(|| {
+        tracing::warn!("Unknown prompt version {} for {}, using {}", version, task.as_str(), DEFAULT_VERSION);
+        find(task, DEFAULT_VERSION).expect("every task has a default prompt")
+    });
+    tracing::debug!("Prompt {} {}", task.as_str(), template.version);
+    template
+}
+
+fn find(task: PromptTask, version: &str) -> Option<&'static PromptTemplate> {
+    TEMPLATES.iter().find(|t| t.task == task && t.version == version)
+}
+
+/// `spec` is a version ("v2") or weighted versions ("v1:80,v2:20"); `roll` is uniform in [0, 1)
+fn choose_version(spec: &str, roll: f64) -> &str {
+    let weighted: Vec<(&str, f64)> = spec
+        .split(',')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .map(|part| match part.split_once(':') {
+            Some((version, weight)) => (version.trim(), weight.trim().parse().unwrap_or(0.0)),
+            None => (part, 1.0),
+        })
+        .filter(|(_, weight)| *weight > 0.0)
+        .collect();
+
+    let total: f64 = weighted.iter().map(|(_, w)| w).sum();
+    let mut threshold = roll * total;
+    for (version, weight) in &weighted {
+        if threshold < *weight {
+            return version;
+        }
+        threshold -= weight;
+    }
+
+    weighted.last().map(|(v, _)| *v).unwrap_or(DEFAULT_VERSION)
+}
+
+const TEMPLATES: &[PromptTemplate] = &[
+    PromptTemplate {
+        task: PromptTask::Highlights,
+        version: "v1",
+        text: r#"Analyze these {target} subtitles from a video and identify the most important/educational sentences for {target} learners.
+
+Subtitles:
+{subtitles}
+
+Return JSON with the indices (numbers) of the 5-10 most important sentences.
+Important sentences include: key phrases, idiomatic expressions, useful grammar patterns, or main points.
+
+Example response: {"indices": [0, 3, 7, 12, 15]}
+
+Response:"#,
+    },
+    PromptTemplate {
+        task: PromptTask::Highlights,
+        version: "v2",
+        text: r#"Analyze these {target} subtitles and identify 5-10 important sentences for {target} learners.
+Return JSON: {"indices": [<numbers>]}
+
+Subtitles:
+{subtitles}
+
+Response (JSON only):"#,
+    },
+    PromptTemplate {
+        task: PromptTask::Ask,
+        version: "v1",
+        text: r#"You are a {target} learning assistant. The user is watching a {target} video and asking a question.
+The user's native language is {native}.
+
+Current subtitle context:
+"{context}"
+{conversation}
+User question: {question}
+
+Answer requirements:
+1. If the question is unrelated to the video or is small talk (e.g. "OK", "thanks"), reply with one short sentence
+2. Only explain in detail when the question is really about the video content or {target} learning
+3. Keep it concise: 2-3 sentences is usually enough, never more than 100 words
+4. When explaining vocabulary or grammar, relate it to the video context
+5. If the question follows up on the conversation (e.g. "what about the second phrase?"), resolve it from the earlier messages
+
+Answer in the same language as the question. If that is unclear, answer in {native}."#,
+    },
+    PromptTemplate {
+        task: PromptTask::Translate,
+        version: "v1",
+        text: r#"Translate the following {target} subtitles to {native}.
+Keep translations natural and conversational.
+Return JSON with one translated string per subtitle, in the same order.
+
+Subtitles:
+{subtitles}
+
+Example response format: {"translations": ["translation 1", "translation 2", "translation 3"]}
+
+Response (JSON only):"#,
+    },
+    PromptTemplate {
+        task: PromptTask::Translate,
+        version: "v2",
+        text: r#"Translate these {target} subtitles to {native}.
+Return JSON with one translated string per subtitle, in order: {"translations": ["..."]}
+
+Subtitles:
+{subtitles}
+
+Response (JSON only):"#,
+    },
+    PromptTemplate {
+        task: PromptTask::Vocabulary,
+        version: "v1",
+        text: r#"Extract key vocabulary and common phrases from the following {target} content.
+
+Content: "{text}"
+
+Requirements:
+1. Key words: vocabulary worth learning for a {system} {range} learner
+2. Common phrases: useful collocations, idioms and spoken expressions
+3. Do not extract the most basic words (articles, particles, auxiliaries and the like)
+4. For each item give: part of speech + meaning in {native}, level, and a practical {target} example sentence
+5. Level tags: {tags} (use {phrase} for phrases)
+6. Deduplicate: keep each word only once
+
+Return JSON:
+{"vocabulary": [
+  {"word": "<word>", "meaning": "(v.) <meaning in {native}>", "level": "{example_level}", "example": "<example sentence in {target}>"},
+  {"word": "<phrase>", "meaning": "(phrase) <meaning in {native}>", "level": "{phrase}", "example": "<example sentence in {target}>"}
+]}
+
+Return ONLY the JSON, nothing else:"#,
+    },
+    PromptTemplate {
+        task: PromptTask::Vocabulary,
+        version: "v2",
+        text: r#"Extract important {target} vocabulary and phrases from this sentence: "{text}"
+Level tags ({system}): {tags}
+Return JSON: {"vocabulary": [{"word": "...", "meaning": "(v.) meaning in {native}", "level": "one level tag", "example": "Short daily example in {target}"}]}
+Only return JSON:"#,
+    },
+    PromptTemplate {
+        task: PromptTask::Mindmap,
+        version: "v1",
+        text: r#"Generate a mind map in Markdown format based on the following video content.
+
+Video title: {title}
+
+Video subtitles:
+{content}
+
+Requirements:
+1. Use Markdown headings for the hierarchy (# level 1, ## level 2, ### level 3)
+2. Extract 3-5 main topics as level 2 headings
+3. List 2-4 key points under each topic as level 3 headings
+4. Key points may have list items (-) with supporting details
+5. Be concise, each point should be a short phrase
+6. Write in {native}, but keep important {target} terms
+
+Example format:
+# Video topic
+## Topic one
+### Key point 1
+- Supporting detail
+### Key point 2
+## Topic two
+### Key point 1
+
+Output the Markdown directly, without any other explanation:"#,
+    },
+    PromptTemplate {
+        task: PromptTask::Mindmap,
+        version: "v2",
+        text: r#"Generate a mind map in Markdown format for this video.
+Title: {title}
+Content: {content}
+
+Use # for main topic, ## for themes (3-5), ### for key points (2-4 each).
+Output in {native}, keep important {target} terms. Be concise.
+Output Markdown only:"#,
+    },
+    PromptTemplate {
+        task: PromptTask::Slides,
+        version: "v1",
+        text: r#"Generate presentation slides based on video content to help users quickly understand and review the key points.
+
+Video Title: {title}
+
+Content:
+{content}
+
+## Requirements
+
+1. Create 8-12 slides with rich content
+2. Structure: Title slide + 6-10 content slides + Summary slide
+3. Each slide should have 3-5 bullet points, each expressing a complete idea
+4. Bullet points should be specific and informative, avoid vague statements
+5. Write in {target}
+6. Use notes field for key details or examples (1-2 sentences)
+
+### JSON Format:
+{"slides": [
+  {"slide_type": "title", "title": "Main Title", "subtitle": "Speaker/Source", "bullets": [], "notes": null},
+  {"slide_type": "content", "title": "Key Point Title", "subtitle": null, "bullets": ["Complete description of the point", "Another informative bullet"], "notes": "Additional details or examples"},
+  {"slide_type": "summary", "title": "Key Takeaways", "subtitle": null, "bullets": ["Specific takeaway 1", "Specific takeaway 2"], "notes": null}
+]}
+
+Output JSON only:"#,
+    },
+    PromptTemplate {
+        task: PromptTask::Slides,
+        version: "v2",
+        text: r#"Generate presentation slides based on video content.
+
+Title: {title}
+Content: {content}
+
+Requirements:
+- Create 8-12 slides with rich content
+- Structure: Title slide + 6-10 content slides + Summary slide
+- Each slide: 3-5 bullet points, each expressing a complete idea
+- Bullet points should be specific and informative
+- Write in {target}
+- Use notes field for key details or examples
+
+Return JSON:
+{"slides": [{"slide_type": "title", "title": "Main Title", "subtitle": "Source", "bullets": [], "notes": null},
+{"slide_type": "content", "title": "Key Point", "subtitle": null, "bullets": ["Complete description"], "notes": "Additional details"},
+{"slide_type": "summary", "title": "Key Takeaways", "subtitle": null, "bullets": ["Specific takeaway"], "notes": null}]}"#,
+    },
+    PromptTemplate {
+        task: PromptTask::Chapters,
+        version: "v1",
+        text: r#"Analyze the following video subtitles and create a table of contents with 6-12 chapters.
+
+Video duration: approximately {duration_minutes} minutes
+Subtitles (sampled with timestamps in seconds):
+{subtitles}
+
+Requirements:
+1. Create 6-12 main chapters covering the ENTIRE video
+2. Each chapter should have a clear, concise title (in {target}, max 6 words)
+3. Use the exact start_time (in seconds) where each topic begins
+4. Chapters MUST be distributed across the full video duration (0 to ~{duration_seconds}s)
+5. First chapter should start at 0
+6. Last chapter should be in the final third of the video
+
+Return ONLY JSON in this format:
+{"chapters": [
+  {"title": "Introduction", "start_time": 0},
+  {"title": "Main Topic One", "start_time": 120},
+  {"title": "Key Concepts", "start_time": 300},
+  {"title": "Final Thoughts", "start_time": 600}
+]}
+
+JSON only:"#,
+    },
+    PromptTemplate {
+        task: PromptTask::Chapters,
+        version: "v2",
+        text: r#"Create 6-12 chapters for this ~{duration_minutes}min video covering the ENTIRE duration (0 to {duration_seconds}s).
+Chapter titles in {target}, max 6 words each.
+Subtitles: {subtitles}
+Return JSON: {"chapters": [{"title": "Chapter Name", "start_time": 0}]}"#,
+    },
+    PromptTemplate {
+        task: PromptTask::ReviewQuestion,
+        version: "v1",
+        text: r#"You are a friendly {target} teacher helping a student review vocabulary.
+
+Word: "{word}"
+Meaning: "{meaning}"
+Original sentence: "{context}"
+
+Write one natural review question. Question type: {question_type}
+- meaning: ask directly what the word means
+- usage: ask the student to make a sentence with the word
+- context: recall the context and ask whether the student remembers what the word meant in the original sentence
+- spelling: tell the student you will play the pronunciation and ask them to spell the word
+
+Requirements:
+1. Ask in {native}
+2. Friendly and relaxed tone, like chatting with a friend
+3. Keep the question short (one sentence)
+4. Do not reveal the answer
+
+Return only the question itself, nothing else:"#,
+    },
+    PromptTemplate {
+        task: PromptTask::ReviewQuestion,
+        version: "v2",
+        text: r#"Write one review question for a {target} learner. Word: "{word}", meaning: "{meaning}", context: "{context}", type: {question_type}
+meaning=ask the meaning, usage=make a sentence, context=recall the context, spelling=dictation
+Ask in {native}, friendly and short (one sentence), don't reveal the answer. Return only the question:"#,
+    },
+    PromptTemplate {
+        task: PromptTask::EvaluateAnswer,
+        version: "v1",
+        text: r#"You are a friendly {target} teacher grading a student's review answer.
+
+Word: "{word}"
+Meaning: "{meaning}"
+Question: "{question}"
+Student answer: "{answer}"
+
+Evaluate the student's answer:
+1. Decide whether it is correct (be lenient: correct or close in meaning is fine)
+2. Give short, friendly feedback in {native} (one short sentence)
+3. If the answer is incomplete, you may add a follow-up question to deepen understanding (optional)
+4. Give a quality score: 0=didn't remember at all, 1=remembered with great difficulty, 2=mostly got it, 3=very fluent
+
+Return JSON:
+{"is_correct": true/false, "feedback": "feedback", "follow_up": "follow-up question or null", "quality": 0-3}
+
+Return ONLY the JSON, nothing else:"#,
+    },
+    PromptTemplate {
+        task: PromptTask::EvaluateAnswer,
+        version: "v2",
+        text: r#"Grade a {target} learner's answer. Word:"{word}" Meaning:"{meaning}" Question:"{question}" Answer:"{answer}"
+Feedback and follow-up in {native}.
+Return JSON: {"is_correct":bool,"feedback":"short feedback","follow_up":null or follow-up question,"quality":0-3}"#,
+    },
+    PromptTemplate {
+        task: PromptTask::MemoryCard,
+        version: "v1",
+        text: r#"You are a vocabulary expert helping students understand {target} word origins and usage.
+
+Word: "{word}"
+Meaning: "{meaning}"
+Context: "{context}"
+
+Generate a memory card with:
+
+1. **Etymology**: Analyze word roots, prefixes, suffixes and explain the origin in {native}
+   - Example: "insulin" = insula (Latin for "island") + -in → substance secreted by islets of Langerhans
+   - If no clear roots, explain the word's historical evolution
+
+2. **Real-life Example**: A {target} sentence from everyday life
+   - Must be specific, realistic - like from TV shows or daily conversations
+   - Example: At the pharmacy, "I need to pick up my insulin prescription."
+
+Return JSON format only:
+{
+  "phonetic": "IPA phonetic transcription (or the standard reading for {target})",
+  "part_of_speech": "noun/verb/adj/adv/etc",
+  "etymology": "Etymology explanation",
+  "example_sentence": "Real-life {target} example sentence in a specific everyday scenario"
+}
+
+Return ONLY the JSON, nothing else:"#,
+    },
+    PromptTemplate {
+        task: PromptTask::MemoryCard,
+        version: "v2",
+        text: r#"Generate vocabulary memory card. Word:"{word}" Meaning:"{meaning}" Context:"{context}"
+Requirements: etymology (word roots analysis), real-life {target} example sentence
+Return JSON:{"phonetic":"IPA","part_of_speech":"pos","etymology":"word origin explained in {native}","example_sentence":"realistic daily life sentence"}"#,
+    },
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_task_has_a_default_template() {
+        let tasks = [
+            PromptTask::Highlights,
+            PromptTask::Ask,
+            PromptTask::Translate,
+            PromptTask::Vocabulary,
+            PromptTask::Mindmap,
+            PromptTask::Slides,
+            PromptTask::Chapters,
+            PromptTask::ReviewQuestion,
+            PromptTask::EvaluateAnswer,
+            PromptTask::MemoryCard,
+        ];
+        for task in tasks {
+            assert!(find(task, DEFAULT_VERSION).is_some(), "{} has no {}", task.as_str(), DEFAULT_VERSION);
+        }
+    }
+
+    #[test]
+    fn test_render_fills_placeholders_once() {
+        let template = find(PromptTask::Translate, "v2").unwrap();
+        let prompt = template.render(&[
+            ("target", "English"),
+            ("native", "Japanese"),
+            ("subtitles", "[0] say {native}"),
+        ]);
+
+        assert!(prompt.starts_with("Translate these English subtitles to Japanese."));
+        assert!(prompt.contains("[0] say {native}"));
+        assert!(prompt.contains(r#"{"translations": ["..."]}"#));
+    }
+
+    #[test]
+    fn test_choose_version() {
+        assert_eq!(choose_version("", 0.5), DEFAULT_VERSION);
+        assert_eq!(choose_version("v2", 0.9), "v2");
+        assert_eq!(choose_version("v1:80,v2:20", 0.5), "v1");
+        assert_eq!(choose_version("v1:80,v2:20", 0.85), "v2");
+        assert_eq!(choose_version("v1:0,v2:1", 0.0), "v2");
+    }
+}
@@ -0,0 +1,534 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use futures_util::future::BoxFuture;
+use reqwest::StatusCode;
+use std::collections::HashMap;
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+use crate::models::{LearnerProfile, Subtitle};
+use crate::services::ai::{
+    build_provider, AiProvider, Chapter, ChatTurn, MemoryCard, ReviewEvaluation, ReviewQuestion, Slide,
+    TextChunkSender, VocabForReview, VocabularyItem,
+};
+
+/// AI operations that can be routed to their own providers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AiMethod {
+    AnalyzeHighlights,
+    /// Also used for streamed answers
+    AskQuestion,
+    TranslateSubtitles,
+    ExtractVocabulary,
+    GenerateMindmap,
+    GenerateSlides,
+    GenerateChapters,
+    /// Batch and single review questions
+    GenerateReviewQuestions,
+    EvaluateReviewAnswer,
+    GenerateMemoryCard,
+}
+
+impl AiMethod {
+    pub const ALL: [AiMethod; 10] = [
+        AiMethod::AnalyzeHighlights,
+        AiMethod::AskQuestion,
+        AiMethod::TranslateSubtitles,
+        AiMethod::ExtractVocabulary,
+        AiMethod::GenerateMindmap,
+        AiMethod::GenerateSlides,
+        AiMethod::GenerateChapters,
+        AiMethod::GenerateReviewQuestions,
+        AiMethod::EvaluateReviewAnswer,
+        AiMethod::GenerateMemoryCard,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AiMethod::AnalyzeHighlights => "analyze_highlights",
+            AiMethod::AskQuestion => "ask_question",
+            AiMethod::TranslateSubtitles => "translate_subtitles",
+            AiMethod::ExtractVocabulary => "extract_vocabulary",
+            AiMethod::GenerateMindmap => "generate_mindmap",
+            AiMethod::GenerateSlides => "generate_slides",
+            AiMethod::GenerateChapters => "generate_chapters",
+            AiMethod::GenerateReviewQuestions => "generate_review_questions",
+            AiMethod::EvaluateReviewAnswer => "evaluate_review_answer",
+            AiMethod::GenerateMemoryCard => "generate_memory_card",
+        }
+    }
+
+    /// Env var overriding the provider chain for this method, e.g. `AI_ROUTE_TRANSLATE_SUBTITLES`
+    fn env_key(&self) -> String {
+        format!("AI_ROUTE_{}", self.as_str().to_uppercase())
+    }
+}
+
+/// How often a provider is retried on transient errors before moving on to the next one
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    /// Doubled after every retry
+    pub base_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 2,
+            base_delay: Duration::from_millis(500),
+        }
+    }
+}
+
+impl RetryPolicy {
+    const MAX_DELAY: Duration = Duration::from_secs(8);
+
+    /// Reads `AI_MAX_RETRIES` and `AI_RETRY_BASE_MS`
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        Self {
+            max_retries: env::var("AI_MAX_RETRIES")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default.max_retries),
+            base_delay: env::var("AI_RETRY_BASE_MS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .map(Duration::from_millis)
+                .unwrap_or(default.base_delay),
+        }
+    }
+
+    fn delay(&self, retry: u32) -> Duration {
+        self.base_delay
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(Self::MAX_DELAY)
+    }
+}
+
+/// Rate limits, server errors and network failures are worth retrying or handing to another provider;
+/// anything else (bad request, bad key, unparseable output) would fail the same way again
+pub(crate) fn is_transient(error: &anyhow::Error) -> bool {
+    error
+        .chain()
+        .filter_map(|e| e.downcast_ref::<reqwest::Error>())
+        .any(|e| match e.status() {
+            Some(status) => status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error(),
+            None => e.is_timeout() || e.is_connect(),
+        })
+}
+
+type ProviderChain = Vec<Arc<dyn AiProvider>>;
+
+/// Provider that sends each call through an ordered chain of providers,
+/// retrying transient errors with backoff and failing over to the next provider
+pub struct AiRouter {
+    default_chain: ProviderChain,
+    routes: HashMap<AiMethod, ProviderChain>,
+    retry: RetryPolicy,
+}
+
+impl AiRouter {
+    /// `default_chain` must not be empty
+    pub fn new(default_chain: ProviderChain, retry: RetryPolicy) -> Self {
+        Self {
+            default_chain,
+            routes: HashMap::new(),
+            retry,
+        }
+    }
+
+    /// Use a different provider chain for one method
+    pub fn with_route(mut self, method: AiMethod, chain: ProviderChain) -> Self {
+        if !chain.is_empty() {
+            self.routes.insert(method, chain);
+        }
+        self
+    }
+
+    /// Build the router from the environment:
+    /// - `AI_PROVIDER`: comma-separated provider specs in failover order (default "gemini")
+    /// - `AI_ROUTE_<METHOD>`: chain for one method, e.g. `AI_ROUTE_TRANSLATE_SUBTITLES=ollama,gemini`
+    /// - `AI_MAX_RETRIES` / `AI_RETRY_BASE_MS`: retry policy
+    ///
+    /// A spec is a provider name with an optional model, see [`build_provider`]
+    pub fn from_env() -> Result<Self> {
+        let mut built: HashMap<String, Arc<dyn AiProvider>> = HashMap::new();
+        let mut build_chain = |value: &str| -> Result<ProviderChain> {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|spec| !spec.is_empty())
+                .map(|spec| {
+                    if let Some(provider) = built.get(spec) {
+                        return Ok(provider.clone());
+                    }
+                    let provider: Arc<dyn AiProvider> = Arc::from(build_provider(spec)?);
+                    built.insert(spec.to_string(), provider.clone());
+                    Ok(provider)
+                })
+                .collect()
+        };
+
+        let default_chain = build_chain(&env::var("AI_PROVIDER").unwrap_or_else(|_| "gemini".to_string()))?;
+        if default_chain.is_empty() {
+            return Err(anyhow!("AI_PROVIDER is empty"));
+        }
+
+        let mut router = Self::new(default_chain, RetryPolicy::from_env());
+        for method in AiMethod::ALL {
+            if let Ok(value) = env::var(method.env_key()) {
+                router = router.with_route(method, build_chain(&value)?);
+            }
+        }
+
+        Ok(router)
+    }
+
+    fn chain(&self, method: AiMethod) -> &[Arc<dyn AiProvider>] {
+        self.routes.get(&method).unwrap_or(&self.default_chain)
+    }
+
+    /// Run `op` against each provider routed for `method` until one succeeds
+    async fn call<'a, T>(
+        &'a self,
+        method: AiMethod,
+        op: impl Fn(&'a dyn AiProvider) -> BoxFuture<'a, Result<T>>,
+    ) -> Result<T> {
+        let mut failures = Vec::new();
+
+        for provider in self.chain(method) {
+            let mut retry = 0;
+            loop {
+                let error = match op(provider.as_ref()).await {
+                    Ok(value) => return Ok(value),
+                    Err(e) if is_transient(&e) => e,
+                    Err(e) => return Err(e),
+                };
+
+                if retry < self.retry.max_retries {
+                    let delay = self.retry.delay(retry);
+                    tracing::warn!(
+                        "{} failed on {} ({}), retrying in {:?}",
+                        method.as_str(),
+                        provider.name(),
+                        error,
+                        delay
+                    );
+                    tokio::time::sleep(delay).await;
+                    retry += 1;
+                    continue;
+                }
+
+                tracing::warn!("{} failed on {} ({}), trying next provider", method.as_str(), provider.name(), error);
+                failures.push(format!("{}: {}", provider.name(), error));
+                break;
+            }
+        }
+
+        Err(anyhow!("All AI providers failed for {}: {}", method.as_str(), failures.join("; ")))
+    }
+}
+
+#[async_trait]
+impl AiProvider for AiRouter {
+    /// The first provider that translates, whose stored translations are preferred; translations
+    /// are stored under the provider that actually made them, see `translate_subtitles_named`
+    fn name(&self) -> &'static str {
+        self.chain(AiMethod::TranslateSubtitles)
+            .first()
+            .map(|p| p.name())
+            .unwrap_or("none")
+    }
+
+    async fn analyze_highlights(&self, subtitles: &[Subtitle], profile: &LearnerProfile) -> Result<Vec<usize>> {
+        self.call(AiMethod::AnalyzeHighlights, |p| p.analyze_highlights(subtitles, profile))
+            .await
+    }
+
+    async fn ask_question(&self, context: &str, question: &str, history: &[ChatTurn], profile: &LearnerProfile) -> Result<String> {
+        self.call(AiMethod::AskQuestion, |p| p.ask_question(context, question, history, profile))
+            .await
+    }
+
+    async fn ask_question_stream(
+        &self,
+        context: &str,
+        question: &str,
+        history: &[ChatTurn],
+        profile: &LearnerProfile,
+        chunks: TextChunkSender,
+    ) -> Result<String> {
+        let chunks = &chunks;
+        self.call(AiMethod::AskQuestion, |p| {
+            Box::pin(async move {
+                // Each attempt gets its own channel so we know whether it already sent text
+                let (tx, rx) = mpsc::channel(32);
+                let mut forwarded = false;
+                let forward = async {
+                    let mut rx = rx;
+                    while let Some(chunk) = rx.recv().await {
+                        forwarded = true;
+                        if chunks.send(chunk).await.is_err() {
+                            break;
+                        }
+                    }
+                };
+                let (result, ()) = tokio::join!(p.ask_question_stream(context, question, history, profile, tx), forward);
+
+                // Once the client has seen part of an answer, switching providers would garble it
+                match result {
+                    Err(e) if forwarded => Err(anyhow!("AI stream interrupted: {}", e)),
+                    result => result,
+                }
+            })
+        })
+        .await
+    }
+
+    async fn translate_subtitles(&self, subtitles: &[Subtitle], profile: &LearnerProfile) -> Result<Vec<String>> {
+        self.call(AiMethod::TranslateSubtitles, |p| p.translate_subtitles(subtitles, profile))
+            .await
+    }
+
+    async fn translate_subtitles_named(
+        &self,
+        subtitles: &[Subtitle],
+        profile: &LearnerProfile,
+    ) -> Result<(Vec<String>, &'static str)> {
+        self.call(AiMethod::TranslateSubtitles, |p| p.translate_subtitles_named(subtitles, profile))
+            .await
+    }
+
+    async fn extract_vocabulary(&self, text: &str, profile: &LearnerProfile) -> Result<Vec<VocabularyItem>> {
+        self.call(AiMethod::ExtractVocabulary, |p| p.extract_vocabulary(text, profile))
+            .await
+    }
+
+    async fn generate_mindmap(&self, title: &str, content: &str, profile: &LearnerProfile) -> Result<String> {
+        self.call(AiMethod::GenerateMindmap, |p| p.generate_mindmap(title, content, profile))
+            .await
+    }
+
+    async fn generate_slides(&self, title: &str, content: &str, profile: &LearnerProfile) -> Result<Vec<Slide>> {
+        self.call(AiMethod::GenerateSlides, |p| p.generate_slides(title, content, profile))
+            .await
+    }
+
+    async fn generate_chapters(&self, subtitles: &[Subtitle], profile: &LearnerProfile) -> Result<Vec<Chapter>> {
+        self.call(AiMethod::GenerateChapters, |p| p.generate_chapters(subtitles, profile))
+            .await
+    }
+
+    async fn generate_review_questions(&self, vocab_list: &[VocabForReview], profile: &LearnerProfile) -> Result<Vec<ReviewQuestion>> {
+        self.call(AiMethod::GenerateReviewQuestions, |p| p.generate_review_questions(vocab_list, profile))
+            .await
+    }
+
+    async fn generate_single_review_question(&self, vocab: &VocabForReview, question_type: &str, profile: &LearnerProfile) -> Result<ReviewQuestion> {
+        self.call(AiMethod::GenerateReviewQuestions, |p| {
+            p.generate_single_review_question(vocab, question_type, profile)
+        })
+        .await
+    }
+
+    async fn evaluate_review_answer(
+        &self,
+        word: &str,
+        meaning: &str,
+        question: &str,
+        user_answer: &str,
+        profile: &LearnerProfile,
+    ) -> Result<ReviewEvaluation> {
+        self.call(AiMethod::EvaluateReviewAnswer, |p| {
+            p.evaluate_review_answer(word, meaning, question, user_answer, profile)
+        })
+        .await
+    }
+
+    async fn generate_memory_card(
+        &self,
+        word: &str,
+        meaning: &str,
+        context: Option<&str>,
+        profile: &LearnerProfile,
+    ) -> Result<MemoryCard> {
+        self.call(AiMethod::GenerateMemoryCard, |p| p.generate_memory_card(word, meaning, context, profile))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::ai::PromptedProvider;
+    use crate::services::ai_mock::MockProvider;
+    use crate::services::ai_transport::OpenAITransport;
+    use axum::{
+        extract::{Path, State},
+        http::{header, StatusCode},
+        response::{IntoResponse, Response},
+        routing::post,
+        Json, Router,
+    };
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// OpenAI-compatible mock where the first path segment picks the behaviour:
+    /// "limited" → 429, "down" → 503, "bad" → 400, "flaky" → 503 on the first call only,
+    /// anything else answers with its own name
+    async fn spawn_mock_server() -> (String, Arc<AtomicUsize>) {
+        async fn chat_completions(
+            State(flaky_calls): State<Arc<AtomicUsize>>,
+            Path(server): Path<String>,
+            Json(body): Json<serde_json::Value>,
+        ) -> Response {
+            let status = match server.as_str() {
+                "limited" => Some(StatusCode::TOO_MANY_REQUESTS),
+                "down" => Some(StatusCode::SERVICE_UNAVAILABLE),
+                "bad" => Some(StatusCode::BAD_REQUEST),
+                "flaky" if flaky_calls.fetch_add(1, Ordering::SeqCst) == 0 => Some(StatusCode::SERVICE_UNAVAILABLE),
+                _ => None,
+            };
+            if let Some(status) = status {
+                return status.into_response();
+            }
+
+            if body["stream"].as_bool().unwrap_or(false) {
+                let events = format!(
+                    "data: {}\n\ndata: [DONE]\n\n",
+                    serde_json::json!({"choices": [{"delta": {"content": server}}]}),
+                );
+                ([(header::CONTENT_TYPE, "text/event-stream")], events).into_response()
+            } else {
+                Json(serde_json::json!({"choices": [{"message": {"content": server}}]})).into_response()
+            }
+        }
+
+        let flaky_calls = Arc::new(AtomicUsize::new(0));
+        let app = Router::new()
+            .route("/:server/v1/chat/completions", post(chat_completions))
+            .with_state(flaky_calls.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        (format!("http://{}", addr), flaky_calls)
+    }
+
+    fn provider(base: &str, server: &'static str) -> Arc<dyn AiProvider> {
+        let transport = OpenAITransport::compatible(server, format!("{}/{}/v1", base, server), "model".to_string(), None);
+        Arc::new(PromptedProvider::new(transport))
+    }
+
+    fn quick_retry(max_retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            base_delay: Duration::from_millis(1),
+        }
+    }
+
+    async fn ask(router: &AiRouter) -> Result<String> {
+        router.ask_question("context", "question", &[], &LearnerProfile::default()).await
+    }
+
+    #[tokio::test]
+    async fn test_fails_over_on_rate_limit_and_server_error() {
+        let (base, _) = spawn_mock_server().await;
+        let router = AiRouter::new(
+            vec![provider(&base, "limited"), provider(&base, "down"), provider(&base, "backup")],
+            quick_retry(1),
+        );
+        assert_eq!(ask(&router).await.unwrap(), "backup");
+    }
+
+    #[tokio::test]
+    async fn test_retries_transient_errors() {
+        let (base, flaky_calls) = spawn_mock_server().await;
+        let router = AiRouter::new(vec![provider(&base, "flaky"), provider(&base, "backup")], quick_retry(1));
+        assert_eq!(ask(&router).await.unwrap(), "flaky");
+        assert_eq!(flaky_calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_client_errors_do_not_fail_over() {
+        let (base, _) = spawn_mock_server().await;
+        let router = AiRouter::new(vec![provider(&base, "bad"), provider(&base, "backup")], quick_retry(1));
+        assert!(ask(&router).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_all_providers_failing_lists_each_error() {
+        let (base, _) = spawn_mock_server().await;
+        let router = AiRouter::new(vec![provider(&base, "limited"), provider(&base, "down")], quick_retry(0));
+        let error = ask(&router).await.unwrap_err().to_string();
+        assert!(error.contains("limited:"), "{}", error);
+        assert!(error.contains("down:"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_routes_methods_to_their_own_chain() {
+        let (base, _) = spawn_mock_server().await;
+        let router = AiRouter::new(vec![provider(&base, "cheap")], quick_retry(0))
+            .with_route(AiMethod::AskQuestion, vec![provider(&base, "strong")])
+            .with_route(AiMethod::TranslateSubtitles, vec![provider(&base, "translator")]);
+        assert_eq!(ask(&router).await.unwrap(), "strong");
+        assert_eq!(router.name(), "translator");
+
+        let mindmap = router
+            .generate_mindmap("title", "content", &LearnerProfile::default())
+            .await
+            .unwrap();
+        assert_eq!(mindmap, "cheap");
+    }
+
+    #[tokio::test]
+    async fn test_translations_name_the_provider_that_served_them() {
+        let (base, _) = spawn_mock_server().await;
+        let router = AiRouter::new(vec![provider(&base, "limited"), Arc::new(MockProvider)], quick_retry(0));
+        let subtitles = vec![Subtitle {
+            index: 0,
+            start: 0.0,
+            end: 1.0,
+            text: "Hello".to_string(),
+            translation: None,
+            words: Vec::new(),
+        }];
+
+        let (translations, name) = router
+            .translate_subtitles_named(&subtitles, &LearnerProfile::default())
+            .await
+            .unwrap();
+        assert_eq!(translations.len(), 1);
+        assert_eq!(name, "mock");
+        assert_eq!(router.name(), "limited");
+    }
+
+    #[tokio::test]
+    async fn test_stream_fails_over_before_any_text() {
+        let (base, _) = spawn_mock_server().await;
+        let router = AiRouter::new(vec![provider(&base, "down"), provider(&base, "backup")], quick_retry(0));
+        let (tx, mut rx) = mpsc::channel(8);
+
+        let answer = router
+            .ask_question_stream("context", "question", &[], &LearnerProfile::default(), tx)
+            .await
+            .unwrap();
+        assert_eq!(answer, "backup");
+        assert_eq!(rx.recv().await.as_deref(), Some("backup"));
+    }
+
+    #[test]
+    fn test_backoff_doubles_up_to_cap() {
+        let policy = RetryPolicy {
+            max_retries: 10,
+            base_delay: Duration::from_millis(500),
+        };
+        assert_eq!(policy.delay(0), Duration::from_millis(500));
+        assert_eq!(policy.delay(2), Duration::from_secs(2));
+        assert_eq!(policy.delay(9), RetryPolicy::MAX_DELAY);
+    }
+}
@@ -0,0 +1,573 @@
+//! Model APIs behind a common "send a prompt, get text back" interface
+//!
+//! Prompts and response parsing live in [`PromptedProvider`](crate::services::ai::PromptedProvider),
+//! so adding a model API only means implementing [`AiTransport`].
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+use crate::services::ai::TextChunkSender;
+use crate::services::ai_json::{claude_tool, claude_tool_input, openai_response_format, OutputSchema};
+use crate::services::ai_usage::{self, TokenUsage};
+
+/// One model API: sends a prompt and returns the generated text
+#[async_trait]
+pub trait AiTransport: Send + Sync {
+    /// Short provider name, used to key stored AI output (e.g. "gemini")
+    fn name(&self) -> &'static str;
+
+    /// Plain text completion
+    async fn complete(&self, prompt: &str) -> Result<String>;
+
+    /// Text completion sent to `chunks` as it is generated; returns the full text
+    async fn complete_stream(&self, prompt: &str, chunks: TextChunkSender) -> Result<String>;
+
+    /// Completion constrained to `schema` with the API's native structured output;
+    /// returns the raw JSON text
+    async fn complete_json(&self, prompt: &str, schema: &OutputSchema) -> Result<String>;
+}
+
+// ============================================================================
+// Gemini
+// ============================================================================
+
+pub struct GeminiTransport {
+    api_key: String,
+    model: String,
+    client: Client,
+}
+
+impl GeminiTransport {
+    pub fn new(api_key: String, model: String) -> Self {
+        Self {
+            api_key,
+            model,
+            client: Client::new(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct GeminiRequest {
+    contents: Vec<GeminiContent>,
+    #[serde(rename = "generationConfig")]
+    generation_config: GeminiGenerationConfig,
+}
+
+#[derive(Serialize)]
+struct GeminiGenerationConfig {
+    #[serde(rename = "maxOutputTokens")]
+    max_output_tokens: u32,
+    #[serde(rename = "responseMimeType", skip_serializing_if = "Option::is_none")]
+    response_mime_type: Option<String>,
+    #[serde(rename = "responseSchema", skip_serializing_if = "Option::is_none")]
+    response_schema: Option<serde_json::Value>,
+}
+
+#[derive(Serialize)]
+struct GeminiContent {
+    parts: Vec<GeminiPart>,
+}
+
+#[derive(Serialize)]
+struct GeminiPart {
+    text: String,
+}
+
+#[derive(Deserialize)]
+struct GeminiResponse {
+    candidates: Option<Vec<GeminiCandidate>>,
+    #[serde(rename = "usageMetadata", default)]
+    usage_metadata: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct GeminiCandidate {
+    content: GeminiContentResponse,
+}
+
+#[derive(Deserialize)]
+struct GeminiContentResponse {
+    parts: Vec<GeminiPartResponse>,
+}
+
+#[derive(Deserialize)]
+struct GeminiPartResponse {
+    text: String,
+}
+
+impl GeminiRequest {
+    fn from_prompt(prompt: &str) -> Self {
+        Self {
+            contents: vec![GeminiContent {
+                parts: vec![GeminiPart {
+                    text: prompt.to_string(),
+                }],
+            }],
+            generation_config: GeminiGenerationConfig {
+                max_output_tokens: 8192,
+                response_mime_type: None,
+                response_schema: None,
+            },
+        }
+    }
+}
+
+impl GeminiTransport {
+    async fn call_gemini(&self, prompt: &str) -> Result<(String, TokenUsage)> {
+        self.send_gemini(&GeminiRequest::from_prompt(prompt)).await
+    }
+
+    async fn send_gemini(&self, request: &GeminiRequest) -> Result<(String, TokenUsage)> {
+        // The key goes in a header: request errors quote the URL, and they reach logs and clients
+        let url = format!(
+            "https://generativelanguage.googleapis.com/v1beta/models/{}:generateContent",
+            self.model
+        );
+
+        let response = self
+            .client
+            .post(&url)
+            .header("x-goog-api-key", &self.api_key)
+            .json(request)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<GeminiResponse>()
+            .await?;
+
+        let usage = gemini_usage(&response.usage_metadata);
+        let text = response
+            .candidates
+            .and_then(|c| c.into_iter().next())
+            .and_then(|c| c.content.parts.into_iter().next())
+            .map(|p| p.text)
+            .ok_or_else(|| anyhow!("No response from Gemini"))?;
+
+        Ok((text, usage.unwrap_or_default()))
+    }
+
+    async fn stream_gemini(&self, prompt: &str, chunks: TextChunkSender) -> Result<(String, TokenUsage)> {
+        // As in `send_gemini`, the key stays out of the URL that errors quote
+        let url = format!(
+            "https://generativelanguage.googleapis.com/v1beta/models/{}:streamGenerateContent?alt=sse",
+            self.model
+        );
+
+        let response = self
+            .client
+            .post(&url)
+            .header("x-goog-api-key", &self.api_key)
+            .json(&GeminiRequest::from_prompt(prompt))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        forward_sse_text(
+            response,
+            &chunks,
+            |event| event["candidates"][0]["content"]["parts"][0]["text"].as_str().map(str::to_string),
+            |event| gemini_usage(&event["usageMetadata"]),
+        )
+        .await
+    }
+}
+
+fn gemini_usage(metadata: &serde_json::Value) -> Option<TokenUsage> {
+    TokenUsage::from_json(metadata, "promptTokenCount", "candidatesTokenCount")
+}
+
+#[async_trait]
+impl AiTransport for GeminiTransport {
+    fn name(&self) -> &'static str {
+        "gemini"
+    }
+
+    async fn complete(&self, prompt: &str) -> Result<String> {
+        ai_usage::timed(self.name(), &self.model, self.call_gemini(prompt)).await
+    }
+
+    async fn complete_stream(&self, prompt: &str, chunks: TextChunkSender) -> Result<String> {
+        ai_usage::timed(self.name(), &self.model, self.stream_gemini(prompt, chunks)).await
+    }
+
+    async fn complete_json(&self, prompt: &str, schema: &OutputSchema) -> Result<String> {
+        let mut request = GeminiRequest::from_prompt(prompt);
+        request.generation_config.response_mime_type = Some("application/json".to_string());
+        request.generation_config.response_schema = Some(schema.gemini());
+        ai_usage::timed(self.name(), &self.model, self.send_gemini(&request)).await
+    }
+}
+
+// ============================================================================
+// Claude
+// ============================================================================
+
+pub struct ClaudeTransport {
+    api_key: String,
+    model: String,
+    client: Client,
+}
+
+impl ClaudeTransport {
+    pub fn new(api_key: String, model: String) -> Self {
+        Self {
+            api_key,
+            model,
+            client: Client::new(),
+        }
+    }
+}
+
+impl ClaudeTransport {
+    async fn call_claude(&self, prompt: &str) -> Result<(String, TokenUsage)> {
+        #[derive(Serialize)]
+        struct ClaudeRequest {
+            model: String,
+            max_tokens: u32,
+            messages: Vec<ClaudeMessage>,
+        }
+
+        #[derive(Serialize)]
+        struct ClaudeMessage {
+            role: String,
+            content: String,
+        }
+
+        #[derive(Deserialize)]
+        struct ClaudeResponse {
+            content: Vec<ClaudeContent>,
+            #[serde(default)]
+            usage: serde_json::Value,
+        }
+
+        #[derive(Deserialize)]
+        struct ClaudeContent {
+            text: String,
+        }
+
+        let request = ClaudeRequest {
+            model: self.model.clone(),
+            max_tokens: 1024,
+            messages: vec![ClaudeMessage {
+                role: "user".to_string(),
+                content: prompt.to_string(),
+            }],
+        };
+
+        let response = self
+            .client
+            .post("https://api.anthropic.com/v1/messages")
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", "2023-06-01")
+            .header("content-type", "application/json")
+            .json(&request)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<ClaudeResponse>()
+            .await?;
+
+        let usage = claude_usage(&response.usage).unwrap_or_default();
+        response
+            .content
+            .into_iter()
+            .next()
+            .map(|c| (c.text, usage))
+            .ok_or_else(|| anyhow!("No response from Claude"))
+    }
+
+    async fn stream_claude(&self, prompt: &str, chunks: TextChunkSender) -> Result<(String, TokenUsage)> {
+        let request = serde_json::json!({
+            "model": self.model,
+            "max_tokens": 1024,
+            "stream": true,
+            "messages": [{"role": "user", "content": prompt}],
+        });
+
+        let response = self
+            .client
+            .post("https://api.anthropic.com/v1/messages")
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", "2023-06-01")
+            .header("content-type", "application/json")
+            .json(&request)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        // Text arrives in `content_block_delta` events; input tokens are counted in `message_start`
+        // and output tokens in the final `message_delta`
+        forward_sse_text(
+            response,
+            &chunks,
+            |event| event["delta"]["text"].as_str().map(str::to_string),
+            |event| claude_usage(&event["message"]["usage"]).or_else(|| claude_usage(&event["usage"])),
+        )
+        .await
+    }
+
+    /// Structured output through a forced tool call
+    async fn call_claude_tool(&self, prompt: &str, schema: &OutputSchema) -> Result<(String, TokenUsage)> {
+        let (tool, tool_choice) = claude_tool(schema);
+        let request = serde_json::json!({
+            "model": self.model,
+            "max_tokens": 4096,
+            "messages": [{"role": "user", "content": prompt}],
+            "tools": [tool],
+            "tool_choice": tool_choice,
+        });
+
+        let response = self
+            .client
+            .post("https://api.anthropic.com/v1/messages")
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", "2023-06-01")
+            .header("content-type", "application/json")
+            .json(&request)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<serde_json::Value>()
+            .await?;
+
+        let input = claude_tool_input(&response).ok_or_else(|| anyhow!("No tool call in Claude response"))?;
+        Ok((input, claude_usage(&response["usage"]).unwrap_or_default()))
+    }
+}
+
+fn claude_usage(usage: &serde_json::Value) -> Option<TokenUsage> {
+    TokenUsage::from_json(usage, "input_tokens", "output_tokens")
+}
+
+#[async_trait]
+impl AiTransport for ClaudeTransport {
+    fn name(&self) -> &'static str {
+        "claude"
+    }
+
+    async fn complete(&self, prompt: &str) -> Result<String> {
+        ai_usage::timed(self.name(), &self.model, self.call_claude(prompt)).await
+    }
+
+    async fn complete_stream(&self, prompt: &str, chunks: TextChunkSender) -> Result<String> {
+        ai_usage::timed(self.name(), &self.model, self.stream_claude(prompt, chunks)).await
+    }
+
+    async fn complete_json(&self, prompt: &str, schema: &OutputSchema) -> Result<String> {
+        ai_usage::timed(self.name(), &self.model, self.call_claude_tool(prompt, schema)).await
+    }
+}
+
+// ============================================================================
+// OpenAI (also used for OpenAI-compatible servers such as Ollama)
+// ============================================================================
+
+pub struct OpenAITransport {
+    name: &'static str,
+    /// Without a trailing slash, e.g. "https://api.openai.com/v1"
+    base_url: String,
+    model: String,
+    /// Local servers usually don't need a key
+    api_key: Option<String>,
+    client: Client,
+}
+
+impl OpenAITransport {
+    pub fn new(api_key: String, model: String) -> Self {
+        Self::compatible("openai", "https://api.openai.com/v1".to_string(), model, Some(api_key))
+    }
+
+    /// Transport for any server implementing the OpenAI chat completions API
+    pub fn compatible(name: &'static str, base_url: String, model: String, api_key: Option<String>) -> Self {
+        Self {
+            name,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            model,
+            api_key,
+            client: Client::new(),
+        }
+    }
+
+    fn chat_completions_request(&self) -> reqwest::RequestBuilder {
+        let request = self.client.post(format!("{}/chat/completions", self.base_url));
+        match &self.api_key {
+            Some(key) => request.header("Authorization", format!("Bearer {}", key)),
+            None => request,
+        }
+    }
+}
+
+impl OpenAITransport {
+    async fn call_openai(&self, prompt: &str) -> Result<(String, TokenUsage)> {
+        self.send_openai(prompt, None).await
+    }
+
+    async fn send_openai(&self, prompt: &str, response_format: Option<serde_json::Value>) -> Result<(String, TokenUsage)> {
+        #[derive(Serialize)]
+        struct OpenAIRequest {
+            model: String,
+            messages: Vec<OpenAIMessage>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            response_format: Option<serde_json::Value>,
+        }
+
+        #[derive(Serialize)]
+        struct OpenAIMessage {
+            role: String,
+            content: String,
+        }
+
+        #[derive(Deserialize)]
+        struct OpenAIResponse {
+            choices: Vec<OpenAIChoice>,
+            #[serde(default)]
+            usage: serde_json::Value,
+        }
+
+        #[derive(Deserialize)]
+        struct OpenAIChoice {
+            message: OpenAIMessageResponse,
+        }
+
+        #[derive(Deserialize)]
+        struct OpenAIMessageResponse {
+            content: String,
+        }
+
+        let request = OpenAIRequest {
+            model: self.model.clone(),
+            messages: vec![OpenAIMessage {
+                role: "user".to_string(),
+                content: prompt.to_string(),
+            }],
+            response_format,
+        };
+
+        let response = self
+            .chat_completions_request()
+            .json(&request)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<OpenAIResponse>()
+            .await?;
+
+        let usage = openai_usage(&response.usage).unwrap_or_default();
+        response
+            .choices
+            .into_iter()
+            .next()
+            .map(|c| (c.message.content, usage))
+            .ok_or_else(|| anyhow!("No response from OpenAI"))
+    }
+
+    async fn stream_openai(&self, prompt: &str, chunks: TextChunkSender) -> Result<(String, TokenUsage)> {
+        // `include_usage` adds a last chunk with the token counts and no choices
+        let request = serde_json::json!({
+            "model": self.model,
+            "stream": true,
+            "stream_options": {"include_usage": true},
+            "messages": [{"role": "user", "content": prompt}],
+        });
+
+        let response = self
+            .chat_completions_request()
+            .json(&request)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        forward_sse_text(
+            response,
+            &chunks,
+            |event| event["choices"][0]["delta"]["content"].as_str().map(str::to_string),
+            |event| openai_usage(&event["usage"]),
+        )
+        .await
+    }
+}
+
+fn openai_usage(usage: &serde_json::Value) -> Option<TokenUsage> {
+    TokenUsage::from_json(usage, "prompt_tokens", "completion_tokens")
+}
+
+#[async_trait]
+impl AiTransport for OpenAITransport {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    async fn complete(&self, prompt: &str) -> Result<String> {
+        ai_usage::timed(self.name, &self.model, self.call_openai(prompt)).await
+    }
+
+    async fn complete_stream(&self, prompt: &str, chunks: TextChunkSender) -> Result<String> {
+        ai_usage::timed(self.name, &self.model, self.stream_openai(prompt, chunks)).await
+    }
+
+    async fn complete_json(&self, prompt: &str, schema: &OutputSchema) -> Result<String> {
+        let response_format = openai_response_format(schema);
+        ai_usage::timed(self.name, &self.model, self.send_openai(prompt, Some(response_format))).await
+    }
+}
+
+/// Read a server-sent events response, sending the text `extract` finds in each `data:` payload to `chunks`
+/// Returns the full text and the token counts `usage` finds in the events;
+/// fails if the stream reports an error or the receiver has gone away
+async fn forward_sse_text(
+    response: reqwest::Response,
+    chunks: &TextChunkSender,
+    extract: impl Fn(&serde_json::Value) -> Option<String>,
+    usage: impl Fn(&serde_json::Value) -> Option<TokenUsage>,
+) -> Result<(String, TokenUsage)> {
+    let mut stream = response.bytes_stream();
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut full_text = String::new();
+    let mut total_usage = TokenUsage::default();
+
+    while let Some(bytes) = stream.next().await {
+        buffer.extend_from_slice(&bytes?);
+
+        // Only handle complete lines; a multi-byte character may be split across network chunks
+        while let Some(pos) = buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line);
+            let Some(data) = line.trim().strip_prefix("data:") else {
+                continue;
+            };
+            let data = data.trim();
+            if data.is_empty() || data == "[DONE]" {
+                continue;
+            }
+
+            let Ok(event) = serde_json::from_str::<serde_json::Value>(data) else {
+                continue;
+            };
+            if let Some(error) = event.get("error") {
+                let message = error["message"].as_str().unwrap_or("unknown error");
+                return Err(anyhow!("AI stream error: {}", message));
+            }
+            if let Some(event_usage) = usage(&event) {
+                total_usage.merge(event_usage);
+            }
+
+            if let Some(text) = extract(&event).filter(|t| !t.is_empty()) {
+                full_text.push_str(&text);
+                chunks
+                    .send(text)
+                    .await
+                    .map_err(|_| anyhow!("Stream receiver closed"))?;
+            }
+        }
+    }
+
+    if full_text.is_empty() {
+        return Err(anyhow!("Empty response from AI stream"));
+    }
+
+    Ok((full_text, total_usage))
+}
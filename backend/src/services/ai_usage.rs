@@ -0,0 +1,166 @@
+//! Token and cost accounting for AI calls
+//!
+//! Transports report every successful model call through [`timed`]; route handlers wrap their
+//! AI work in [`track`], which stores the calls made for that request in `ai_calls`.
+
+use anyhow::Result;
+use serde_json::Value;
+use std::cell::RefCell;
+use std::future::Future;
+use std::time::Instant;
+
+use crate::db::{self, DbPool};
+
+tokio::task_local! {
+    /// Calls made so far by the request being tracked
+    static CALLS: RefCell<Vec<AiCall>>;
+}
+
+/// Tokens reported by the model API for one call
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TokenUsage {
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+}
+
+impl TokenUsage {
+    /// Read token counts from a response object, e.g. `body["usage"]` with "input_tokens" and "output_tokens"
+    pub fn from_json(usage: &Value, input_key: &str, output_key: &str) -> Option<Self> {
+        let input = usage.get(input_key).and_then(Value::as_i64);
+        let output = usage.get(output_key).and_then(Value::as_i64);
+        if input.is_none() && output.is_none() {
+            return None;
+        }
+        Some(Self {
+            input_tokens: input.unwrap_or(0),
+            output_tokens: output.unwrap_or(0),
+        })
+    }
+
+    /// Combine counts from several stream events; APIs report running totals or one side per event
+    pub fn merge(&mut self, other: TokenUsage) {
+        self.input_tokens = self.input_tokens.max(other.input_tokens);
+        self.output_tokens = self.output_tokens.max(other.output_tokens);
+    }
+}
+
+/// One call to a model API
+#[derive(Debug, Clone)]
+pub struct AiCall {
+    pub provider: &'static str,
+    pub model: String,
+    pub usage: TokenUsage,
+    pub latency_ms: i64,
+    pub cost_usd: f64,
+}
+
+/// USD per million input and output tokens, matched by model name prefix (first match wins,
+/// so longer names come first). Models not listed, e.g. local ones, are counted as free.
+const PRICES: &[(&str, f64, f64)] = &[
+    ("gemini-2.0-flash-lite", 0.075, 0.30),
+    ("gemini-2.0-flash", 0.10, 0.40),
+    ("gemini-1.5-flash", 0.075, 0.30),
+    ("gemini-1.5-pro", 1.25, 5.00),
+    ("gemini-2.5-flash", 0.30, 2.50),
+    ("gemini-2.5-pro", 1.25, 10.00),
+    ("claude-3-haiku", 0.25, 1.25),
+    ("claude-3-5-haiku", 0.80, 4.00),
+    ("claude-3-5-sonnet", 3.00, 15.00),
+    ("claude-3-7-sonnet", 3.00, 15.00),
+    ("claude-sonnet-4", 3.00, 15.00),
+    ("claude-3-opus", 15.00, 75.00),
+    ("gpt-3.5-turbo", 0.50, 1.50),
+    ("gpt-4o-mini", 0.15, 0.60),
+    ("gpt-4o", 2.50, 10.00),
+    ("gpt-4.1-nano", 0.10, 0.40),
+    ("gpt-4.1-mini", 0.40, 1.60),
+    ("gpt-4.1", 2.00, 8.00),
+];
+
+/// Estimated cost of a call in USD
+pub fn estimate_cost(model: &str, usage: TokenUsage) -> f64 {
+    PRICES
+        .iter()
+        .find(|(prefix, _, _)| model.starts_with(prefix))
+        .map(|(_, input, output)| {
+            (usage.input_tokens as f64 * input + usage.output_tokens as f64 * output) / 1_000_000.0
+        })
+        .unwrap_or(0.0)
+}
+
+/// Run one model call and record its tokens, latency and cost for the request being tracked
+pub async fn timed<F>(provider: &'static str, model: &str, call: F) -> Result<String>
+where
+    F: Future<Output = Result<(String, TokenUsage)>>,
+{
+    let started = Instant::now();
+    let (text, usage) = call.await?;
+
+    let call = AiCall {
+        provider,
+        model: model.to_string(),
+        usage,
+        latency_ms: started.elapsed().as_millis() as i64,
+        cost_usd: estimate_cost(model, usage),
+    };
+    // Calls outside `track` (background jobs, tests) are not recorded
+    let _ = CALLS.try_with(|calls| calls.borrow_mut().push(call));
+
+    Ok(text)
+}
+
+/// Run `work` and return its output with the model calls it made
+pub async fn collect<F: Future>(work: F) -> (F::Output, Vec<AiCall>) {
+    CALLS
+        .scope(RefCell::new(Vec::new()), async move {
+            let output = work.await;
+            (output, CALLS.with(|calls| calls.take()))
+        })
+        .await
+}
+
+/// Run `work` for `user_id` and store the model calls it made under `endpoint` (e.g. "ai/slides")
+pub async fn track<F: Future>(pool: &DbPool, user_id: &str, endpoint: &str, work: F) -> F::Output {
+    let (output, calls) = collect(work).await;
+    if !calls.is_empty() {
+        if let Err(e) = db::save_ai_calls(pool, user_id, endpoint, &calls).await {
+            tracing::warn!("Failed to save AI usage for {}: {}", endpoint, e);
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate_cost_matches_longest_prefix() {
+        let usage = TokenUsage { input_tokens: 1_000_000, output_tokens: 1_000_000 };
+        assert!((estimate_cost("gpt-4o-mini-2024-07-18", usage) - 0.75).abs() < 1e-9);
+        assert!((estimate_cost("gpt-4o", usage) - 12.5).abs() < 1e-9);
+        assert_eq!(estimate_cost("llama3.2", usage), 0.0);
+    }
+
+    #[test]
+    fn test_merge_keeps_running_totals() {
+        let mut usage = TokenUsage::from_json(&serde_json::json!({"input_tokens": 12, "output_tokens": 1}), "input_tokens", "output_tokens").unwrap();
+        usage.merge(TokenUsage::from_json(&serde_json::json!({"output_tokens": 40}), "input_tokens", "output_tokens").unwrap());
+        assert_eq!(usage, TokenUsage { input_tokens: 12, output_tokens: 40 });
+        assert!(TokenUsage::from_json(&serde_json::json!({}), "input_tokens", "output_tokens").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_collect_records_calls_made_inside() {
+        let usage = TokenUsage { input_tokens: 10, output_tokens: 5 };
+        let (text, calls) = collect(timed("openai", "gpt-4o", async move { Ok(("hi".to_string(), usage)) })).await;
+
+        assert_eq!(text.unwrap(), "hi");
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].usage, usage);
+        assert!(calls[0].cost_usd > 0.0);
+
+        // Outside a scope nothing is recorded and nothing fails
+        assert!(timed("openai", "gpt-4o", async move { Ok(("hi".to_string(), usage)) }).await.is_ok());
+    }
+}
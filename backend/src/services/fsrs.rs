@@ -0,0 +1,217 @@
+//! FSRS (Free Spaced Repetition Scheduler), version 5
+//!
+//! A card's memory is its stability `S` (days until recall probability drops to 90%) and
+//! difficulty `D` (1-10). Each review updates both from the rating and how likely the card was
+//! to be recalled at that moment (its retrievability), and the next interval is the time until
+//! retrievability falls to the desired retention.
+//! Formulas and default weights follow the reference implementation (open-spaced-repetition/fsrs-rs).
+
+/// Power forgetting curve: R(t, S) = (1 + FACTOR * t / S) ^ DECAY
+const DECAY: f64 = -0.5;
+/// Chosen so that R(S, S) = 0.9
+const FACTOR: f64 = 19.0 / 81.0;
+const MIN_STABILITY: f64 = 0.01;
+const MAX_STABILITY: f64 = 36500.0;
+const MIN_DIFFICULTY: f64 = 1.0;
+const MAX_DIFFICULTY: f64 = 10.0;
+
+/// FSRS-5 default weights, fitted on a large set of Anki review logs
+pub const DEFAULT_WEIGHTS: [f64; 19] = [
+    0.40255, 1.18385, 3.173, 15.69105, 7.1949, 0.5345, 1.4604, 0.0046, 1.54575, 0.1192, 1.01925, 1.9395, 0.11, 0.29605,
+    2.2698, 0.2315, 2.9898, 0.51655, 0.6621,
+];
+
+/// Answer to a review; the API's 0-3 quality maps onto it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rating {
+    Again = 1,
+    Hard = 2,
+    Good = 3,
+    Easy = 4,
+}
+
+impl Rating {
+    /// 0=forgot, 1=hard, 2=good, 3=easy; out-of-range values are clamped
+    pub fn from_quality(quality: i32) -> Self {
+        match quality {
+            i32::MIN..=0 => Rating::Again,
+            1 => Rating::Hard,
+            2 => Rating::Good,
+            _ => Rating::Easy,
+        }
+    }
+
+    fn value(self) -> f64 {
+        self as i32 as f64
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemoryState {
+    pub stability: f64,
+    pub difficulty: f64,
+}
+
+/// Probability of recalling a card of `stability` after `elapsed_days`
+pub fn retrievability(elapsed_days: f64, stability: f64) -> f64 {
+    (1.0 + FACTOR * elapsed_days.max(0.0) / stability.max(MIN_STABILITY)).powf(DECAY)
+}
+
+#[derive(Debug, Clone)]
+pub struct Parameters {
+    pub weights: [f64; 19],
+    /// Recall probability the next review is scheduled at
+    pub desired_retention: f64,
+    pub maximum_interval_days: f64,
+}
+
+impl Default for Parameters {
+    fn default() -> Self {
+        Self {
+            weights: DEFAULT_WEIGHTS,
+            desired_retention: 0.9,
+            maximum_interval_days: MAX_STABILITY,
+        }
+    }
+}
+
+impl Parameters {
+    /// Memory after a review rated `rating`, `elapsed_days` after the previous one (`None` for a new card)
+    pub fn next_state(&self, state: Option<MemoryState>, elapsed_days: f64, rating: Rating) -> MemoryState {
+        let Some(state) = state else {
+            return MemoryState {
+                stability: self.initial_stability(rating),
+                difficulty: self.initial_difficulty(rating),
+            };
+        };
+
+        let stability = if elapsed_days < 1.0 {
+            self.short_term_stability(state.stability, rating)
+        } else {
+            let r = retrievability(elapsed_days, state.stability);
+            match rating {
+                Rating::Again => self.forget_stability(state, r),
+                _ => self.recall_stability(state, r, rating),
+            }
+        };
+
+        MemoryState {
+            stability: stability.clamp(MIN_STABILITY, MAX_STABILITY),
+            difficulty: self.next_difficulty(state.difficulty, rating),
+        }
+    }
+
+    /// Days until a card of `stability` falls to the desired retention, at least one day
+    pub fn next_interval_days(&self, stability: f64) -> f64 {
+        let interval = stability / FACTOR * (self.desired_retention.powf(1.0 / DECAY) - 1.0);
+        interval.round().clamp(1.0, self.maximum_interval_days.max(1.0))
+    }
+
+    /// Memory state matching an SM-2 card's ease and interval, so existing cards keep their schedule
+    pub fn memory_from_sm2(&self, ease_factor: f64, interval_days: f64) -> MemoryState {
+        let w = &self.weights;
+        // With 90% retention an interval of N days means a stability of N days
+        let sm2_retention: f64 = 0.9;
+        let stability = (interval_days.max(MIN_STABILITY) * FACTOR / (sm2_retention.powf(1.0 / DECAY) - 1.0))
+            .clamp(MIN_STABILITY, MAX_STABILITY);
+        // Invert the recall stability formula: a Good review multiplies stability by the ease factor
+        let difficulty = 11.0
+            - (ease_factor - 1.0) / (w[8].exp() * stability.powf(-w[9]) * ((1.0 - sm2_retention) * w[10]).exp_m1());
+
+        MemoryState {
+            stability,
+            difficulty: difficulty.clamp(MIN_DIFFICULTY, MAX_DIFFICULTY),
+        }
+    }
+
+    fn initial_stability(&self, rating: Rating) -> f64 {
+        self.weights[rating as usize - 1].max(0.1)
+    }
+
+    fn initial_difficulty(&self, rating: Rating) -> f64 {
+        let w = &self.weights;
+        (w[4] - (w[5] * (rating.value() - 1.0)).exp() + 1.0).clamp(MIN_DIFFICULTY, MAX_DIFFICULTY)
+    }
+
+    fn next_difficulty(&self, difficulty: f64, rating: Rating) -> f64 {
+        let w = &self.weights;
+        let delta = -w[6] * (rating.value() - 3.0);
+        let damped = difficulty + delta * (10.0 - difficulty) / 9.0;
+        // Mean reversion towards the difficulty of a card first rated Easy
+        let reverted = w[7] * self.initial_difficulty(Rating::Easy) + (1.0 - w[7]) * damped;
+        reverted.clamp(MIN_DIFFICULTY, MAX_DIFFICULTY)
+    }
+
+    fn recall_stability(&self, state: MemoryState, r: f64, rating: Rating) -> f64 {
+        let w = &self.weights;
+        let hard_penalty = if rating == Rating::Hard { w[15] } else { 1.0 };
+        let easy_bonus = if rating == Rating::Easy { w[16] } else { 1.0 };
+        state.stability
+            * (1.0
+                + w[8].exp()
+                    * (11.0 - state.difficulty)
+                    * state.stability.powf(-w[9])
+                    * (((1.0 - r) * w[10]).exp() - 1.0)
+                    * hard_penalty
+                    * easy_bonus)
+    }
+
+    fn forget_stability(&self, state: MemoryState, r: f64) -> f64 {
+        let w = &self.weights;
+        let forgotten = w[11]
+            * state.difficulty.powf(-w[12])
+            * ((state.stability + 1.0).powf(w[13]) - 1.0)
+            * ((1.0 - r) * w[14]).exp();
+        // Forgetting never leaves a card more stable than a same-day lapse would
+        forgotten.min(state.stability / (w[17] * w[18]).exp())
+    }
+
+    /// Reviews on the same day barely change long-term memory
+    fn short_term_stability(&self, stability: f64, rating: Rating) -> f64 {
+        let w = &self.weights;
+        stability * (w[17] * (rating.value() - 3.0 + w[18])).exp()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retrievability_is_ninety_percent_after_stability_days() {
+        assert!((retrievability(10.0, 10.0) - 0.9).abs() < 1e-9);
+        assert_eq!(retrievability(0.0, 10.0), 1.0);
+        assert!(retrievability(30.0, 10.0) < retrievability(20.0, 10.0));
+    }
+
+    #[test]
+    fn test_better_ratings_give_longer_intervals() {
+        let params = Parameters::default();
+        let new_card: Vec<f64> = [Rating::Again, Rating::Hard, Rating::Good, Rating::Easy]
+            .iter()
+            .map(|&rating| params.next_state(None, 0.0, rating).stability)
+            .collect();
+        assert!(new_card.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(params.next_interval_days(params.next_state(None, 0.0, Rating::Good).stability), 3.0);
+
+        // A Good review on time grows stability, Again shrinks it and makes the card harder
+        let state = MemoryState { stability: 10.0, difficulty: 5.0 };
+        let good = params.next_state(Some(state), 10.0, Rating::Good);
+        let again = params.next_state(Some(state), 10.0, Rating::Again);
+        assert!(good.stability > 20.0);
+        assert!(again.stability < 10.0);
+        assert!(again.difficulty > state.difficulty);
+        assert!(params.next_state(Some(state), 10.0, Rating::Easy).difficulty < state.difficulty);
+    }
+
+    #[test]
+    fn test_from_sm2_keeps_the_interval() {
+        let params = Parameters::default();
+        let state = params.memory_from_sm2(2.5, 20.0);
+        assert!((params.next_interval_days(state.stability) - 20.0).abs() <= 1.0);
+        assert!((1.0..=10.0).contains(&state.difficulty));
+        // A lower ease means a harder card
+        assert!(params.memory_from_sm2(1.3, 20.0).difficulty > state.difficulty);
+        assert_eq!(Rating::from_quality(7), Rating::Easy);
+    }
+}
@@ -0,0 +1,256 @@
+//! Fit a user's FSRS weights to their review log
+//!
+//! Each card's logged reviews are replayed with candidate weights; every review made a day or
+//! more after the previous one is a prediction (the retrievability at that moment) checked
+//! against whether the word was recalled. Weights are tuned by gradient descent on the log loss,
+//! kept within the reference implementation's bounds and pulled towards the defaults so a short
+//! history cannot move them far. [`spawn_fitting_job`] refits users with new reviews periodically.
+
+use std::time::Duration;
+
+use crate::db::{self, DbPool};
+use crate::services::fsrs::{retrievability, MemoryState, Parameters, Rating, DEFAULT_WEIGHTS};
+
+/// Reviews that can be predicted (a day or more after the previous one) needed before fitting
+pub const MIN_SCORED_REVIEWS: usize = 100;
+/// Logged reviews a user needs before the job looks at them
+pub const MIN_LOGGED_REVIEWS: i64 = 150;
+
+const ITERATIONS: usize = 150;
+const LEARNING_RATE: f64 = 0.04;
+/// Strength of the pull towards the default weights, spread over the user's reviews
+const PRIOR_STRENGTH: f64 = 20.0;
+const GRADIENT_STEP: f64 = 1e-4;
+
+/// Allowed range of each weight
+const WEIGHT_BOUNDS: [(f64, f64); 19] = [
+    (0.01, 100.0),
+    (0.01, 100.0),
+    (0.01, 100.0),
+    (0.01, 100.0),
+    (1.0, 10.0),
+    (0.001, 4.0),
+    (0.001, 4.0),
+    (0.001, 0.75),
+    (0.0, 4.5),
+    (0.0, 0.8),
+    (0.001, 3.5),
+    (0.001, 5.0),
+    (0.001, 0.25),
+    (0.001, 0.9),
+    (0.0, 4.0),
+    (0.0, 1.0),
+    (1.0, 6.0),
+    (0.0, 2.0),
+    (0.0, 2.0),
+];
+
+/// One logged review of a card
+#[derive(Debug, Clone, Copy)]
+pub struct ReviewEntry {
+    pub rating: Rating,
+    /// Days since the card's previous review; `None` on its first review
+    pub elapsed_days: Option<f64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Fit {
+    pub weights: [f64; 19],
+    /// Mean log loss of the fitted and the default weights on the user's reviews
+    pub log_loss: f64,
+    pub default_log_loss: f64,
+    pub scored_reviews: usize,
+}
+
+/// Fit weights to `histories` (each card's reviews, oldest first); `None` with too few reviews
+/// Cards whose first review is missing from the log are left out, since their state is unknown
+/// When fitting does not beat the defaults on the user's own reviews, the defaults are returned
+pub fn fit(histories: &[Vec<ReviewEntry>]) -> Option<Fit> {
+    let histories: Vec<&[ReviewEntry]> = histories
+        .iter()
+        .filter(|history| history.first().is_some_and(|first| first.elapsed_days.is_none()))
+        .map(Vec::as_slice)
+        .collect();
+
+    let (default_loss, scored) = log_loss(&DEFAULT_WEIGHTS, &histories);
+    if scored < MIN_SCORED_REVIEWS {
+        return None;
+    }
+    let objective = |weights: &[f64; 19]| log_loss(weights, &histories).0 + prior(weights, scored);
+
+    // Adam with central-difference gradients
+    let mut weights = DEFAULT_WEIGHTS;
+    let mut first_moment = [0.0; 19];
+    let mut second_moment = [0.0; 19];
+    for t in 1..=ITERATIONS {
+        for i in 0..weights.len() {
+            let mut up = weights;
+            let mut down = weights;
+            up[i] += GRADIENT_STEP;
+            down[i] -= GRADIENT_STEP;
+            let gradient = (objective(&up) - objective(&down)) / (2.0 * GRADIENT_STEP);
+
+            first_moment[i] = 0.9 * first_moment[i] + 0.1 * gradient;
+            second_moment[i] = 0.999 * second_moment[i] + 0.001 * gradient * gradient;
+            let m = first_moment[i] / (1.0 - 0.9f64.powi(t as i32));
+            let v = second_moment[i] / (1.0 - 0.999f64.powi(t as i32));
+            weights[i] -= LEARNING_RATE * m / (v.sqrt() + 1e-8);
+        }
+        clamp_weights(&mut weights);
+    }
+
+    let (loss, _) = log_loss(&weights, &histories);
+    let weights = if loss < default_loss { weights } else { DEFAULT_WEIGHTS };
+    Some(Fit {
+        weights,
+        log_loss: loss.min(default_loss),
+        default_log_loss: default_loss,
+        scored_reviews: scored,
+    })
+}
+
+/// Mean log loss of the recall predictions and how many reviews were predicted
+fn log_loss(weights: &[f64; 19], histories: &[&[ReviewEntry]]) -> (f64, usize) {
+    let params = Parameters { weights: *weights, ..Parameters::default() };
+    let mut total = 0.0;
+    let mut scored = 0;
+
+    for history in histories {
+        let mut state: Option<MemoryState> = None;
+        for review in history.iter() {
+            let elapsed = review.elapsed_days.unwrap_or(0.0);
+            if let Some(memory) = state.filter(|_| elapsed >= 1.0) {
+                let p = retrievability(elapsed, memory.stability).clamp(1e-4, 1.0 - 1e-4);
+                total -= if review.rating == Rating::Again { (1.0 - p).ln() } else { p.ln() };
+                scored += 1;
+            }
+            state = Some(params.next_state(state, elapsed, review.rating));
+        }
+    }
+
+    (if scored == 0 { 0.0 } else { total / scored as f64 }, scored)
+}
+
+fn prior(weights: &[f64; 19], scored: usize) -> f64 {
+    let distance: f64 = weights
+        .iter()
+        .zip(DEFAULT_WEIGHTS.iter())
+        .map(|(w, d)| ((w - d) / (d.abs() + 0.1)).powi(2))
+        .sum();
+    PRIOR_STRENGTH * distance / scored.max(1) as f64
+}
+
+fn clamp_weights(weights: &mut [f64; 19]) {
+    for (w, (min, max)) in weights.iter_mut().zip(WEIGHT_BOUNDS) {
+        *w = w.clamp(min, max);
+    }
+}
+
+/// Refit, every `SCHEDULER_FIT_INTERVAL_SECS` (default one hour), the weights of users who have
+/// logged enough reviews and reviewed since their last fit (at most once a day per user)
+pub fn spawn_fitting_job(pool: DbPool) {
+    let period = std::env::var("SCHEDULER_FIT_INTERVAL_SECS")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(Duration::from_secs(3600));
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(period);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = fit_due_users(&pool).await {
+                tracing::warn!("Scheduler parameter fitting failed: {}", e);
+            }
+        }
+    });
+}
+
+/// A failure for one user is logged and the others are still fitted
+async fn fit_due_users(pool: &DbPool) -> anyhow::Result<()> {
+    for user_id in db::users_due_for_fitting(pool, MIN_LOGGED_REVIEWS).await? {
+        let histories = match db::get_review_histories(pool, &user_id).await {
+            Ok(histories) => histories,
+            Err(e) => {
+                tracing::warn!("Failed to load review history of {} for fitting: {}", user_id, e);
+                continue;
+            }
+        };
+        let fit = match tokio::task::spawn_blocking(move || fit(&histories)).await {
+            Ok(Some(fit)) => fit,
+            Ok(None) => continue,
+            Err(e) => {
+                tracing::warn!("Fitting scheduler weights for {} failed: {}", user_id, e);
+                continue;
+            }
+        };
+        tracing::info!(
+            "Fitted scheduler weights for {}: log loss {:.4} (defaults {:.4}) over {} reviews",
+            user_id,
+            fit.log_loss,
+            fit.default_log_loss,
+            fit.scored_reviews
+        );
+        if let Err(e) = db::save_fsrs_weights(pool, &user_id, &fit).await {
+            tracing::warn!("Failed to save scheduler weights for {}: {}", user_id, e);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reviews of cards a learner remembers much better than the defaults expect
+    fn strong_memory_histories() -> Vec<Vec<ReviewEntry>> {
+        let mut seed: u64 = 7;
+        let mut random = move || {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 33) as f64 / (1u64 << 31) as f64
+        };
+        let mut truth = Parameters::default();
+        truth.weights[2] = 12.0;
+        truth.weights[8] = 2.2;
+
+        (0..60)
+            .map(|_| {
+                let mut history = vec![ReviewEntry { rating: Rating::Good, elapsed_days: None }];
+                let mut state = truth.next_state(None, 0.0, Rating::Good);
+                for _ in 0..4 {
+                    // Reviewed later than planned, when recall is uncertain
+                    let elapsed = (state.stability * (1.0 + 2.0 * random())).max(1.0);
+                    let recalled = random() < retrievability(elapsed, state.stability);
+                    let rating = if recalled { Rating::Good } else { Rating::Again };
+                    history.push(ReviewEntry { rating, elapsed_days: Some(elapsed.round()) });
+                    state = truth.next_state(Some(state), elapsed.round(), rating);
+                }
+                history
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_fit_beats_defaults_on_the_users_reviews() {
+        let fit = fit(&strong_memory_histories()).unwrap();
+        assert!(fit.log_loss < fit.default_log_loss, "{:?}", fit);
+        assert_eq!(fit.scored_reviews, 240);
+        assert!(fit.weights.iter().zip(WEIGHT_BOUNDS).all(|(w, (min, max))| (min..=max).contains(w)));
+        // The learner remembers Good cards longer than the defaults assume
+        assert!(fit.weights[2] > DEFAULT_WEIGHTS[2]);
+    }
+
+    #[test]
+    fn test_fit_needs_enough_reviews_with_known_start() {
+        let mut histories = strong_memory_histories();
+        histories.truncate(10);
+        assert!(fit(&histories).is_none());
+
+        // Cards whose first review is not in the log are ignored
+        let mut headless = strong_memory_histories();
+        for history in &mut headless {
+            history.remove(0);
+        }
+        assert!(fit(&headless).is_none());
+    }
+}
@@ -0,0 +1,171 @@
+/// Native language used when a user hasn't chosen one
+pub const DEFAULT_NATIVE_LANGUAGE: &str = "zh";
+
+/// Language being learned when a user hasn't chosen one
+pub const DEFAULT_TARGET_LANGUAGE: &str = "en";
+
+/// Level tag for multi-word expressions, used alongside every level system
+pub const PHRASE_LEVEL: &str = "Phrase";
+
+/// Supported native and target languages: (code, name used in AI prompts)
+pub const SUPPORTED_LANGUAGES: &[(&str, &str)] = &[
+    ("zh", "Simplified Chinese"),
+    ("zh-Hant", "Traditional Chinese"),
+    ("ja", "Japanese"),
+    ("ko", "Korean"),
+    ("es", "Spanish"),
+    ("fr", "French"),
+    ("de", "German"),
+    ("pt", "Portuguese"),
+    ("ru", "Russian"),
+    ("vi", "Vietnamese"),
+    ("en", "English"),
+];
+
+pub fn is_supported(code: &str) -> bool {
+    SUPPORTED_LANGUAGES.iter().any(|(c, _)| *c == code)
+}
+
+/// The supported code matching `code` in any letter case, e.g. "ZH-hant" -> "zh-Hant"
+pub fn canonical_code(code: &str) -> Option<&'static str> {
+    SUPPORTED_LANGUAGES
+        .iter()
+        .find(|(c, _)| c.eq_ignore_ascii_case(code))
+        .map(|(c, _)| *c)
+}
+
+/// Language name for prompts, e.g. "ja" -> "Japanese"
+pub fn language_name(code: &str) -> &'static str {
+    SUPPORTED_LANGUAGES
+        .iter()
+        .find(|(c, _)| *c == code)
+        .map(|(_, name)| *name)
+        .unwrap_or("Simplified Chinese")
+}
+
+/// Proficiency scale used to tag vocabulary of a target language
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LevelSystem {
+    pub name: &'static str,
+    /// Levels from easiest to hardest
+    pub levels: &'static [&'static str],
+}
+
+const CEFR: LevelSystem = LevelSystem {
+    name: "CEFR",
+    levels: &["A1", "A2", "B1", "B2", "C1", "C2"],
+};
+
+const JLPT: LevelSystem = LevelSystem {
+    name: "JLPT",
+    levels: &["N5", "N4", "N3", "N2", "N1"],
+};
+
+const HSK: LevelSystem = LevelSystem {
+    name: "HSK",
+    levels: &["HSK1", "HSK2", "HSK3", "HSK4", "HSK5", "HSK6"],
+};
+
+const TOPIK: LevelSystem = LevelSystem {
+    name: "TOPIK",
+    levels: &["TOPIK1", "TOPIK2", "TOPIK3", "TOPIK4", "TOPIK5", "TOPIK6"],
+};
+
+/// Level system for a target language (CEFR for European languages and anything unlisted)
+pub fn level_system(target: &str) -> LevelSystem {
+    match target {
+        "ja" => JLPT,
+        "zh" | "zh-Hant" => HSK,
+        "ko" => TOPIK,
+        _ => CEFR,
+    }
+}
+
+impl LevelSystem {
+    /// Tags the AI may use, e.g. "N5, N4, N3, N2, N1, Phrase"
+    pub fn prompt_tags(&self) -> String {
+        let mut tags = self.levels.to_vec();
+        tags.push(PHRASE_LEVEL);
+        tags.join(", ")
+    }
+
+    /// Map a level returned by the AI onto this system ("n3" -> "N3", "HSK 4" -> "HSK4")
+    /// Unknown values are kept as-is so nothing the AI said is lost
+    pub fn normalize(&self, raw: &str) -> String {
+        let key: String = raw.chars().filter(|c| !c.is_whitespace() && *c != '-').collect::<String>().to_uppercase();
+        if key == PHRASE_LEVEL.to_uppercase() {
+            return PHRASE_LEVEL.to_string();
+        }
+        self.levels
+            .iter()
+            .find(|level| level.to_uppercase() == key || format!("{}{}", self.name, level) == key)
+            .map(|level| level.to_string())
+            .unwrap_or_else(|| raw.trim().to_string())
+    }
+}
+
+/// Question shown when the AI fails to generate a review question
+pub fn fallback_review_question(lang: &str, question_type: &str, word: &str) -> String {
+    match (lang, question_type) {
+        ("zh" | "zh-Hant", "meaning") => format!("「{}」这个词是什么意思？", word),
+        ("zh" | "zh-Hant", "usage") => format!("用「{}」造一个句子吧！", word),
+        ("zh" | "zh-Hant", "context") => format!("还记得「{}」在视频里是什么意思吗？", word),
+        ("zh" | "zh-Hant", "spelling") => "听发音，把这个单词拼出来吧！".to_string(),
+        ("zh" | "zh-Hant", _) => format!("「{}」是什么意思？", word),
+        ("ja", "usage") => format!("「{}」を使って文を作ってみましょう！", word),
+        ("ja", "spelling") => "発音を聞いて、単語をつづってみましょう！".to_string(),
+        ("ja", _) => format!("「{}」はどういう意味ですか？", word),
+        ("ko", "usage") => format!("'{}'(으)로 문장을 만들어 보세요!", word),
+        ("ko", "spelling") => "발음을 듣고 단어의 철자를 써 보세요!".to_string(),
+        ("ko", _) => format!("'{}'은(는) 무슨 뜻인가요?", word),
+        ("es", "usage") => format!("¡Escribe una oración con \"{}\"!", word),
+        ("es", "spelling") => "¡Escucha la pronunciación y escribe la palabra!".to_string(),
+        ("es", _) => format!("¿Qué significa \"{}\"?", word),
+        (_, "usage") => format!("Write a sentence using \"{}\"!", word),
+        (_, "spelling") => "Listen to the pronunciation and spell the word!".to_string(),
+        (_, _) => format!("What does \"{}\" mean?", word),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_canonical_code_ignores_case() {
+        assert_eq!(canonical_code("zh-Hant"), Some("zh-Hant"));
+        assert_eq!(canonical_code("zh-hant"), Some("zh-Hant"));
+        assert_eq!(canonical_code("EN"), Some("en"));
+        assert_eq!(canonical_code("xx"), None);
+    }
+
+    #[test]
+    fn test_level_system_per_target_language() {
+        assert_eq!(level_system("ja"), JLPT);
+        assert_eq!(level_system("zh"), HSK);
+        assert_eq!(level_system("zh-Hant"), HSK);
+        assert_eq!(level_system("ko"), TOPIK);
+        assert_eq!(level_system("fr"), CEFR);
+        assert_eq!(level_system("xx"), CEFR);
+    }
+
+    #[test]
+    fn test_normalize_levels() {
+        assert_eq!(CEFR.normalize(" b2 "), "B2");
+        assert_eq!(CEFR.normalize("CEFR C1"), "C1");
+        assert_eq!(JLPT.normalize("n3"), "N3");
+        assert_eq!(JLPT.normalize("JLPT-N1"), "N1");
+        assert_eq!(HSK.normalize("hsk 4"), "HSK4");
+        assert_eq!(TOPIK.normalize("Topik-2"), "TOPIK2");
+        assert_eq!(TOPIK.normalize("PHRASE"), PHRASE_LEVEL);
+    }
+
+    #[test]
+    fn test_normalize_keeps_unknown_levels() {
+        assert_eq!(CEFR.normalize(" advanced "), "advanced");
+        assert_eq!(JLPT.normalize("N6"), "N6");
+        // A level from another system is not mapped onto this one
+        assert_eq!(HSK.normalize("B1"), "B1");
+        assert_eq!(TOPIK.normalize(""), "");
+    }
+}
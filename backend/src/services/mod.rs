@@ -1,5 +1,22 @@
 pub mod ai;
+pub mod ai_json;
+pub mod ai_mock;
+pub mod ai_prompts;
+pub mod ai_router;
+pub mod ai_transport;
+pub mod ai_usage;
+pub mod fsrs;
+pub mod fsrs_optimizer;
+pub mod language;
 pub mod r2;
+pub mod review_queue;
+pub mod scheduling;
+pub mod segmentation;
+pub mod subtitle_export;
+pub mod subtitle_parser;
+pub mod transcript;
+pub mod translation;
+pub mod usage_ledger;
 pub mod youtube;
 
 pub use ai::*;
@@ -4,6 +4,7 @@ use aws_sdk_s3::{
     primitives::ByteStream,
     Client,
 };
+use std::path::Path;
 
 pub struct R2Client {
     client: Client,
@@ -47,8 +48,16 @@ impl R2Client {
     }
 
     pub async fn upload(&self, key: &str, data: Vec<u8>, content_type: &str) -> Result<String> {
-        let body = ByteStream::from(data);
+        self.put(key, ByteStream::from(data), content_type).await
+    }
+
+    /// Upload a file from disk, streaming it instead of reading it into memory
+    pub async fn upload_file(&self, key: &str, path: &Path, content_type: &str) -> Result<String> {
+        let body = ByteStream::from_path(path).await?;
+        self.put(key, body, content_type).await
+    }
 
+    async fn put(&self, key: &str, body: ByteStream, content_type: &str) -> Result<String> {
         self.client
             .put_object()
             .bucket(&self.bucket)
@@ -63,7 +72,6 @@ impl R2Client {
         Ok(url)
     }
 
-    #[allow(dead_code)]
     pub async fn delete(&self, key: &str) -> Result<()> {
         self.client
             .delete_object()
@@ -0,0 +1,199 @@
+//! Build a review session from a user's due cards
+//!
+//! Due cards fall into three buckets: new (never reviewed), learning (in the learning or
+//! relearning steps) and review. Each bucket keeps its own order and the buckets are spread
+//! evenly through the session, so new words are mixed in with reviews instead of coming in a
+//! block at the end. Words saved from the same sentence are siblings: only one of them is shown
+//! per day and the others are buried until tomorrow, since seeing one gives the others away.
+
+use std::collections::HashSet;
+
+use serde::Serialize;
+
+use crate::db::{CardPhase, SavedVocabulary};
+
+pub const DEFAULT_SESSION_SIZE: usize = 100;
+pub const MAX_SESSION_SIZE: usize = 500;
+
+#[derive(Debug, Clone)]
+pub struct QueueOptions {
+    /// Most cards in the session
+    pub limit: usize,
+    /// Most new cards in the session, on top of the daily new card limit
+    pub new_limit: Option<usize>,
+    pub bury_siblings: bool,
+}
+
+impl Default for QueueOptions {
+    fn default() -> Self {
+        Self {
+            limit: DEFAULT_SESSION_SIZE,
+            new_limit: None,
+            bury_siblings: true,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReviewQueue {
+    pub cards: Vec<SavedVocabulary>,
+    pub new_count: usize,
+    pub learning_count: usize,
+    pub review_count: usize,
+    /// Due cards held back because a sibling is in the session or was reviewed today
+    pub buried: usize,
+}
+
+/// Source video and sentence shared by sibling cards
+pub type SiblingKey = (Option<String>, String);
+
+pub fn sibling_key(card: &SavedVocabulary) -> Option<SiblingKey> {
+    let sentence = card.source_sentence.as_deref()?.trim();
+    (!sentence.is_empty()).then(|| (card.source_video_id.clone(), sentence.to_string()))
+}
+
+/// Order `due` (the cards due now, within the daily limits, oldest due first) into a session
+/// `reviewed_today` lists the cards reviewed today with their sibling key, for burying
+pub fn build_queue(
+    due: Vec<SavedVocabulary>,
+    reviewed_today: &[(i32, SiblingKey)],
+    options: &QueueOptions,
+) -> ReviewQueue {
+    let mut new = Vec::new();
+    let mut learning = Vec::new();
+    let mut review = Vec::new();
+    let mut seen: HashSet<SiblingKey> = HashSet::new();
+    let mut buried = 0;
+
+    for card in due {
+        let is_new = card.review_count == 0;
+        // Learning cards are mid-way through their steps and are never buried
+        if options.bury_siblings && (is_new || card.phase == CardPhase::Review) {
+            if let Some(key) = sibling_key(&card) {
+                let sibling_reviewed = reviewed_today.iter().any(|(id, reviewed)| *id != card.id && *reviewed == key);
+                if sibling_reviewed || !seen.insert(key) {
+                    buried += 1;
+                    continue;
+                }
+            }
+        }
+
+        match card.phase {
+            _ if is_new => new.push(card),
+            CardPhase::Review => review.push(card),
+            _ => learning.push(card),
+        }
+    }
+
+    if let Some(new_limit) = options.new_limit {
+        new.truncate(new_limit);
+    }
+
+    let mut cards = interleave(vec![learning, review, new]);
+    cards.truncate(options.limit);
+
+    let new_count = cards.iter().filter(|card| card.review_count == 0).count();
+    let review_count = cards.iter().filter(|card| card.review_count > 0 && card.phase == CardPhase::Review).count();
+    ReviewQueue {
+        learning_count: cards.len() - new_count - review_count,
+        new_count,
+        review_count,
+        buried,
+        cards,
+    }
+}
+
+/// Spread each bucket evenly over the result: the i-th of n cards goes at position (i + 0.5) / n,
+/// ties going to the earlier bucket
+fn interleave(buckets: Vec<Vec<SavedVocabulary>>) -> Vec<SavedVocabulary> {
+    let mut positioned: Vec<(f64, usize, SavedVocabulary)> = buckets
+        .into_iter()
+        .enumerate()
+        .flat_map(|(bucket, cards)| {
+            let len = cards.len() as f64;
+            cards
+                .into_iter()
+                .enumerate()
+                .map(move |(i, card)| ((i as f64 + 0.5) / len, bucket, card))
+        })
+        .collect();
+    positioned.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+    positioned.into_iter().map(|(_, _, card)| card).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn card(id: i32, phase: CardPhase, review_count: i32, sentence: Option<&str>) -> SavedVocabulary {
+        SavedVocabulary {
+            id,
+            word: format!("word{}", id),
+            meaning: String::new(),
+            level: "B1".to_string(),
+            example: None,
+            ease_factor: 2.5,
+            interval_days: 0,
+            interval_minutes: 0,
+            due_date: None,
+            due_at: None,
+            review_count,
+            learning_step: 0,
+            phase,
+            source_video_id: sentence.map(|_| "video".to_string()),
+            source_sentence: sentence.map(str::to_string),
+            source_timestamp: None,
+            created_at: String::new(),
+            last_reviewed_at: None,
+            stability: None,
+            difficulty: None,
+            retrievability: 1.0,
+        }
+    }
+
+    fn ids(queue: &ReviewQueue) -> Vec<i32> {
+        queue.cards.iter().map(|card| card.id).collect()
+    }
+
+    #[test]
+    fn test_buckets_are_interleaved_and_limited() {
+        let due = vec![
+            card(1, CardPhase::Review, 5, None),
+            card(2, CardPhase::Review, 5, None),
+            card(3, CardPhase::Review, 5, None),
+            card(4, CardPhase::Review, 5, None),
+            card(5, CardPhase::Learning, 0, None),
+            card(6, CardPhase::Learning, 0, None),
+            card(7, CardPhase::Relearning, 8, None),
+        ];
+
+        let queue = build_queue(due.clone(), &[], &QueueOptions::default());
+        // New cards are spread through the reviews rather than appended
+        assert_eq!(ids(&queue), vec![1, 5, 2, 7, 3, 6, 4]);
+        assert_eq!((queue.new_count, queue.learning_count, queue.review_count), (2, 1, 4));
+
+        let options = QueueOptions { limit: 4, new_limit: Some(1), ..QueueOptions::default() };
+        let queue = build_queue(due, &[], &options);
+        assert_eq!(ids(&queue), vec![1, 2, 7, 5]);
+    }
+
+    #[test]
+    fn test_siblings_are_buried() {
+        let due = vec![
+            card(1, CardPhase::Review, 3, Some("The cat sat on the mat.")),
+            card(2, CardPhase::Learning, 0, Some("The cat sat on the mat.")),
+            card(3, CardPhase::Relearning, 6, Some("The cat sat on the mat.")),
+            card(4, CardPhase::Review, 3, Some("A bird flew by.")),
+            card(5, CardPhase::Learning, 0, Some("A bird flew by.")),
+        ];
+        let reviewed_today = vec![(9, (Some("video".to_string()), "A bird flew by.".to_string()))];
+
+        let queue = build_queue(due.clone(), &reviewed_today, &QueueOptions::default());
+        // One card per sentence, none from a sentence reviewed today; learning cards always stay
+        assert_eq!(ids(&queue), vec![3, 1]);
+        assert_eq!(queue.buried, 3);
+
+        let options = QueueOptions { bury_siblings: false, ..QueueOptions::default() };
+        assert_eq!(build_queue(due, &reviewed_today, &options).cards.len(), 5);
+    }
+}
@@ -0,0 +1,453 @@
+//! How a review reschedules a card
+//!
+//! New cards go through the user's learning steps (minutes apart) before graduating to the
+//! review phase; a forgotten review card goes through the relearning steps and back. In the
+//! review phase SM-2 grows the interval by the card's ease factor, FSRS by its memory model.
+//! Step lengths, the graduating and maximum intervals and the easy bonus come from the user's
+//! [`DeckOptions`].
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::models::DeckOptions;
+use crate::services::fsrs;
+
+/// Spaced repetition algorithm a user reviews with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Scheduler {
+    /// Fixed learning steps, then intervals grown by an ease factor
+    #[default]
+    Sm2,
+    /// Stability and difficulty per card, see `services::fsrs`
+    Fsrs,
+}
+
+impl Scheduler {
+    pub const ALL: [Scheduler; 2] = [Scheduler::Sm2, Scheduler::Fsrs];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|s| s.as_str().eq_ignore_ascii_case(name.trim()))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scheduler::Sm2 => "sm2",
+            Scheduler::Fsrs => "fsrs",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CardPhase {
+    /// Going through the learning steps (new cards start here)
+    #[default]
+    Learning,
+    /// Graduated; reviewed at growing intervals
+    Review,
+    /// Forgotten in review, going through the relearning steps
+    Relearning,
+}
+
+impl CardPhase {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "learning" => Some(CardPhase::Learning),
+            "review" => Some(CardPhase::Review),
+            "relearning" => Some(CardPhase::Relearning),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CardPhase::Learning => "learning",
+            CardPhase::Review => "review",
+            CardPhase::Relearning => "relearning",
+        }
+    }
+
+    fn steps<'a>(&self, options: &'a DeckOptions) -> &'a [i32] {
+        match self {
+            CardPhase::Relearning => &options.relearning_steps,
+            _ => &options.learning_steps,
+        }
+    }
+}
+
+/// Scheduling fields of a card, as stored
+#[derive(Debug, Clone)]
+pub struct CardSchedule {
+    pub phase: CardPhase,
+    /// Index of the current (re)learning step
+    pub learning_step: i32,
+    pub ease_factor: f64,
+    pub interval_days: i32,
+    pub interval_minutes: i32,
+    pub review_count: i32,
+    pub memory: Option<fsrs::MemoryState>,
+    pub last_reviewed_at: Option<DateTime<Utc>>,
+}
+
+impl CardSchedule {
+    /// Days since the previous review; `None` before the first one
+    pub fn elapsed_days(&self, now: DateTime<Utc>) -> Option<f64> {
+        self.last_reviewed_at.map(|at| (now - at).num_seconds().max(0) as f64 / 86400.0)
+    }
+}
+
+/// What a review changes on a card
+#[derive(Debug, Clone)]
+pub struct ReviewOutcome {
+    pub phase: CardPhase,
+    pub learning_step: i32,
+    pub ease_factor: f64,
+    pub interval_days: i32,
+    pub interval_minutes: i32,
+    pub memory: Option<fsrs::MemoryState>,
+    /// A card in the review phase was forgotten
+    pub lapsed: bool,
+}
+
+impl ReviewOutcome {
+    /// Fuzz the interval of a card in the review phase, see [`fuzz_interval_days`]
+    pub fn with_fuzz(self, options: &DeckOptions, roll: f64) -> Self {
+        if self.phase != CardPhase::Review {
+            return self;
+        }
+        let days = fuzz_interval_days(self.interval_days, options.maximum_interval_days, roll);
+        ReviewOutcome {
+            interval_days: days,
+            interval_minutes: days * MINUTES_PER_DAY,
+            ..self
+        }
+    }
+}
+
+const MINUTES_PER_DAY: i32 = 24 * 60;
+
+/// Every scheduling column of a stored card; a copy is kept before each review to undo it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CardState {
+    pub ease_factor: f64,
+    pub interval_days: i32,
+    pub interval_minutes: i32,
+    pub due_date: Option<String>,
+    pub due_at: Option<String>,
+    pub learning_step: i32,
+    pub phase: CardPhase,
+    pub stability: Option<f64>,
+    pub difficulty: Option<f64>,
+    pub lapses: i32,
+    pub review_count: i32,
+    pub last_reviewed_at: Option<DateTime<Utc>>,
+}
+
+impl CardState {
+    /// What [`schedule_review`] needs to know about the card
+    pub fn schedule(&self) -> CardSchedule {
+        CardSchedule {
+            phase: self.phase,
+            learning_step: self.learning_step,
+            ease_factor: self.ease_factor,
+            interval_days: self.interval_days,
+            interval_minutes: self.interval_minutes,
+            review_count: self.review_count,
+            memory: match (self.stability, self.difficulty) {
+                (Some(stability), Some(difficulty)) => Some(fsrs::MemoryState { stability, difficulty }),
+                _ => None,
+            },
+            last_reviewed_at: self.last_reviewed_at,
+        }
+    }
+
+    /// The card after a review at `now` with `outcome`
+    pub fn reviewed(&self, outcome: &ReviewOutcome, now: DateTime<Utc>) -> CardState {
+        let due = now + chrono::Duration::minutes(outcome.interval_minutes as i64);
+        CardState {
+            ease_factor: outcome.ease_factor,
+            interval_days: outcome.interval_days,
+            interval_minutes: outcome.interval_minutes,
+            due_date: Some(due.format("%Y-%m-%d").to_string()),
+            due_at: Some(due.format("%Y-%m-%dT%H:%M:%S").to_string()),
+            learning_step: outcome.learning_step,
+            phase: outcome.phase,
+            stability: outcome.memory.map(|m| m.stability),
+            difficulty: outcome.memory.map(|m| m.difficulty),
+            lapses: self.lapses + outcome.lapsed as i32,
+            review_count: self.review_count + 1,
+            last_reviewed_at: Some(now),
+        }
+    }
+}
+
+/// Share of the interval, per interval range in days, that fuzz may add or remove
+const FUZZ_RANGES: [(f64, f64, f64); 3] = [(2.5, 7.0, 0.15), (7.0, 20.0, 0.1), (20.0, f64::INFINITY, 0.05)];
+
+/// Pick an interval near `days` so that cards reviewed together don't keep coming due on the
+/// same day; `roll` is uniform in 0..1. Intervals under 3 days are not fuzzed
+pub fn fuzz_interval_days(days: i32, maximum_interval_days: i32, roll: f64) -> i32 {
+    if days < 3 {
+        return days;
+    }
+    let interval = days as f64;
+    let delta = 1.0
+        + FUZZ_RANGES
+            .iter()
+            .map(|&(start, end, factor)| factor * (interval.min(end) - start).max(0.0))
+            .sum::<f64>();
+    let min = ((interval - delta).round() as i32).max(2);
+    let max = ((interval + delta).round() as i32).min(maximum_interval_days).max(min);
+    (min + ((max - min + 1) as f64 * roll.clamp(0.0, 1.0)) as i32).min(max)
+}
+
+/// Reschedule `card` after a review of `quality` (0=forgot, 1=hard, 2=good, 3=easy)
+pub fn schedule_review(
+    scheduler: Scheduler,
+    params: &fsrs::Parameters,
+    options: &DeckOptions,
+    card: &CardSchedule,
+    quality: i32,
+    now: DateTime<Utc>,
+) -> ReviewOutcome {
+    match scheduler {
+        Scheduler::Sm2 => sm2_review(options, card, quality),
+        Scheduler::Fsrs => fsrs_review(params, options, card, quality, now),
+    }
+}
+
+/// Outcome placing the card on step `step` of its phase's steps, or graduating it after
+/// `graduate_days` when there is no such step
+fn step_or_graduate(
+    options: &DeckOptions,
+    phase: CardPhase,
+    step: i32,
+    graduate_days: i32,
+    card: &CardSchedule,
+) -> ReviewOutcome {
+    match phase.steps(options).get(step as usize) {
+        Some(&minutes) => ReviewOutcome {
+            phase,
+            learning_step: step,
+            ease_factor: card.ease_factor,
+            interval_days: 0,
+            interval_minutes: minutes,
+            memory: card.memory,
+            lapsed: false,
+        },
+        None => graduate(options, graduate_days, card),
+    }
+}
+
+fn graduate(options: &DeckOptions, days: i32, card: &CardSchedule) -> ReviewOutcome {
+    let days = days.clamp(1, options.maximum_interval_days.max(1));
+    ReviewOutcome {
+        phase: CardPhase::Review,
+        learning_step: 0,
+        ease_factor: card.ease_factor,
+        interval_days: days,
+        interval_minutes: days * MINUTES_PER_DAY,
+        memory: card.memory,
+        lapsed: false,
+    }
+}
+
+/// Learning steps, then SM-2 intervals; Hard counts as a failure
+/// SM-2 reviews drop any FSRS state, which is rebuilt from the SM-2 interval if FSRS is enabled again
+fn sm2_review(options: &DeckOptions, card: &CardSchedule, quality: i32) -> ReviewOutcome {
+    let failed = quality < 2;
+
+    let mut outcome = match card.phase {
+        // Without relearning steps a forgotten card comes back after the graduating interval
+        CardPhase::Review if failed => ReviewOutcome {
+            lapsed: true,
+            ..step_or_graduate(options, CardPhase::Relearning, 0, options.graduating_interval_days, card)
+        },
+        CardPhase::Review => {
+            let graduating = options.graduating_interval_days;
+            let days = match quality {
+                2 => ((card.interval_days as f64 * card.ease_factor) as i32).max(graduating),
+                _ => ((card.interval_days as f64 * card.ease_factor * options.easy_bonus) as i32).max(graduating + 1),
+            };
+            ReviewOutcome {
+                ease_factor: if quality == 3 { (card.ease_factor + 0.1).min(3.0) } else { card.ease_factor },
+                ..graduate(options, days, card)
+            }
+        }
+        phase if failed => step_or_graduate(options, phase, 0, options.graduating_interval_days, card),
+        phase => step_or_graduate(options, phase, card.learning_step + 1, options.graduating_interval_days, card),
+    };
+
+    if failed {
+        outcome.ease_factor = (card.ease_factor - 0.2).max(1.3);
+    }
+    outcome.memory = None;
+    outcome
+}
+
+/// FSRS: the memory model is updated on every review and sets the interval once the card is
+/// in the review phase. In (re)learning, Hard repeats the step, Good moves on and Easy graduates
+fn fsrs_review(
+    params: &fsrs::Parameters,
+    options: &DeckOptions,
+    card: &CardSchedule,
+    quality: i32,
+    now: DateTime<Utc>,
+) -> ReviewOutcome {
+    let params = fsrs::Parameters {
+        maximum_interval_days: options.maximum_interval_days as f64,
+        ..params.clone()
+    };
+    let rating = fsrs::Rating::from_quality(quality);
+    // Cards reviewed before FSRS was enabled start from their SM-2 schedule
+    let memory = card.memory.or_else(|| {
+        (card.review_count > 0).then(|| params.memory_from_sm2(card.ease_factor, card.interval_minutes as f64 / 1440.0))
+    });
+    let next = params.next_state(memory, card.elapsed_days(now).unwrap_or(0.0), rating);
+    let graduate_days = params.next_interval_days(next.stability) as i32;
+    let card = CardSchedule { memory: Some(next), ..card.clone() };
+
+    let (phase, step) = match (card.phase, rating) {
+        (CardPhase::Review | CardPhase::Relearning, fsrs::Rating::Again) => (CardPhase::Relearning, 0),
+        (CardPhase::Learning, fsrs::Rating::Again) => (CardPhase::Learning, 0),
+        (CardPhase::Review, _) => (CardPhase::Review, 0),
+        (phase, fsrs::Rating::Hard) => (phase, card.learning_step),
+        (phase, fsrs::Rating::Good) => (phase, card.learning_step + 1),
+        (phase, fsrs::Rating::Easy) => (phase, i32::MAX),
+    };
+
+    let outcome = match phase {
+        CardPhase::Review => graduate(options, graduate_days, &card),
+        phase => step_or_graduate(options, phase, step, graduate_days, &card),
+    };
+    ReviewOutcome {
+        lapsed: card.phase == CardPhase::Review && rating == fsrs::Rating::Again,
+        ..outcome
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn card(phase: CardPhase, learning_step: i32, interval_days: i32) -> CardSchedule {
+        CardSchedule {
+            phase,
+            learning_step,
+            ease_factor: 2.5,
+            interval_days,
+            interval_minutes: interval_days * MINUTES_PER_DAY,
+            review_count: 3,
+            memory: None,
+            last_reviewed_at: None,
+        }
+    }
+
+    fn sm2(options: &DeckOptions, card: &CardSchedule, quality: i32) -> ReviewOutcome {
+        schedule_review(Scheduler::Sm2, &fsrs::Parameters::default(), options, card, quality, Utc::now())
+    }
+
+    #[test]
+    fn test_default_options_keep_the_original_schedule() {
+        let options = DeckOptions::default();
+
+        let second_step = sm2(&options, &card(CardPhase::Learning, 0, 0), 2);
+        assert_eq!((second_step.phase, second_step.interval_minutes), (CardPhase::Learning, 60));
+
+        let graduated = sm2(&options, &card(CardPhase::Learning, 3, 0), 2);
+        assert_eq!((graduated.phase, graduated.interval_days), (CardPhase::Review, 2));
+
+        let easy = sm2(&options, &card(CardPhase::Review, 0, 10), 3);
+        assert_eq!(easy.interval_days, (10.0 * 2.5 * 1.3) as i32);
+        assert!((easy.ease_factor - 2.6).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_custom_steps_intervals_and_lapses() {
+        let options = DeckOptions {
+            learning_steps: vec![1, 10],
+            relearning_steps: vec![15],
+            graduating_interval_days: 1,
+            maximum_interval_days: 30,
+            ..DeckOptions::default()
+        };
+
+        assert_eq!(sm2(&options, &card(CardPhase::Learning, 1, 0), 2).interval_days, 1);
+        assert_eq!(sm2(&options, &card(CardPhase::Review, 0, 20), 2).interval_days, 30);
+
+        let lapse = sm2(&options, &card(CardPhase::Review, 0, 20), 0);
+        assert!(lapse.lapsed);
+        assert_eq!((lapse.phase, lapse.interval_minutes), (CardPhase::Relearning, 15));
+        // After the last relearning step the card is back in review
+        let relearned = sm2(&options, &card(CardPhase::Relearning, 0, 0), 2);
+        assert_eq!(relearned.phase, CardPhase::Review);
+    }
+
+    #[test]
+    fn test_fuzz_spreads_review_intervals() {
+        assert_eq!(fuzz_interval_days(2, 36500, 0.9), 2);
+        let spread: Vec<i32> = [0.0, 0.5, 0.999].iter().map(|&roll| fuzz_interval_days(30, 36500, roll)).collect();
+        assert_eq!(spread, vec![27, 30, 33]);
+        // Never beyond the maximum interval
+        assert_eq!(fuzz_interval_days(30, 30, 0.999), 30);
+
+        let options = DeckOptions::default();
+        let learning = sm2(&options, &card(CardPhase::Learning, 0, 0), 2).with_fuzz(&options, 0.0);
+        assert_eq!(learning.interval_minutes, 60);
+    }
+
+    #[test]
+    fn test_fsrs_uses_steps_until_graduation() {
+        let options = DeckOptions::default();
+        let new_card = CardSchedule { review_count: 0, ..card(CardPhase::Learning, 0, 0) };
+        let review = |card: &CardSchedule, quality| {
+            schedule_review(Scheduler::Fsrs, &fsrs::Parameters::default(), &options, card, quality, Utc::now())
+        };
+
+        let good = review(&new_card, 2);
+        assert_eq!((good.phase, good.learning_step, good.interval_minutes), (CardPhase::Learning, 1, 60));
+        assert!(good.memory.is_some());
+
+        let easy = review(&new_card, 3);
+        assert_eq!(easy.phase, CardPhase::Review);
+        assert!(easy.interval_days >= 1);
+
+        let lapse = review(&card(CardPhase::Review, 0, 20), 0);
+        assert!(lapse.lapsed);
+        assert_eq!((lapse.phase, lapse.interval_minutes), (CardPhase::Relearning, 20));
+    }
+
+    #[test]
+    fn test_undo_restores_the_card_from_before_the_review() {
+        let before = CardState {
+            ease_factor: 2.3,
+            interval_days: 12,
+            interval_minutes: 12 * MINUTES_PER_DAY,
+            due_date: Some("2026-03-01".to_string()),
+            due_at: Some("2026-03-01T09:30:00".to_string()),
+            learning_step: 0,
+            phase: CardPhase::Review,
+            stability: Some(11.5),
+            difficulty: Some(6.2),
+            lapses: 1,
+            review_count: 7,
+            last_reviewed_at: Some(Utc::now() - chrono::Duration::days(12)),
+        };
+        // Kept as JSON until the undo
+        let snapshot = serde_json::to_string(&before).unwrap();
+
+        let now = Utc::now();
+        let params = fsrs::Parameters::default();
+        let outcome = schedule_review(Scheduler::Fsrs, &params, &DeckOptions::default(), &before.schedule(), 0, now);
+        let after = before.reviewed(&outcome, now);
+        assert_eq!((after.phase, after.learning_step), (CardPhase::Relearning, 0));
+        assert_eq!((after.lapses, after.review_count), (2, 8));
+        assert_eq!(after.interval_minutes, 20);
+        assert_ne!(after.stability, before.stability);
+        assert_eq!(after.last_reviewed_at, Some(now));
+
+        let restored: CardState = serde_json::from_str(&snapshot).unwrap();
+        assert_eq!(restored, before);
+        assert_eq!(restored.schedule().memory.map(|m| m.stability), Some(11.5));
+    }
+}
@@ -0,0 +1,369 @@
+//! Sentence re-segmentation of caption tracks
+//!
+//! Auto-generated captions arrive as rolling fragments that overlap, cut sentences in half and
+//! carry no punctuation. [`to_sentences`] turns any track into one subtitle per sentence: it
+//! drops the repeated words of rolling (unpunctuated) captions, spreads each fragment's time
+//! over its words (unless the source timed them),
+//! splits at sentence punctuation (or pauses and length limits when there is none) and, for
+//! unpunctuated tracks, restores capitalisation and the final punctuation mark.
+
+use crate::models::{Subtitle, SubtitleWord};
+
+/// Which version of a video's subtitles to serve
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SubtitleTrack {
+    /// Cues as the source delivered them
+    #[default]
+    Raw,
+    /// One cue per sentence
+    Sentences,
+}
+
+impl SubtitleTrack {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "raw" => Some(SubtitleTrack::Raw),
+            "sentences" | "sentence" => Some(SubtitleTrack::Sentences),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubtitleTrack::Raw => "raw",
+            SubtitleTrack::Sentences => "sentences",
+        }
+    }
+
+    /// Key stored translations are saved under: tracks number their lines differently, and
+    /// subtitles in another source language are different lines
+    pub fn translation_key(&self, video_id: &str, lang: &str) -> String {
+        match self {
+            SubtitleTrack::Raw => format!("{}@{}", video_id, lang),
+            SubtitleTrack::Sentences => format!("{}@{}#sentences", video_id, lang),
+        }
+    }
+}
+
+/// Without punctuation, a sentence is cut at this many words...
+const MAX_SENTENCE_WORDS: usize = 25;
+/// ...or this many seconds
+const MAX_SENTENCE_SECS: f64 = 12.0;
+/// A silence this long between fragments ends a sentence
+const PAUSE_SECS: f64 = 1.0;
+/// Rolling captions repeat at most this many words of the previous fragment
+const MAX_OVERLAP_WORDS: usize = 40;
+
+/// Words a long unpunctuated sentence is preferably cut before
+const CLAUSE_STARTERS: &[&str] = &[
+    "and", "but", "so", "because", "then", "which", "now", "okay", "ok", "well", "actually", "anyway", "however",
+];
+/// Words that start a question when the track has no punctuation
+const QUESTION_STARTERS: &[&str] = &[
+    "what", "why", "how", "who", "where", "when", "which", "is", "are", "do", "does", "did", "can", "could",
+    "would", "will", "should", "have", "has",
+];
+/// Dots that do not end a sentence
+const ABBREVIATIONS: &[&str] = &["mr.", "mrs.", "ms.", "dr.", "prof.", "st.", "vs.", "etc.", "e.g.", "i.e.", "a.m.", "p.m."];
+
+#[derive(Debug, Clone)]
+struct Word {
+    text: String,
+    start: f64,
+    end: f64,
+    /// Silence before this word
+    pause_before: f64,
+    /// Timing came from the source rather than the estimate
+    timed: bool,
+}
+
+/// Merge fragments into sentences; see the module docs
+pub fn to_sentences(subtitles: &[Subtitle]) -> Vec<Subtitle> {
+    let tokens: Vec<&str> = subtitles.iter().flat_map(|s| s.text.split_whitespace()).collect();
+    let punctuated = is_punctuated(&tokens);
+    // Only auto captions roll; in human-made ones a repeated word at a cue boundary is real speech
+    let words = collect_words(subtitles, !punctuated);
+    if words.is_empty() {
+        return Vec::new();
+    }
+
+    split_sentences(&words, punctuated)
+        .into_iter()
+        .enumerate()
+        .map(|(index, sentence)| {
+            let text = join_words(sentence.iter().map(|w| w.text.as_str()));
+            Subtitle {
+                index,
+                start: sentence[0].start,
+                end: sentence[sentence.len() - 1].end,
+                text: if punctuated { text } else { restore_punctuation(&text) },
+                translation: None,
+                words: match sentence.iter().all(|w| w.timed) {
+                    true => sentence
+                        .iter()
+                        .map(|w| SubtitleWord { text: w.text.clone(), start: w.start, end: w.end })
+                        .collect(),
+                    false => Vec::new(),
+                },
+            }
+        })
+        .collect()
+}
+
+/// Words of every subtitle in order, without the words rolling captions repeat when `rolling`
+fn collect_words(subtitles: &[Subtitle], rolling: bool) -> Vec<Word> {
+    let mut words: Vec<Word> = Vec::new();
+    let mut previous_end: Option<f64> = None;
+
+    for subtitle in subtitles {
+        let mut tokens: Vec<&str> = subtitle.text.split_whitespace().collect();
+        let mut timings = match subtitle.words.len() == tokens.len() {
+            true => subtitle.words.iter().map(|w| (w.start, w.end)).collect(),
+            false => Vec::new(),
+        };
+        let overlap = if rolling { overlap_len(&words, &tokens) } else { 0 };
+        tokens.drain(..overlap);
+        timings.drain(..overlap.min(timings.len()));
+
+        let pause = previous_end.map(|end| (subtitle.start - end).max(0.0)).unwrap_or(0.0);
+        previous_end = Some(subtitle.end.max(previous_end.unwrap_or(0.0)));
+        if tokens.is_empty() {
+            continue;
+        }
+
+        // Share the cue's time between its words by length
+        let total_chars: usize = tokens.iter().map(|t| t.chars().count()).sum();
+        let duration = (subtitle.end - subtitle.start).max(0.0);
+        let mut start = subtitle.start;
+        for (i, token) in tokens.iter().enumerate() {
+            let share = token.chars().count() as f64 / total_chars.max(1) as f64;
+            let end = if i + 1 == tokens.len() { subtitle.end } else { start + duration * share };
+            let (word_start, word_end) = timings.get(i).copied().unwrap_or((start, end));
+            words.push(Word {
+                text: token.to_string(),
+                start: word_start,
+                end: word_end,
+                pause_before: if i == 0 { pause } else { 0.0 },
+                timed: !timings.is_empty(),
+            });
+            start = end;
+        }
+    }
+
+    words
+}
+
+/// How many leading `tokens` repeat the last words collected so far
+fn overlap_len(words: &[Word], tokens: &[&str]) -> usize {
+    let max = tokens.len().min(words.len()).min(MAX_OVERLAP_WORDS);
+    (1..=max)
+        .rev()
+        .find(|&k| {
+            words[words.len() - k..]
+                .iter()
+                .zip(&tokens[..k])
+                .all(|(word, token)| normalize(&word.text) == normalize(token))
+        })
+        .unwrap_or(0)
+}
+
+fn normalize(word: &str) -> String {
+    word.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase()
+}
+
+/// Human-made captions punctuate; auto captions have (almost) none
+fn is_punctuated(tokens: &[&str]) -> bool {
+    let endings = tokens.iter().filter(|t| ends_sentence(t)).count();
+    endings * MAX_SENTENCE_WORDS >= tokens.len()
+}
+
+fn ends_sentence(word: &str) -> bool {
+    if ABBREVIATIONS.contains(&word.to_lowercase().as_str()) {
+        return false;
+    }
+    let trimmed = word.trim_end_matches(['"', '\'', '”', '’', ')', ']', '」', '』']);
+    trimmed.ends_with(['.', '?', '!', '…', '。', '？', '！'])
+}
+
+fn split_sentences(words: &[Word], punctuated: bool) -> Vec<&[Word]> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+
+    for i in 0..words.len() {
+        let is_last = i + 1 == words.len();
+        let current = &words[start..=i];
+        let next_pause = words.get(i + 1).map(|w| w.pause_before).unwrap_or(0.0);
+
+        let boundary = is_last
+            || ends_sentence(&words[i].text)
+            || (!punctuated && next_pause >= PAUSE_SECS)
+            || current.len() >= MAX_SENTENCE_WORDS
+            || current[current.len() - 1].end - current[0].start >= MAX_SENTENCE_SECS;
+        if !boundary {
+            continue;
+        }
+
+        // A sentence cut for length ends better before a clause starter
+        let forced = !is_last && !ends_sentence(&words[i].text) && next_pause < PAUSE_SECS;
+        let cut = match forced {
+            true => clause_break(current).map(|offset| start + offset).unwrap_or(i + 1),
+            false => i + 1,
+        };
+
+        sentences.push(&words[start..cut]);
+        start = cut;
+    }
+
+    if start < words.len() {
+        sentences.push(&words[start..]);
+    }
+    sentences
+}
+
+/// Position of the last clause starter in `words` that leaves at least a few words on each side
+fn clause_break(words: &[Word]) -> Option<usize> {
+    (4..words.len().saturating_sub(2))
+        .rev()
+        .find(|&i| CLAUSE_STARTERS.contains(&normalize(&words[i].text).as_str()))
+}
+
+/// Join words with spaces, except between CJK characters
+fn join_words<'a>(words: impl Iterator<Item = &'a str>) -> String {
+    let mut text = String::new();
+    for word in words {
+        let cjk_join = text.chars().last().is_some_and(is_cjk) && word.chars().next().is_some_and(is_cjk);
+        if !text.is_empty() && !cjk_join {
+            text.push(' ');
+        }
+        text.push_str(word);
+    }
+    text
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c, '\u{3040}'..='\u{30ff}' | '\u{3400}'..='\u{4dbf}' | '\u{4e00}'..='\u{9fff}' | '\u{ac00}'..='\u{d7af}')
+}
+
+/// Capitalise and end an unpunctuated sentence ("so what is rust" -> "So what is rust?")
+fn restore_punctuation(text: &str) -> String {
+    let mut words: Vec<String> = text
+        .split(' ')
+        .map(|word| match word {
+            "i" => "I".to_string(),
+            w if w.starts_with("i'") => format!("I{}", &w[1..]),
+            w => w.to_string(),
+        })
+        .collect();
+
+    if let Some(first) = words.first_mut() {
+        let mut chars = first.chars();
+        if let Some(c) = chars.next() {
+            *first = c.to_uppercase().chain(chars).collect();
+        }
+    }
+
+    let mut text = words.join(" ");
+    if text.chars().last().is_some_and(is_cjk) {
+        text.push('。');
+    } else if !ends_sentence(&text) && text.chars().last().is_some_and(char::is_alphanumeric) {
+        // The question word may follow a filler ("so what is ...")
+        let is_question = words
+            .iter()
+            .take(2)
+            .any(|w| QUESTION_STARTERS.contains(&normalize(w).as_str()));
+        text.push(if is_question { '?' } else { '.' });
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cue(start: f64, end: f64, text: &str) -> Subtitle {
+        Subtitle { index: 0, start, end, text: text.to_string(), translation: None, words: Vec::new() }
+    }
+
+    fn texts(subtitles: &[Subtitle]) -> Vec<&str> {
+        subtitles.iter().map(|s| s.text.as_str()).collect()
+    }
+
+    #[test]
+    fn test_translation_key_per_track_and_language() {
+        assert_eq!(SubtitleTrack::Raw.translation_key("abc", "en"), "abc@en");
+        assert_eq!(SubtitleTrack::Sentences.translation_key("abc", "en"), "abc@en#sentences");
+        assert_ne!(SubtitleTrack::Raw.translation_key("abc", "en"), SubtitleTrack::Raw.translation_key("abc", "ja"));
+    }
+
+    #[test]
+    fn test_rolling_auto_captions_become_sentences() {
+        let raw = vec![
+            cue(0.0, 2.0, "so what is"),
+            cue(2.0, 4.0, "so what is the borrow checker"),
+            cue(4.0, 6.0, "the borrow checker i think"),
+            cue(7.5, 9.0, "it keeps references valid"),
+        ];
+        let sentences = to_sentences(&raw);
+
+        assert_eq!(texts(&sentences), vec!["So what is the borrow checker I think?", "It keeps references valid."]);
+        assert_eq!(sentences[0].start, 0.0);
+        assert_eq!(sentences[0].end, 6.0);
+        assert_eq!(sentences[1].start, 7.5);
+        assert_eq!(sentences[1].index, 1);
+    }
+
+    #[test]
+    fn test_punctuated_lines_split_at_sentence_ends() {
+        let raw = vec![
+            cue(0.0, 2.0, "Hello everyone. Today we"),
+            cue(2.0, 4.0, "talk about Dr. Smith's"),
+            cue(4.0, 5.0, "work, okay?"),
+        ];
+        let sentences = to_sentences(&raw);
+
+        assert_eq!(texts(&sentences), vec!["Hello everyone.", "Today we talk about Dr. Smith's work, okay?"]);
+        // The first fragment's time is shared by length, so the split falls inside it
+        assert!(sentences[0].end > 0.0 && sentences[0].end < 2.0);
+        assert_eq!(sentences[1].start, sentences[0].end);
+    }
+
+    #[test]
+    fn test_repeated_boundary_word_is_kept_in_punctuated_tracks() {
+        let raw = vec![cue(0.0, 2.0, "And then I said no."), cue(2.0, 4.0, "No, I didn't.")];
+        let sentences = to_sentences(&raw);
+
+        assert_eq!(texts(&sentences), vec!["And then I said no.", "No, I didn't."]);
+    }
+
+    #[test]
+    fn test_long_unpunctuated_run_breaks_before_clause_starter() {
+        let text = "we start with the basic types and then we move on to ownership but before that we look at how memory works on the stack and on the heap";
+        let sentences = to_sentences(&[cue(0.0, 10.0, text)]);
+
+        assert!(sentences.len() >= 2);
+        assert!(sentences.iter().all(|s| s.text.split(' ').count() <= MAX_SENTENCE_WORDS));
+        assert!(sentences[1].text.starts_with("But") || sentences[1].text.starts_with("And"));
+    }
+
+    #[test]
+    fn test_source_word_timings_are_kept() {
+        let mut first = cue(0.0, 2.0, "so what is");
+        first.words = ["so", "what", "is"]
+            .iter()
+            .zip([0.0, 0.4, 0.8, 2.0].windows(2))
+            .map(|(text, t)| SubtitleWord { text: text.to_string(), start: t[0], end: t[1] })
+            .collect();
+        let sentences = to_sentences(&[first, cue(3.5, 5.0, "it is a checker")]);
+
+        assert_eq!(sentences[0].words.iter().map(|w| w.start).collect::<Vec<_>>(), vec![0.0, 0.4, 0.8]);
+        // Estimated timings are not passed off as word timings
+        assert!(sentences[1].words.is_empty());
+    }
+
+    #[test]
+    fn test_cjk_fragments_join_without_spaces() {
+        let sentences = to_sentences(&[cue(0.0, 1.0, "今天我们"), cue(1.0, 2.0, "学习所有权。"), cue(2.0, 3.0, "很重要")]);
+        assert_eq!(texts(&sentences), vec!["今天我们学习所有权。", "很重要"]);
+        assert_eq!(SubtitleTrack::from_name("Sentences"), Some(SubtitleTrack::Sentences));
+    }
+}
@@ -0,0 +1,151 @@
+//! Render subtitles, optionally with their translations, as downloadable SRT, WebVTT or plain text
+
+use crate::models::Subtitle;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Srt,
+    Vtt,
+    Txt,
+}
+
+impl ExportFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "srt" => Some(ExportFormat::Srt),
+            "vtt" | "webvtt" => Some(ExportFormat::Vtt),
+            "txt" | "text" => Some(ExportFormat::Txt),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Srt => "srt",
+            ExportFormat::Vtt => "vtt",
+            ExportFormat::Txt => "txt",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Srt => "application/x-subrip; charset=utf-8",
+            ExportFormat::Vtt => "text/vtt; charset=utf-8",
+            ExportFormat::Txt => "text/plain; charset=utf-8",
+        }
+    }
+}
+
+/// Render `subtitles`; with `bilingual`, each cue's translation (when there is one) goes on the line below
+pub fn render(subtitles: &[Subtitle], format: ExportFormat, bilingual: bool) -> String {
+    let mut out = String::new();
+    if format == ExportFormat::Vtt {
+        out.push_str("WEBVTT\n\n");
+    }
+
+    for (i, subtitle) in subtitles.iter().enumerate() {
+        let translation = subtitle
+            .translation
+            .as_deref()
+            .filter(|t| bilingual && !t.trim().is_empty());
+
+        match format {
+            ExportFormat::Srt | ExportFormat::Vtt => {
+                let (start, end) = match format {
+                    ExportFormat::Srt => (srt_time(subtitle.start), srt_time(subtitle.end)),
+                    _ => (vtt_time(subtitle.start), vtt_time(subtitle.end)),
+                };
+                out.push_str(&format!("{}\n{} --> {}\n{}\n", i + 1, start, end, cue_text(&subtitle.text, format)));
+                if let Some(translation) = translation {
+                    out.push_str(&cue_text(translation, format));
+                    out.push('\n');
+                }
+                out.push('\n');
+            }
+            ExportFormat::Txt => {
+                out.push_str(subtitle.text.trim());
+                out.push('\n');
+                if let Some(translation) = translation {
+                    out.push_str(translation.trim());
+                    out.push('\n');
+                }
+                // Bilingual cues are separated by a blank line, translated or not
+                if bilingual {
+                    out.push('\n');
+                }
+            }
+        }
+    }
+
+    out
+}
+
+/// Blank lines end a cue, and WebVTT treats '<' and '&' as markup
+fn cue_text(text: &str, format: ExportFormat) -> String {
+    let text = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+    match format {
+        ExportFormat::Vtt => text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;"),
+        _ => text,
+    }
+}
+
+fn split_time(seconds: f64) -> (u64, u64, u64, u64) {
+    let millis = (seconds.max(0.0) * 1000.0).round() as u64;
+    (millis / 3_600_000, millis / 60_000 % 60, millis / 1000 % 60, millis % 1000)
+}
+
+/// "HH:MM:SS,mmm"
+fn srt_time(seconds: f64) -> String {
+    let (h, m, s, ms) = split_time(seconds);
+    format!("{:02}:{:02}:{:02},{:03}", h, m, s, ms)
+}
+
+/// "HH:MM:SS.mmm"
+fn vtt_time(seconds: f64) -> String {
+    let (h, m, s, ms) = split_time(seconds);
+    format!("{:02}:{:02}:{:02}.{:03}", h, m, s, ms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::subtitle_parser::{parse_srt, parse_vtt};
+
+    fn subtitles() -> Vec<Subtitle> {
+        vec![
+            Subtitle { index: 0, start: 1.0, end: 3.5, text: "Fish & chips".to_string(), translation: Some("炸鱼薯条".to_string()), words: Vec::new() },
+            Subtitle { index: 1, start: 3661.25, end: 3662.0, text: "See you".to_string(), translation: None, words: Vec::new() },
+        ]
+    }
+
+    #[test]
+    fn test_srt_round_trips() {
+        let srt = render(&subtitles(), ExportFormat::Srt, true);
+        assert!(srt.starts_with("1\n00:00:01,000 --> 00:00:03,500\nFish & chips\n炸鱼薯条\n\n2\n01:01:01,250"));
+
+        let parsed = parse_srt(&render(&subtitles(), ExportFormat::Srt, false)).unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].text, "Fish & chips");
+        assert!((parsed[1].start - 3661.25).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_vtt_escapes_markup() {
+        let vtt = render(&subtitles(), ExportFormat::Vtt, false);
+        assert!(vtt.starts_with("WEBVTT\n\n1\n00:00:01.000 --> 00:00:03.500\nFish &amp; chips\n\n"));
+        assert!(!vtt.contains("炸鱼薯条"));
+        assert_eq!(parse_vtt(&vtt).unwrap()[0].text, "Fish & chips");
+    }
+
+    #[test]
+    fn test_txt_puts_translation_below() {
+        assert_eq!(render(&subtitles(), ExportFormat::Txt, true), "Fish & chips\n炸鱼薯条\n\nSee you\n\n");
+        assert_eq!(render(&subtitles(), ExportFormat::Txt, false), "Fish & chips\nSee you\n");
+        assert_eq!(ExportFormat::from_name("WebVTT"), Some(ExportFormat::Vtt));
+    }
+}
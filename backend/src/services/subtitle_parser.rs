@@ -0,0 +1,536 @@
+//! Subtitle file parsers: WebVTT, SRT, ASS/SSA and YouTube's json3/srv3, all producing `Vec<Subtitle>`
+
+use anyhow::{anyhow, Result};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::models::{Subtitle, SubtitleWord};
+
+/// Subtitle file formats we can read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubtitleFormat {
+    Vtt,
+    Srt,
+    Ass,
+    /// YouTube timedtext JSON (`fmt=json3`)
+    Json3,
+    /// YouTube timedtext XML (`fmt=srv3`)
+    Srv3,
+}
+
+impl SubtitleFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubtitleFormat::Vtt => "vtt",
+            SubtitleFormat::Srt => "srt",
+            SubtitleFormat::Ass => "ass",
+            SubtitleFormat::Json3 => "json3",
+            SubtitleFormat::Srv3 => "srv3",
+        }
+    }
+
+    /// Format from a file name's extension, e.g. "talk.en.srt"
+    pub fn from_file_name(name: &str) -> Option<Self> {
+        let ext = name.rsplit_once('.')?.1.to_lowercase();
+        match ext.as_str() {
+            "vtt" => Some(SubtitleFormat::Vtt),
+            "srt" => Some(SubtitleFormat::Srt),
+            "ass" | "ssa" => Some(SubtitleFormat::Ass),
+            "json3" => Some(SubtitleFormat::Json3),
+            "srv3" => Some(SubtitleFormat::Srv3),
+            _ => None,
+        }
+    }
+
+    /// Guess the format from the file contents
+    pub fn detect(content: &str) -> Option<Self> {
+        let start = content.trim_start_matches('\u{feff}').trim_start();
+        if start.starts_with("WEBVTT") {
+            Some(SubtitleFormat::Vtt)
+        } else if start.starts_with("[Script Info]") || content.contains("\nDialogue:") {
+            Some(SubtitleFormat::Ass)
+        } else if start.starts_with('{') && content.contains("\"events\"") {
+            Some(SubtitleFormat::Json3)
+        } else if content.contains("<timedtext") {
+            Some(SubtitleFormat::Srv3)
+        } else if content.contains("-->") {
+            Some(SubtitleFormat::Srt)
+        } else {
+            None
+        }
+    }
+}
+
+/// Parse a subtitle file in `format`
+pub fn parse(content: &str, format: SubtitleFormat) -> Result<Vec<Subtitle>> {
+    match format {
+        SubtitleFormat::Vtt => parse_vtt(content),
+        SubtitleFormat::Srt => parse_srt(content),
+        SubtitleFormat::Ass => parse_ass(content),
+        SubtitleFormat::Json3 => parse_json3(content),
+        SubtitleFormat::Srv3 => parse_srv3(content),
+    }
+}
+
+/// Parse WebVTT (the format yt-dlp and Apify return)
+/// Cue settings after the timings ("align:start position:0%") are allowed, NOTE, STYLE and
+/// REGION blocks are skipped, and cue text loses its markup (<c>, <b>, voice spans)
+/// YouTube auto captions time each word with inline `<00:00:01.240>` tags; those become the
+/// cue's `words`. Their cues also repeat the previous line above the one being spoken, so in a
+/// cue with timed lines only those lines are kept
+pub fn parse_vtt(content: &str) -> Result<Vec<Subtitle>> {
+    let mut subtitles: Vec<Subtitle> = Vec::new();
+    let lines: Vec<&str> = content.lines().collect();
+
+    let timestamp_re = Regex::new(r"((?:\d+:)?\d{2}:\d{2}\.\d{3})\s*-->\s*((?:\d+:)?\d{2}:\d{2}\.\d{3})")?;
+    let word_time_re = Regex::new(r"<((?:\d+:)?\d{2}:\d{2}\.\d{3})>")?;
+    let tag_re = Regex::new(r"<[^>]+>")?;
+
+    let mut i = 0;
+    let mut index = 0;
+
+    while i < lines.len() {
+        let line = lines[i].trim();
+
+        // Comment and style blocks run until the next blank line
+        if ["NOTE", "STYLE", "REGION"].iter().any(|block| line == *block || line.starts_with(&format!("{} ", block))) {
+            while i < lines.len() && !lines[i].trim().is_empty() {
+                i += 1;
+            }
+            continue;
+        }
+
+        if let Some(caps) = timestamp_re.captures(line) {
+            let start = parse_timestamp(&caps[1])?;
+            let end = parse_timestamp(&caps[2])?;
+
+            let mut text_parts = Vec::new();
+            let mut timed_parts = Vec::new();
+            let mut words = Vec::new();
+            i += 1;
+            while i < lines.len() {
+                // Only an empty line ends the cue; yt-dlp puts whitespace-only lines inside cues
+                let text_line = lines[i].trim();
+                if lines[i].is_empty() || timestamp_re.is_match(text_line) {
+                    break;
+                }
+                let clean = |text: &str| decode_entities(&tag_re.replace_all(text, "")).trim().to_string();
+                let clean_text = clean(text_line);
+                if !clean_text.is_empty() {
+                    if word_time_re.is_match(text_line) {
+                        // Text before the first tag starts with the cue, each tag starts the text after it
+                        let mut segments = vec![(start, clean(word_time_re.split(text_line).next().unwrap_or("")))];
+                        for (time, text) in word_time_re.captures_iter(text_line).zip(word_time_re.split(text_line).skip(1)) {
+                            segments.push((parse_timestamp(&time[1])?, clean(text)));
+                        }
+                        words.extend(timed_words(segments, end));
+                        timed_parts.push(clean_text.clone());
+                    }
+                    text_parts.push(clean_text);
+                }
+                i += 1;
+            }
+
+            if !timed_parts.is_empty() {
+                text_parts = timed_parts;
+            }
+            let text = text_parts.join(" ").trim().to_string();
+
+            if !text.is_empty() {
+                if let Some(last) = subtitles.last_mut() {
+                    if last.text == text {
+                        last.end = end;
+                        continue;
+                    }
+                }
+
+                subtitles.push(Subtitle {
+                    index,
+                    start,
+                    end,
+                    text,
+                    translation: None,
+                    words,
+                });
+                index += 1;
+            }
+        } else {
+            i += 1;
+        }
+    }
+
+    Ok(subtitles)
+}
+
+/// "HH:MM:SS.mmm" or "MM:SS.mmm" to seconds; SRT's comma decimal separator is accepted
+fn parse_timestamp(ts: &str) -> Result<f64> {
+    let ts = ts.trim().replace(',', ".");
+    let parts: Vec<&str> = ts.split(':').collect();
+    let (hours, minutes, seconds) = match parts.as_slice() {
+        [h, m, s] => (h.parse::<f64>()?, m.parse::<f64>()?, s.parse::<f64>()?),
+        [m, s] => (0.0, m.parse::<f64>()?, s.parse::<f64>()?),
+        _ => return Err(anyhow!("Invalid timestamp format: {}", ts)),
+    };
+
+    Ok(hours * 3600.0 + minutes * 60.0 + seconds)
+}
+
+static ENTITY: Lazy<Regex> = Lazy::new(|| Regex::new(r"&(#x[0-9a-fA-F]+|#\d+|[a-zA-Z]+);").unwrap());
+
+/// Decode the HTML entities subtitle files use
+fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+    ENTITY
+        .replace_all(text, |caps: &regex::Captures| {
+            let entity = &caps[1];
+            let decoded = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some(' '),
+                _ => entity
+                    .strip_prefix("#x")
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                    .and_then(char::from_u32),
+            };
+            decoded.map(String::from).unwrap_or_else(|| caps[0].to_string())
+        })
+        .into_owned()
+}
+
+/// Parse SRT: numbered blocks with "00:00:01,000 --> 00:00:04,000" timings
+pub fn parse_srt(content: &str) -> Result<Vec<Subtitle>> {
+    let timestamp_re = Regex::new(r"(\d{1,2}:\d{2}:\d{2}[,.]\d{1,3})\s*-->\s*(\d{1,2}:\d{2}:\d{2}[,.]\d{1,3})")?;
+    let tag_re = Regex::new(r"<[^>]+>|\{[^}]*\}")?;
+
+    let mut cues = Vec::new();
+    let mut lines = content.lines().map(|line| line.trim_start_matches('\u{feff}').trim()).peekable();
+
+    while let Some(line) = lines.next() {
+        let Some(caps) = timestamp_re.captures(line) else {
+            continue;
+        };
+        let start = parse_timestamp(&caps[1])?;
+        let end = parse_timestamp(&caps[2])?;
+
+        let mut text_parts = Vec::new();
+        while let Some(text_line) = lines.next_if(|l| !l.is_empty()) {
+            let clean_text = decode_entities(&tag_re.replace_all(text_line, "")).trim().to_string();
+            if !clean_text.is_empty() {
+                text_parts.push(clean_text);
+            }
+        }
+
+        cues.push(cue(start, end, text_parts.join(" ")));
+    }
+
+    Ok(into_subtitles(cues))
+}
+
+/// Parse ASS/SSA: `Dialogue:` lines of the [Events] section, fields named by its `Format:` line
+pub fn parse_ass(content: &str) -> Result<Vec<Subtitle>> {
+    let override_re = Regex::new(r"\{[^}]*\}")?;
+
+    // Default field order of ASS v4+ files
+    let mut fields: Vec<String> = ["layer", "start", "end", "style", "name", "marginl", "marginr", "marginv", "effect", "text"]
+        .iter()
+        .map(|f| f.to_string())
+        .collect();
+    let mut in_events = false;
+    let mut cues = Vec::new();
+
+    for line in content.lines() {
+        let line = line.trim_start_matches('\u{feff}').trim();
+        if line.starts_with('[') {
+            in_events = line.eq_ignore_ascii_case("[events]");
+            continue;
+        }
+        if !in_events {
+            continue;
+        }
+
+        if let Some(format) = line.strip_prefix("Format:") {
+            fields = format.split(',').map(|f| f.trim().to_lowercase()).collect();
+        } else if let Some(dialogue) = line.strip_prefix("Dialogue:") {
+            // Text is the last field and may itself contain commas
+            let values: Vec<&str> = dialogue.trim_start().splitn(fields.len(), ',').collect();
+            let field = |name: &str| fields.iter().position(|f| f == name).and_then(|i| values.get(i).copied());
+
+            let (Some(start), Some(end), Some(text)) = (field("start"), field("end"), field("text")) else {
+                continue;
+            };
+            let text = override_re
+                .replace_all(text, "")
+                .replace("\\N", " ")
+                .replace("\\n", " ")
+                .replace("\\h", " ");
+            let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+
+            cues.push(cue(parse_ass_timestamp(start)?, parse_ass_timestamp(end)?, text));
+        }
+    }
+
+    Ok(into_subtitles(cues))
+}
+
+/// ASS times are "H:MM:SS.cc"
+fn parse_ass_timestamp(ts: &str) -> Result<f64> {
+    parse_timestamp(ts)
+}
+
+#[derive(Deserialize)]
+struct Json3 {
+    #[serde(default)]
+    events: Vec<Json3Event>,
+}
+
+#[derive(Deserialize)]
+struct Json3Event {
+    #[serde(rename = "tStartMs", default)]
+    start_ms: f64,
+    #[serde(rename = "dDurationMs", default)]
+    duration_ms: f64,
+    /// Window and style events have no segments
+    #[serde(default)]
+    segs: Vec<Json3Segment>,
+}
+
+#[derive(Deserialize)]
+struct Json3Segment {
+    #[serde(default)]
+    utf8: String,
+    /// Start relative to the event; auto captions set it on every word but the first
+    #[serde(rename = "tOffsetMs")]
+    offset_ms: Option<f64>,
+}
+
+/// Parse YouTube json3: one event per caption, its text split into segments (words on auto captions)
+pub fn parse_json3(content: &str) -> Result<Vec<Subtitle>> {
+    let data: Json3 = serde_json::from_str(content)?;
+
+    let cues = data
+        .events
+        .into_iter()
+        .filter(|event| !event.segs.is_empty())
+        .map(|event| {
+            let start = event.start_ms / 1000.0;
+            let end = (event.start_ms + event.duration_ms) / 1000.0;
+            let text: String = event.segs.iter().map(|seg| seg.utf8.as_str()).collect();
+            let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+
+            let mut subtitle = cue(start, end, text);
+            if event.segs.iter().any(|seg| seg.offset_ms.is_some()) {
+                let segments = event
+                    .segs
+                    .into_iter()
+                    .map(|seg| ((event.start_ms + seg.offset_ms.unwrap_or(0.0)) / 1000.0, seg.utf8))
+                    .collect();
+                subtitle.words = timed_words(segments, end);
+            }
+            subtitle
+        })
+        .collect();
+
+    Ok(into_subtitles(cues))
+}
+
+/// Parse YouTube srv3: `<p t="start ms" d="duration ms">` paragraphs, optionally split into `<s>` segments
+pub fn parse_srv3(content: &str) -> Result<Vec<Subtitle>> {
+    let paragraph_re = Regex::new(r"(?s)<p\b([^>]*)>(.*?)</p>")?;
+    let segment_re = Regex::new(r"(?s)<s\b([^>]*)>(.*?)</s>")?;
+    let attr_re = Regex::new(r#"\b([td])="(\d+)""#)?;
+    let tag_re = Regex::new(r"<[^>]+>")?;
+
+    let mut cues = Vec::new();
+    for caps in paragraph_re.captures_iter(content) {
+        let mut start_ms = None;
+        let mut duration_ms = 0.0;
+        for attr in attr_re.captures_iter(&caps[1]) {
+            let value: f64 = attr[2].parse()?;
+            match &attr[1] {
+                "t" => start_ms = Some(value),
+                _ => duration_ms = value,
+            }
+        }
+        let Some(start_ms) = start_ms else {
+            continue;
+        };
+
+        let text = decode_entities(&tag_re.replace_all(&caps[2], ""));
+        let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        let end = (start_ms + duration_ms) / 1000.0;
+        let mut subtitle = cue(start_ms / 1000.0, end, text);
+
+        // `<s t="offset ms">` segments time the words of auto captions
+        let segments: Vec<(Option<f64>, String)> = segment_re
+            .captures_iter(&caps[2])
+            .map(|seg| {
+                let offset = attr_re
+                    .captures_iter(&seg[1])
+                    .find(|attr| &attr[1] == "t")
+                    .and_then(|attr| attr[2].parse::<f64>().ok());
+                (offset, decode_entities(&tag_re.replace_all(&seg[2], "")))
+            })
+            .collect();
+        if segments.iter().any(|(offset, _)| offset.is_some()) {
+            let segments = segments
+                .into_iter()
+                .map(|(offset, text)| ((start_ms + offset.unwrap_or(0.0)) / 1000.0, text))
+                .collect();
+            subtitle.words = timed_words(segments, end);
+        }
+        cues.push(subtitle);
+    }
+
+    Ok(into_subtitles(cues))
+}
+
+/// Words of `segments`, each given as (start, text); a segment lasts until the next one starts,
+/// the last until `end`, and a segment of several words shares its time evenly between them
+fn timed_words(segments: Vec<(f64, String)>, end: f64) -> Vec<SubtitleWord> {
+    let segments: Vec<(f64, String)> = segments.into_iter().filter(|(_, text)| !text.trim().is_empty()).collect();
+    let mut words = Vec::new();
+
+    for (i, (start, text)) in segments.iter().enumerate() {
+        let segment_end = segments.get(i + 1).map(|(next, _)| *next).unwrap_or(end).max(*start);
+        let tokens: Vec<&str> = text.split_whitespace().collect();
+        let step = (segment_end - start) / tokens.len() as f64;
+        for (j, token) in tokens.iter().enumerate() {
+            words.push(SubtitleWord {
+                text: token.to_string(),
+                start: start + step * j as f64,
+                end: start + step * (j + 1) as f64,
+            });
+        }
+    }
+
+    words
+}
+
+fn cue(start: f64, end: f64, text: String) -> Subtitle {
+    Subtitle {
+        index: 0,
+        start,
+        end,
+        text,
+        translation: None,
+        words: Vec::new(),
+    }
+}
+
+/// Order cues by start time, drop empty ones and number them
+fn into_subtitles(mut cues: Vec<Subtitle>) -> Vec<Subtitle> {
+    cues.retain(|cue| !cue.text.is_empty());
+    cues.sort_by(|a, b| a.start.total_cmp(&b.start));
+    for (index, cue) in cues.iter_mut().enumerate() {
+        cue.index = index;
+    }
+    cues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SRT: &str = include_str!("../../tests/fixtures/subtitles/sample.srt");
+    const VTT: &str = include_str!("../../tests/fixtures/subtitles/sample.vtt");
+    const AUTO_VTT: &str = include_str!("../../tests/fixtures/subtitles/auto.vtt");
+    const ASS: &str = include_str!("../../tests/fixtures/subtitles/sample.ass");
+    const JSON3: &str = include_str!("../../tests/fixtures/subtitles/sample.json3");
+    const SRV3: &str = include_str!("../../tests/fixtures/subtitles/sample.srv3");
+
+    fn texts(subtitles: &[Subtitle]) -> Vec<&str> {
+        subtitles.iter().map(|s| s.text.as_str()).collect()
+    }
+
+    fn word_starts(subtitle: &Subtitle) -> Vec<(&str, f64)> {
+        subtitle.words.iter().map(|w| (w.text.as_str(), (w.start * 1000.0).round() / 1000.0)).collect()
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        assert!((parse_timestamp("00:01:30.500").unwrap() - 90.5).abs() < 0.001);
+        assert!((parse_timestamp("01:30,250").unwrap() - 90.25).abs() < 0.001);
+        assert!(parse_timestamp("90").is_err());
+    }
+
+    #[test]
+    fn test_parse_srt() {
+        let subs = parse_srt(SRT).unwrap();
+        assert_eq!(texts(&subs), vec!["Welcome to the talk.", "Today we cover parsers & formats.", "Thanks!"]);
+        assert!((subs[1].start - 4.2).abs() < 0.001);
+        assert!((subs[1].end - 7.9).abs() < 0.001);
+        assert_eq!(subs[2].index, 2);
+    }
+
+    #[test]
+    fn test_parse_vtt() {
+        let subs = parse_vtt(VTT).unwrap();
+        assert_eq!(texts(&subs), vec!["Hello world", "This cue has settings", "Short timestamps work"]);
+        // Repeated rolling captions extend the previous cue
+        assert!((subs[0].end - 4.0).abs() < 0.001);
+        assert!((subs[2].start - 65.0).abs() < 0.001);
+        assert!(subs.iter().all(|s| s.words.is_empty()));
+    }
+
+    #[test]
+    fn test_parse_vtt_word_timings() {
+        let subs = parse_vtt(AUTO_VTT).unwrap();
+        // The repeated line above the spoken one is dropped, and the short transition cue merges back
+        assert_eq!(texts(&subs), vec!["so what is", "the borrow checker"]);
+        assert!((subs[0].end - 2.01).abs() < 0.001);
+
+        assert_eq!(word_starts(&subs[0]), vec![("so", 0.0), ("what", 0.4), ("is", 0.8)]);
+        assert_eq!(word_starts(&subs[1]), vec![("the", 2.01), ("borrow", 2.3), ("checker", 3.1)]);
+        assert!((subs[1].words[1].end - 3.1).abs() < 0.001);
+        assert!((subs[1].words[2].end - 4.5).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_parse_ass() {
+        let subs = parse_ass(ASS).unwrap();
+        assert_eq!(texts(&subs), vec!["First line", "Hi, all of you", "Custom field order"]);
+        assert!((subs[1].start - 2.5).abs() < 0.001);
+        assert!((subs[2].end - 9.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_parse_json3() {
+        let subs = parse_json3(JSON3).unwrap();
+        assert_eq!(texts(&subs), vec!["we are going to learn", "about subtitles"]);
+        assert!((subs[0].start - 1.2).abs() < 0.001);
+        assert!((subs[1].end - 6.4).abs() < 0.001);
+
+        assert_eq!(word_starts(&subs[0])[..2], [("we", 1.2), ("are", 1.44)]);
+        assert!((subs[0].words[4].end - 4.0).abs() < 0.001);
+        assert_eq!(word_starts(&subs[1]), vec![("about", 4.0), ("subtitles", 4.6)]);
+    }
+
+    #[test]
+    fn test_parse_srv3() {
+        let subs = parse_srv3(SRV3).unwrap();
+        assert_eq!(texts(&subs), vec!["we are going to learn", "Tom & Jerry's \"show\""]);
+        assert!((subs[1].start - 3.5).abs() < 0.001);
+        assert!((subs[1].end - 5.0).abs() < 0.001);
+
+        assert_eq!(word_starts(&subs[0]).last(), Some(&("learn", 2.2)));
+        assert!(subs[1].words.is_empty());
+    }
+
+    #[test]
+    fn test_detect_format() {
+        assert_eq!(SubtitleFormat::detect(VTT), Some(SubtitleFormat::Vtt));
+        assert_eq!(SubtitleFormat::detect(SRT), Some(SubtitleFormat::Srt));
+        assert_eq!(SubtitleFormat::detect(ASS), Some(SubtitleFormat::Ass));
+        assert_eq!(SubtitleFormat::detect(JSON3), Some(SubtitleFormat::Json3));
+        assert_eq!(SubtitleFormat::detect(SRV3), Some(SubtitleFormat::Srv3));
+        assert_eq!(SubtitleFormat::from_file_name("talk.en.SSA"), Some(SubtitleFormat::Ass));
+        assert_eq!(SubtitleFormat::detect("just text"), None);
+    }
+}
@@ -0,0 +1,552 @@
+//! Video info and subtitles from an ordered list of transcript sources
+//!
+//! Each backend (yt-dlp, Apify, Supadata) implements [`TranscriptSource`]. Requests go through
+//! the sources in `TRANSCRIPT_SOURCES` order; a source that keeps failing is skipped for a
+//! while by its circuit breaker, paid sources are charged to the user's daily quota, and every
+//! request is logged in `transcript_fetches` with the source that served it.
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use futures_util::future::BoxFuture;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::env;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::db::{self, DbPool, ExternalSource, TranscriptFetch};
+use crate::models::{Subtitle, VideoInfo};
+use crate::services::usage_ledger::UsageLedger;
+use crate::services::youtube::{ApifySource, SupadataSource, YtDlpSource};
+
+/// Source order when `TRANSCRIPT_SOURCES` is not set
+const DEFAULT_SOURCES: &str = "yt-dlp,apify,supadata";
+/// Consecutive failures that open a source's circuit
+const BREAKER_FAILURES_DEFAULT: u32 = 5;
+/// How long an open circuit skips the source before letting a request try it again
+const BREAKER_COOLDOWN_SECS_DEFAULT: u64 = 300;
+
+/// A backend that can look up video info and subtitles
+#[async_trait]
+pub trait TranscriptSource: Send + Sync {
+    /// Name used in config, logs and the `source` of cached subtitles
+    fn name(&self) -> &'static str;
+
+    /// Paid sources are charged to the user's daily quota
+    fn metered(&self) -> Option<ExternalSource> {
+        None
+    }
+
+    /// Give up on the source after this long and move on to the next one
+    fn timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    async fn video_info(&self, video_id: &str) -> Result<VideoInfo>;
+
+    async fn subtitles(&self, video_id: &str, lang: &str) -> Result<Vec<Subtitle>>;
+}
+
+/// The source works but has nothing for this video or language; does not count against its health
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct NotAvailable(pub String);
+
+fn is_not_available(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<NotAvailable>().is_some()
+}
+
+/// Skips a source after `failure_threshold` failures in a row, for `cooldown`
+/// Once the cooldown is over requests try the source again; one success closes the circuit
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    cooldown: Duration,
+    state: Mutex<BreakerState>,
+}
+
+#[derive(Default)]
+struct BreakerState {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            cooldown,
+            state: Mutex::new(BreakerState::default()),
+        }
+    }
+
+    /// Whether the source may be tried now
+    pub fn allows(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        !matches!(state.open_until, Some(until) if Instant::now() < until)
+    }
+
+    pub fn record_success(&self) {
+        *self.state.lock().unwrap() = BreakerState::default();
+    }
+
+    pub fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures += 1;
+        if state.consecutive_failures >= self.failure_threshold {
+            state.open_until = Some(Instant::now() + self.cooldown);
+        }
+    }
+
+    fn consecutive_failures(&self) -> u32 {
+        self.state.lock().unwrap().consecutive_failures
+    }
+}
+
+/// Health of one configured source, for the admin status endpoint
+#[derive(Debug, Clone, Serialize)]
+pub struct SourceHealth {
+    pub source: String,
+    pub available: bool,
+    pub consecutive_failures: u32,
+}
+
+/// Build a source from its config name
+pub fn source_by_name(name: &str) -> Option<Arc<dyn TranscriptSource>> {
+    match name.trim().to_lowercase().as_str() {
+        "yt-dlp" | "ytdlp" => Some(Arc::new(YtDlpSource)),
+        "apify" => Some(Arc::new(ApifySource)),
+        "supadata" => Some(Arc::new(SupadataSource)),
+        _ => None,
+    }
+}
+
+struct Slot {
+    source: Arc<dyn TranscriptSource>,
+    breaker: CircuitBreaker,
+}
+
+/// Who a fetch is for: paid sources are charged to this user
+pub struct FetchContext<'a> {
+    pub pool: &'a DbPool,
+    /// Where paid source calls are counted
+    pub ledger: &'a dyn UsageLedger,
+    pub user_id: &'a str,
+    /// Users who have invited friends get more Apify calls per day
+    pub has_invited: bool,
+}
+
+/// The sources in priority order, each with its own circuit breaker
+pub struct TranscriptSources {
+    slots: Vec<Slot>,
+}
+
+impl TranscriptSources {
+    pub fn new(sources: Vec<Arc<dyn TranscriptSource>>, failure_threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            slots: sources
+                .into_iter()
+                .map(|source| Slot {
+                    source,
+                    breaker: CircuitBreaker::new(failure_threshold, cooldown),
+                })
+                .collect(),
+        }
+    }
+
+    /// Build the list from the environment:
+    /// - `TRANSCRIPT_SOURCES`: comma-separated source names in priority order (default "yt-dlp,apify,supadata")
+    /// - `TRANSCRIPT_BREAKER_FAILURES`: failures in a row before a source is skipped (default 5)
+    /// - `TRANSCRIPT_BREAKER_COOLDOWN_SECS`: how long it is skipped (default 300)
+    pub fn from_env() -> Self {
+        let configured = env::var("TRANSCRIPT_SOURCES").unwrap_or_default();
+        let mut sources: Vec<Arc<dyn TranscriptSource>> = parse_source_list(&configured);
+        if sources.is_empty() {
+            sources = parse_source_list(DEFAULT_SOURCES);
+        }
+
+        let failure_threshold = env::var("TRANSCRIPT_BREAKER_FAILURES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(BREAKER_FAILURES_DEFAULT);
+        let cooldown = env::var("TRANSCRIPT_BREAKER_COOLDOWN_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(Duration::from_secs(BREAKER_COOLDOWN_SECS_DEFAULT));
+
+        Self::new(sources, failure_threshold, cooldown)
+    }
+
+    pub fn health(&self) -> Vec<SourceHealth> {
+        self.slots
+            .iter()
+            .map(|slot| SourceHealth {
+                source: slot.source.name().to_string(),
+                available: slot.breaker.allows(),
+                consecutive_failures: slot.breaker.consecutive_failures(),
+            })
+            .collect()
+    }
+
+    pub async fn video_info(&self, ctx: &FetchContext<'_>, video_id: &str) -> Result<VideoInfo> {
+        let started = Instant::now();
+        let result = self
+            .call(ctx, "video info", |source| Box::pin(source.video_info(video_id)))
+            .await;
+
+        record(ctx, video_id, "info", None, &result, started).await;
+        result
+            .map(|(info, _)| info)
+            .map_err(|failures| anyhow!("Failed to fetch video info from all sources: {}", failures))
+    }
+
+    /// Returns the subtitles with the name of the source that served them
+    pub async fn subtitles(&self, ctx: &FetchContext<'_>, video_id: &str, lang: &str) -> Result<(Vec<Subtitle>, &'static str)> {
+        let started = Instant::now();
+        let result = self
+            .call(ctx, "subtitles", |source| Box::pin(source.subtitles(video_id, lang)))
+            .await;
+
+        record(ctx, video_id, "subtitles", Some(lang), &result, started).await;
+        result.map_err(|failures| anyhow!("Failed to fetch subtitles from all sources: {}", failures))
+    }
+
+    /// Run `op` against each source in order until one succeeds
+    /// On failure, returns what went wrong with each source
+    async fn call<'a, T>(
+        &'a self,
+        ctx: &FetchContext<'_>,
+        what: &str,
+        op: impl Fn(&'a dyn TranscriptSource) -> BoxFuture<'a, Result<T>>,
+    ) -> std::result::Result<(T, &'static str), String> {
+        let mut failures = Vec::new();
+
+        for slot in &self.slots {
+            let name = slot.source.name();
+            if !slot.breaker.allows() {
+                tracing::debug!("Skipping {} for {}: circuit open", name, what);
+                failures.push(format!("{}: temporarily disabled", name));
+                continue;
+            }
+
+            if let Some(metered) = slot.source.metered() {
+                if let Err(e) = reserve_source_call(ctx, metered).await {
+                    failures.push(format!("{}: {}", name, e));
+                    continue;
+                }
+            }
+
+            let result = match slot.source.timeout() {
+                Some(timeout) => tokio::time::timeout(timeout, op(slot.source.as_ref()))
+                    .await
+                    .unwrap_or_else(|_| Err(anyhow!("timed out after {}s", timeout.as_secs()))),
+                None => op(slot.source.as_ref()).await,
+            };
+
+            match result {
+                Ok(value) => {
+                    slot.breaker.record_success();
+                    tracing::info!("Got {} from {}", what, name);
+                    return Ok((value, name));
+                }
+                Err(e) => {
+                    if is_not_available(&e) {
+                        slot.breaker.record_success();
+                    } else {
+                        slot.breaker.record_failure();
+                    }
+                    if let Some(metered) = slot.source.metered() {
+                        refund_source_call(ctx, metered).await;
+                    }
+                    tracing::warn!("{} failed for {}: {}", name, what, e);
+                    failures.push(format!("{}: {}", name, e));
+                }
+            }
+        }
+
+        Err(failures.join("; "))
+    }
+}
+
+fn parse_source_list(value: &str) -> Vec<Arc<dyn TranscriptSource>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .filter_map(|name| {
+            let source = source_by_name(name);
+            if source.is_none() {
+                tracing::warn!("Unknown transcript source '{}' in TRANSCRIPT_SOURCES, ignoring", name);
+            }
+            source
+        })
+        .collect()
+}
+
+/// Take one of today's calls to a paid source, failing when the user's daily limit is reached
+/// or when the quota cannot be checked: the source is only called once the call is counted
+async fn reserve_source_call(ctx: &FetchContext<'_>, source: ExternalSource) -> Result<()> {
+    let limit = source.daily_limit(ctx.has_invited);
+    let used = ctx
+        .ledger
+        .reserve_source_call(ctx.user_id, source, limit)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to check {} quota: {}", source.as_str(), e);
+            anyhow!("quota unavailable, skipped")
+        })?;
+
+    match (used, limit) {
+        (Some(used), Some(limit)) => {
+            tracing::info!("{} call {}/{} today for user {}", source.as_str(), used, limit, ctx.user_id);
+            Ok(())
+        }
+        (Some(used), None) => {
+            tracing::info!("{} call {} today for user {}", source.as_str(), used, ctx.user_id);
+            Ok(())
+        }
+        (None, limit) => Err(anyhow!(
+            "daily limit reached ({0}/{0} used). Please try again tomorrow or invite friends for more quota.",
+            limit.unwrap_or_default()
+        )),
+    }
+}
+
+/// Return a reserved call after the source failed
+async fn refund_source_call(ctx: &FetchContext<'_>, source: ExternalSource) {
+    if let Err(e) = ctx.ledger.refund_source_call(ctx.user_id, source).await {
+        tracing::warn!("Failed to refund {} call: {}", source.as_str(), e);
+    }
+}
+
+/// Log which source served a request (or why none could)
+async fn record<T>(
+    ctx: &FetchContext<'_>,
+    video_id: &str,
+    kind: &str,
+    lang: Option<&str>,
+    result: &std::result::Result<(T, &'static str), String>,
+    started: Instant,
+) {
+    let fetch = TranscriptFetch {
+        user_id: ctx.user_id,
+        video_id,
+        kind,
+        lang,
+        source: result.as_ref().ok().map(|(_, source)| *source),
+        error: result.as_ref().err().map(String::as_str),
+        latency_ms: started.elapsed().as_millis() as i64,
+    };
+    if let Err(e) = db::save_transcript_fetch(ctx.pool, &fetch).await {
+        tracing::warn!("Failed to record transcript fetch for {}: {}", video_id, e);
+    }
+}
+
+/// Sources configured for this process; breaker state lives as long as the process
+static SOURCES: Lazy<TranscriptSources> = Lazy::new(TranscriptSources::from_env);
+
+/// Fetch video info from the first configured source that has it
+pub async fn fetch_video_info(ctx: &FetchContext<'_>, video_id: &str) -> Result<VideoInfo> {
+    SOURCES.video_info(ctx, video_id).await
+}
+
+/// Fetch subtitles from the first configured source that has them
+/// Returns the subtitles together with the name of the source that served them
+pub async fn fetch_subtitles(ctx: &FetchContext<'_>, video_id: &str, lang: &str) -> Result<(Vec<Subtitle>, &'static str)> {
+    SOURCES.subtitles(ctx, video_id, lang).await
+}
+
+/// Circuit state of every configured source
+pub fn source_health() -> Vec<SourceHealth> {
+    SOURCES.health()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::test_support::{unreachable_db, MemoryLedger};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct Fake {
+        name: &'static str,
+        fail: bool,
+        metered: Option<ExternalSource>,
+        calls: AtomicUsize,
+    }
+
+    impl Fake {
+        fn new(name: &'static str, fail: bool) -> Arc<Self> {
+            Arc::new(Self { name, fail, metered: None, calls: AtomicUsize::new(0) })
+        }
+
+        fn apify(fail: bool) -> Arc<Self> {
+            Arc::new(Self { name: "apify", fail, metered: Some(ExternalSource::Apify), calls: AtomicUsize::new(0) })
+        }
+    }
+
+    #[async_trait]
+    impl TranscriptSource for Fake {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn metered(&self) -> Option<ExternalSource> {
+            self.metered
+        }
+
+        async fn video_info(&self, _video_id: &str) -> Result<VideoInfo> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Err(NotAvailable("no info".to_string()).into())
+        }
+
+        async fn subtitles(&self, _video_id: &str, _lang: &str) -> Result<Vec<Subtitle>> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if self.fail {
+                return Err(anyhow!("{} is down", self.name));
+            }
+            Ok(vec![Subtitle { index: 0, start: 0.0, end: 1.0, text: "hi".to_string(), translation: None, words: Vec::new() }])
+        }
+    }
+
+    /// Ledger whose database is down
+    struct BrokenLedger;
+
+    #[async_trait]
+    impl UsageLedger for BrokenLedger {
+        async fn reserve_ai_units(&self, _user_id: &str, _units: i32, _limit: Option<i32>) -> Result<bool> {
+            Err(anyhow!("database unavailable"))
+        }
+
+        async fn refund_ai_units(&self, _user_id: &str, _units: i32) -> Result<()> {
+            Err(anyhow!("database unavailable"))
+        }
+
+        async fn reserve_source_call(&self, _user_id: &str, _source: ExternalSource, _limit: Option<i32>) -> Result<Option<i32>> {
+            Err(anyhow!("database unavailable"))
+        }
+
+        async fn refund_source_call(&self, _user_id: &str, _source: ExternalSource) -> Result<()> {
+            Err(anyhow!("database unavailable"))
+        }
+    }
+
+    fn context<'a>(pool: &'a DbPool, ledger: &'a dyn UsageLedger, has_invited: bool) -> FetchContext<'a> {
+        FetchContext { pool, ledger, user_id: "test-user", has_invited }
+    }
+
+    /// Which source served each of `count` subtitle requests
+    async fn serving_sources(sources: &TranscriptSources, ctx: &FetchContext<'_>, count: usize) -> Vec<&'static str> {
+        let mut served = Vec::new();
+        for _ in 0..count {
+            served.push(sources.subtitles(ctx, "vid", "en").await.unwrap().1);
+        }
+        served
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_and_reports_serving_source() {
+        let (pool, ledger) = (unreachable_db(), MemoryLedger::default());
+        let (down, up) = (Fake::new("down", true), Fake::new("up", false));
+        let sources = TranscriptSources::new(vec![down.clone(), up.clone()], 5, Duration::from_secs(60));
+
+        let (subtitles, source) = sources.subtitles(&context(&pool, &ledger, false), "vid", "en").await.unwrap();
+        assert_eq!(subtitles.len(), 1);
+        assert_eq!(source, "up");
+        assert_eq!(down.calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_open_circuit_skips_failing_source() {
+        let (pool, ledger) = (unreachable_db(), MemoryLedger::default());
+        let (down, up) = (Fake::new("down", true), Fake::new("up", false));
+        let sources = TranscriptSources::new(vec![down.clone(), up.clone()], 2, Duration::from_secs(60));
+
+        for _ in 0..3 {
+            sources.subtitles(&context(&pool, &ledger, false), "vid", "en").await.unwrap();
+        }
+        assert_eq!(down.calls.load(Ordering::SeqCst), 2);
+        assert!(!sources.health()[0].available);
+    }
+
+    #[tokio::test]
+    async fn test_not_available_does_not_open_circuit() {
+        let (pool, ledger) = (unreachable_db(), MemoryLedger::default());
+        let source = Fake::new("no-info", false);
+        let sources = TranscriptSources::new(vec![source.clone()], 1, Duration::from_secs(60));
+
+        // With a threshold of 1, a single counted failure would open the circuit
+        for _ in 0..2 {
+            assert!(sources.video_info(&context(&pool, &ledger, false), "vid").await.is_err());
+        }
+        assert_eq!(source.calls.load(Ordering::SeqCst), 2);
+        assert!(sources.health()[0].available);
+        assert!(!is_not_available(&anyhow!("down")));
+    }
+
+    #[tokio::test]
+    async fn test_metered_source_stops_at_daily_limit() {
+        let (pool, ledger) = (unreachable_db(), MemoryLedger::default());
+        let (apify, up) = (Fake::apify(false), Fake::new("up", false));
+        let sources = TranscriptSources::new(vec![apify.clone(), up], 5, Duration::from_secs(60));
+
+        let served = serving_sources(&sources, &context(&pool, &ledger, false), 3).await;
+        assert_eq!(served, vec!["apify", "apify", "up"]);
+        assert_eq!(apify.calls.load(Ordering::SeqCst), 2);
+        assert_eq!(ledger.source_calls_used("test-user", ExternalSource::Apify), db::APIFY_DAILY_LIMIT_DEFAULT);
+    }
+
+    #[tokio::test]
+    async fn test_invited_users_get_more_metered_calls() {
+        let (pool, ledger) = (unreachable_db(), MemoryLedger::default());
+        let (apify, up) = (Fake::apify(false), Fake::new("up", false));
+        let sources = TranscriptSources::new(vec![apify.clone(), up], 5, Duration::from_secs(60));
+
+        let served = serving_sources(&sources, &context(&pool, &ledger, true), 4).await;
+        assert_eq!(served, vec!["apify", "apify", "apify", "up"]);
+        assert_eq!(ledger.source_calls_used("test-user", ExternalSource::Apify), db::APIFY_DAILY_LIMIT_INVITED);
+    }
+
+    #[tokio::test]
+    async fn test_failed_metered_call_is_refunded() {
+        let (pool, ledger) = (unreachable_db(), MemoryLedger::default());
+        let (apify, up) = (Fake::apify(true), Fake::new("up", false));
+        let sources = TranscriptSources::new(vec![apify.clone(), up], 5, Duration::from_secs(60));
+
+        // More failed calls than the daily limit: each one was given back
+        let served = serving_sources(&sources, &context(&pool, &ledger, false), 3).await;
+        assert_eq!(served, vec!["up", "up", "up"]);
+        assert_eq!(apify.calls.load(Ordering::SeqCst), 3);
+        assert_eq!(ledger.source_calls_used("test-user", ExternalSource::Apify), 0);
+    }
+
+    #[tokio::test]
+    async fn test_exhausted_quota_reports_daily_limit() {
+        let (pool, ledger) = (unreachable_db(), MemoryLedger::default());
+        let apify = Fake::apify(false);
+        let sources = TranscriptSources::new(vec![apify.clone()], 5, Duration::from_secs(60));
+        let ctx = context(&pool, &ledger, false);
+
+        serving_sources(&sources, &ctx, 2).await;
+        let error = sources.subtitles(&ctx, "vid", "en").await.unwrap_err().to_string();
+        assert!(error.contains("apify: daily limit reached (2/2 used)"), "{}", error);
+        assert_eq!(apify.calls.load(Ordering::SeqCst), 2);
+        assert_eq!(ExternalSource::Supadata.daily_limit(false), None);
+    }
+
+    #[tokio::test]
+    async fn test_unavailable_quota_skips_metered_source() {
+        let pool = unreachable_db();
+        let (apify, up) = (Fake::apify(false), Fake::new("up", false));
+        let sources = TranscriptSources::new(vec![apify.clone(), up], 5, Duration::from_secs(60));
+
+        let served = serving_sources(&sources, &context(&pool, &BrokenLedger, false), 1).await;
+        assert_eq!(served, vec!["up"]);
+        assert_eq!(apify.calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_source_list_skips_unknown_names() {
+        let names: Vec<_> = parse_source_list("supadata, nope ,yt-dlp").iter().map(|s| s.name()).collect();
+        assert_eq!(names, vec!["supadata", "yt-dlp"]);
+    }
+}
@@ -0,0 +1,199 @@
+use anyhow::Result;
+use std::collections::HashMap;
+
+use crate::db::{self, DbPool, StoredTranslation};
+use crate::models::{LearnerProfile, Subtitle};
+use crate::services::ai::AiProvider;
+
+/// Translate subtitles for a video into the learner's native language,
+/// reusing stored lines and sending only the missing ones to the AI
+/// Returns one translation per subtitle, in the same order
+pub async fn translate_subtitles_cached(
+    pool: &DbPool,
+    provider: &dyn AiProvider,
+    video_id: &str,
+    profile: &LearnerProfile,
+    subtitles: &[Subtitle],
+) -> Result<Vec<String>> {
+    let lang = profile.native_language.as_str();
+    let stored = load_translations(pool, video_id, lang, Some(provider.name())).await;
+    let translated = translate_missing(provider, &stored, video_id, profile, subtitles).await?;
+
+    if !translated.new.is_empty() {
+        if let Err(e) =
+            db::save_subtitle_translations(pool, video_id, lang, translated.translated_by, &translated.new).await
+        {
+            tracing::warn!("Failed to store translations for {}: {}", video_id, e);
+        }
+    }
+
+    Ok(translated.lines)
+}
+
+struct Translated {
+    /// One translation per subtitle, in order
+    lines: Vec<String>,
+    /// Lines the AI translated, to store
+    new: Vec<StoredTranslation>,
+    translated_by: &'static str,
+}
+
+/// Take each line from `stored` when it is there, and translate the rest with `provider`
+async fn translate_missing(
+    provider: &dyn AiProvider,
+    stored: &HashMap<usize, StoredTranslation>,
+    video_id: &str,
+    profile: &LearnerProfile,
+    subtitles: &[Subtitle],
+) -> Result<Translated> {
+    let mut translations: Vec<Option<String>> = subtitles
+        .iter()
+        .map(|s| lookup(stored, s))
+        .collect();
+
+    let missing: Vec<Subtitle> = subtitles
+        .iter()
+        .zip(&translations)
+        .filter(|(_, t)| t.is_none())
+        .map(|(s, _)| s.clone())
+        .collect();
+
+    let mut translated = Translated { lines: Vec::new(), new: Vec::new(), translated_by: provider.name() };
+    if !missing.is_empty() {
+        tracing::info!(
+            "Translating {} of {} subtitles for {} ({})",
+            missing.len(),
+            subtitles.len(),
+            video_id,
+            profile.native_language
+        );
+
+        let (new_translations, translated_by) = provider.translate_subtitles_named(&missing, profile).await?;
+        translated.translated_by = translated_by;
+
+        // Empty strings are lines whose batch failed to translate; they are retried next time
+        translated.new = missing
+            .iter()
+            .zip(&new_translations)
+            .filter(|(_, t)| !t.trim().is_empty())
+            .map(|(s, t)| StoredTranslation {
+                subtitle_index: s.index as i32,
+                source_text: s.text.clone(),
+                translation: t.clone(),
+            })
+            .collect();
+
+        let mut new_iter = new_translations.into_iter();
+        for slot in translations.iter_mut().filter(|t| t.is_none()) {
+            *slot = Some(new_iter.next().unwrap_or_default());
+        }
+    }
+
+    translated.lines = translations.into_iter().map(Option::unwrap_or_default).collect();
+    Ok(translated)
+}
+
+/// Fill `Subtitle.translation` from stored translations only (no AI calls)
+pub async fn apply_stored_translations(pool: &DbPool, video_id: &str, lang: &str, subtitles: &mut [Subtitle]) {
+    let stored = load_translations(pool, video_id, lang, None).await;
+    for subtitle in subtitles.iter_mut() {
+        subtitle.translation = lookup(&stored, subtitle);
+    }
+}
+
+async fn load_translations(
+    pool: &DbPool,
+    video_id: &str,
+    lang: &str,
+    preferred_provider: Option<&str>,
+) -> HashMap<usize, StoredTranslation> {
+    match db::get_subtitle_translations(pool, video_id, lang, preferred_provider).await {
+        Ok(rows) => rows
+            .into_iter()
+            .map(|t| (t.subtitle_index as usize, t))
+            .collect(),
+        Err(e) => {
+            tracing::warn!("Failed to load stored translations for {}: {}", video_id, e);
+            HashMap::new()
+        }
+    }
+}
+
+/// A stored translation only counts if it was made from the same source text
+/// (subtitles re-fetched from a different source may shift indices)
+fn lookup(stored: &HashMap<usize, StoredTranslation>, subtitle: &Subtitle) -> Option<String> {
+    stored
+        .get(&subtitle.index)
+        .filter(|t| t.source_text == subtitle.text)
+        .map(|t| t.translation.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::ai_mock::MockProvider;
+
+    fn subtitle(index: usize, text: &str) -> Subtitle {
+        Subtitle {
+            index,
+            start: index as f64,
+            end: index as f64 + 1.0,
+            text: text.to_string(),
+            translation: None,
+            words: Vec::new(),
+        }
+    }
+
+    fn stored(index: i32, source_text: &str, translation: &str) -> (usize, StoredTranslation) {
+        let translation = StoredTranslation {
+            subtitle_index: index,
+            source_text: source_text.to_string(),
+            translation: translation.to_string(),
+        };
+        (index as usize, translation)
+    }
+
+    #[tokio::test]
+    async fn test_only_lines_missing_from_the_store_are_translated() {
+        let stored: HashMap<_, _> = [stored(0, "Hello there", "stored hello"), stored(2, "Good night", "stored night")].into();
+        let subtitles = vec![subtitle(0, "Hello there"), subtitle(1, "How are you"), subtitle(2, "Good night")];
+        let profile = LearnerProfile::default();
+
+        let translated = translate_missing(&MockProvider, &stored, "video", &profile, &subtitles).await.unwrap();
+        let fresh = format!("[{}] How are you", profile.native_language);
+        assert_eq!(translated.lines, vec!["stored hello".to_string(), fresh.clone(), "stored night".to_string()]);
+        assert_eq!(translated.translated_by, "mock");
+        assert_eq!(translated.new.len(), 1);
+        assert_eq!((translated.new[0].subtitle_index, translated.new[0].translation.as_str()), (1, fresh.as_str()));
+    }
+
+    #[tokio::test]
+    async fn test_changed_source_text_is_translated_again() {
+        // The subtitles were re-fetched and line 0 now says something else
+        let stored: HashMap<_, _> = [stored(0, "Hello there", "stored hello")].into();
+        let subtitles = vec![subtitle(0, "Welcome back")];
+        let profile = LearnerProfile::default();
+
+        assert_eq!(lookup(&stored, &subtitles[0]), None);
+        let translated = translate_missing(&MockProvider, &stored, "video", &profile, &subtitles).await.unwrap();
+        assert_eq!(translated.lines, vec![format!("[{}] Welcome back", profile.native_language)]);
+        assert_eq!(translated.new[0].source_text, "Welcome back");
+    }
+
+    #[tokio::test]
+    async fn test_fully_stored_subtitles_are_not_sent_to_the_ai() {
+        let stored: HashMap<_, _> = [stored(0, "Hello there", "stored hello")].into();
+        let mut subtitles = vec![subtitle(0, "Hello there")];
+
+        let translated = translate_missing(&MockProvider, &stored, "video", &LearnerProfile::default(), &subtitles)
+            .await
+            .unwrap();
+        assert_eq!(translated.lines, vec!["stored hello".to_string()]);
+        assert!(translated.new.is_empty());
+
+        // Without a reachable database nothing is stored, and nothing is made up
+        let pool = crate::routes::test_support::unreachable_db();
+        apply_stored_translations(&pool, "video", "zh", &mut subtitles).await;
+        assert_eq!(subtitles[0].translation, None);
+    }
+}
@@ -0,0 +1,59 @@
+//! Per-user daily usage counters that quotas are reserved from
+//!
+//! Requests reach the ledger through an `Extension<SharedLedger>` layered onto the API routes:
+//! [`PgUsageLedger`] counts in `daily_usage`, and tests layer their own in-memory ledger.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use std::sync::Arc;
+
+use crate::db::{self, DbPool, ExternalSource};
+
+pub type SharedLedger = Arc<dyn UsageLedger>;
+
+#[async_trait]
+pub trait UsageLedger: Send + Sync {
+    /// Take `units` from today's AI quota unless that would go over `limit` (`None`: no limit)
+    /// Returns whether the units were reserved; concurrent reservations must not overdraw
+    async fn reserve_ai_units(&self, user_id: &str, units: i32, limit: Option<i32>) -> Result<bool>;
+
+    /// Give back units reserved by `reserve_ai_units`
+    async fn refund_ai_units(&self, user_id: &str, units: i32) -> Result<()>;
+
+    /// Count one call to a paid transcript source unless `limit` calls were already made today
+    /// Returns the new count, or `None` when the limit is reached
+    async fn reserve_source_call(&self, user_id: &str, source: ExternalSource, limit: Option<i32>) -> Result<Option<i32>>;
+
+    /// Give back a call counted by `reserve_source_call`
+    async fn refund_source_call(&self, user_id: &str, source: ExternalSource) -> Result<()>;
+}
+
+/// Ledger kept in the `daily_usage` table
+pub struct PgUsageLedger {
+    pool: DbPool,
+}
+
+impl PgUsageLedger {
+    pub fn shared(pool: DbPool) -> SharedLedger {
+        Arc::new(Self { pool })
+    }
+}
+
+#[async_trait]
+impl UsageLedger for PgUsageLedger {
+    async fn reserve_ai_units(&self, user_id: &str, units: i32, limit: Option<i32>) -> Result<bool> {
+        db::try_reserve_ai_units(&self.pool, user_id, units, limit).await
+    }
+
+    async fn refund_ai_units(&self, user_id: &str, units: i32) -> Result<()> {
+        db::refund_ai_units(&self.pool, user_id, units).await
+    }
+
+    async fn reserve_source_call(&self, user_id: &str, source: ExternalSource, limit: Option<i32>) -> Result<Option<i32>> {
+        db::try_consume_source_call(&self.pool, user_id, source, limit).await
+    }
+
+    async fn refund_source_call(&self, user_id: &str, source: ExternalSource) -> Result<()> {
+        db::refund_source_call(&self.pool, user_id, source).await
+    }
+}
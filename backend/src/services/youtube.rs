@@ -1,69 +1,19 @@
+use crate::db::ExternalSource;
 use crate::models::{Subtitle, VideoInfo};
+use crate::services::language;
+use crate::services::subtitle_parser::parse_vtt;
+use crate::services::transcript::{NotAvailable, TranscriptSource};
 use anyhow::{anyhow, Result};
-use chrono::{Datelike, Utc};
-use once_cell::sync::Lazy;
-use regex::Regex;
+use async_trait::async_trait;
 use serde::Deserialize;
-use std::collections::HashMap;
 use std::process::Stdio;
-use std::sync::Mutex;
+use std::time::Duration;
 use tokio::process::Command;
 
-// ============ Apify Rate Limiting ============
-
-/// Default Apify calls per user per day
-const APIFY_DAILY_LIMIT_DEFAULT: u32 = 2;
-
-/// Bonus Apify calls for users who have invited friends
-const APIFY_DAILY_LIMIT_INVITED: u32 = 3;
-
-/// yt-dlp timeout before falling back to Apify (seconds)
+/// yt-dlp timeout before falling back to the next source (seconds)
 /// Keep short for better UX - if yt-dlp doesn't respond quickly, fallback to Apify
 const YTDLP_TIMEOUT_SECS: u64 = 6;
 
-/// (user_id, year, day_of_year) -> usage count
-static APIFY_USAGE: Lazy<Mutex<HashMap<(String, i32, u32), u32>>> =
-    Lazy::new(|| Mutex::new(HashMap::new()));
-
-fn get_apify_usage_key(user_id: &str) -> (String, i32, u32) {
-    let now = Utc::now();
-    (user_id.to_string(), now.year(), now.ordinal())
-}
-
-fn get_apify_limit(has_invited: bool) -> u32 {
-    if has_invited {
-        APIFY_DAILY_LIMIT_INVITED
-    } else {
-        APIFY_DAILY_LIMIT_DEFAULT
-    }
-}
-
-fn check_apify_rate_limit(user_id: &str, has_invited: bool) -> bool {
-    let key = get_apify_usage_key(user_id);
-    let usage = APIFY_USAGE.lock().unwrap();
-    let count = usage.get(&key).copied().unwrap_or(0);
-    count < get_apify_limit(has_invited)
-}
-
-fn increment_apify_usage(user_id: &str) {
-    let key = get_apify_usage_key(user_id);
-    let mut usage = APIFY_USAGE.lock().unwrap();
-
-    // Clean old entries (keep only today's)
-    let (_, year, day) = &key;
-    usage.retain(|(_, y, d), _| y == year && d == day);
-
-    // Increment
-    *usage.entry(key).or_insert(0) += 1;
-}
-
-fn get_apify_remaining(user_id: &str, has_invited: bool) -> u32 {
-    let key = get_apify_usage_key(user_id);
-    let usage = APIFY_USAGE.lock().unwrap();
-    let count = usage.get(&key).copied().unwrap_or(0);
-    get_apify_limit(has_invited).saturating_sub(count)
-}
-
 /// Extract video ID from YouTube URL
 pub fn extract_video_id(url: &str) -> Option<String> {
     // Handle youtu.be/VIDEO_ID
@@ -222,7 +172,7 @@ async fn fetch_subtitles_apify(video_id: &str, lang: &str) -> Result<Vec<Subtitl
     // Find matching language subtitle or first available
     let subtitle = subtitles_list.into_iter()
         .find(|s| s.language.as_deref() == Some(lang))
-        .ok_or_else(|| anyhow!("No {} subtitles found", lang))?;
+        .ok_or_else(|| NotAvailable(format!("No {} subtitles found", lang)))?;
 
     let vtt_content = subtitle.vtt
         .ok_or_else(|| anyhow!("Subtitle VTT content is empty"))?;
@@ -296,7 +246,8 @@ async fn fetch_subtitles_supadata(video_id: &str, lang: &str) -> Result<Vec<Subt
     let client = reqwest::Client::new();
     let url = format!(
         "https://api.supadata.ai/v1/youtube/transcript?videoId={}&lang={}",
-        video_id, lang
+        urlencoding::encode(video_id),
+        urlencoding::encode(lang)
     );
 
     tracing::info!("Fetching subtitles from Supadata for: {} (lang: {})", video_id, lang);
@@ -315,7 +266,7 @@ async fn fetch_subtitles_supadata(video_id: &str, lang: &str) -> Result<Vec<Subt
     }
 
     let data: SupadataTranscriptResponse = response.json().await?;
-    let segments = data.content.ok_or_else(|| anyhow!("No transcript content"))?;
+    let segments = data.content.ok_or_else(|| NotAvailable("No transcript content".to_string()))?;
 
     let subtitles: Vec<Subtitle> = segments
         .into_iter()
@@ -326,6 +277,7 @@ async fn fetch_subtitles_supadata(video_id: &str, lang: &str) -> Result<Vec<Subt
             end: (seg.offset + seg.duration) / 1000.0,
             text: seg.text,
             translation: None,
+            words: Vec::new(),
         })
         .collect();
 
@@ -393,17 +345,22 @@ async fn fetch_video_info_ytdlp(video_id: &str) -> Result<VideoInfo> {
 }
 
 async fn fetch_subtitles_ytdlp(video_id: &str, lang: &str) -> Result<Vec<Subtitle>> {
+    // The code goes into yt-dlp's language pattern and into file names
+    if !language::is_supported(lang) {
+        return Err(anyhow!("Unsupported language: {}", lang));
+    }
     let url = format!("https://www.youtube.com/watch?v={}", video_id);
     let temp_dir = std::env::temp_dir();
     let output_template = temp_dir.join(format!("eng_learner_{}", video_id));
     let output_path = output_template.to_string_lossy();
 
-    let sub_lang = if lang == "zh" { "zh-Hans,zh-Hant,zh" } else { "en" };
+    // Regional variants (e.g. "fr-FR", "pt-BR") are accepted for the requested language
+    let sub_lang = if lang == "zh" { "zh-Hans,zh-Hant,zh".to_string() } else { format!("{0},{0}-.*", lang) };
 
     let mut args = vec![
         "--write-sub",
         "--write-auto-sub",
-        "--sub-lang", sub_lang,
+        "--sub-lang", &sub_lang,
         "--sub-format", "vtt",
         "--skip-download",
         "--js-runtimes", "node",
@@ -430,12 +387,21 @@ async fn fetch_subtitles_ytdlp(video_id: &str, lang: &str) -> Result<Vec<Subtitl
         tracing::warn!("yt-dlp subtitle fetch warning: {}", stderr);
     }
 
-    let lang_code = if lang == "zh" { "zh" } else { "en" };
-    let possible_files = vec![
-        format!("{}.{}.vtt", output_path, lang_code),
-        format!("{}.{}-Hans.vtt", output_path, lang_code),
-        format!("{}.{}-Hant.vtt", output_path, lang_code),
-    ];
+    // Exact language first, then regional variants in name order (zh-Hans before zh-Hant)
+    let file_prefix = format!("eng_learner_{}.{}", video_id, lang);
+    let mut variants = Vec::new();
+    if let Ok(mut entries) = tokio::fs::read_dir(&temp_dir).await {
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with(&format!("{}-", file_prefix)) && name.ends_with(".vtt") {
+                variants.push(entry.path().to_string_lossy().to_string());
+            }
+        }
+    }
+    variants.sort();
+
+    let mut possible_files = vec![format!("{}.{}.vtt", output_path, lang)];
+    possible_files.extend(variants);
 
     for file_path in &possible_files {
         if let Ok(content) = tokio::fs::read_to_string(file_path).await {
@@ -445,221 +411,74 @@ async fn fetch_subtitles_ytdlp(video_id: &str, lang: &str) -> Result<Vec<Subtitl
         }
     }
 
-    Err(anyhow!("No subtitles found for language: {}", lang))
+    Err(NotAvailable(format!("No subtitles found for language: {}", lang)).into())
 }
 
-fn parse_vtt(content: &str) -> Result<Vec<Subtitle>> {
-    let mut subtitles: Vec<Subtitle> = Vec::new();
-    let lines: Vec<&str> = content.lines().collect();
-
-    let timestamp_re = Regex::new(r"(\d{2}:\d{2}:\d{2}\.\d{3})\s*-->\s*(\d{2}:\d{2}:\d{2}\.\d{3})")?;
-    let tag_re = Regex::new(r"<[^>]+>")?;
-
-    let mut i = 0;
-    let mut index = 0;
-
-    while i < lines.len() {
-        let line = lines[i].trim();
-
-        if let Some(caps) = timestamp_re.captures(line) {
-            let start = parse_timestamp(&caps[1])?;
-            let end = parse_timestamp(&caps[2])?;
-
-            let mut text_parts = Vec::new();
-            i += 1;
-            while i < lines.len() {
-                let text_line = lines[i].trim();
-                if text_line.is_empty() || timestamp_re.is_match(text_line) {
-                    break;
-                }
-                let clean_text = tag_re.replace_all(text_line, "").to_string();
-                if !clean_text.is_empty() {
-                    text_parts.push(clean_text);
-                }
-                i += 1;
-            }
+// ============ Transcript Sources ============
 
-            let text = text_parts.join(" ").trim().to_string();
-
-            if !text.is_empty() {
-                if let Some(last) = subtitles.last_mut() {
-                    if last.text == text {
-                        last.end = end;
-                        continue;
-                    }
-                }
-
-                subtitles.push(Subtitle {
-                    index,
-                    start,
-                    end,
-                    text,
-                    translation: None,
-                });
-                index += 1;
-            }
-        } else {
-            i += 1;
-        }
-    }
+/// Local yt-dlp: free, but often blocked or slow on cloud hosts
+pub struct YtDlpSource;
 
-    Ok(subtitles)
-}
+#[async_trait]
+impl TranscriptSource for YtDlpSource {
+    fn name(&self) -> &'static str {
+        "yt-dlp"
+    }
 
-fn parse_timestamp(ts: &str) -> Result<f64> {
-    let parts: Vec<&str> = ts.split(':').collect();
-    if parts.len() != 3 {
-        return Err(anyhow!("Invalid timestamp format: {}", ts));
+    fn timeout(&self) -> Option<Duration> {
+        Some(Duration::from_secs(YTDLP_TIMEOUT_SECS))
     }
 
-    let hours: f64 = parts[0].parse()?;
-    let minutes: f64 = parts[1].parse()?;
-    let seconds: f64 = parts[2].parse()?;
+    async fn video_info(&self, video_id: &str) -> Result<VideoInfo> {
+        fetch_video_info_ytdlp(video_id).await
+    }
 
-    Ok(hours * 3600.0 + minutes * 60.0 + seconds)
+    async fn subtitles(&self, video_id: &str, lang: &str) -> Result<Vec<Subtitle>> {
+        fetch_subtitles_ytdlp(video_id, lang).await
+    }
 }
 
-// ============ Public API ============
-
-/// Fetch video info: try yt-dlp with timeout, fallback to Apify with rate limiting
-/// - user_id: for rate limiting tracking
-/// - has_invited: if true, user gets 3 Apify calls/day instead of 2
-pub async fn fetch_video_info(video_id: &str, user_id: Option<&str>, has_invited: bool) -> Result<VideoInfo> {
-    let user = user_id.unwrap_or("anonymous");
-
-    // Try yt-dlp with timeout
-    let ytdlp_result = tokio::time::timeout(
-        std::time::Duration::from_secs(YTDLP_TIMEOUT_SECS),
-        fetch_video_info_ytdlp(video_id),
-    )
-    .await;
-
-    match ytdlp_result {
-        Ok(Ok(info)) => {
-            tracing::info!("Got video info from yt-dlp for: {}", video_id);
-            return Ok(info);
-        }
-        Ok(Err(e)) => {
-            tracing::warn!("yt-dlp failed for {}: {}", video_id, e);
-        }
-        Err(_) => {
-            tracing::warn!("yt-dlp timeout ({}s) for {}", YTDLP_TIMEOUT_SECS, video_id);
-        }
+/// Apify YouTube scraper (paid, metered per user)
+pub struct ApifySource;
+
+#[async_trait]
+impl TranscriptSource for ApifySource {
+    fn name(&self) -> &'static str {
+        "apify"
     }
 
-    // Check Apify rate limit
-    let limit = get_apify_limit(has_invited);
-    if !check_apify_rate_limit(user, has_invited) {
-        let remaining = get_apify_remaining(user, has_invited);
-        return Err(anyhow!(
-            "Apify daily limit reached ({}/{} used). Please try again tomorrow or invite friends for more quota.",
-            limit - remaining,
-            limit
-        ));
-    }
-
-    // Fallback to Apify
-    tracing::info!("Falling back to Apify for: {} (user: {}, has_invited: {})", video_id, user, has_invited);
-    match fetch_video_info_apify(video_id).await {
-        Ok(info) => {
-            increment_apify_usage(user);
-            let remaining = get_apify_remaining(user, has_invited);
-            tracing::info!(
-                "Got video info from Apify for: {} (user: {}, remaining: {}/{})",
-                video_id,
-                user,
-                remaining,
-                limit
-            );
-            return Ok(info);
-        }
-        Err(e) => {
-            tracing::warn!("Apify failed for {}: {}", video_id, e);
-        }
+    fn metered(&self) -> Option<ExternalSource> {
+        Some(ExternalSource::Apify)
     }
 
-    // Final fallback to Supadata
-    tracing::info!("Falling back to Supadata for: {}", video_id);
-    match fetch_video_info_supadata(video_id).await {
-        Ok(info) => {
-            tracing::info!("Got video info from Supadata for: {}", video_id);
-            Ok(info)
-        }
-        Err(e) => {
-            tracing::error!("All sources failed for {}: {}", video_id, e);
-            Err(anyhow!("Failed to fetch video info from all sources"))
-        }
+    async fn video_info(&self, video_id: &str) -> Result<VideoInfo> {
+        fetch_video_info_apify(video_id).await
+    }
+
+    async fn subtitles(&self, video_id: &str, lang: &str) -> Result<Vec<Subtitle>> {
+        fetch_subtitles_apify(video_id, lang).await
     }
 }
 
-/// Fetch subtitles: try yt-dlp with timeout, fallback to Apify with rate limiting
-/// - user_id: for rate limiting tracking
-/// - has_invited: if true, user gets 3 Apify calls/day instead of 2
-pub async fn fetch_subtitles(video_id: &str, lang: &str, user_id: Option<&str>, has_invited: bool) -> Result<Vec<Subtitle>> {
-    let user = user_id.unwrap_or("anonymous");
-
-    // Try yt-dlp with timeout
-    let ytdlp_result = tokio::time::timeout(
-        std::time::Duration::from_secs(YTDLP_TIMEOUT_SECS),
-        fetch_subtitles_ytdlp(video_id, lang),
-    )
-    .await;
-
-    match ytdlp_result {
-        Ok(Ok(subs)) => {
-            tracing::info!("Got subtitles from yt-dlp for: {} (lang: {})", video_id, lang);
-            return Ok(subs);
-        }
-        Ok(Err(e)) => {
-            tracing::warn!("yt-dlp subtitles failed for {}: {}", video_id, e);
-        }
-        Err(_) => {
-            tracing::warn!("yt-dlp subtitles timeout ({}s) for {}", YTDLP_TIMEOUT_SECS, video_id);
-        }
+/// Supadata transcript API (paid, metered per user)
+pub struct SupadataSource;
+
+#[async_trait]
+impl TranscriptSource for SupadataSource {
+    fn name(&self) -> &'static str {
+        "supadata"
     }
 
-    // Check Apify rate limit
-    let limit = get_apify_limit(has_invited);
-    if !check_apify_rate_limit(user, has_invited) {
-        let remaining = get_apify_remaining(user, has_invited);
-        return Err(anyhow!(
-            "Apify daily limit reached ({}/{} used). Please try again tomorrow or invite friends for more quota.",
-            limit - remaining,
-            limit
-        ));
-    }
-
-    // Fallback to Apify
-    tracing::info!("Falling back to Apify subtitles for: {} (user: {}, has_invited: {})", video_id, user, has_invited);
-    match fetch_subtitles_apify(video_id, lang).await {
-        Ok(subs) => {
-            increment_apify_usage(user);
-            let remaining = get_apify_remaining(user, has_invited);
-            tracing::info!(
-                "Got subtitles from Apify for: {} (user: {}, remaining: {}/{})",
-                video_id,
-                user,
-                remaining,
-                limit
-            );
-            return Ok(subs);
-        }
-        Err(e) => {
-            tracing::warn!("Apify subtitles failed for {}: {}", video_id, e);
-        }
+    fn metered(&self) -> Option<ExternalSource> {
+        Some(ExternalSource::Supadata)
     }
 
-    // Final fallback to Supadata
-    tracing::info!("Falling back to Supadata subtitles for: {} (lang: {})", video_id, lang);
-    match fetch_subtitles_supadata(video_id, lang).await {
-        Ok(subs) => {
-            tracing::info!("Got subtitles from Supadata for: {} (lang: {})", video_id, lang);
-            Ok(subs)
-        }
-        Err(e) => {
-            tracing::error!("All subtitle sources failed for {}: {}", video_id, e);
-            Err(anyhow!("Failed to fetch subtitles from all sources"))
-        }
+    async fn video_info(&self, video_id: &str) -> Result<VideoInfo> {
+        fetch_video_info_supadata(video_id).await
+    }
+
+    async fn subtitles(&self, video_id: &str, lang: &str) -> Result<Vec<Subtitle>> {
+        fetch_subtitles_supadata(video_id, lang).await
     }
 }
 
@@ -667,6 +486,12 @@ pub async fn fetch_subtitles(video_id: &str, lang: &str, user_id: Option<&str>,
 mod tests {
     use super::*;
 
+    #[tokio::test]
+    async fn test_ytdlp_rejects_unknown_language_before_running() {
+        let error = fetch_subtitles_ytdlp("dQw4w9WgXcQ", "en,.*").await.unwrap_err();
+        assert_eq!(error.to_string(), "Unsupported language: en,.*");
+    }
+
     #[test]
     fn test_extract_video_id() {
         assert_eq!(
@@ -679,11 +504,6 @@ mod tests {
         );
     }
 
-    #[test]
-    fn test_parse_timestamp() {
-        assert!((parse_timestamp("00:01:30.500").unwrap() - 90.5).abs() < 0.001);
-    }
-
     #[test]
     fn test_parse_duration_string() {
         // Test HH:MM:SS format
@@ -727,46 +547,4 @@ mod tests {
         assert!(!subs[0].text.is_empty());
         assert!(subs[0].start >= 0.0);
     }
-
-    #[test]
-    fn test_apify_rate_limit() {
-        // Use unique user ID to avoid conflicts with other tests
-        let test_user = "test_normal_user_unique_12345";
-        let has_invited = false;
-
-        // Initially should have full quota (2 for non-invited users)
-        assert_eq!(get_apify_remaining(test_user, has_invited), APIFY_DAILY_LIMIT_DEFAULT);
-        assert!(check_apify_rate_limit(test_user, has_invited));
-
-        // Use up the quota
-        for _ in 0..APIFY_DAILY_LIMIT_DEFAULT {
-            assert!(check_apify_rate_limit(test_user, has_invited));
-            increment_apify_usage(test_user);
-        }
-
-        // Should be at limit now
-        assert_eq!(get_apify_remaining(test_user, has_invited), 0);
-        assert!(!check_apify_rate_limit(test_user, has_invited));
-    }
-
-    #[test]
-    fn test_apify_rate_limit_with_invite() {
-        // Use a unique user ID to avoid conflicts with other tests
-        let test_user = "test_invited_user_unique_67890";
-        let has_invited = true;
-
-        // Invited users should have 3 quota
-        assert_eq!(get_apify_remaining(test_user, has_invited), APIFY_DAILY_LIMIT_INVITED);
-        assert!(check_apify_rate_limit(test_user, has_invited));
-
-        // Use up the quota
-        for _ in 0..APIFY_DAILY_LIMIT_INVITED {
-            assert!(check_apify_rate_limit(test_user, has_invited));
-            increment_apify_usage(test_user);
-        }
-
-        // Should be at limit now
-        assert_eq!(get_apify_remaining(test_user, has_invited), 0);
-        assert!(!check_apify_rate_limit(test_user, has_invited));
-    }
 }
@@ -1,5 +1,5 @@
 import axios from 'axios';
-import type { ApiResponse, VideoInfo, SubtitleResponse, Subtitle, AnalyzeResponse, AskResponse, TranslateResponse, VocabularyResponse, SlidesResponse, ChaptersResponse } from '../types';
+import type { ApiResponse, VideoInfo, SubtitleResponse, SubtitleTrack, Subtitle, AnalyzeResponse, AskResponse, TranslateResponse, VocabularyResponse, SlidesResponse, ChaptersResponse } from '../types';
 import { useAuthStore } from '../store/authStore';
 
 const api = axios.create({
@@ -38,9 +38,10 @@ export async function parseVideo(url: string): Promise<VideoInfo> {
   return response.data.data;
 }
 
-export async function getSubtitles(videoId: string, lang: string = 'en'): Promise<SubtitleResponse> {
+// Omit lang to get subtitles in the user's target language
+export async function getSubtitles(videoId: string, lang?: string, track?: SubtitleTrack): Promise<SubtitleResponse> {
   const response = await api.get<ApiResponse<SubtitleResponse>>(`/video/${videoId}/subtitles`, {
-    params: { lang },
+    params: { lang, track },
     timeout: 90000, // 1.5 minutes for subtitle fetching
   });
   if (!response.data.success || !response.data.data) {
@@ -49,6 +50,25 @@ export async function getSubtitles(videoId: string, lang: string = 'en'): Promis
   return response.data.data;
 }
 
+// Download subtitles as a file; bilingual adds stored translations under each line
+export async function exportSubtitles(
+  videoId: string,
+  format: 'srt' | 'vtt' | 'txt' = 'srt',
+  options: { bilingual?: boolean; lang?: string; track?: SubtitleTrack } = {},
+): Promise<Blob> {
+  const response = await api.get<Blob>(`/video/${videoId}/subtitles/export`, {
+    params: { format, bilingual: options.bilingual || undefined, lang: options.lang, track: options.track },
+    responseType: 'blob',
+    timeout: 90000,
+  });
+  // Errors come back as JSON instead of a file
+  if (response.data.type.startsWith('application/json')) {
+    const body = JSON.parse(await response.data.text()) as ApiResponse<null>;
+    throw new Error(body.error || 'Failed to export subtitles');
+  }
+  return response.data;
+}
+
 export async function analyzeHighlights(subtitles: Subtitle[]): Promise<AnalyzeResponse> {
   const response = await api.post<ApiResponse<AnalyzeResponse>>('/ai/analyze', { subtitles });
   if (!response.data.success || !response.data.data) {
@@ -75,8 +95,68 @@ export async function askAI(context: string, question: string): Promise<AskRespo
   return response.data.data;
 }
 
-export async function translateSubtitles(subtitles: Subtitle[]): Promise<TranslateResponse> {
-  const response = await api.post<ApiResponse<TranslateResponse>>('/ai/translate', { subtitles }, {
+// Streaming variant of askAI: onDelta receives answer text as it is generated
+export async function askAIStream(
+  context: string,
+  question: string,
+  onDelta: (text: string) => void,
+  threadId?: string,
+): Promise<AskResponse> {
+  const token = useAuthStore.getState().getToken();
+  const response = await fetch('/api/ai/ask/stream', {
+    method: 'POST',
+    headers: {
+      'Content-Type': 'application/json',
+      ...(token ? { Authorization: `Bearer ${token}` } : {}),
+    },
+    body: JSON.stringify({ context, question, thread_id: threadId }),
+  });
+
+  // Errors before the answer starts (e.g. rate limit) come back as regular JSON
+  if (!response.headers.get('content-type')?.includes('text/event-stream')) {
+    const data: ApiResponse<AskResponse> = await response.json();
+    if (data.code === 'RATE_LIMIT_EXCEEDED') {
+      throw new RateLimitError(data.error || 'Daily limit reached');
+    }
+    throw new Error(data.error || 'Failed to get AI response');
+  }
+
+  const reader = response.body!.getReader();
+  const decoder = new TextDecoder();
+  let buffer = '';
+
+  for (;;) {
+    const { done, value } = await reader.read();
+    if (done) break;
+    buffer += decoder.decode(value, { stream: true });
+
+    // Events are separated by a blank line
+    let boundary;
+    while ((boundary = buffer.indexOf('\n\n')) !== -1) {
+      const raw = buffer.slice(0, boundary);
+      buffer = buffer.slice(boundary + 2);
+
+      const event = raw.match(/^event: ?(.*)$/m)?.[1];
+      const data = raw
+        .split('\n')
+        .filter((line) => line.startsWith('data:'))
+        .map((line) => line.replace(/^data: ?/, ''))
+        .join('\n');
+      if (!event || !data) continue;
+
+      const payload = JSON.parse(data);
+      if (event === 'delta') onDelta(payload.text);
+      if (event === 'done') return { answer: payload.answer };
+      if (event === 'error') throw new Error(payload.error || 'Failed to get AI response');
+    }
+  }
+
+  throw new Error('AI response ended unexpectedly');
+}
+
+// lang is the language of the subtitles (defaults to the learner's target language)
+export async function translateSubtitles(subtitles: Subtitle[], videoId?: string, track?: SubtitleTrack, lang?: string): Promise<TranslateResponse> {
+  const response = await api.post<ApiResponse<TranslateResponse>>('/ai/translate', { subtitles, video_id: videoId, track, lang }, {
     timeout: 120000, // 2 minutes for translation
   });
   if (!response.data.success || !response.data.data) {
@@ -157,6 +237,7 @@ export interface SaveVocabularyRequest {
   example?: string;
   source_video_id?: string;
   source_sentence?: string;
+  source_timestamp?: number;  // Seconds into the video, e.g. the clicked word's start
 }
 
 export interface SavedVocabulary {
@@ -171,12 +252,16 @@ export interface SavedVocabulary {
   due_date?: string;
   due_at?: string;           // Precise datetime (ISO 8601)
   review_count: number;
-  learning_step: number;     // 0-3: learning phase, 4+: review phase
+  learning_step: number;     // Current (re)learning step
+  phase: CardPhase;
   source_video_id?: string;
   source_sentence?: string;
+  source_timestamp?: number;
   created_at: string;
   last_reviewed_at?: string;
-  memory_strength: number;   // 0.0-1.0, based on forgetting curve
+  stability?: number;        // FSRS: days until recall drops to 90%
+  difficulty?: number;       // FSRS: 1-10
+  retrievability: number;    // 0.0-1.0, probability of recalling the word now
 }
 
 export async function saveVocabulary(data: SaveVocabularyRequest): Promise<{ id: number }> {
@@ -197,6 +282,25 @@ export async function getVocabularyList(dueOnly = false): Promise<{ vocabulary:
   return response.data.data;
 }
 
+// Due cards ordered into a review session
+export interface ReviewQueue {
+  cards: SavedVocabulary[];
+  new_count: number;
+  learning_count: number;
+  review_count: number;
+  buried: number;            // Held back because a word from the same sentence is shown
+}
+
+export async function getReviewQueue(options: { limit?: number; newLimit?: number; burySiblings?: boolean } = {}): Promise<ReviewQueue> {
+  const response = await api.get<ApiResponse<ReviewQueue>>('/vocabulary/queue', {
+    params: { limit: options.limit, new_limit: options.newLimit, bury_siblings: options.burySiblings },
+  });
+  if (!response.data.success || !response.data.data) {
+    throw new Error(response.data.error || 'Failed to get review queue');
+  }
+  return response.data.data;
+}
+
 export async function reviewVocabulary(vocabId: number, quality: number): Promise<void> {
   const response = await api.post<ApiResponse<null>>('/vocabulary/review', {
     vocab_id: vocabId,
@@ -207,6 +311,21 @@ export async function reviewVocabulary(vocabId: number, quality: number): Promis
   }
 }
 
+export interface UndoneReview {
+  vocab_id: number;
+  word: string;
+  quality: number;           // Rating of the undone review, 0-3
+}
+
+// Undo the most recent review; the card gets its previous schedule back
+export async function undoLastReview(): Promise<UndoneReview> {
+  const response = await api.post<ApiResponse<UndoneReview>>('/vocabulary/review/undo');
+  if (!response.data.success || !response.data.data) {
+    throw new Error(response.data.error || 'Failed to undo review');
+  }
+  return response.data.data;
+}
+
 export async function deleteVocabulary(vocabId: number): Promise<void> {
   const response = await api.delete<ApiResponse<null>>(`/vocabulary/delete/${vocabId}`);
   if (!response.data.success) {
@@ -404,7 +523,8 @@ export interface NoteData {
   video_id: string;
   timestamp: number;
   english?: string;
-  chinese?: string;
+  translation?: string;
+  translation_lang?: string;
   note_text?: string;
   images?: string[];
 }
@@ -414,7 +534,8 @@ export interface NoteResponse {
   video_id: string;
   timestamp: number;
   english?: string;
-  chinese?: string;
+  translation?: string;
+  translation_lang?: string;
   note_text?: string;
   images?: string[];
   created_at: string;
@@ -441,6 +562,7 @@ export interface WatchHistoryItem {
   title: string;
   thumbnail: string;
   watched_at: string;
+  latest_thread_id?: string;  // AI chat thread to reopen for this video
 }
 
 export async function getWatchHistory(): Promise<WatchHistoryItem[]> {
@@ -464,6 +586,68 @@ export async function clearWatchHistory(): Promise<void> {
   await api.post('/history/clear');
 }
 
+// AI Chat Thread APIs
+export interface ChatThread {
+  id: string;
+  video_id: string;
+  title: string;
+  message_count: number;
+  created_at: string;
+  updated_at: string;
+}
+
+export interface ChatThreadMessage {
+  id: number;
+  role: 'user' | 'assistant';
+  content: string;
+  created_at: string;
+}
+
+export interface ChatThreadWithMessages {
+  thread: ChatThread;
+  messages: ChatThreadMessage[];
+}
+
+export async function listChatThreads(videoId?: string): Promise<ChatThread[]> {
+  const response = await api.get<ApiResponse<{ threads: ChatThread[] }>>('/chat/threads', {
+    params: { video_id: videoId },
+  });
+  if (!response.data.success || !response.data.data) {
+    throw new Error(response.data.error || 'Failed to list chat threads');
+  }
+  return response.data.data.threads;
+}
+
+export async function createChatThread(videoId: string, title?: string): Promise<ChatThreadWithMessages> {
+  const response = await api.post<ApiResponse<ChatThreadWithMessages>>('/chat/threads', { video_id: videoId, title });
+  if (!response.data.success || !response.data.data) {
+    throw new Error(response.data.error || 'Failed to create chat thread');
+  }
+  return response.data.data;
+}
+
+export async function getChatThread(threadId: string): Promise<ChatThreadWithMessages> {
+  const response = await api.get<ApiResponse<ChatThreadWithMessages>>(`/chat/threads/${threadId}`);
+  if (!response.data.success || !response.data.data) {
+    throw new Error(response.data.error || 'Failed to get chat thread');
+  }
+  return response.data.data;
+}
+
+export async function renameChatThread(threadId: string, title: string): Promise<void> {
+  const response = await api.patch<ApiResponse<null>>(`/chat/threads/${threadId}`, { title });
+  if (!response.data.success) {
+    throw new Error(response.data.error || 'Failed to rename chat thread');
+  }
+}
+
+export async function deleteChatThread(threadId: string): Promise<void> {
+  const response = await api.delete<ApiResponse<null>>(`/chat/threads/${threadId}`);
+  if (!response.data.success) {
+    throw new Error(response.data.error || 'Failed to delete chat thread');
+  }
+}
+
 // Usage / Rate Limiting APIs
 export interface UsageStatus {
   used: number;
@@ -472,7 +656,16 @@ export interface UsageStatus {
   bonus_quota: number;  // Bonus quota from invitations
 }
 
-export interface AiChatUsageStatus {
+// Daily AI quota in weighted units; limit and remaining are -1 when unlimited
+export interface AiQuotaStatus {
+  used: number;
+  limit: number;
+  remaining: number;
+}
+
+// Today's calls to a paid transcript source (apify, supadata); limit and remaining are -1 when unlimited
+export interface SourceUsageStatus {
+  source: string;
   used: number;
   limit: number;
   remaining: number;
@@ -480,7 +673,8 @@ export interface AiChatUsageStatus {
 
 export interface DailyUsageStatus {
   video_parse: UsageStatus;
-  ai_chat: AiChatUsageStatus;
+  ai: AiQuotaStatus;
+  sources: SourceUsageStatus[];
 }
 
 export async function getUsageStatus(): Promise<DailyUsageStatus> {
@@ -561,4 +755,127 @@ export async function uploadImage(file: File): Promise<UploadResponse> {
   return response.data;
 }
 
+// Uploaded videos: your own recording (optional) plus an SRT/VTT/ASS subtitle file.
+// Their video_id works everywhere a YouTube ID does (subtitles, AI, notes, vocabulary).
+export interface UploadedVideo {
+  video_id: string;
+  title: string;
+  duration: number;
+  lang: string;
+  subtitle_format: string;
+  media_url?: string | null;
+  media_type?: string | null;
+  created_at: string;
+}
+
+export async function uploadVideo(subtitles: File, options: { media?: File; title?: string; lang?: string } = {}): Promise<UploadedVideo> {
+  const formData = new FormData();
+  formData.append('subtitles', subtitles);
+  if (options.media) formData.append('media', options.media);
+  if (options.title) formData.append('title', options.title);
+  if (options.lang) formData.append('lang', options.lang);
+
+  const response = await api.post<ApiResponse<UploadedVideo>>('/video/upload', formData, {
+    headers: {
+      'Content-Type': 'multipart/form-data',
+    },
+    timeout: 600000, // 10 minutes for large recordings
+  });
+  if (!response.data.success || !response.data.data) {
+    throw new Error(response.data.error || 'Failed to upload video');
+  }
+  return response.data.data;
+}
+
+export async function getUploadedVideos(): Promise<UploadedVideo[]> {
+  const response = await api.get<ApiResponse<UploadedVideo[]>>('/video/uploads');
+  if (!response.data.success || !response.data.data) {
+    throw new Error(response.data.error || 'Failed to get uploaded videos');
+  }
+  return response.data.data;
+}
+
+export async function getUploadedVideo(videoId: string): Promise<UploadedVideo> {
+  const response = await api.get<ApiResponse<UploadedVideo>>(`/video/uploads/${videoId}`);
+  if (!response.data.success || !response.data.data) {
+    throw new Error(response.data.error || 'Failed to get uploaded video');
+  }
+  return response.data.data;
+}
+
+export async function deleteUploadedVideo(videoId: string): Promise<void> {
+  const response = await api.delete<ApiResponse<null>>(`/video/uploads/${videoId}`);
+  if (!response.data.success) {
+    throw new Error(response.data.error || 'Failed to delete uploaded video');
+  }
+}
+
+// Learner Settings API
+export interface LanguageOption {
+  code: string;
+  name: string;
+}
+
+export interface LevelSystem {
+  name: string;      // "CEFR", "JLPT", "HSK", "TOPIK"
+  levels: string[];  // Easiest to hardest
+}
+
+export interface LearnerSettings {
+  native_language: string;
+  target_language: string;
+  level_system: LevelSystem;
+  supported_languages: LanguageOption[];
+  scheduler: Scheduler;
+  supported_schedulers: Scheduler[];
+}
+
+// Spaced repetition algorithm used for reviews
+export type Scheduler = 'sm2' | 'fsrs';
+
+export type CardPhase = 'learning' | 'review' | 'relearning';
+
+export async function getSettings(): Promise<LearnerSettings> {
+  const response = await api.get<ApiResponse<LearnerSettings>>('/settings');
+  if (!response.data.success || !response.data.data) {
+    throw new Error(response.data.error || 'Failed to get settings');
+  }
+  return response.data.data;
+}
+
+export async function updateSettings(settings: { native_language?: string; target_language?: string; scheduler?: Scheduler }): Promise<LearnerSettings> {
+  const response = await api.put<ApiResponse<LearnerSettings>>('/settings', settings);
+  if (!response.data.success || !response.data.data) {
+    throw new Error(response.data.error || 'Failed to update settings');
+  }
+  return response.data.data;
+}
+
+// Learning steps (minutes), daily limits and interval settings
+export interface DeckOptions {
+  learning_steps: number[];
+  relearning_steps: number[];
+  graduating_interval_days: number;
+  new_cards_per_day: number;
+  reviews_per_day: number;
+  maximum_interval_days: number;
+  easy_bonus: number;
+}
+
+export async function getDeckOptions(): Promise<DeckOptions> {
+  const response = await api.get<ApiResponse<DeckOptions>>('/settings/deck');
+  if (!response.data.success || !response.data.data) {
+    throw new Error(response.data.error || 'Failed to get deck options');
+  }
+  return response.data.data;
+}
+
+export async function updateDeckOptions(options: Partial<DeckOptions>): Promise<DeckOptions> {
+  const response = await api.put<ApiResponse<DeckOptions>>('/settings/deck', options);
+  if (!response.data.success || !response.data.data) {
+    throw new Error(response.data.error || 'Failed to update deck options');
+  }
+  return response.data.data;
+}
+
 export default api;
@@ -3,7 +3,7 @@ import { ArrowUp, Sparkles, Loader2, ChevronDown, StickyNote, Check } from 'luci
 import { useVideoStore } from '@/stores/videoStore';
 import { useAuthStore } from '@/store/authStore';
 import { useNoteStore } from '@/stores/noteStore';
-import { askAI, RateLimitError } from '@/api/client';
+import { askAIStream, RateLimitError } from '@/api/client';
 import { ChatMessage } from './ChatMessage';
 import type { ChatMessage as ChatMessageType } from '@/types';
 import { AuthDialog } from '@/components/AuthDialog';
@@ -93,27 +93,26 @@ export function AIChat() {
     setMessages(prev => [...prev, userMessage]);
     setIsLoading(true);
 
-    try {
-      const response = await askAI(context, question);
+    // The answer streams into a single assistant message, created on the first chunk
+    const aiMessageId = crypto.randomUUID();
+    const setAnswer = (update: (content: string) => string) => {
+      setMessages(prev => {
+        const existing = prev.find(m => m.id === aiMessageId);
+        if (existing) {
+          return prev.map(m => (m.id === aiMessageId ? { ...m, content: update(m.content) } : m));
+        }
+        return [...prev, { id: aiMessageId, role: 'assistant', content: update(''), timestamp: new Date() }];
+      });
+    };
 
-      const aiMessage: ChatMessageType = {
-        id: crypto.randomUUID(),
-        role: 'assistant',
-        content: response.answer,
-        timestamp: new Date(),
-      };
-      setMessages(prev => [...prev, aiMessage]);
+    try {
+      const response = await askAIStream(context, question, (text) => setAnswer(content => content + text));
+      setAnswer(() => response.answer);
     } catch (error) {
       const isRateLimited = error instanceof RateLimitError;
-      const errorMessage: ChatMessageType = {
-        id: crypto.randomUUID(),
-        role: 'assistant',
-        content: isRateLimited
-          ? 'Daily AI chat limit reached (20/day). Please try again tomorrow.'
-          : 'Sorry, I encountered an error. Please try again.',
-        timestamp: new Date(),
-      };
-      setMessages(prev => [...prev, errorMessage]);
+      setAnswer(() => isRateLimited
+        ? 'Daily AI chat limit reached (20/day). Please try again tomorrow.'
+        : 'Sorry, I encountered an error. Please try again.');
     } finally {
       setIsLoading(false);
     }
@@ -192,7 +191,7 @@ export function AIChat() {
                   onSaveAsNote={handleSaveMessageAsNote}
                 />
               ))}
-              {isLoading && (
+              {isLoading && messages[messages.length - 1]?.role !== 'assistant' && (
                 <div className="flex items-center gap-2 text-muted-foreground">
                   <Loader2 className="w-3.5 h-3.5 animate-spin" />
                   <span className="text-xs">Thinking...</span>
@@ -29,7 +29,7 @@ export function CurrentSubtitle() {
     addNote({
       video_id: videoInfo.video_id,
       english: currentSubtitle.text,
-      chinese: translation,
+      translation,
       timestamp: currentSubtitle.start,
     });
   };
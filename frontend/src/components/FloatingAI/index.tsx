@@ -11,7 +11,8 @@ import {
 } from 'lucide-react';
 import { useVideoStore } from '@/stores/videoStore';
 import { useAuthStore } from '@/store/authStore';
-import { askAI, RateLimitError } from '@/api/client';
+import { useWatchHistoryStore } from '@/store/watchHistoryStore';
+import { askAIStream, createChatThread, getChatThread, listChatThreads, RateLimitError } from '@/api/client';
 import { AuthDialog } from '@/components/AuthDialog';
 
 const FREE_QUESTION_LIMIT = 5;
@@ -29,13 +30,17 @@ interface Position {
 }
 
 export function FloatingAI() {
-  const { subtitlesEn } = useVideoStore();
+  const { subtitlesEn, videoInfo } = useVideoStore();
   const { isAuthenticated } = useAuthStore();
+  const { history } = useWatchHistoryStore();
+  const videoId = videoInfo?.video_id;
 
   const [isOpen, setIsOpen] = useState(false);
   const [isMinimized, setIsMinimized] = useState(false);
   const [isExpanded, setIsExpanded] = useState(false);
   const [messages, setMessages] = useState<ChatMessage[]>([]);
+  // Saved conversation for the current video (logged-in users only)
+  const [threadId, setThreadId] = useState<string | null>(null);
   const [inputValue, setInputValue] = useState('');
   const [isLoading, setIsLoading] = useState(false);
   const [showAuthDialog, setShowAuthDialog] = useState(false);
@@ -80,6 +85,33 @@ export function FloatingAI() {
     }
   }, [isOpen, isMinimized, isExpanded, getBottomRightPosition]);
 
+  // Reopen the latest conversation for this video
+  useEffect(() => {
+    setMessages([]);
+    setThreadId(null);
+    if (!isAuthenticated || !videoId) return;
+
+    let cancelled = false;
+    const latestThreadId = history.find(h => h.videoId === videoId)?.latestThreadId;
+    (async () => {
+      try {
+        const id = latestThreadId ?? (await listChatThreads(videoId))[0]?.id;
+        if (!id) return;
+        const { thread, messages: saved } = await getChatThread(id);
+        if (cancelled) return;
+        setThreadId(thread.id);
+        setMessages(saved.map(m => ({ id: String(m.id), role: m.role, content: m.content })));
+      } catch (e) {
+        console.warn('Failed to load chat thread:', e);
+      }
+    })();
+
+    return () => {
+      cancelled = true;
+    };
+    // eslint-disable-next-line react-hooks/exhaustive-deps
+  }, [videoId, isAuthenticated]);
+
   // Auto scroll chat
   useEffect(() => {
     if (chatRef.current) {
@@ -214,24 +246,42 @@ export function FloatingAI() {
       context = subtitlesEn.map(s => s.text).join(' ');
     }
 
+    // The answer streams into a single assistant message, created on the first chunk
+    const aiMessageId = crypto.randomUUID();
+    const setAnswer = (update: (content: string) => string) => {
+      setMessages(prev => {
+        const existing = prev.find(m => m.id === aiMessageId);
+        if (existing) {
+          return prev.map(m => (m.id === aiMessageId ? { ...m, content: update(m.content) } : m));
+        }
+        return [...prev, { id: aiMessageId, role: 'assistant', content: update('') }];
+      });
+    };
+
     try {
-      const response = await askAI(context || 'No video context available', question);
-      const aiMessage: ChatMessage = {
-        id: crypto.randomUUID(),
-        role: 'assistant',
-        content: response.answer,
-      };
-      setMessages(prev => [...prev, aiMessage]);
+      // Start a saved thread on the first question so follow-ups keep their context
+      let currentThreadId = threadId;
+      if (!currentThreadId && isAuthenticated && videoId) {
+        try {
+          currentThreadId = (await createChatThread(videoId)).thread.id;
+          setThreadId(currentThreadId);
+        } catch (e) {
+          console.warn('Failed to create chat thread:', e);
+        }
+      }
+
+      const response = await askAIStream(
+        context || 'No video context available',
+        question,
+        (text) => setAnswer(content => content + text),
+        currentThreadId ?? undefined,
+      );
+      setAnswer(() => response.answer);
     } catch (error) {
       const isRateLimited = error instanceof RateLimitError;
-      const errorMessage: ChatMessage = {
-        id: crypto.randomUUID(),
-        role: 'assistant',
-        content: isRateLimited
-          ? 'Daily AI chat limit reached (20/day). Please try again tomorrow.'
-          : 'Sorry, I encountered an error. Please try again.',
-      };
-      setMessages(prev => [...prev, errorMessage]);
+      setAnswer(() => isRateLimited
+        ? 'Daily AI chat limit reached (20/day). Please try again tomorrow.'
+        : 'Sorry, I encountered an error. Please try again.');
     } finally {
       setIsLoading(false);
       // Re-focus input after response
@@ -246,8 +296,10 @@ export function FloatingAI() {
     }
   };
 
+  // Start a new conversation; the previous thread stays saved
   const handleClearChat = () => {
     setMessages([]);
+    setThreadId(null);
   };
 
   // Floating button when closed
@@ -346,7 +398,7 @@ export function FloatingAI() {
               <button
                 onClick={handleClearChat}
                 className="p-1 hover:bg-muted rounded transition-colors text-muted-foreground hover:text-foreground"
-                title="Clear chat"
+                title="New chat"
               >
                 <Trash2 className="w-3.5 h-3.5" />
               </button>
@@ -412,7 +464,7 @@ export function FloatingAI() {
                 </div>
               ))}
 
-              {isLoading && (
+              {isLoading && messages[messages.length - 1]?.role !== 'assistant' && (
                 <div className="flex gap-2">
                   <div className="w-6 h-6 rounded-full bg-primary flex items-center justify-center shrink-0">
                     <Sparkles className="w-3 h-3 text-primary-foreground" />
@@ -199,13 +199,13 @@ function EditModal({ note, editText, setEditText, onSave, onCancel }: EditModalP
         {/* Content */}
         <div className="flex-1 p-4 overflow-auto">
           {/* Original subtitle if exists */}
-          {(note.english || note.chinese) && (
+          {(note.english || note.translation) && (
             <div className="mb-4 p-3 bg-muted/50 rounded-lg">
               {note.english && (
                 <p className="text-sm text-foreground">{note.english}</p>
               )}
-              {note.chinese && (
-                <p className="text-xs text-muted-foreground mt-1">{note.chinese}</p>
+              {note.translation && (
+                <p className="text-xs text-muted-foreground mt-1">{note.translation}</p>
               )}
             </div>
           )}
@@ -323,8 +323,8 @@ function NoteItem({ note, onSeek, onUpdate, onDelete }: NoteItemProps) {
           {note.english && (
             <p className="text-xs text-foreground leading-relaxed">{note.english}</p>
           )}
-          {note.chinese && (
-            <p className="text-[11px] text-muted-foreground">{note.chinese}</p>
+          {note.translation && (
+            <p className="text-[11px] text-muted-foreground">{note.translation}</p>
           )}
           {note.note_text && (
             <MarkdownText text={note.note_text} />
@@ -44,7 +44,7 @@ export function SubtitleLine({
     await addNote({
       video_id: videoInfo.video_id,
       english: subtitle.text,
-      chinese: translation,
+      translation,
       timestamp: subtitle.start,
       note_text: comment.trim() || undefined,
     });
@@ -133,7 +133,7 @@ export function TranscriptPanel() {
     await addNote({
       video_id: videoInfo.video_id,
       english: merged.text,
-      chinese: merged.translation || undefined,
+      translation: merged.translation || undefined,
       timestamp: merged.start,
       note_text: noteComment.trim() || undefined,
     });
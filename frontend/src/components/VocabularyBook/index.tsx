@@ -17,7 +17,7 @@ import { Button } from '@/components/ui/button';
 import { Badge } from '@/components/ui/badge';
 import { Input } from '@/components/ui/input';
 import { Tabs, TabsList, TabsTrigger } from '@/components/ui/tabs';
-import { getVocabularyList, reviewVocabulary, deleteVocabulary, type SavedVocabulary } from '@/api/client';
+import { getVocabularyList, getReviewQueue, reviewVocabulary, deleteVocabulary, type SavedVocabulary } from '@/api/client';
 import { useAuthStore } from '@/store/authStore';
 import { AuthDialog } from '@/components/AuthDialog';
 import { AIReview } from '@/components/AIReview';
@@ -29,10 +29,19 @@ const levelColors: Record<string, string> = {
   'CET4': 'bg-green-100 text-green-700',
   'CET6': 'bg-amber-100 text-amber-700',
   'Daily': 'bg-gray-100 text-gray-700',
+  // CEFR / JLPT / HSK / TOPIK levels, grouped by difficulty
+  ...Object.fromEntries(['A1', 'A2', 'N5', 'N4', 'HSK1', 'HSK2', 'TOPIK1', 'TOPIK2'].map((level) => [level, 'bg-green-100 text-green-700'])),
+  ...Object.fromEntries(['B1', 'B2', 'N3', 'HSK3', 'HSK4', 'TOPIK3', 'TOPIK4'].map((level) => [level, 'bg-blue-100 text-blue-700'])),
+  ...Object.fromEntries(['C1', 'C2', 'N2', 'N1', 'HSK5', 'HSK6', 'TOPIK5', 'TOPIK6'].map((level) => [level, 'bg-purple-100 text-purple-700'])),
 };
 
-// Learning phase names
-const learningStepNames = ['New', '20min', '1h', '9h', 'Learned'];
+// Learning phase label: new, relearning, or the current step's length
+function learningPhaseName(item: SavedVocabulary): string {
+  if (item.review_count === 0) return 'New';
+  if (item.phase === 'relearning') return 'Relearn';
+  const minutes = item.interval_minutes;
+  return minutes >= 60 ? `${Math.round(minutes / 60)}h` : `${minutes}min`;
+}
 
 // Get dot color based on memory strength (0-1)
 function getMemoryDotColor(strength: number): string {
@@ -130,14 +139,20 @@ export function VocabularyBook() {
     setHasPlayedAudio(true);
   }, []);
 
-  const startAIReview = () => {
+  const startAIReview = async () => {
     // Check if user is authenticated
     if (!isAuthenticated) {
       setShowAuthDialog(true);
       return;
     }
 
-    const dueWords = vocabulary.filter(isDueForReview);
+    let dueWords: SavedVocabulary[];
+    try {
+      dueWords = (await getReviewQueue()).cards;
+    } catch (error) {
+      console.error('Failed to get review queue:', error);
+      return;
+    }
     if (dueWords.length === 0) {
       alert('No words to review!');
       return;
@@ -208,7 +223,7 @@ export function VocabularyBook() {
   }, [showAnswer, revealAnswer]);
 
   const dueCount = vocabulary.filter(isDueForReview).length;
-  const learningCount = vocabulary.filter(v => (v.learning_step || 0) < 4).length;
+  const learningCount = vocabulary.filter(v => v.phase !== 'review').length;
 
   // AI Review Mode UI
   if (viewMode === 'ai-review' && reviewList.length > 0) {
@@ -423,10 +438,9 @@ export function VocabularyBook() {
         <div className="space-y-2">
           {vocabulary.map((item) => {
             const isDue = isDueForReview(item);
-            const step = item.learning_step || 0;
-            const isLearning = step < 4;
+            const isLearning = item.phase !== 'review';
             const dueTime = formatDueTime(item.due_at, item.due_date);
-            const strength = item.memory_strength ?? 1;
+            const strength = item.retrievability ?? 1;
             const dotColor = getMemoryDotColor(strength);
             return (
               <div
@@ -463,7 +477,7 @@ export function VocabularyBook() {
                       {/* Learning phase indicator */}
                       {isLearning && (
                         <Badge variant="outline" className="text-blue-600 border-blue-300 text-[10px] sm:text-xs px-1.5 sm:px-2">
-                          {learningStepNames[step]}
+                          {learningPhaseName(item)}
                         </Badge>
                       )}
                       {/* Due time indicator */}
@@ -12,6 +12,10 @@ const levelColors: Record<string, string> = {
   'CET-4': 'bg-green-100 text-green-700 border-green-200',
   'CET-6': 'bg-amber-100 text-amber-700 border-amber-200',
   'Basic': 'bg-gray-100 text-gray-700 border-gray-200',
+  // CEFR / JLPT / HSK / TOPIK levels, grouped by difficulty
+  ...Object.fromEntries(['A1', 'A2', 'N5', 'N4', 'HSK1', 'HSK2', 'TOPIK1', 'TOPIK2'].map((level) => [level, 'bg-green-100 text-green-700 border-green-200'])),
+  ...Object.fromEntries(['B1', 'B2', 'N3', 'HSK3', 'HSK4', 'TOPIK3', 'TOPIK4'].map((level) => [level, 'bg-blue-100 text-blue-700 border-blue-200'])),
+  ...Object.fromEntries(['C1', 'C2', 'N2', 'N1', 'HSK5', 'HSK6', 'TOPIK5', 'TOPIK6'].map((level) => [level, 'bg-purple-100 text-purple-700 border-purple-200'])),
 };
 
 // Speak word using Youdao Dictionary audio
@@ -34,6 +34,10 @@ const levelColors: Record<string, string> = {
   'Phrase': 'bg-cyan-100 text-cyan-700 dark:bg-cyan-900/30 dark:text-cyan-400',
   'Advanced': 'bg-pink-100 text-pink-700 dark:bg-pink-900/30 dark:text-pink-400',
   'Basic': 'bg-gray-100 text-gray-700 dark:bg-gray-800 dark:text-gray-400',
+  // CEFR / JLPT / HSK / TOPIK levels, grouped by difficulty
+  ...Object.fromEntries(['A1', 'A2', 'N5', 'N4', 'HSK1', 'HSK2', 'TOPIK1', 'TOPIK2'].map((level) => [level, 'bg-green-100 text-green-700 dark:bg-green-900/30 dark:text-green-400'])),
+  ...Object.fromEntries(['B1', 'B2', 'N3', 'HSK3', 'HSK4', 'TOPIK3', 'TOPIK4'].map((level) => [level, 'bg-blue-100 text-blue-700 dark:bg-blue-900/30 dark:text-blue-400'])),
+  ...Object.fromEntries(['C1', 'C2', 'N2', 'N1', 'HSK5', 'HSK6', 'TOPIK5', 'TOPIK6'].map((level) => [level, 'bg-purple-100 text-purple-700 dark:bg-purple-900/30 dark:text-purple-400'])),
 };
 
 export function VocabularyPanel() {
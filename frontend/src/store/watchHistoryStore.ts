@@ -7,6 +7,7 @@ export interface WatchHistoryItem {
   title: string;
   thumbnail: string;
   watchedAt: number; // timestamp
+  latestThreadId?: string; // AI chat thread to reopen
 }
 
 interface WatchHistoryState {
@@ -83,6 +84,7 @@ export const useWatchHistoryStore = create<WatchHistoryState>()(
             title: item.title,
             thumbnail: item.thumbnail,
             watchedAt: new Date(item.watched_at).getTime(),
+            latestThreadId: item.latest_thread_id,
           }));
           set({ history: localHistory, isLoading: false });
         } catch (e) {
@@ -81,7 +81,8 @@ export const useNoteStore = create<NoteState>()(
               video_id: note.video_id,
               timestamp: note.timestamp,
               english: note.english,
-              chinese: note.chinese,
+              translation: note.translation,
+              translation_lang: note.translation_lang,
               note_text: note.note_text,
               images: note.images,
             });
@@ -149,7 +150,8 @@ export const useNoteStore = create<NoteState>()(
               video_id: n.video_id,
               timestamp: n.timestamp,
               english: n.english,
-              chinese: n.chinese,
+              translation: n.translation,
+              translation_lang: n.translation_lang,
               note_text: n.note_text,
               images: n.images,
               created_at: n.created_at,
@@ -167,7 +169,8 @@ export const useNoteStore = create<NoteState>()(
                 video_id: note.video_id,
                 timestamp: note.timestamp,
                 english: note.english,
-                chinese: note.chinese,
+                translation: note.translation,
+                translation_lang: note.translation_lang,
                 note_text: note.note_text,
                 images: note.images,
               });
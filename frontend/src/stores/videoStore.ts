@@ -275,7 +275,7 @@ export const useVideoStore = create<VideoState>((set, get) => ({
 
       // Fetch English subtitles only (Chinese translation via AI)
       try {
-        const enSubs = await getSubtitles(videoInfo.video_id, 'en');
+        const enSubs = await getSubtitles(videoInfo.video_id);
         set({ subtitlesEn: enSubs.subtitles, isLoading: false });
 
         // Start AI translation in background
@@ -645,7 +645,7 @@ export const useVideoStore = create<VideoState>((set, get) => ({
     set({ isTranslating: true });
 
     try {
-      const result = await translateSubtitles(batch);
+      const result = await translateSubtitles(batch, videoInfo.video_id);
 
       // Update translations map
       const newTranslations = new Map(translations);
@@ -678,7 +678,7 @@ async function requestTranslationBatch(startIndex: number) {
   if (pendingTranslation) return;
 
   const state = useVideoStore.getState();
-  const { subtitlesEn } = state;
+  const { subtitlesEn, videoInfo } = state;
 
   if (startIndex >= subtitlesEn.length) return;
 
@@ -691,7 +691,7 @@ async function requestTranslationBatch(startIndex: number) {
   useVideoStore.setState({ isTranslating: true });
 
   try {
-    const result = await translateSubtitles(batch);
+    const result = await translateSubtitles(batch, videoInfo?.video_id);
 
     // Merge new translations into existing map
     const currentState = useVideoStore.getState();
@@ -11,20 +11,34 @@ export interface Subtitle {
   end: number;
   text: string;
   translation?: string;
+  translation_lang?: string;
+  // Per-word timings for karaoke-style highlighting, when the source has them
+  words?: SubtitleWord[];
+}
+
+export interface SubtitleWord {
+  text: string;
+  start: number;
+  end: number;
 }
 
 export interface SubtitleResponse {
   video_id: string;
   subtitles: Subtitle[];
   language: string;
+  // 'raw' cues as delivered, or 'sentences' re-segmented one sentence per cue
+  track: SubtitleTrack;
 }
 
+export type SubtitleTrack = 'raw' | 'sentences';
+
 export interface Note {
   id: string;
   video_id: string;
   timestamp: number;
   english?: string;
-  chinese?: string;
+  translation?: string;
+  translation_lang?: string;
   note_text?: string;
   images?: string[];  // R2 image URLs
   created_at: string;